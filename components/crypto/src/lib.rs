@@ -13,6 +13,8 @@ extern crate hex_buffer_serde;
 extern crate pwbox;
 extern crate rand;
 extern crate rust_decimal;
+#[cfg(feature = "openapi")]
+extern crate schemars;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
@@ -31,6 +33,12 @@ pub use self::utils::{generate_keys_file, read_keys_from_file};
 use byteorder::{ByteOrder, LittleEndian};
 use chrono::{DateTime, Duration, Utc};
 use rust_decimal::Decimal;
+#[cfg(feature = "openapi")]
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{InstanceType, Schema, SchemaObject, StringValidation},
+    JsonSchema,
+};
 use serde::{
     de::{self, Deserialize, Deserializer, Visitor},
     Serialize, Serializer,
@@ -490,6 +498,13 @@ implement_serde! {SecretKey}
 implement_serde! {Seed}
 implement_serde! {Signature}
 
+#[cfg(feature = "openapi")]
+implement_json_schema! {Hash, HASH_SIZE}
+#[cfg(feature = "openapi")]
+implement_json_schema! {PublicKey, PUBLIC_KEY_LENGTH}
+#[cfg(feature = "openapi")]
+implement_json_schema! {Signature, SIGNATURE_LENGTH}
+
 implement_index_traits! {Hash}
 implement_index_traits! {PublicKey}
 implement_index_traits! {SecretKey}
@@ -710,6 +725,16 @@ mod tests {
         assert_serialize_deserialize(&SecretKey::new([211; SECRET_KEY_LENGTH]));
     }
 
+    #[cfg(feature = "openapi")]
+    #[test]
+    fn json_schema() {
+        let schema = SchemaGenerator::default().into_root_schema_for::<Hash>();
+        let schema = serde_json::to_value(&schema).unwrap();
+        assert_eq!(schema["type"], "string");
+        assert_eq!(schema["minLength"], 2 * HASH_SIZE);
+        assert_eq!(schema["maxLength"], 2 * HASH_SIZE);
+    }
+
     #[test]
     fn debug_format() {
         // Check zero padding.
@@ -156,6 +156,31 @@ macro_rules! implement_serde {
     };
 }
 
+#[cfg(feature = "openapi")]
+macro_rules! implement_json_schema {
+    ($name:ident, $size:expr) => {
+        impl JsonSchema for $name {
+            fn schema_name() -> String {
+                stringify!($name).to_owned()
+            }
+
+            fn json_schema(_gen: &mut SchemaGenerator) -> Schema {
+                let length = 2 * $size as u32;
+                SchemaObject {
+                    instance_type: Some(InstanceType::String.into()),
+                    string: Some(Box::new(StringValidation {
+                        max_length: Some(length),
+                        min_length: Some(length),
+                        pattern: Some("^[0-9a-f]*$".to_owned()),
+                    })),
+                    ..Default::default()
+                }
+                .into()
+            }
+        }
+    };
+}
+
 macro_rules! implement_index_traits {
     ($new_type:ident) => {
         impl Index<Range<usize>> for $new_type {
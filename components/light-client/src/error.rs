@@ -0,0 +1,102 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use exonum::{
+    crypto::Hash,
+    helpers::{Height, ValidatorId},
+    storage::{proof_list_index::ListProofError, proof_map_index::MapProofError},
+};
+
+/// Errors returned when data received from a node cannot be verified.
+#[derive(Debug, Fail)]
+pub enum Error {
+    /// The block precedes the first configuration known to the client.
+    #[fail(display = "No known configuration is actual at height {}", _0)]
+    UnknownConfiguration(Height),
+
+    /// The precommit is not signed by the validator with the given id.
+    #[fail(display = "Precommit is not signed by validator {}", _0)]
+    UnknownValidator(ValidatorId),
+
+    /// The precommit of the validator refers to another block or round.
+    #[fail(display = "Precommit of validator {} does not match the block", _0)]
+    PrecommitMismatch(ValidatorId),
+
+    /// The block is not signed by the majority of the validators.
+    #[fail(
+        display = "Block has {} precommits, while {} are required",
+        actual, required
+    )]
+    NotEnoughPrecommits {
+        /// Number of the valid precommits.
+        actual: usize,
+        /// Number of the precommits required to accept the block.
+        required: usize,
+    },
+
+    /// The map proof is malformed.
+    #[fail(display = "Invalid map proof: {}", _0)]
+    InvalidProof(#[cause] MapProofError),
+
+    /// The list proof is malformed or does not match the root hash.
+    #[fail(display = "Invalid list proof: {:?}", _0)]
+    InvalidListProof(ListProofError),
+
+    /// The proof of the service table does not lead to the state hash of the block.
+    #[fail(display = "Proof does not match the state hash of the block")]
+    StateHashMismatch,
+
+    /// The proof does not contain the hash of the service table, or the hash does
+    /// not match the root of the table proof.
+    #[fail(display = "Proof does not match the hash of the service table")]
+    TableHashMismatch,
+
+    /// The proof does not contain the transaction at its location in the block.
+    #[fail(display = "Transaction is absent at its location in the block")]
+    TransactionNotInBlock,
+
+    /// The result of the transaction is proven in the state of a block preceding
+    /// the block of the transaction.
+    #[fail(
+        display = "State block at height {} precedes the transaction block at height {}",
+        state_height, block_height
+    )]
+    OutdatedStateBlock {
+        /// Height of the block in which state the result is proven.
+        state_height: Height,
+        /// Height of the block containing the transaction.
+        block_height: Height,
+    },
+
+    /// The proof does not contain the result of the transaction.
+    #[fail(display = "Transaction result is absent in the proof")]
+    MissingTransactionResult,
+
+    /// The proof does not contain a configuration.
+    #[fail(display = "Configuration is absent in the proof")]
+    MissingConfiguration,
+
+    /// The configuration does not follow the last configuration known to the client.
+    #[fail(
+        display = "Configuration {:?} does not follow the last known configuration",
+        _0
+    )]
+    UnexpectedConfiguration(Hash),
+}
+
+impl From<MapProofError> for Error {
+    fn from(e: MapProofError) -> Self {
+        Error::InvalidProof(e)
+    }
+}
@@ -0,0 +1,640 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Light client verifying the data returned by the REST API of Exonum nodes.
+//!
+//! The client does not trust the node it talks to. Starting from a trusted
+//! configuration (usually the genesis one), it follows the validator sets through
+//! the configuration changes in the same order as the `configs_actual_from` table
+//! of the blockchain, checks block headers by the `Precommit` messages of the
+//! validators and checks proofs of the service data against the state hash of
+//! a verified block.
+//!
+//! # Examples
+//!
+//! Verifying a wallet returned by the `v1/wallets/info` endpoint of
+//! the cryptocurrency service:
+//!
+//! ```ignore
+//! let mut client = LightClient::new(genesis_config);
+//! // Fetched from `api/services/configuration/v1/configs/proof?hash=...`
+//! // for each committed configuration in turn.
+//! client.add_config(config_proof)?;
+//!
+//! let info: WalletInfo = /* `api/services/cryptocurrency/v1/wallets/info` */;
+//! let wallets = client.verify_service_proof(
+//!     &info.block_proof,
+//!     CRYPTOCURRENCY_SERVICE_ID,
+//!     0,
+//!     info.wallet_proof.to_table,
+//!     info.wallet_proof.to_wallet,
+//! )?;
+//! let wallet = wallets.entries().next();
+//! ```
+//!
+//! Verifying that a transaction is committed with the given result, with the proof
+//! returned by the `v1/transactions/proof` endpoint of the explorer:
+//!
+//! ```ignore
+//! let proof: TransactionProof = /* `api/explorer/v1/transactions/proof?hash=...` */;
+//! let result = client.verify_transaction(&tx_hash, proof)?;
+//! ```
+
+#![deny(
+    missing_debug_implementations,
+    missing_docs,
+    unsafe_code,
+    bare_trait_objects
+)]
+
+extern crate exonum;
+#[macro_use]
+extern crate failure;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+#[cfg(test)]
+extern crate chrono;
+#[cfg(test)]
+extern crate serde_json;
+
+pub use error::Error;
+
+mod error;
+
+use exonum::{
+    blockchain::{
+        BlockProof, Blockchain, StoredConfiguration, TransactionResult, CONFIGS_TABLE,
+        CORE_SERVICE, TRANSACTION_RESULTS_TABLE,
+    },
+    crypto::{CryptoHash, Hash},
+    explorer::TransactionProof,
+    helpers::Height,
+    node::State,
+    storage::{
+        proof_map_index::{CheckedMapProof, ProofMapKey},
+        MapProof, StorageValue,
+    },
+};
+
+use std::collections::BTreeSet;
+
+/// Proof that a configuration is committed to the blockchain, as returned by
+/// the `v1/configs/proof` endpoint of the configuration service.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConfigProof {
+    /// Latest block of the blockchain.
+    pub block_proof: BlockProof,
+    /// Proof of the configurations table in the state of the block.
+    pub to_table: MapProof<Hash, Hash>,
+    /// Proof of the configuration in the configurations table.
+    pub to_config: MapProof<Hash, StoredConfiguration>,
+}
+
+/// Verifier of the blocks and proofs returned by the nodes.
+#[derive(Debug, Clone)]
+pub struct LightClient {
+    /// Known configurations in the increasing order of their `actual_from` height.
+    configs: Vec<StoredConfiguration>,
+}
+
+impl LightClient {
+    /// Creates a client which trusts the given configuration. Blocks before the height
+    /// from which the configuration is actual cannot be verified by the client.
+    pub fn new(trusted_config: StoredConfiguration) -> Self {
+        Self {
+            configs: vec![trusted_config],
+        }
+    }
+
+    /// Returns the last configuration known to the client.
+    pub fn last_config(&self) -> &StoredConfiguration {
+        self.configs
+            .last()
+            .expect("Light client without configurations")
+    }
+
+    /// Returns the configuration that is actual at the given height, as far as it is
+    /// known to the client.
+    ///
+    /// The result is reliable only if the client has been informed about all the
+    /// configurations committed up to this height.
+    pub fn config_at(&self, height: Height) -> Option<&StoredConfiguration> {
+        self.configs
+            .iter()
+            .rev()
+            .find(|config| config.actual_from <= height)
+    }
+
+    /// Checks that the block is signed by the majority of the validators actual
+    /// at its height.
+    pub fn verify_block(&self, block_proof: &BlockProof) -> Result<(), Error> {
+        let height = block_proof.block.height();
+        let config = self
+            .config_at(height)
+            .ok_or_else(|| Error::UnknownConfiguration(height))?;
+        Self::verify_block_signed_by(config, block_proof)
+    }
+
+    // Checks that the block is signed by the majority of the validators of the configuration.
+    fn verify_block_signed_by(
+        config: &StoredConfiguration,
+        block_proof: &BlockProof,
+    ) -> Result<(), Error> {
+        let block = &block_proof.block;
+        let block_hash = block.hash();
+
+        let mut block_round = None;
+        let mut validators = BTreeSet::new();
+        for precommit in &block_proof.precommits {
+            let validator = precommit.validator();
+            let is_signed_by_validator = config
+                .validator_keys
+                .get(validator.0 as usize)
+                .map_or(false, |keys| keys.consensus_key == precommit.author());
+            if !is_signed_by_validator {
+                return Err(Error::UnknownValidator(validator));
+            }
+
+            let round = *block_round.get_or_insert(precommit.round());
+            if precommit.height() != block.height()
+                || *precommit.block_hash() != block_hash
+                || precommit.round() != round
+            {
+                return Err(Error::PrecommitMismatch(validator));
+            }
+            validators.insert(validator);
+        }
+
+        let required = State::byzantine_majority_count(config.validator_keys.len());
+        if validators.len() < required {
+            return Err(Error::NotEnoughPrecommits {
+                actual: validators.len(),
+                required,
+            });
+        }
+        Ok(())
+    }
+
+    /// Verifies the proof of the entries of the service table with the given index,
+    /// returned together with the block. Returns the entries of the table proven
+    /// to be present or absent in the state of the block.
+    pub fn verify_service_proof<K, V>(
+        &self,
+        block_proof: &BlockProof,
+        service_id: u16,
+        table_idx: usize,
+        to_table: MapProof<Hash, Hash>,
+        to_value: MapProof<K, V>,
+    ) -> Result<CheckedMapProof<K, V>, Error>
+    where
+        K: ProofMapKey,
+        V: StorageValue,
+    {
+        self.verify_block(block_proof)?;
+        Self::verify_state_proof(block_proof, service_id, table_idx, to_table, to_value)
+    }
+
+    // Checks the proof of the service table entries against the state hash of the block.
+    fn verify_state_proof<K, V>(
+        block_proof: &BlockProof,
+        service_id: u16,
+        table_idx: usize,
+        to_table: MapProof<Hash, Hash>,
+        to_value: MapProof<K, V>,
+    ) -> Result<CheckedMapProof<K, V>, Error>
+    where
+        K: ProofMapKey,
+        V: StorageValue,
+    {
+        let to_table = to_table.check()?;
+        if to_table.merkle_root() != *block_proof.block.state_hash() {
+            return Err(Error::StateHashMismatch);
+        }
+
+        let to_value = to_value.check()?;
+        let table_key = Blockchain::service_table_unique_key(service_id, table_idx);
+        let table_hash = to_table
+            .entries()
+            .find(|&(key, _)| *key == table_key)
+            .map(|(_, hash)| *hash);
+        if table_hash != Some(to_value.merkle_root()) {
+            return Err(Error::TableHashMismatch);
+        }
+        Ok(to_value)
+    }
+
+    /// Verifies the proof that the transaction with the given hash is committed and
+    /// returns its execution result. The result must be proven in the state of
+    /// the block of the transaction or a later one.
+    pub fn verify_transaction(
+        &self,
+        tx_hash: &Hash,
+        proof: TransactionProof,
+    ) -> Result<TransactionResult, Error> {
+        self.verify_block(&proof.block_proof)?;
+        let block = &proof.block_proof.block;
+        if proof.location.block_height() != block.height() {
+            return Err(Error::TransactionNotInBlock);
+        }
+        let txs = proof
+            .location_proof
+            .validate(*block.tx_hash(), u64::from(block.tx_count()))
+            .map_err(Error::InvalidListProof)?;
+        if txs != vec![(proof.location.position_in_block(), tx_hash)] {
+            return Err(Error::TransactionNotInBlock);
+        }
+        // The transaction result is absent in the state of the earlier blocks.
+        let state_height = proof.state_block_proof.block.height();
+        if state_height < block.height() {
+            return Err(Error::OutdatedStateBlock {
+                state_height,
+                block_height: block.height(),
+            });
+        }
+
+        let results = self.verify_service_proof(
+            &proof.state_block_proof,
+            CORE_SERVICE,
+            TRANSACTION_RESULTS_TABLE,
+            proof.to_table,
+            proof.to_result,
+        )?;
+        results
+            .entries()
+            .find(|&(hash, _)| hash == tx_hash)
+            .map(|(_, result)| result.clone())
+            .ok_or(Error::MissingTransactionResult)
+    }
+
+    /// Adds the configuration following the last known one. The proof must be
+    /// signed by the majority of the validators of the last known configuration.
+    ///
+    /// The proof may come from a block committed after the configuration has become
+    /// actual, so a client that is behind can catch up as long as the validators it
+    /// trusts still sign the blocks. A client that has missed a configuration change
+    /// replacing the majority of the validators should be recreated from a trusted
+    /// configuration.
+    pub fn add_config(&mut self, proof: ConfigProof) -> Result<&StoredConfiguration, Error> {
+        Self::verify_block_signed_by(self.last_config(), &proof.block_proof)?;
+        let configs = Self::verify_state_proof(
+            &proof.block_proof,
+            CORE_SERVICE,
+            CONFIGS_TABLE,
+            proof.to_table,
+            proof.to_config,
+        )?;
+
+        let (config_hash, config) = configs
+            .entries()
+            .next()
+            .map(|(hash, config)| (*hash, config.clone()))
+            .ok_or(Error::MissingConfiguration)?;
+
+        let is_next = {
+            let last_config = self.last_config();
+            config.hash() == config_hash
+                && config.previous_cfg_hash == last_config.hash()
+                && config.actual_from > last_config.actual_from
+        };
+        if !is_next {
+            return Err(Error::UnexpectedConfiguration(config_hash));
+        }
+
+        self.configs.push(config);
+        Ok(self.last_config())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use exonum::{
+        blockchain::{Block, ConsensusConfig, TransactionError, TxLocation, ValidatorKeys},
+        crypto::{self, gen_keypair, PublicKey, SecretKey},
+        helpers::{Round, ValidatorId},
+        messages::{Message, Precommit},
+        storage::{Database, MemoryDB, ProofListIndex, ProofMapIndex},
+    };
+    use serde_json;
+
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    const SERVICE_ID: u16 = 5;
+
+    fn gen_keys(count: usize) -> Vec<(PublicKey, SecretKey)> {
+        (0..count).map(|_| gen_keypair()).collect()
+    }
+
+    fn make_config(
+        keys: &[(PublicKey, SecretKey)],
+        previous_cfg_hash: Hash,
+        actual_from: Height,
+    ) -> StoredConfiguration {
+        StoredConfiguration {
+            previous_cfg_hash,
+            actual_from,
+            validator_keys: keys
+                .iter()
+                .map(|&(consensus_key, _)| ValidatorKeys {
+                    consensus_key,
+                    service_key: gen_keypair().0,
+                })
+                .collect(),
+            consensus: ConsensusConfig::default(),
+            services: BTreeMap::new(),
+        }
+    }
+
+    fn make_block_proof(
+        keys: &[(PublicKey, SecretKey)],
+        height: Height,
+        state_hash: &Hash,
+    ) -> BlockProof {
+        let block = Block::new(
+            ValidatorId(0),
+            height,
+            0,
+            &Hash::zero(),
+            &Hash::zero(),
+            state_hash,
+        );
+        sign_block(keys, block)
+    }
+
+    fn sign_block(keys: &[(PublicKey, SecretKey)], block: Block) -> BlockProof {
+        let height = block.height();
+        let precommits = keys
+            .iter()
+            .enumerate()
+            .map(|(id, &(public_key, ref secret_key))| {
+                let precommit = Precommit::new(
+                    ValidatorId(id as u16),
+                    height,
+                    Round(1),
+                    &Hash::zero(),
+                    &block.hash(),
+                    Utc::now(),
+                );
+                Message::concrete(precommit, public_key, secret_key)
+            })
+            .collect();
+        BlockProof { block, precommits }
+    }
+
+    // Builds the state with a single table of the service and returns its state hash
+    // with proofs of the table and the value.
+    fn make_state<V: StorageValue>(
+        service_id: u16,
+        table_idx: usize,
+        key: Hash,
+        value: V,
+    ) -> (Hash, MapProof<Hash, Hash>, MapProof<Hash, V>) {
+        let db = MemoryDB::new();
+        let mut fork = db.fork();
+        let (table_hash, to_value) = {
+            let mut table = ProofMapIndex::new("table", &mut fork);
+            table.put(&key, value);
+            (table.merkle_root(), table.get_proof(key))
+        };
+        let table_key = Blockchain::service_table_unique_key(service_id, table_idx);
+        let mut aggregator = ProofMapIndex::new("aggregator", &mut fork);
+        aggregator.put(&table_key, table_hash);
+        (
+            aggregator.merkle_root(),
+            aggregator.get_proof(table_key),
+            to_value,
+        )
+    }
+
+    #[test]
+    fn test_verify_block() {
+        let keys = gen_keys(4);
+        let client = LightClient::new(make_config(&keys, Hash::zero(), Height(0)));
+        let state_hash = Hash::zero();
+
+        let proof = make_block_proof(&keys, Height(1), &state_hash);
+        assert!(client.verify_block(&proof).is_ok());
+
+        let mut proof = make_block_proof(&keys[..3], Height(1), &state_hash);
+        assert!(client.verify_block(&proof).is_ok());
+        // Duplicate precommits are counted once.
+        proof.precommits[2] = proof.precommits[1].clone();
+        match client.verify_block(&proof) {
+            Err(Error::NotEnoughPrecommits { actual, required }) => {
+                assert_eq!((actual, required), (2, 3))
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        let mut other_keys = gen_keys(4);
+        other_keys[1] = keys[1].clone();
+        let proof = make_block_proof(&other_keys, Height(1), &state_hash);
+        match client.verify_block(&proof) {
+            Err(Error::UnknownValidator(ValidatorId(0))) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        let mut proof = make_block_proof(&keys, Height(1), &state_hash);
+        proof.precommits[3] = make_block_proof(&keys, Height(2), &state_hash).precommits[3].clone();
+        match client.verify_block(&proof) {
+            Err(Error::PrecommitMismatch(ValidatorId(3))) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_verify_service_proof() {
+        let keys = gen_keys(4);
+        let client = LightClient::new(make_config(&keys, Hash::zero(), Height(0)));
+        let key = Hash::zero();
+        let (state_hash, to_table, to_value) = make_state(SERVICE_ID, 0, key, 42_u64);
+
+        let proof = make_block_proof(&keys, Height(3), &state_hash);
+        let entries = client
+            .verify_service_proof(&proof, SERVICE_ID, 0, to_table.clone(), to_value.clone())
+            .unwrap();
+        assert_eq!(entries.entries().collect::<Vec<_>>(), vec![(&key, &42)]);
+
+        match client.verify_service_proof(&proof, SERVICE_ID, 1, to_table.clone(), to_value.clone())
+        {
+            Err(Error::TableHashMismatch) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        let proof = make_block_proof(&keys, Height(3), &Hash::zero());
+        match client.verify_service_proof(&proof, SERVICE_ID, 0, to_table, to_value) {
+            Err(Error::StateHashMismatch) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    // Builds the proof of the transaction at the given position in a block of three
+    // transactions, with the result proven in the state of the same block.
+    fn make_transaction_proof(
+        keys: &[(PublicKey, SecretKey)],
+        height: Height,
+        position: u64,
+        result: TransactionResult,
+    ) -> (Hash, TransactionProof) {
+        let tx_hashes: Vec<_> = (0..3).map(|i| crypto::hash(&[i])).collect();
+        let tx_hash = tx_hashes[position as usize];
+        let db = MemoryDB::new();
+        let mut fork = db.fork();
+        let (txs_hash, location_proof) = {
+            let mut txs = ProofListIndex::new("txs", &mut fork);
+            txs.extend(tx_hashes.iter().cloned());
+            (txs.merkle_root(), txs.get_proof(position))
+        };
+        let (state_hash, to_table, to_result) =
+            make_state(CORE_SERVICE, TRANSACTION_RESULTS_TABLE, tx_hash, result);
+
+        let block = Block::new(
+            ValidatorId(0),
+            height,
+            tx_hashes.len() as u32,
+            &Hash::zero(),
+            &txs_hash,
+            &state_hash,
+        );
+        let block_proof = sign_block(keys, block);
+        let proof = TransactionProof {
+            block_proof: block_proof.clone(),
+            location: TxLocation::new(height, position),
+            location_proof,
+            state_block_proof: block_proof,
+            to_table,
+            to_result,
+        };
+        (tx_hash, proof)
+    }
+
+    #[test]
+    fn test_verify_transaction() {
+        let keys = gen_keys(4);
+        let client = LightClient::new(make_config(&keys, Hash::zero(), Height(0)));
+        let result = TransactionResult(Err(TransactionError::code(2, None)));
+
+        let (tx_hash, proof) = make_transaction_proof(&keys, Height(3), 1, result.clone());
+        // Proofs are parsed from the JSON returned by the REST API.
+        let proof: TransactionProof =
+            serde_json::from_str(&serde_json::to_string(&proof).unwrap()).unwrap();
+        assert_eq!(client.verify_transaction(&tx_hash, proof).unwrap(), result);
+
+        let (tx_hash, mut proof) = make_transaction_proof(&keys, Height(3), 1, result.clone());
+        proof.location = TxLocation::new(Height(3), 2);
+        match client.verify_transaction(&tx_hash, proof) {
+            Err(Error::TransactionNotInBlock) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        let (_, proof) = make_transaction_proof(&keys, Height(3), 1, result.clone());
+        match client.verify_transaction(&crypto::hash(&[7]), proof) {
+            Err(Error::TransactionNotInBlock) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        // The result is proven in the state of a block preceding the transaction.
+        let (tx_hash, mut proof) = make_transaction_proof(&keys, Height(3), 1, result);
+        let state_hash = *proof.state_block_proof.block.state_hash();
+        proof.state_block_proof = make_block_proof(&keys, Height(2), &state_hash);
+        match client.verify_transaction(&tx_hash, proof) {
+            Err(Error::OutdatedStateBlock {
+                state_height: Height(2),
+                block_height: Height(3),
+            }) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_add_config() {
+        let (old_keys, new_keys) = (gen_keys(4), gen_keys(4));
+        let genesis = make_config(&old_keys, Hash::zero(), Height(0));
+        let mut client = LightClient::new(genesis.clone());
+
+        let config = make_config(&new_keys, genesis.hash(), Height(10));
+        let (state_hash, to_table, to_config) =
+            make_state(CORE_SERVICE, 0, config.hash(), config.clone());
+        let proof = ConfigProof {
+            block_proof: make_block_proof(&old_keys, Height(5), &state_hash),
+            to_table,
+            to_config,
+        };
+        // Proofs are parsed from the JSON returned by the REST API.
+        let proof: ConfigProof =
+            serde_json::from_str(&serde_json::to_string(&proof).unwrap()).unwrap();
+        assert_eq!(*client.add_config(proof).unwrap(), config);
+
+        assert_eq!(client.config_at(Height(9)), Some(&genesis));
+        assert_eq!(client.config_at(Height(10)), Some(&config));
+        let block = make_block_proof(&old_keys, Height(10), &Hash::zero());
+        assert!(client.verify_block(&block).is_err());
+        let block = make_block_proof(&new_keys, Height(10), &Hash::zero());
+        assert!(client.verify_block(&block).is_ok());
+
+        // The configuration does not follow the last known one.
+        let unexpected = make_config(&old_keys, genesis.hash(), Height(20));
+        let (state_hash, to_table, to_config) =
+            make_state(CORE_SERVICE, 0, unexpected.hash(), unexpected.clone());
+        let proof = ConfigProof {
+            block_proof: make_block_proof(&new_keys, Height(15), &state_hash),
+            to_table,
+            to_config,
+        };
+        match client.add_config(proof) {
+            Err(Error::UnexpectedConfiguration(hash)) => assert_eq!(hash, unexpected.hash()),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_add_config_after_actual_from() {
+        let old_keys = gen_keys(4);
+        let mut new_keys = old_keys.clone();
+        new_keys[3] = gen_keypair();
+        let genesis = make_config(&old_keys, Hash::zero(), Height(0));
+        let config = make_config(&new_keys, genesis.hash(), Height(10));
+        let (state_hash, to_table, to_config) =
+            make_state(CORE_SERVICE, 0, config.hash(), config.clone());
+
+        // The block is signed by all the validators of the new configuration, including
+        // the one the client does not know yet.
+        let mut client = LightClient::new(genesis.clone());
+        let proof = ConfigProof {
+            block_proof: make_block_proof(&new_keys, Height(15), &state_hash),
+            to_table: to_table.clone(),
+            to_config: to_config.clone(),
+        };
+        match client.add_config(proof) {
+            Err(Error::UnknownValidator(ValidatorId(3))) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        // The client is behind, and the block is signed by the validators it trusts.
+        let proof = ConfigProof {
+            block_proof: make_block_proof(&new_keys[..3], Height(15), &state_hash),
+            to_table,
+            to_config,
+        };
+        assert_eq!(*client.add_config(proof).unwrap(), config);
+
+        // Transactions from the blocks after the configuration change are verified.
+        let result = TransactionResult(Ok(()));
+        let (tx_hash, proof) = make_transaction_proof(&new_keys, Height(15), 1, result.clone());
+        assert_eq!(client.verify_transaction(&tx_hash, proof).unwrap(), result);
+    }
+}
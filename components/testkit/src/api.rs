@@ -0,0 +1,221 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! In-process calls of the API endpoints.
+
+use actix_web::{
+    http::{header, Method, StatusCode},
+    test::TestRequest,
+    Body,
+};
+use exonum::api::{
+    self, backends::actix::RequestHandler, ApiAccess, ApiAggregator, ExtendApiBackend,
+    ServiceApiScope, ServiceApiState,
+};
+use futures::Future;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json;
+use serde_urlencoded;
+
+/// Kind of the API an endpoint belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiKind {
+    /// Node information, at `api/system`.
+    System,
+    /// Blockchain explorer, at `api/explorer`.
+    Explorer,
+    /// API of the service with the given name, at `api/services/{name}`.
+    Service(&'static str),
+}
+
+impl ApiKind {
+    fn prefix(self) -> String {
+        match self {
+            ApiKind::System => "system".to_owned(),
+            ApiKind::Explorer => "explorer".to_owned(),
+            ApiKind::Service(name) => format!("services/{}", name),
+        }
+    }
+}
+
+/// Endpoint handlers by their paths.
+#[derive(Debug, Default)]
+struct Handlers(Vec<(String, RequestHandler)>);
+
+impl Handlers {
+    fn find(&self, path: &str, method: &Method) -> Option<&RequestHandler> {
+        self.0
+            .iter()
+            .find(|(handler_path, handler)| handler_path == path && handler.method == *method)
+            .map(|(_, handler)| handler)
+    }
+}
+
+impl ExtendApiBackend for Handlers {
+    fn extend<'a, I>(mut self, items: I) -> Self
+    where
+        I: IntoIterator<Item = (&'a str, &'a ServiceApiScope)>,
+    {
+        for (prefix, scope) in items {
+            for handler in scope.handlers() {
+                let path = format!("{}/{}", prefix, handler.name);
+                self.0.push((path, handler.clone()));
+            }
+        }
+        self
+    }
+}
+
+/// API of the testkit blockchain.
+///
+/// Requests are passed to the endpoint handlers directly; the handlers see the same
+/// requests and produce the same responses as with the HTTP server of a node.
+/// Transactions sent by the handlers reach the testkit pool on the next
+/// `poll_events` or `create_block` call.
+#[derive(Debug)]
+pub struct TestKitApi {
+    state: ServiceApiState,
+    public: Handlers,
+    private: Handlers,
+}
+
+impl TestKitApi {
+    pub(crate) fn new(aggregator: &ApiAggregator) -> Self {
+        Self {
+            state: ServiceApiState::new(aggregator.blockchain().clone()),
+            public: aggregator.extend_backend(ApiAccess::Public, Handlers::default()),
+            private: aggregator.extend_backend(ApiAccess::Private, Handlers::default()),
+        }
+    }
+
+    /// Creates a request to a public endpoint of the given API.
+    pub fn public(&self, kind: ApiKind) -> RequestBuilder {
+        RequestBuilder::new(&self.public, &self.state, kind)
+    }
+
+    /// Creates a request to a private endpoint of the given API.
+    pub fn private(&self, kind: ApiKind) -> RequestBuilder {
+        RequestBuilder::new(&self.private, &self.state, kind)
+    }
+}
+
+/// Request to an API endpoint.
+#[derive(Debug)]
+pub struct RequestBuilder<'a, Q: 'a = ()> {
+    handlers: &'a Handlers,
+    state: &'a ServiceApiState,
+    prefix: String,
+    query: Option<&'a Q>,
+}
+
+impl<'a> RequestBuilder<'a, ()> {
+    fn new(handlers: &'a Handlers, state: &'a ServiceApiState, kind: ApiKind) -> Self {
+        Self {
+            handlers,
+            state,
+            prefix: kind.prefix(),
+            query: None,
+        }
+    }
+}
+
+impl<'a, Q: Serialize + 'a> RequestBuilder<'a, Q> {
+    /// Sets the query: the query string of a `GET` request or the JSON body
+    /// of a `POST` request.
+    pub fn query<T: Serialize>(self, query: &'a T) -> RequestBuilder<'a, T> {
+        RequestBuilder {
+            handlers: self.handlers,
+            state: self.state,
+            prefix: self.prefix,
+            query: Some(query),
+        }
+    }
+
+    /// Sends a `GET` request to the endpoint, such as `v1/wallets/info`.
+    pub fn get<R: DeserializeOwned>(self, endpoint: &str) -> api::Result<R> {
+        let query = match self.query {
+            Some(query) => serde_urlencoded::to_string(query)
+                .map_err(|e| api::Error::BadRequest(e.to_string()))?,
+            None => String::new(),
+        };
+        self.send(Method::GET, endpoint, &query, None)
+    }
+
+    /// Sends a `POST` request to the endpoint.
+    pub fn post<R: DeserializeOwned>(self, endpoint: &str) -> api::Result<R> {
+        let body = match self.query {
+            Some(query) => {
+                serde_json::to_vec(query).map_err(|e| api::Error::BadRequest(e.to_string()))?
+            }
+            None => b"null".to_vec(),
+        };
+        self.send(Method::POST, endpoint, "", Some(body))
+    }
+
+    fn send<R: DeserializeOwned>(
+        &self,
+        method: Method,
+        endpoint: &str,
+        query: &str,
+        body: Option<Vec<u8>>,
+    ) -> api::Result<R> {
+        let path = format!("{}/{}", self.prefix, endpoint);
+        let handler = self
+            .handlers
+            .find(&path, &method)
+            .ok_or_else(|| api::Error::NotFound(format!("Unknown endpoint {} {}", method, path)))?;
+
+        let uri = if query.is_empty() {
+            format!("/api/{}", path)
+        } else {
+            format!("/api/{}?{}", path, query)
+        };
+        let mut request = TestRequest::with_state(self.state.clone())
+            .method(method)
+            .uri(&uri);
+        if let Some(body) = body {
+            request = request
+                .header(header::CONTENT_TYPE, "application/json")
+                .set_payload(body);
+        }
+
+        let response = match (handler.inner)(request.finish()).wait() {
+            Ok(response) => response,
+            Err(e) => e.as_response_error().error_response(),
+        };
+        let body = match *response.body() {
+            Body::Empty => Vec::new(),
+            Body::Binary(ref binary) => binary.as_ref().to_vec(),
+            _ => {
+                return Err(api::Error::InternalError(format_err!(
+                    "Streaming response of {} is not supported",
+                    path
+                )))
+            }
+        };
+
+        let status = response.status();
+        if status.is_success() {
+            return serde_json::from_slice(&body).map_err(|e| api::Error::InternalError(e.into()));
+        }
+        let message = String::from_utf8_lossy(&body).into_owned();
+        Err(match status {
+            StatusCode::BAD_REQUEST => api::Error::BadRequest(message),
+            StatusCode::NOT_FOUND => api::Error::NotFound(message),
+            StatusCode::UNAUTHORIZED => api::Error::Unauthorized,
+            StatusCode::FORBIDDEN => api::Error::Forbidden,
+            _ => api::Error::InternalError(format_err!("{}: {}", status, message)),
+        })
+    }
+}
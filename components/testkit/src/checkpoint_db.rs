@@ -0,0 +1,176 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use exonum::storage::{Database, Patch, Result as StorageResult, Snapshot};
+
+use std::{
+    fmt,
+    sync::{Arc, RwLock},
+};
+
+/// Database which can roll back the changes merged into it after a checkpoint.
+///
+/// Before a patch is merged, the database saves the previous values of all keys
+/// changed by the patch. Checkpoints can be nested; a rollback undoes the changes made
+/// after the latest checkpoint and removes it. Clones share the same data.
+pub struct CheckpointDb<T> {
+    inner: Arc<RwLock<CheckpointDbInner<T>>>,
+}
+
+struct CheckpointDbInner<T> {
+    db: T,
+    backups: Vec<Vec<Patch>>,
+}
+
+impl<T: Database> CheckpointDb<T> {
+    /// Wraps the given database.
+    pub fn new(db: T) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(CheckpointDbInner {
+                db,
+                backups: Vec::new(),
+            })),
+        }
+    }
+
+    /// Sets a checkpoint.
+    pub fn checkpoint(&self) {
+        self.inner.write().unwrap().backups.push(Vec::new());
+    }
+
+    /// Undoes the changes made after the latest checkpoint and removes the checkpoint.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no checkpoints.
+    pub fn rollback(&self) {
+        let mut inner = self.inner.write().unwrap();
+        let backups = inner.backups.pop().expect("Rollback without a checkpoint");
+        for patch in backups.into_iter().rev() {
+            inner.db.merge(patch).expect("Cannot roll back the changes");
+        }
+    }
+}
+
+impl<T: Database> CheckpointDbInner<T> {
+    fn merge(&mut self, patch: Patch, sync: bool) -> StorageResult<()> {
+        let backup = if self.backups.is_empty() {
+            None
+        } else {
+            Some(self.backup(&patch))
+        };
+        if sync {
+            self.db.merge_sync(patch)?;
+        } else {
+            self.db.merge(patch)?;
+        }
+        if let Some(backup) = backup {
+            self.backups.last_mut().unwrap().push(backup);
+        }
+        Ok(())
+    }
+
+    /// Creates the patch restoring the values changed by the given patch.
+    fn backup(&self, patch: &Patch) -> Patch {
+        let snapshot = self.db.snapshot();
+        let mut fork = self.db.fork();
+        for (name, changes) in patch.iter() {
+            for (key, _) in changes.iter() {
+                match snapshot.get(name, key) {
+                    Some(value) => fork.put(name, key.clone(), value),
+                    None => fork.remove(name, key.clone()),
+                }
+            }
+        }
+        fork.into_patch()
+    }
+}
+
+impl<T> Clone for CheckpointDb<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<T> fmt::Debug for CheckpointDb<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CheckpointDb")
+            .field("checkpoints", &self.inner.read().unwrap().backups.len())
+            .finish()
+    }
+}
+
+impl<T: Database> Database for CheckpointDb<T> {
+    fn snapshot(&self) -> Box<dyn Snapshot> {
+        self.inner.read().unwrap().db.snapshot()
+    }
+
+    fn merge(&self, patch: Patch) -> StorageResult<()> {
+        self.inner.write().unwrap().merge(patch, false)
+    }
+
+    fn merge_sync(&self, patch: Patch) -> StorageResult<()> {
+        self.inner.write().unwrap().merge(patch, true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use exonum::storage::{Database, MemoryDB, Snapshot};
+
+    use super::CheckpointDb;
+
+    fn put(db: &CheckpointDb<MemoryDB>, key: u8, value: Option<u8>) {
+        let mut fork = db.fork();
+        match value {
+            Some(value) => fork.put("table", vec![key], vec![value]),
+            None => fork.remove("table", vec![key]),
+        }
+        db.merge(fork.into_patch()).unwrap();
+    }
+
+    fn get(snapshot: &dyn Snapshot, key: u8) -> Option<Vec<u8>> {
+        snapshot.get("table", &[key])
+    }
+
+    #[test]
+    fn test_nested_rollbacks() {
+        let db = CheckpointDb::new(MemoryDB::new());
+        put(&db, 1, Some(1));
+
+        db.checkpoint();
+        put(&db, 1, Some(2));
+        put(&db, 2, Some(2));
+
+        db.checkpoint();
+        put(&db, 1, None);
+        put(&db, 3, Some(3));
+        let snapshot = db.snapshot();
+        assert_eq!(get(&*snapshot, 1), None);
+        assert_eq!(get(&*snapshot, 3), Some(vec![3]));
+
+        db.rollback();
+        let snapshot = db.snapshot();
+        assert_eq!(get(&*snapshot, 1), Some(vec![2]));
+        assert_eq!(get(&*snapshot, 2), Some(vec![2]));
+        assert_eq!(get(&*snapshot, 3), None);
+
+        db.rollback();
+        let snapshot = db.snapshot();
+        assert_eq!(get(&*snapshot, 1), Some(vec![1]));
+        assert_eq!(get(&*snapshot, 2), None);
+    }
+}
@@ -0,0 +1,246 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Property-based checks that services execute transactions deterministically.
+//!
+//! A service whose state depends on anything but the executed transactions, such as
+//! the iteration order of a hash map, the local time or the storage backend, makes
+//! the validators disagree on the `state_hash` of a block. `check_determinism`
+//! executes the same blocks several times, on `MemoryDB` and on `RocksDB`, and
+//! compares the resulting blocks. Transactions for the check are generated
+//! by `proptest` strategies built with `signed_transactions` and `blocks`.
+//!
+//! # Examples
+//!
+//! ```
+//! #[macro_use]
+//! extern crate proptest;
+//! extern crate cryptocurrency;
+//! extern crate exonum_testkit;
+//!
+//! use cryptocurrency::transactions::{Issue, WalletTransactions};
+//! use exonum_testkit::determinism::{blocks, check_determinism, signed_transactions};
+//! use proptest::prelude::*;
+//!
+//! proptest! {
+//!     #![proptest_config(ProptestConfig::with_cases(4))]
+//!     fn issue_is_deterministic(
+//!         blocks in blocks(
+//!             signed_transactions(
+//!                 128,
+//!                 (0..100_u64, any::<u64>()).prop_map(|(amount, seed)| {
+//!                     WalletTransactions::Issue(Issue { amount, seed })
+//!                 }),
+//!             ),
+//!             3,
+//!             5,
+//!         )
+//!     ) {
+//!         check_determinism(|| vec![cryptocurrency::Service.into()], &blocks)?;
+//!     }
+//! }
+//! # fn main() { issue_is_deterministic(); }
+//! ```
+
+use proptest::{collection, prelude::*, test_runner::TestCaseError};
+use tempdir::TempDir;
+
+use std::{collections::HashSet, sync::Arc};
+
+use exonum::{
+    blockchain::{Blockchain, Schema, Service},
+    crypto::{self, Hash, PublicKey, SecretKey, Seed, SEED_LENGTH},
+    messages::{Message, RawTransaction, ServiceTransaction, Signed},
+    node::ApiSender,
+    storage::{Database, DbOptions, MemoryDB, RocksDB},
+};
+use futures::sync::mpsc;
+
+use network::{TestNetwork, TestNode};
+
+/// Number of keypairs which sign the generated transactions.
+pub const AUTHORS: usize = 8;
+
+/// Returns the keypair of the transaction author with the given index. The keys
+/// are derived from the index, so the generated transactions can refer to the
+/// authors, for example, as receivers of transfers.
+pub fn author_keypair(index: usize) -> (PublicKey, SecretKey) {
+    assert!(index < AUTHORS, "There are only {} authors", AUTHORS);
+    keypair_from_seed(1 + index as u8)
+}
+
+/// Signs the transactions generated by the given strategy by randomly chosen authors.
+pub fn signed_transactions<S>(
+    service_id: u16,
+    transactions: S,
+) -> impl Strategy<Value = Signed<RawTransaction>>
+where
+    S: Strategy,
+    S::Value: Into<ServiceTransaction>,
+{
+    (transactions, 0..AUTHORS).prop_map(move |(transaction, author)| {
+        let (public_key, secret_key) = author_keypair(author);
+        Message::sign_transaction(transaction, service_id, public_key, &secret_key)
+    })
+}
+
+/// Groups the transactions generated by the given strategy into sequences of
+/// up to `max_blocks` blocks with up to `max_block_size` transactions each.
+pub fn blocks<S>(
+    transactions: S,
+    max_blocks: usize,
+    max_block_size: usize,
+) -> impl Strategy<Value = Vec<Vec<Signed<RawTransaction>>>>
+where
+    S: Strategy<Value = Signed<RawTransaction>>,
+{
+    collection::vec(
+        collection::vec(transactions, 0..max_block_size + 1),
+        1..max_blocks + 1,
+    )
+}
+
+/// Checks that the blocks with the given transactions have the same hashes and state
+/// hashes when they are executed twice on `MemoryDB` and once on `RocksDB`.
+///
+/// Each run starts from the genesis block with the services returned by the closure
+/// and the same validator keys. Transactions which are repeated in the blocks are
+/// executed only once.
+pub fn check_determinism<F>(
+    services: F,
+    blocks: &[Vec<Signed<RawTransaction>>],
+) -> Result<(), TestCaseError>
+where
+    F: Fn() -> Vec<Box<dyn Service>>,
+{
+    let dir = TempDir::new("exonum_determinism")
+        .map_err(|e| TestCaseError::fail(format!("Cannot create directory: {}", e)))?;
+    let mut options = DbOptions::default();
+    options.create_if_missing = true;
+    let rocksdb = RocksDB::open(dir.path(), &options)
+        .map_err(|e| TestCaseError::fail(format!("Cannot open RocksDB: {}", e)))?;
+
+    let first = execute(MemoryDB::new().into(), services(), blocks);
+    let replay = execute(MemoryDB::new().into(), services(), blocks);
+    prop_assert_eq!(&first, &replay, "Replay on MemoryDB differs");
+    let persistent = execute(rocksdb.into(), services(), blocks);
+    prop_assert_eq!(&first, &persistent, "Execution on RocksDB differs");
+    Ok(())
+}
+
+/// Hashes of a committed block.
+#[derive(Debug, PartialEq)]
+struct BlockHashes {
+    block_hash: Hash,
+    state_hash: Hash,
+}
+
+fn execute(
+    db: Arc<dyn Database>,
+    services: Vec<Box<dyn Service>>,
+    blocks: &[Vec<Signed<RawTransaction>>],
+) -> Vec<BlockHashes> {
+    let validator = TestNode::from_keys(keypair_from_seed(0), keypair_from_seed(0));
+    let network = TestNetwork::new(vec![validator], None);
+    let (service_public_key, service_secret_key) = keypair_from_seed(0);
+    let mut blockchain = Blockchain::new(
+        db,
+        services,
+        service_public_key,
+        service_secret_key,
+        ApiSender::new(mpsc::channel(0).0),
+    );
+    blockchain
+        .initialize(network.genesis_config(Default::default()))
+        .expect("Cannot create the genesis block");
+
+    blocks
+        .iter()
+        .map(|txs| {
+            let tx_hashes = add_to_pool(&mut blockchain, txs);
+            network.commit_block(&mut blockchain, &tx_hashes);
+            let block = blockchain.last_block();
+            BlockHashes {
+                block_hash: blockchain.last_hash(),
+                state_hash: *block.state_hash(),
+            }
+        })
+        .collect()
+}
+
+/// Adds the transactions to the pool, skipping the known ones, and returns the hashes
+/// of the added transactions.
+fn add_to_pool(blockchain: &mut Blockchain, txs: &[Signed<RawTransaction>]) -> Vec<Hash> {
+    let mut fork = blockchain.fork();
+    let mut tx_hashes = Vec::new();
+    {
+        let mut schema = Schema::new(&mut fork);
+        let mut added = HashSet::new();
+        for tx in txs {
+            let tx_hash = tx.hash();
+            if schema.transactions().contains(&tx_hash) || !added.insert(tx_hash) {
+                continue;
+            }
+            schema.add_transaction_into_pool(tx.clone());
+            tx_hashes.push(tx_hash);
+        }
+    }
+    blockchain
+        .merge(fork.into_patch())
+        .expect("Cannot add transactions to the pool");
+    tx_hashes
+}
+
+fn keypair_from_seed(seed: u8) -> (PublicKey, SecretKey) {
+    crypto::gen_keypair_from_seed(&Seed::new([seed; SEED_LENGTH]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cryptocurrency::{
+        transactions::{CreateWallet, Issue, Transfer, WalletTransactions},
+        Service as CurrencyService,
+    };
+
+    fn wallet_transactions() -> impl Strategy<Value = WalletTransactions> {
+        prop_oneof![
+            "[a-z]{1,8}".prop_map(|name| WalletTransactions::CreateWallet(CreateWallet { name })),
+            (0..AUTHORS, 0..200_u64, any::<u64>()).prop_map(|(to, amount, seed)| {
+                WalletTransactions::Transfer(Transfer {
+                    to: author_keypair(to).0,
+                    amount,
+                    seed,
+                })
+            }),
+            (0..50_u64, any::<u64>())
+                .prop_map(|(amount, seed)| WalletTransactions::Issue(Issue { amount, seed })),
+        ]
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(16))]
+
+        #[test]
+        fn test_cryptocurrency_is_deterministic(
+            blocks in blocks(
+                signed_transactions(CurrencyService.service_id(), wallet_transactions()),
+                4,
+                8,
+            )
+        ) {
+            check_determinism(|| vec![CurrencyService.into()], &blocks)?;
+        }
+    }
+}
@@ -0,0 +1,585 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Testkit for unit testing of Exonum services.
+//!
+//! `TestKit` runs a blockchain with the given services over an in-memory database
+//! without consensus and networking. Transactions are collected in the pool and
+//! committed when the test calls `create_block`; blocks are signed by emulated
+//! validators. Tests can inspect the results of transactions and the service data,
+//! call the API endpoints of the services without an HTTP server and roll the
+//! blockchain back to a checkpoint.
+//!
+//! The `determinism` module, available with the `determinism` feature, checks with
+//! generated transactions that services compute the same state on replays and
+//! on different storage backends. For tests involving several nodes, consensus or
+//! network failures, see the `exonum::node::simulation` module.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate cryptocurrency;
+//! extern crate exonum;
+//! extern crate exonum_testkit;
+//!
+//! use cryptocurrency::{api::{WalletInfo, WalletQuery}, transactions::CreateWallet, Schema};
+//! use exonum::crypto;
+//! use exonum_testkit::{ApiKind, TestKitBuilder};
+//!
+//! # fn main() {
+//! let mut testkit = TestKitBuilder::validator()
+//!     .with_validators(4)
+//!     .with_service(cryptocurrency::Service)
+//!     .create();
+//!
+//! let (pub_key, sec_key) = crypto::gen_keypair();
+//! let tx = CreateWallet::sign("Alice", &pub_key, &sec_key);
+//! let block = testkit.create_block_with_transaction(tx.clone());
+//! assert_eq!(block.len(), 1);
+//! assert!(testkit.transaction_result(&tx.hash()).unwrap().0.is_ok());
+//!
+//! let snapshot = testkit.snapshot();
+//! assert_eq!(Schema::new(&snapshot).wallet(&pub_key).unwrap().balance, 100);
+//!
+//! let info: WalletInfo = testkit
+//!     .api()
+//!     .public(ApiKind::Service("cryptocurrency"))
+//!     .query(&WalletQuery { pub_key })
+//!     .get("v1/wallets/info")
+//!     .unwrap();
+//! assert!(info.wallet_history.is_some());
+//! # }
+//! ```
+
+#![deny(
+    missing_debug_implementations,
+    missing_docs,
+    unsafe_code,
+    bare_trait_objects
+)]
+
+extern crate actix_web;
+extern crate chrono;
+extern crate exonum;
+#[macro_use]
+extern crate failure;
+extern crate futures;
+#[cfg(feature = "determinism")]
+#[macro_use]
+extern crate proptest;
+extern crate serde;
+extern crate serde_json;
+extern crate serde_urlencoded;
+#[cfg(feature = "determinism")]
+extern crate tempdir;
+
+#[cfg(test)]
+extern crate cryptocurrency;
+
+pub use api::{ApiKind, RequestBuilder, TestKitApi};
+pub use checkpoint_db::CheckpointDb;
+pub use network::{TestNetwork, TestNode};
+
+use futures::{sync::mpsc, Async, Future, Stream};
+
+use std::{fmt, sync::Arc};
+
+use exonum::{
+    api::ApiAggregator,
+    blockchain::{
+        Blockchain, ConsensusConfig, Schema, Service, SharedNodeState, TransactionResult,
+    },
+    crypto::Hash,
+    explorer::{BlockWithTransactions, BlockchainExplorer},
+    helpers::{Height, ValidatorId},
+    messages::{RawTransaction, Signed},
+    node::{ApiSender, ExternalMessage},
+    storage::{Database, MemoryDB, Snapshot},
+};
+
+#[cfg(feature = "determinism")]
+pub mod determinism;
+
+mod api;
+mod checkpoint_db;
+mod network;
+
+/// Capacity of the channel for the transactions broadcast by the services.
+const API_CHANNEL_CAPACITY: usize = 1_024;
+/// Update timeout of the node state used by the system API, in milliseconds.
+const STATE_UPDATE_TIMEOUT: u64 = 10_000;
+
+/// Builder of a `TestKit`.
+pub struct TestKitBuilder {
+    our_node: Option<TestNode>,
+    validators: Vec<TestNode>,
+    services: Vec<Box<dyn Service>>,
+    consensus: ConsensusConfig,
+    explorer_indexes: bool,
+}
+
+impl fmt::Debug for TestKitBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TestKitBuilder")
+            .field("our_node", &self.our_node)
+            .field("validators", &self.validators)
+            .field(
+                "services",
+                &self
+                    .services
+                    .iter()
+                    .map(|service| service.service_name())
+                    .collect::<Vec<_>>(),
+            )
+            .field("consensus", &self.consensus)
+            .field("explorer_indexes", &self.explorer_indexes)
+            .finish()
+    }
+}
+
+impl TestKitBuilder {
+    /// Creates a builder of the testkit whose node is a validator. The network
+    /// has a single validator unless specified otherwise.
+    pub fn validator() -> Self {
+        Self {
+            our_node: None,
+            validators: vec![TestNode::new_validator(ValidatorId::zero())],
+            services: Vec::new(),
+            consensus: ConsensusConfig::default(),
+            explorer_indexes: false,
+        }
+    }
+
+    /// Creates a builder of the testkit whose node is an auditor.
+    pub fn auditor() -> Self {
+        Self {
+            our_node: Some(TestNode::new_auditor()),
+            ..Self::validator()
+        }
+    }
+
+    /// Sets the number of validators with random keys.
+    pub fn with_validators(mut self, count: u16) -> Self {
+        assert!(count > 0, "At least one validator should be specified");
+        self.validators = (0..count)
+            .map(|id| TestNode::new_validator(ValidatorId(id)))
+            .collect();
+        self
+    }
+
+    /// Sets the validators with the given keys. The validator identifiers are assigned
+    /// in the order of the nodes.
+    pub fn with_validator_nodes(mut self, validators: Vec<TestNode>) -> Self {
+        assert!(
+            !validators.is_empty(),
+            "At least one validator should be specified"
+        );
+        self.validators = validators;
+        self
+    }
+
+    /// Adds a service to the blockchain.
+    pub fn with_service<S: Into<Box<dyn Service>>>(mut self, service: S) -> Self {
+        self.services.push(service.into());
+        self
+    }
+
+    /// Sets the consensus configuration of the genesis block.
+    pub fn with_consensus(mut self, consensus: ConsensusConfig) -> Self {
+        self.consensus = consensus;
+        self
+    }
+
+    /// Enables the explorer indexes of the blockchain.
+    pub fn with_explorer_indexes(mut self) -> Self {
+        self.explorer_indexes = true;
+        self
+    }
+
+    /// Creates the testkit and commits the genesis block.
+    pub fn create(self) -> TestKit {
+        exonum::crypto::init();
+
+        let network = TestNetwork::new(self.validators, self.our_node);
+        let genesis = network.genesis_config(self.consensus);
+        let (service_public_key, service_secret_key) = {
+            let (public_key, secret_key) = network.us().service_keypair();
+            (*public_key, secret_key.clone())
+        };
+
+        let db = CheckpointDb::new(MemoryDB::new());
+        let api_channel = mpsc::channel(API_CHANNEL_CAPACITY);
+        let mut blockchain = Blockchain::new(
+            Arc::new(db.clone()) as Arc<dyn Database>,
+            self.services,
+            service_public_key,
+            service_secret_key,
+            ApiSender::new(api_channel.0),
+        );
+        blockchain.set_explorer_indexes(self.explorer_indexes);
+        blockchain
+            .initialize(genesis)
+            .expect("Cannot create the genesis block");
+
+        TestKit {
+            blockchain,
+            db,
+            network,
+            api_requests_rx: api_channel.1,
+            node_state: SharedNodeState::new(STATE_UPDATE_TIMEOUT),
+        }
+    }
+}
+
+/// Blockchain with services for unit tests.
+///
+/// The blocks are proposed by the first validator and signed by all validators.
+/// Transactions are not verified when they are added to the pool; a transaction
+/// which a service cannot parse makes `create_block` panic.
+pub struct TestKit {
+    blockchain: Blockchain,
+    db: CheckpointDb<MemoryDB>,
+    network: TestNetwork,
+    api_requests_rx: mpsc::Receiver<ExternalMessage>,
+    node_state: SharedNodeState,
+}
+
+impl fmt::Debug for TestKit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TestKit")
+            .field("height", &self.height())
+            .field("network", &self.network)
+            .field("db", &self.db)
+            .finish()
+    }
+}
+
+impl TestKit {
+    /// Creates a testkit with a single validator and the given service.
+    pub fn for_service<S: Into<Box<dyn Service>>>(service: S) -> Self {
+        TestKitBuilder::validator().with_service(service).create()
+    }
+
+    /// Returns the blockchain of the testkit.
+    pub fn blockchain(&self) -> &Blockchain {
+        &self.blockchain
+    }
+
+    /// Returns the blockchain of the testkit.
+    pub fn blockchain_mut(&mut self) -> &mut Blockchain {
+        &mut self.blockchain
+    }
+
+    /// Returns the emulated network.
+    pub fn network(&self) -> &TestNetwork {
+        &self.network
+    }
+
+    /// Creates a snapshot of the current blockchain state.
+    pub fn snapshot(&self) -> Box<dyn Snapshot> {
+        self.blockchain.snapshot()
+    }
+
+    /// Returns the height of the latest committed block.
+    pub fn height(&self) -> Height {
+        self.blockchain.last_block().height()
+    }
+
+    /// Returns the blockchain explorer.
+    pub fn explorer(&self) -> BlockchainExplorer {
+        BlockchainExplorer::new(&self.blockchain)
+    }
+
+    /// Returns the API of the blockchain.
+    pub fn api(&self) -> TestKitApi {
+        let aggregator = ApiAggregator::new(self.blockchain.clone(), self.node_state.clone());
+        TestKitApi::new(&aggregator)
+    }
+
+    /// Adds the transaction to the pool unless it is already known.
+    pub fn add_tx(&mut self, tx: Signed<RawTransaction>) {
+        let tx_hash = tx.hash();
+        let is_known = Schema::new(&self.snapshot())
+            .transactions()
+            .contains(&tx_hash);
+        if is_known {
+            return;
+        }
+
+        let mut fork = self.blockchain.fork();
+        Schema::new(&mut fork).add_transaction_into_pool(tx);
+        self.blockchain
+            .merge(fork.into_patch())
+            .expect("Cannot add transaction to the pool");
+    }
+
+    /// Adds the transactions to the pool.
+    pub fn add_txs<I>(&mut self, txs: I)
+    where
+        I: IntoIterator<Item = Signed<RawTransaction>>,
+    {
+        for tx in txs {
+            self.add_tx(tx);
+        }
+    }
+
+    /// Returns `true` if the transaction is in the pool.
+    pub fn is_tx_in_pool(&self, tx_hash: &Hash) -> bool {
+        Schema::new(&self.snapshot())
+            .transactions_pool()
+            .contains(tx_hash)
+    }
+
+    /// Moves the transactions broadcast by the services and the API handlers
+    /// to the pool.
+    pub fn poll_events(&mut self) {
+        while let Some(message) = self.poll_api_request() {
+            if let ExternalMessage::Transaction(tx) = message {
+                self.add_tx(tx);
+            }
+        }
+    }
+
+    fn poll_api_request(&mut self) -> Option<ExternalMessage> {
+        let rx = &mut self.api_requests_rx;
+        futures::lazy(|| -> Result<Option<ExternalMessage>, ()> {
+            match rx.poll()? {
+                Async::Ready(message) => Ok(message),
+                Async::NotReady => Ok(None),
+            }
+        })
+        .wait()
+        .unwrap()
+    }
+
+    /// Creates a block with all transactions from the pool, up to the `txs_block_limit`
+    /// of the actual configuration.
+    pub fn create_block(&mut self) -> BlockWithTransactions {
+        self.poll_events();
+        let tx_hashes = {
+            let snapshot = self.snapshot();
+            let schema = Schema::new(&snapshot);
+            let limit = schema.actual_configuration().consensus.txs_block_limit;
+            schema
+                .transactions_pool()
+                .iter()
+                .take(limit as usize)
+                .collect::<Vec<_>>()
+        };
+        self.create_block_with_tx_hashes(&tx_hashes)
+    }
+
+    /// Creates a block with the given transactions in the given order. The
+    /// transactions are added to the pool first; other transactions stay in the pool.
+    pub fn create_block_with_transactions<I>(&mut self, txs: I) -> BlockWithTransactions
+    where
+        I: IntoIterator<Item = Signed<RawTransaction>>,
+    {
+        let tx_hashes = txs
+            .into_iter()
+            .map(|tx| {
+                let tx_hash = tx.hash();
+                self.add_tx(tx);
+                tx_hash
+            })
+            .collect::<Vec<_>>();
+        self.create_block_with_tx_hashes(&tx_hashes)
+    }
+
+    /// Creates a block with the given transaction.
+    pub fn create_block_with_transaction(
+        &mut self,
+        tx: Signed<RawTransaction>,
+    ) -> BlockWithTransactions {
+        self.create_block_with_transactions(vec![tx])
+    }
+
+    /// Creates a block with the transactions from the pool with the given hashes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a transaction is not in the pool.
+    pub fn create_block_with_tx_hashes(&mut self, tx_hashes: &[Hash]) -> BlockWithTransactions {
+        for tx_hash in tx_hashes {
+            assert!(
+                self.is_tx_in_pool(tx_hash),
+                "Transaction {:?} is not in the pool",
+                tx_hash
+            );
+        }
+
+        let height = self.network.commit_block(&mut self.blockchain, tx_hashes);
+        self.poll_events();
+        self.explorer()
+            .block_with_txs(height)
+            .expect("Committed block is not found")
+    }
+
+    /// Creates empty blocks until the blockchain reaches the given height.
+    pub fn create_blocks_until(&mut self, height: Height) {
+        while self.height() < height {
+            self.create_block_with_tx_hashes(&[]);
+        }
+    }
+
+    /// Returns the result of the committed transaction.
+    pub fn transaction_result(&self, tx_hash: &Hash) -> Option<TransactionResult> {
+        Schema::new(&self.snapshot())
+            .transaction_results()
+            .get(tx_hash)
+    }
+
+    /// Sets a checkpoint for a later rollback. Checkpoints can be nested.
+    pub fn checkpoint(&mut self) {
+        self.db.checkpoint();
+    }
+
+    /// Rolls the blockchain back to the state at the latest checkpoint, including
+    /// the transaction pool, and removes the checkpoint.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no checkpoints.
+    pub fn rollback(&mut self) {
+        self.db.rollback();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cryptocurrency::{
+        api::{WalletInfo, WalletQuery},
+        transactions::{CreateWallet, Transfer},
+        Schema as CurrencySchema, Service as CurrencyService,
+    };
+    use exonum::{
+        api::{
+            self,
+            node::public::explorer::{TransactionHex, TransactionResponse},
+        },
+        blockchain::TransactionErrorType,
+        crypto::{self, PublicKey},
+        messages::to_hex_string,
+    };
+
+    use super::*;
+
+    fn balance(testkit: &TestKit, pub_key: &PublicKey) -> Option<u64> {
+        let snapshot = testkit.snapshot();
+        let balance = CurrencySchema::new(&snapshot)
+            .wallet(pub_key)
+            .map(|wallet| wallet.balance);
+        balance
+    }
+
+    #[test]
+    fn test_transaction_results() {
+        let mut testkit = TestKitBuilder::validator()
+            .with_validators(4)
+            .with_service(CurrencyService)
+            .create();
+        let (alice, alice_key) = crypto::gen_keypair();
+        let (bob, _) = crypto::gen_keypair();
+
+        let create = CreateWallet::sign("Alice", &alice, &alice_key);
+        let transfer = Transfer::sign(&alice, &bob, 10, 0, &alice_key);
+        let block = testkit.create_block_with_transactions(vec![create.clone(), transfer.clone()]);
+        assert_eq!(block.height(), Height(1));
+        assert_eq!(block.len(), 2);
+        assert_eq!(block.precommits.len(), 4);
+
+        assert_eq!(
+            testkit.transaction_result(&create.hash()).unwrap().0,
+            Ok(())
+        );
+        let error = testkit
+            .transaction_result(&transfer.hash())
+            .unwrap()
+            .0
+            .unwrap_err();
+        // The receiver does not exist.
+        assert_eq!(error.error_type(), TransactionErrorType::Code(2));
+        assert_eq!(balance(&testkit, &alice), Some(100));
+    }
+
+    #[test]
+    fn test_pool_and_api() {
+        let mut testkit = TestKit::for_service(CurrencyService);
+        let (alice, alice_key) = crypto::gen_keypair();
+        let create = CreateWallet::sign("Alice", &alice, &alice_key);
+
+        // Transactions submitted through the explorer API reach the pool.
+        let response: TransactionResponse = testkit
+            .api()
+            .public(ApiKind::Explorer)
+            .query(&TransactionHex {
+                tx_body: to_hex_string(&create),
+            })
+            .post("v1/transactions")
+            .unwrap();
+        assert_eq!(response.tx_hash, create.hash());
+        testkit.poll_events();
+        assert!(testkit.is_tx_in_pool(&create.hash()));
+
+        let block = testkit.create_block();
+        assert_eq!(block.len(), 1);
+        assert!(!testkit.is_tx_in_pool(&create.hash()));
+
+        let api = testkit.api();
+        let info: WalletInfo = api
+            .public(ApiKind::Service("cryptocurrency"))
+            .query(&WalletQuery { pub_key: alice })
+            .get("v1/wallets/info")
+            .unwrap();
+        assert_eq!(info.wallet_history.unwrap().transactions.len(), 1);
+
+        let result: api::Result<WalletInfo> = api
+            .public(ApiKind::Service("cryptocurrency"))
+            .get("v1/wallets/unknown");
+        match result {
+            Err(api::Error::NotFound(_)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_rollback() {
+        let mut testkit = TestKitBuilder::auditor()
+            .with_validators(2)
+            .with_service(CurrencyService)
+            .create();
+        assert_eq!(testkit.network().us().validator_id(), None);
+        let (alice, alice_key) = crypto::gen_keypair();
+        let (bob, bob_key) = crypto::gen_keypair();
+        testkit.create_block_with_transaction(CreateWallet::sign("Alice", &alice, &alice_key));
+
+        testkit.checkpoint();
+        let create_bob = CreateWallet::sign("Bob", &bob, &bob_key);
+        testkit.create_block_with_transaction(create_bob.clone());
+        testkit.add_tx(Transfer::sign(&alice, &bob, 10, 0, &alice_key));
+
+        testkit.checkpoint();
+        testkit.create_blocks_until(Height(5));
+        assert_eq!(testkit.height(), Height(5));
+        testkit.rollback();
+        assert_eq!(testkit.height(), Height(2));
+
+        testkit.rollback();
+        assert_eq!(testkit.height(), Height(1));
+        assert_eq!(balance(&testkit, &alice), Some(100));
+        assert_eq!(balance(&testkit, &bob), None);
+        assert!(testkit.transaction_result(&create_bob.hash()).is_none());
+        assert!(testkit.create_block().is_empty());
+    }
+}
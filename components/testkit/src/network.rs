@@ -0,0 +1,204 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use chrono::Utc;
+use exonum::{
+    blockchain::{Blockchain, ConsensusConfig, GenesisConfig, ValidatorKeys},
+    crypto::{self, Hash, PublicKey, SecretKey},
+    helpers::{Height, Round, ValidatorId},
+    messages::{Message, Precommit, Propose, Signed},
+};
+
+/// Node of the emulated network: a validator or an auditor.
+#[derive(Debug, Clone)]
+pub struct TestNode {
+    validator_id: Option<ValidatorId>,
+    consensus_public_key: PublicKey,
+    consensus_secret_key: SecretKey,
+    service_public_key: PublicKey,
+    service_secret_key: SecretKey,
+}
+
+impl TestNode {
+    /// Creates a validator with the given identifier and random keys.
+    pub fn new_validator(validator_id: ValidatorId) -> Self {
+        let mut node = Self::new_auditor();
+        node.validator_id = Some(validator_id);
+        node
+    }
+
+    /// Creates an auditor with random keys.
+    pub fn new_auditor() -> Self {
+        Self::from_keys(crypto::gen_keypair(), crypto::gen_keypair())
+    }
+
+    /// Creates an auditor with the given consensus and service keys. The testkit builder
+    /// turns the auditor into a validator if it is passed in the list of validators.
+    pub fn from_keys(
+        consensus_keys: (PublicKey, SecretKey),
+        service_keys: (PublicKey, SecretKey),
+    ) -> Self {
+        Self {
+            validator_id: None,
+            consensus_public_key: consensus_keys.0,
+            consensus_secret_key: consensus_keys.1,
+            service_public_key: service_keys.0,
+            service_secret_key: service_keys.1,
+        }
+    }
+
+    /// Returns the identifier of the validator, or `None` for an auditor.
+    pub fn validator_id(&self) -> Option<ValidatorId> {
+        self.validator_id
+    }
+
+    /// Returns the public keys of the node.
+    pub fn public_keys(&self) -> ValidatorKeys {
+        ValidatorKeys {
+            consensus_key: self.consensus_public_key,
+            service_key: self.service_public_key,
+        }
+    }
+
+    /// Returns the consensus keypair of the node.
+    pub fn consensus_keypair(&self) -> (&PublicKey, &SecretKey) {
+        (&self.consensus_public_key, &self.consensus_secret_key)
+    }
+
+    /// Returns the service keypair of the node.
+    pub fn service_keypair(&self) -> (&PublicKey, &SecretKey) {
+        (&self.service_public_key, &self.service_secret_key)
+    }
+
+    /// Creates a `Propose` message signed by the validator.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node is an auditor.
+    pub fn create_propose(
+        &self,
+        height: Height,
+        last_hash: &Hash,
+        tx_hashes: &[Hash],
+    ) -> Signed<Propose> {
+        Message::concrete(
+            Propose::new(
+                self.expect_validator_id(),
+                height,
+                Round::first(),
+                last_hash,
+                tx_hashes,
+            ),
+            self.consensus_public_key,
+            &self.consensus_secret_key,
+        )
+    }
+
+    /// Creates a `Precommit` message signed by the validator.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node is an auditor.
+    pub fn create_precommit(
+        &self,
+        height: Height,
+        propose_hash: &Hash,
+        block_hash: &Hash,
+    ) -> Signed<Precommit> {
+        Message::concrete(
+            Precommit::new(
+                self.expect_validator_id(),
+                height,
+                Round::first(),
+                propose_hash,
+                block_hash,
+                Utc::now(),
+            ),
+            self.consensus_public_key,
+            &self.consensus_secret_key,
+        )
+    }
+
+    fn expect_validator_id(&self) -> ValidatorId {
+        self.validator_id
+            .expect("Only validators can sign consensus messages")
+    }
+}
+
+/// Emulated network of the testkit: the validators and the node of the testkit itself.
+#[derive(Debug, Clone)]
+pub struct TestNetwork {
+    us: TestNode,
+    validators: Vec<TestNode>,
+}
+
+impl TestNetwork {
+    /// Creates a network with the given validators. The testkit node is the first
+    /// validator, or the given auditor.
+    pub(crate) fn new(validators: Vec<TestNode>, auditor: Option<TestNode>) -> Self {
+        let validators = validators
+            .into_iter()
+            .enumerate()
+            .map(|(id, mut node)| {
+                node.validator_id = Some(ValidatorId(id as u16));
+                node
+            })
+            .collect::<Vec<_>>();
+        let us = auditor.unwrap_or_else(|| validators[0].clone());
+        Self { us, validators }
+    }
+
+    /// Returns the node of the testkit.
+    pub fn us(&self) -> &TestNode {
+        &self.us
+    }
+
+    /// Returns the validators of the network.
+    pub fn validators(&self) -> &[TestNode] {
+        &self.validators
+    }
+
+    /// Returns the validator proposing blocks, which is always the first one.
+    pub fn leader(&self) -> &TestNode {
+        &self.validators[0]
+    }
+
+    pub(crate) fn genesis_config(&self, consensus: ConsensusConfig) -> GenesisConfig {
+        GenesisConfig::new_with_consensus(
+            consensus,
+            self.validators.iter().map(TestNode::public_keys),
+        )
+    }
+
+    /// Commits the block with the given transactions from the pool, proposed by the leader
+    /// and signed by all validators. Returns the height of the block.
+    pub(crate) fn commit_block(&self, blockchain: &mut Blockchain, tx_hashes: &[Hash]) -> Height {
+        let height = blockchain.last_block().height().next();
+        let last_hash = blockchain.last_hash();
+        let leader = self.leader();
+        let (block_hash, patch) =
+            blockchain.create_patch(leader.expect_validator_id(), height, tx_hashes);
+
+        let propose_hash = leader.create_propose(height, &last_hash, tx_hashes).hash();
+        let precommits = self
+            .validators
+            .iter()
+            .map(|validator| validator.create_precommit(height, &propose_hash, &block_hash))
+            .collect::<Vec<_>>();
+        blockchain
+            .commit(&patch, block_hash, precommits.into_iter())
+            .expect("Cannot commit the block");
+        height
+    }
+}
@@ -0,0 +1,142 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate chrono;
+extern crate exonum;
+
+use chrono::{TimeZone, Utc};
+use exonum::crypto::{hash, Hash, PublicKey, SecretKey};
+use exonum::helpers::{Height, Round, ValidatorId};
+use exonum::messages::{Message, Precommit, Prevote, Propose, Status};
+use exonum::node::simulation::{SimulatedNetwork, SimulatedNetworkBuilder};
+
+use std::time::Duration;
+
+/// Index of the validator whose keys are controlled by the fuzzer.
+const BYZANTINE: usize = 3;
+/// Number of the honest validators.
+const HONEST: usize = 3;
+
+/// Reader of the fuzzer input.
+struct Input<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Input<'a> {
+    fn byte(&mut self) -> Option<u8> {
+        let (&first, rest) = self.data.split_first()?;
+        self.data = rest;
+        Some(first)
+    }
+
+    fn bytes(&mut self, len: usize) -> &'a [u8] {
+        let len = len.min(self.data.len());
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        bytes
+    }
+}
+
+/// Validator which sends arbitrary, but correctly signed, consensus messages.
+struct Byzantine {
+    public_key: PublicKey,
+    secret_key: SecretKey,
+    proposes: Vec<Hash>,
+}
+
+impl Byzantine {
+    fn choose_propose(&self, choice: u8) -> Hash {
+        if self.proposes.is_empty() || choice as usize >= self.proposes.len() {
+            hash(&[choice])
+        } else {
+            self.proposes[choice as usize]
+        }
+    }
+
+    /// Interprets the next operation of the input. Returns `None` at the end of the input.
+    fn act(&mut self, network: &mut SimulatedNetwork, input: &mut Input) -> Option<()> {
+        let op = input.byte()?;
+        let target = input.byte()? as usize % HONEST;
+        let height_offset = u64::from(input.byte()? % 3);
+        let height = Height(network.node(target).height().next().0 + height_offset);
+        let round = Round(1 + u32::from(input.byte()? % 4));
+        let last_hash = network.node(target).blockchain().last_hash();
+        let validator = ValidatorId(BYZANTINE as u16);
+
+        let message = match op % 6 {
+            0 => {
+                network.advance(Duration::from_millis(u64::from(input.byte()?) * 20));
+                return Some(());
+            }
+            1 => {
+                let tx_count = input.byte()? as usize % 4;
+                let tx_hashes = (0..tx_count)
+                    .map(|i| hash(&[i as u8]))
+                    .collect::<Vec<_>>();
+                let propose = Message::concrete(
+                    Propose::new(validator, height, round, &last_hash, &tx_hashes),
+                    self.public_key,
+                    &self.secret_key,
+                );
+                self.proposes.push(propose.hash());
+                propose.serialize()
+            }
+            2 => {
+                let propose_hash = self.choose_propose(input.byte()?);
+                let locked_round = Round(u32::from(input.byte()? % 4));
+                Message::concrete(
+                    Prevote::new(validator, height, round, &propose_hash, locked_round),
+                    self.public_key,
+                    &self.secret_key,
+                )
+                .serialize()
+            }
+            3 => {
+                let propose_hash = self.choose_propose(input.byte()?);
+                let block_hash = hash(&[input.byte()?]);
+                let time = Utc.timestamp(i64::from(input.byte()?), 0);
+                Message::concrete(
+                    Precommit::new(validator, height, round, &propose_hash, &block_hash, time),
+                    self.public_key,
+                    &self.secret_key,
+                )
+                .serialize()
+            }
+            4 => Message::concrete(
+                Status::new(height, &last_hash),
+                self.public_key,
+                &self.secret_key,
+            )
+            .serialize(),
+            _ => {
+                let len = input.byte()? as usize;
+                input.bytes(len).to_vec()
+            }
+        };
+        network.deliver_message(target, self.public_key, message);
+        Some(())
+    }
+}
+
+fn fuzz_target(data: &[u8]) {
+    let mut network = SimulatedNetworkBuilder::new(4).build();
+    network.isolate(BYZANTINE);
+    let mut byzantine = {
+        let config = network.node(BYZANTINE).config();
+        Byzantine {
+            public_key: config.consensus_public_key,
+            secret_key: config.consensus_secret_key.clone(),
+            proposes: Vec::new(),
+        }
+    };
+
+    let mut input = Input { data };
+    while byzantine.act(&mut network, &mut input).is_some() {}
+    network.advance(Duration::from_secs(5));
+
+    // Safety: the validators never commit different blocks at the same height.
+    assert_eq!(network.find_conflicting_blocks(), None);
+}
+
+fuzz_target!(|data| {
+    fuzz_target(data);
+});
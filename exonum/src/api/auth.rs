@@ -0,0 +1,964 @@
+//! Authentication and role-based access control for the private API.
+//!
+//! By default, the private API is protected only by the address it is bound to.
+//! If `NodeApiConfig::private_api_auth` is set or an authenticator is added with
+//! `Node::add_private_api_authenticator`, every request to the private API must be
+//! authenticated by one of the authenticators, and the role of its author must
+//! permit calling the endpoint.
+//!
+//! Two authentication schemes are supported out of the box:
+//!
+//! - Bearer tokens listed in the node configuration, sent in the
+//!   `Authorization: Bearer <token>` header.
+//! - Requests signed by a node operator key, sent in the header created by
+//!   [`sign_request`](fn.sign_request.html):
+//!
+//!   ```text
+//!   Authorization: Exonum-Signature key=<hex>, timestamp=<seconds>, nonce=<number>, content_hash=<hex>, signature=<hex>
+//!   ```
+//!
+//!   The signature covers the method, the `Host` header, the path with the query,
+//!   the Unix timestamp, the nonce and the hash of the body of the request.
+//!   The timestamp may differ from the local time of the node by at most
+//!   `MAX_CLOCK_SKEW` seconds, and a nonce cannot be reused by the same key while
+//!   the timestamp is valid, so a signed request cannot be replayed to the node
+//!   or sent to another node.
+//!
+//! Immutable endpoints require the `read_only` role and mutable endpoints require
+//! the `admin` role, unless a different role is set in the `EndpointSpec` of the
+//! endpoint. Requests to the endpoints requiring the `admin` role, both permitted
+//! and denied, are recorded in the audit log.
+
+use actix_web::{
+    http::{header, Method, StatusCode},
+    middleware::{Middleware, Response, Started},
+    HttpRequest, HttpResponse, Result as ActixResult,
+};
+use chrono::{DateTime, Utc};
+use hex::{self, FromHex};
+use rand;
+use serde_json;
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{ApiAccess, ApiAggregator, ServiceApiState};
+use crypto::{self, Hash, PublicKey, SecretKey, Signature};
+
+/// Maximum difference in seconds between the timestamp of a signed request and
+/// the local time of the node.
+pub const MAX_CLOCK_SKEW: u64 = 300;
+/// Maximum number of the nonces of the signed requests remembered by the node for
+/// a single key. Requests signed by a key are rejected while the nonces of this many
+/// valid requests signed by it are remembered; the other keys are not affected.
+pub const MAX_REMEMBERED_NONCES: usize = 10_000;
+
+const BEARER_SCHEME: &str = "Bearer ";
+const SIGNATURE_SCHEME: &str = "Exonum-Signature ";
+
+/// Role of the author of a private API request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiRole {
+    /// May call the immutable endpoints.
+    ReadOnly,
+    /// May call all endpoints.
+    Admin,
+}
+
+impl ApiRole {
+    /// Returns `true` if the role permits calling an endpoint requiring the given role.
+    pub fn permits(self, required: ApiRole) -> bool {
+        self >= required
+    }
+}
+
+/// Authenticated author of a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Principal {
+    /// Name of the author, as recorded in the audit log.
+    pub name: String,
+    /// Role of the author.
+    pub role: ApiRole,
+    /// Hash of the request body the credentials are bound to, if any. The body of
+    /// the request to a mutable endpoint is rejected if its hash differs.
+    pub content_hash: Option<Hash>,
+}
+
+/// Parts of an HTTP request used for authentication.
+#[derive(Debug, Clone, Copy)]
+pub struct AuthRequest<'a> {
+    /// Method of the request.
+    pub method: &'a str,
+    /// Path of the request with the query string.
+    pub path: &'a str,
+    /// Value of the `Host` header.
+    pub host: Option<&'a str>,
+    /// Value of the `Authorization` header.
+    pub authorization: Option<&'a str>,
+}
+
+/// Authenticator of the private API requests.
+pub trait Authenticator: Send + Sync {
+    /// Returns the author of the request, or `None` if the request does not carry
+    /// valid credentials for this authenticator.
+    fn authenticate(&self, request: &AuthRequest) -> Option<Principal>;
+}
+
+impl fmt::Debug for dyn Authenticator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Authenticator").finish()
+    }
+}
+
+/// Bearer token of the private API.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiToken {
+    /// Name of the token owner.
+    pub name: String,
+    /// Secret value of the token.
+    pub token: String,
+    /// Role of the token owner.
+    pub role: ApiRole,
+}
+
+impl fmt::Debug for ApiToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ApiToken")
+            .field("name", &self.name)
+            .field("role", &self.role)
+            .finish()
+    }
+}
+
+/// Public key of a node operator which may sign the private API requests.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OperatorKey {
+    /// Name of the operator.
+    pub name: String,
+    /// Public key of the operator.
+    pub public_key: PublicKey,
+    /// Role of the operator.
+    pub role: ApiRole,
+}
+
+/// Authentication configuration of the private API.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AuthConfig {
+    /// Bearer tokens.
+    #[serde(default)]
+    pub tokens: Vec<ApiToken>,
+    /// Keys of the node operators.
+    #[serde(default)]
+    pub operator_keys: Vec<OperatorKey>,
+    /// File to append the audit log to. If not set, the audit log is written to
+    /// the node log with the `exonum::api::audit` target.
+    #[serde(default)]
+    pub audit_log: Option<PathBuf>,
+}
+
+impl AuthConfig {
+    /// Creates the access control of the private API served by the aggregator, with
+    /// the authenticators from the configuration and the given additional ones.
+    pub(crate) fn access_control(
+        &self,
+        aggregator: &ApiAggregator,
+        authenticators: &[Arc<dyn Authenticator>],
+    ) -> io::Result<AccessControl> {
+        let mut all_authenticators: Vec<Arc<dyn Authenticator>> = vec![
+            Arc::new(TokenAuthenticator::new(self.tokens.clone())),
+            Arc::new(SignatureAuthenticator::new(self.operator_keys.clone())),
+        ];
+        all_authenticators.extend(authenticators.iter().cloned());
+        let audit_log: Arc<dyn AuditLog> = match self.audit_log {
+            Some(ref path) => Arc::new(FileAuditLog::open(path)?),
+            None => Arc::new(LogAuditLog),
+        };
+        Ok(AccessControl::new(
+            aggregator,
+            all_authenticators,
+            audit_log,
+        ))
+    }
+}
+
+/// Authenticator of the requests with bearer tokens.
+#[derive(Debug, Clone)]
+pub struct TokenAuthenticator {
+    tokens: Vec<ApiToken>,
+}
+
+impl TokenAuthenticator {
+    /// Creates an authenticator accepting the given tokens.
+    pub fn new(tokens: Vec<ApiToken>) -> Self {
+        Self { tokens }
+    }
+}
+
+impl Authenticator for TokenAuthenticator {
+    fn authenticate(&self, request: &AuthRequest) -> Option<Principal> {
+        let token = strip_scheme(request.authorization?, BEARER_SCHEME)?;
+        self.tokens
+            .iter()
+            .find(|known| constant_time_eq(known.token.as_bytes(), token.as_bytes()))
+            .map(|known| Principal {
+                name: known.name.clone(),
+                role: known.role,
+                content_hash: None,
+            })
+    }
+}
+
+/// Authenticator of the requests signed by the node operator keys.
+#[derive(Debug, Clone)]
+pub struct SignatureAuthenticator {
+    keys: Vec<OperatorKey>,
+    nonces: Arc<Mutex<NonceCache>>,
+}
+
+impl SignatureAuthenticator {
+    /// Creates an authenticator accepting the requests signed by the given keys.
+    pub fn new(keys: Vec<OperatorKey>) -> Self {
+        Self {
+            keys,
+            nonces: Arc::new(Mutex::new(NonceCache::new(MAX_REMEMBERED_NONCES))),
+        }
+    }
+
+    fn authenticate_at(&self, request: &AuthRequest, now: u64) -> Option<Principal> {
+        let credentials = strip_scheme(request.authorization?, SIGNATURE_SCHEME)?;
+        let credentials = SignedCredentials::parse(credentials)?;
+        let operator = self
+            .keys
+            .iter()
+            .find(|operator| operator.public_key == credentials.public_key)?;
+
+        let skew = if now > credentials.timestamp {
+            now - credentials.timestamp
+        } else {
+            credentials.timestamp - now
+        };
+        if skew > MAX_CLOCK_SKEW {
+            return None;
+        }
+        let message = signed_data(
+            request.method,
+            request.host.unwrap_or_default(),
+            request.path,
+            credentials.timestamp,
+            credentials.nonce,
+            &credentials.content_hash,
+        );
+        if !crypto::verify(&credentials.signature, &message, &operator.public_key) {
+            return None;
+        }
+        let is_new_nonce = self.nonces.lock().expect("Nonce cache lock").insert(
+            credentials.public_key,
+            credentials.nonce,
+            now,
+        );
+        if !is_new_nonce {
+            return None;
+        }
+        Some(Principal {
+            name: operator.name.clone(),
+            role: operator.role,
+            content_hash: Some(credentials.content_hash),
+        })
+    }
+}
+
+impl Authenticator for SignatureAuthenticator {
+    fn authenticate(&self, request: &AuthRequest) -> Option<Principal> {
+        self.authenticate_at(request, unix_time())
+    }
+}
+
+/// Nonces of the recently authenticated signed requests.
+#[derive(Debug)]
+struct NonceCache {
+    nonces: HashSet<(PublicKey, u64)>,
+    /// Number of the remembered nonces of each key.
+    counts: HashMap<PublicKey, usize>,
+    /// Nonces with the times they can be forgotten at, from the oldest to the newest.
+    expirations: VecDeque<(u64, PublicKey, u64)>,
+    /// Maximum number of the remembered nonces of a single key.
+    capacity: usize,
+}
+
+impl NonceCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            nonces: HashSet::new(),
+            counts: HashMap::new(),
+            expirations: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// Remembers the nonce of the key. Returns `false` if the nonce has already been
+    /// used by the key or the key has used up its share of the cache.
+    ///
+    /// A request with a timestamp accepted at `now` is rejected by the clock skew check
+    /// after `now + 2 * MAX_CLOCK_SKEW`, so the nonce can be forgotten after that.
+    /// Nonces are never forgotten earlier, since that would let their requests
+    /// be replayed.
+    fn insert(&mut self, key: PublicKey, nonce: u64, now: u64) -> bool {
+        while let Some(&(expiration, key, nonce)) = self.expirations.front() {
+            if expiration > now {
+                break;
+            }
+            self.expirations.pop_front();
+            self.forget(key, nonce);
+        }
+        let count = self.counts.get(&key).cloned().unwrap_or(0);
+        if count >= self.capacity || !self.nonces.insert((key, nonce)) {
+            return false;
+        }
+        self.counts.insert(key, count + 1);
+        self.expirations
+            .push_back((now + 2 * MAX_CLOCK_SKEW, key, nonce));
+        true
+    }
+
+    fn forget(&mut self, key: PublicKey, nonce: u64) {
+        self.nonces.remove(&(key, nonce));
+        let count = self.counts.get(&key).cloned().unwrap_or(0);
+        if count > 1 {
+            self.counts.insert(key, count - 1);
+        } else {
+            self.counts.remove(&key);
+        }
+    }
+}
+
+/// Credentials of a signed request.
+struct SignedCredentials {
+    public_key: PublicKey,
+    timestamp: u64,
+    nonce: u64,
+    content_hash: Hash,
+    signature: Signature,
+}
+
+impl SignedCredentials {
+    fn parse(credentials: &str) -> Option<Self> {
+        let mut params = HashMap::new();
+        for param in credentials.split(',') {
+            let mut parts = param.trim().splitn(2, '=');
+            let name = parts.next()?;
+            let value = parts.next()?;
+            params.insert(name, value);
+        }
+        Some(Self {
+            public_key: PublicKey::from_hex(params.get("key")?).ok()?,
+            timestamp: params.get("timestamp")?.parse().ok()?,
+            nonce: params.get("nonce")?.parse().ok()?,
+            content_hash: Hash::from_hex(params.get("content_hash")?).ok()?,
+            signature: Signature::from_hex(params.get("signature")?).ok()?,
+        })
+    }
+}
+
+/// Creates the value of the `Authorization` header of the request signed by
+/// the operator key, with a random nonce.
+///
+/// `host` is the value of the `Host` header of the request, such as `127.0.0.1:8081`,
+/// `path` is the path of the request with the query string, such as
+/// `/api/system/v1/peers`, and `timestamp` is the current Unix time in seconds.
+pub fn sign_request(
+    method: &str,
+    host: &str,
+    path: &str,
+    body: &[u8],
+    timestamp: u64,
+    public_key: &PublicKey,
+    secret_key: &SecretKey,
+) -> String {
+    let nonce = rand::random::<u64>();
+    let content_hash = crypto::hash(body);
+    let signature = crypto::sign(
+        &signed_data(method, host, path, timestamp, nonce, &content_hash),
+        secret_key,
+    );
+    format!(
+        "{}key={}, timestamp={}, nonce={}, content_hash={}, signature={}",
+        SIGNATURE_SCHEME,
+        hex::encode(public_key.as_ref()),
+        timestamp,
+        nonce,
+        hex::encode(content_hash.as_ref()),
+        hex::encode(signature.as_ref())
+    )
+}
+
+fn signed_data(
+    method: &str,
+    host: &str,
+    path: &str,
+    timestamp: u64,
+    nonce: u64,
+    content_hash: &Hash,
+) -> Vec<u8> {
+    format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method,
+        host,
+        path,
+        timestamp,
+        nonce,
+        hex::encode(content_hash.as_ref())
+    )
+    .into_bytes()
+}
+
+fn strip_scheme<'a>(authorization: &'a str, scheme: &str) -> Option<&'a str> {
+    if authorization.starts_with(scheme) {
+        Some(authorization[scheme.len()..].trim())
+    } else {
+        None
+    }
+}
+
+/// Compares the secrets in time that does not depend on the position of
+/// the first mismatch.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+/// Entry of the audit log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Time of the request.
+    pub time: DateTime<Utc>,
+    /// Name of the authenticated author of the request, if any.
+    pub principal: Option<String>,
+    /// Method of the request.
+    pub method: String,
+    /// Path of the request with the query string.
+    pub path: String,
+    /// Status code of the response.
+    pub status: u16,
+}
+
+/// Audit log of the admin actions.
+pub trait AuditLog: Send + Sync {
+    /// Records the entry.
+    fn record(&self, entry: &AuditEntry);
+}
+
+/// Audit log writing the entries to the node log.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogAuditLog;
+
+impl AuditLog for LogAuditLog {
+    fn record(&self, entry: &AuditEntry) {
+        info!(
+            target: "exonum::api::audit",
+            "{}",
+            serde_json::to_string(entry).expect("Cannot serialize audit entry")
+        );
+    }
+}
+
+/// Audit log appending the entries to a file as JSON lines.
+#[derive(Debug)]
+pub struct FileAuditLog {
+    file: Mutex<File>,
+}
+
+impl FileAuditLog {
+    /// Opens the file for appending, creating it if it does not exist.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl AuditLog for FileAuditLog {
+    fn record(&self, entry: &AuditEntry) {
+        let mut line = serde_json::to_vec(entry).expect("Cannot serialize audit entry");
+        line.push(b'\n');
+        let mut file = self.file.lock().expect("Audit log lock");
+        if let Err(e) = file.write_all(&line).and_then(|()| file.flush()) {
+            error!("Cannot write the audit log entry {:?}: {}", entry, e);
+        }
+    }
+}
+
+/// Middleware enforcing the authentication and the roles of the private API.
+#[derive(Clone)]
+pub struct AccessControl {
+    authenticators: Vec<Arc<dyn Authenticator>>,
+    audit_log: Arc<dyn AuditLog>,
+    roles: Arc<HashMap<(Method, String), ApiRole>>,
+}
+
+impl AccessControl {
+    /// Creates the access control of the private API served by the aggregator.
+    pub fn new(
+        aggregator: &ApiAggregator,
+        authenticators: Vec<Arc<dyn Authenticator>>,
+        audit_log: Arc<dyn AuditLog>,
+    ) -> Self {
+        let mut roles = HashMap::new();
+        for (prefix, scope) in aggregator.scopes(ApiAccess::Private) {
+            for endpoint in &scope.endpoints {
+                let method = if endpoint.mutable {
+                    Method::POST
+                } else {
+                    Method::GET
+                };
+                let path = format!("/api/{}/{}", prefix, endpoint.name);
+                roles.insert((method, path), endpoint.role);
+            }
+        }
+        Self {
+            authenticators,
+            audit_log,
+            roles: Arc::new(roles),
+        }
+    }
+
+    /// Returns the role required to call the endpoint. Endpoints added directly to
+    /// the web backend require the `admin` role unless they are called with `GET`.
+    fn required_role(&self, method: &Method, path: &str) -> ApiRole {
+        let key = (method.clone(), path.to_owned());
+        self.roles.get(&key).cloned().unwrap_or_else(|| {
+            if *method == Method::GET {
+                ApiRole::ReadOnly
+            } else {
+                ApiRole::Admin
+            }
+        })
+    }
+
+    fn authenticate(&self, request: &AuthRequest) -> Option<Principal> {
+        self.authenticators
+            .iter()
+            .filter_map(|authenticator| authenticator.authenticate(request))
+            .next()
+    }
+
+    fn audit(
+        &self,
+        request: &HttpRequest<ServiceApiState>,
+        principal: Option<&Principal>,
+        status: StatusCode,
+    ) {
+        self.audit_log.record(&AuditEntry {
+            time: Utc::now(),
+            principal: principal.map(|principal| principal.name.clone()),
+            method: request.method().to_string(),
+            path: path_and_query(request).to_owned(),
+            status: status.as_u16(),
+        });
+    }
+}
+
+impl fmt::Debug for AccessControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AccessControl")
+            .field("authenticators", &self.authenticators.len())
+            .field("roles", &self.roles)
+            .finish()
+    }
+}
+
+impl Middleware<ServiceApiState> for AccessControl {
+    fn start(&self, request: &HttpRequest<ServiceApiState>) -> ActixResult<Started> {
+        let required_role = self.required_role(request.method(), request.path());
+        let principal = {
+            let headers = request.headers();
+            let header_value =
+                |name: header::HeaderName| headers.get(name).and_then(|value| value.to_str().ok());
+            self.authenticate(&AuthRequest {
+                method: request.method().as_str(),
+                path: path_and_query(request),
+                host: header_value(header::HOST),
+                authorization: header_value(header::AUTHORIZATION),
+            })
+        };
+
+        let status = match principal {
+            None => StatusCode::UNAUTHORIZED,
+            Some(ref principal) if !principal.role.permits(required_role) => StatusCode::FORBIDDEN,
+            Some(principal) => {
+                request.extensions_mut().insert(principal);
+                return Ok(Started::Done);
+            }
+        };
+        if required_role == ApiRole::Admin {
+            self.audit(request, principal.as_ref(), status);
+        }
+        Ok(Started::Response(HttpResponse::new(status)))
+    }
+
+    fn response(
+        &self,
+        request: &HttpRequest<ServiceApiState>,
+        response: HttpResponse,
+    ) -> ActixResult<Response> {
+        if self.required_role(request.method(), request.path()) == ApiRole::Admin {
+            let principal = request.extensions().get::<Principal>().cloned();
+            if principal.is_some() {
+                self.audit(request, principal.as_ref(), response.status());
+            }
+        }
+        Ok(Response::Done(response))
+    }
+}
+
+fn path_and_query<S>(request: &HttpRequest<S>) -> &str {
+    request
+        .uri()
+        .path_and_query()
+        .map_or_else(|| request.path(), |path| path.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+    use futures::{sync::mpsc, Future};
+
+    use super::*;
+    use api::backends::actix::json_body;
+    use blockchain::{Blockchain, SharedNodeState};
+    use node::ApiSender;
+    use storage::MemoryDB;
+
+    const HOST: &str = "127.0.0.1:8081";
+
+    /// Audit log keeping the entries in memory.
+    #[derive(Debug, Default)]
+    struct MemoryAuditLog(Mutex<Vec<AuditEntry>>);
+
+    impl AuditLog for MemoryAuditLog {
+        fn record(&self, entry: &AuditEntry) {
+            self.0.lock().unwrap().push(entry.clone());
+        }
+    }
+
+    fn api_state() -> ServiceApiState {
+        let (public_key, secret_key) = crypto::gen_keypair();
+        let api_channel = mpsc::channel(1);
+        let blockchain = Blockchain::new(
+            MemoryDB::new(),
+            Vec::new(),
+            public_key,
+            secret_key,
+            ApiSender::new(api_channel.0),
+        );
+        ServiceApiState::new(blockchain)
+    }
+
+    /// Creates the access control of the private API with the `admin` token of the `admin`
+    /// role and the `monitoring` token of the `read_only` role.
+    fn access_control(state: &ServiceApiState, audit_log: Arc<MemoryAuditLog>) -> AccessControl {
+        let aggregator = ApiAggregator::new(state.blockchain().clone(), SharedNodeState::new(5000));
+        let tokens = vec![
+            ApiToken {
+                name: "admin".to_owned(),
+                token: "admin".to_owned(),
+                role: ApiRole::Admin,
+            },
+            ApiToken {
+                name: "monitoring".to_owned(),
+                token: "monitoring".to_owned(),
+                role: ApiRole::ReadOnly,
+            },
+        ];
+        let authenticators: Vec<Arc<dyn Authenticator>> =
+            vec![Arc::new(TokenAuthenticator::new(tokens))];
+        AccessControl::new(&aggregator, authenticators, audit_log)
+    }
+
+    fn http_request(
+        state: &ServiceApiState,
+        method: Method,
+        path: &str,
+        authorization: Option<&str>,
+    ) -> HttpRequest<ServiceApiState> {
+        let mut request = TestRequest::with_state(state.clone())
+            .method(method)
+            .uri(path)
+            .header(header::HOST, HOST);
+        if let Some(authorization) = authorization {
+            request = request.header(header::AUTHORIZATION, authorization);
+        }
+        request.finish()
+    }
+
+    /// Returns the status of the response of the middleware, or `None` if the request
+    /// is passed to the endpoint.
+    fn start(
+        access_control: &AccessControl,
+        request: &HttpRequest<ServiceApiState>,
+    ) -> Option<StatusCode> {
+        match access_control.start(request).unwrap() {
+            Started::Done => None,
+            Started::Response(response) => Some(response.status()),
+            Started::Future(_) => panic!("Unexpected future"),
+        }
+    }
+
+    fn request<'a>(method: &'a str, path: &'a str, authorization: &'a str) -> AuthRequest<'a> {
+        AuthRequest {
+            method,
+            path,
+            host: Some(HOST),
+            authorization: Some(authorization),
+        }
+    }
+
+    #[test]
+    fn test_roles() {
+        assert!(ApiRole::Admin.permits(ApiRole::ReadOnly));
+        assert!(ApiRole::Admin.permits(ApiRole::Admin));
+        assert!(ApiRole::ReadOnly.permits(ApiRole::ReadOnly));
+        assert!(!ApiRole::ReadOnly.permits(ApiRole::Admin));
+    }
+
+    #[test]
+    fn test_token_authenticator() {
+        let authenticator = TokenAuthenticator::new(vec![ApiToken {
+            name: "monitoring".to_owned(),
+            token: "secret".to_owned(),
+            role: ApiRole::ReadOnly,
+        }]);
+
+        let principal = authenticator
+            .authenticate(&request("GET", "/api/system/v1/peers", "Bearer secret"))
+            .unwrap();
+        assert_eq!(principal.name, "monitoring");
+        assert_eq!(principal.role, ApiRole::ReadOnly);
+
+        for authorization in &["Bearer secre", "Bearer secret2", "secret", "Basic secret"] {
+            assert_eq!(
+                authenticator.authenticate(&request("GET", "/", authorization)),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn test_signature_authenticator() {
+        let (public_key, secret_key) = crypto::gen_keypair();
+        let authenticator = SignatureAuthenticator::new(vec![OperatorKey {
+            name: "operator".to_owned(),
+            public_key,
+            role: ApiRole::Admin,
+        }]);
+        let path = "/api/system/v1/peers";
+        let body = br#"{"address":"127.0.0.1:2000"}"#;
+        let now = 1_550_000_000;
+        let authorization = sign_request("POST", HOST, path, body, now, &public_key, &secret_key);
+
+        let principal = authenticator
+            .authenticate_at(&request("POST", path, &authorization), now + 10)
+            .unwrap();
+        assert_eq!(principal.name, "operator");
+        assert_eq!(principal.role, ApiRole::Admin);
+        assert_eq!(principal.content_hash, Some(crypto::hash(body)));
+
+        // The signed request cannot be replayed.
+        assert_eq!(
+            authenticator.authenticate_at(&request("POST", path, &authorization), now + 20),
+            None
+        );
+
+        // The signature is bound to the host, the method, the path and the time.
+        let authorization = sign_request("POST", HOST, path, body, now, &public_key, &secret_key);
+        let other_host = AuthRequest {
+            host: Some("127.0.0.1:9081"),
+            ..request("POST", path, &authorization)
+        };
+        assert_eq!(authenticator.authenticate_at(&other_host, now), None);
+        let other_path = "/api/system/v1/shutdown";
+        assert_eq!(
+            authenticator.authenticate_at(&request("POST", other_path, &authorization), now),
+            None
+        );
+        assert_eq!(
+            authenticator.authenticate_at(&request("GET", path, &authorization), now),
+            None
+        );
+        assert_eq!(
+            authenticator.authenticate_at(
+                &request("POST", path, &authorization),
+                now + MAX_CLOCK_SKEW + 1
+            ),
+            None
+        );
+
+        // Requests signed by unknown keys are rejected.
+        let (other_public_key, other_secret_key) = crypto::gen_keypair();
+        let authorization = sign_request(
+            "POST",
+            HOST,
+            path,
+            body,
+            now,
+            &other_public_key,
+            &other_secret_key,
+        );
+        assert_eq!(
+            authenticator.authenticate_at(&request("POST", path, &authorization), now),
+            None
+        );
+    }
+
+    #[test]
+    fn test_nonce_cache() {
+        let (first_key, second_key) = (crypto::gen_keypair().0, crypto::gen_keypair().0);
+        let now = 1_550_000_000;
+        let mut cache = NonceCache::new(2);
+
+        assert!(cache.insert(first_key, 1, now));
+        assert!(!cache.insert(first_key, 1, now + 1));
+        assert!(cache.insert(first_key, 2, now + 1));
+        // The first key has used up its share of the cache, which does not affect
+        // the second key.
+        assert!(!cache.insert(first_key, 3, now + 2));
+        assert!(cache.insert(second_key, 1, now + 2));
+
+        // The nonces are forgotten once the requests cannot pass the clock skew check.
+        let later = now + 2 * MAX_CLOCK_SKEW;
+        assert!(cache.insert(first_key, 1, later));
+        assert!(!cache.insert(first_key, 3, later));
+        assert!(!cache.insert(second_key, 1, later));
+        assert_eq!(cache.nonces.len(), 3);
+        assert_eq!(cache.counts[&first_key], 2);
+    }
+
+    #[test]
+    fn test_access_control() {
+        let state = api_state();
+        let audit_log = Arc::new(MemoryAuditLog::default());
+        let access_control = access_control(&state, audit_log.clone());
+        let peers = "/api/system/v1/peers";
+
+        // Requests without valid credentials are rejected.
+        for authorization in &[None, Some("Bearer wrong"), Some("Exonum-Signature key=00")] {
+            let request = http_request(&state, Method::GET, peers, *authorization);
+            assert_eq!(
+                start(&access_control, &request),
+                Some(StatusCode::UNAUTHORIZED)
+            );
+        }
+        let request = http_request(&state, Method::GET, peers, Some("Bearer monitoring"));
+        assert_eq!(start(&access_control, &request), None);
+
+        // Requests to the endpoints requiring the `admin` role are recorded in the audit log.
+        let request = http_request(&state, Method::POST, peers, Some("Bearer monitoring"));
+        assert_eq!(
+            start(&access_control, &request),
+            Some(StatusCode::FORBIDDEN)
+        );
+        let request = http_request(&state, Method::POST, peers, None);
+        assert_eq!(
+            start(&access_control, &request),
+            Some(StatusCode::UNAUTHORIZED)
+        );
+        let request = http_request(&state, Method::POST, peers, Some("Bearer admin"));
+        assert_eq!(start(&access_control, &request), None);
+        let principal = request.extensions().get::<Principal>().cloned().unwrap();
+        assert_eq!(principal.name, "admin");
+        access_control
+            .response(&request, HttpResponse::Ok().finish())
+            .unwrap();
+
+        let entries = audit_log
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|entry| {
+                (
+                    entry.principal.clone(),
+                    entry.method.clone(),
+                    entry.path.clone(),
+                    entry.status,
+                )
+            })
+            .collect::<Vec<_>>();
+        let entry = |principal: Option<&str>, status| {
+            (
+                principal.map(str::to_owned),
+                "POST".to_owned(),
+                peers.to_owned(),
+                status,
+            )
+        };
+        assert_eq!(
+            entries,
+            vec![
+                entry(Some("monitoring"), 403),
+                entry(None, 401),
+                entry(Some("admin"), 200),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_content_hash() {
+        let state = api_state();
+        let body = br#"{"address":"127.0.0.1:2000"}"#;
+        let read_body = |content: &[u8]| {
+            let request = TestRequest::with_state(state.clone())
+                .method(Method::POST)
+                .uri("/api/system/v1/peers")
+                .set_payload(content.to_vec())
+                .finish();
+            request.extensions_mut().insert(Principal {
+                name: "operator".to_owned(),
+                role: ApiRole::Admin,
+                content_hash: Some(crypto::hash(body)),
+            });
+            json_body::<serde_json::Value>(&request).wait()
+        };
+
+        assert_eq!(
+            read_body(body).unwrap(),
+            json!({ "address": "127.0.0.1:2000" })
+        );
+        // The body differs from the one the credentials are bound to.
+        let error = read_body(br#"{"address":"127.0.0.1:3000"}"#).unwrap_err();
+        assert_eq!(
+            error.as_response_error().error_response().status(),
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[test]
+    fn test_auth_config_format() {
+        let config: AuthConfig = ::toml::from_str(
+            r#"
+            [[tokens]]
+            name = "monitoring"
+            token = "secret"
+            role = "read_only"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.tokens[0].role, ApiRole::ReadOnly);
+        assert!(config.operator_keys.is_empty());
+        assert_eq!(config.audit_log, None);
+    }
+}
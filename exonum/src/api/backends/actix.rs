@@ -19,6 +19,7 @@ use serde::{
     de::{self, DeserializeOwned},
     ser, Serialize,
 };
+use serde_json;
 
 use std::{
     fmt,
@@ -30,9 +31,11 @@ use std::{
 };
 
 use api::{
-    error::Error as ApiError, ApiAccess, ApiAggregator, ExtendApiBackend, FutureResult, Immutable,
-    Mutable, NamedWith, Result, ServiceApiBackend, ServiceApiScope, ServiceApiState,
+    auth::Principal, error::Error as ApiError, ApiAccess, ApiAggregator, ExtendApiBackend,
+    FutureResult, Immutable, Mutable, NamedWith, Result, ServiceApiBackend, ServiceApiScope,
+    ServiceApiState,
 };
+use crypto;
 
 /// Type alias for the concrete `actix-web` HTTP response.
 pub type FutureResponse = actix_web::FutureResponse<HttpResponse, actix_web::Error>;
@@ -76,6 +79,11 @@ impl ApiBuilder {
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// Returns the handlers added to the builder.
+    pub fn handlers(&self) -> &[RequestHandler] {
+        &self.handlers
+    }
 }
 
 impl ServiceApiBackend for ApiBuilder {
@@ -121,6 +129,7 @@ impl ResponseError for ApiError {
             ApiError::Storage(err) => HttpResponse::InternalServerError().body(err.to_string()),
             ApiError::NotFound(err) => HttpResponse::NotFound().body(err.to_string()),
             ApiError::Unauthorized => HttpResponse::Unauthorized().finish(),
+            ApiError::Forbidden => HttpResponse::Forbidden().finish(),
         }
     }
 }
@@ -162,9 +171,7 @@ where
         let index = move |request: HttpRequest| -> FutureResponse {
             let handler = handler.clone();
             let context = request.state().clone();
-            request
-                .json()
-                .from_err()
+            json_body(&request)
                 .and_then(move |query: Q| {
                     handler(&context, query)
                         .map(|value| HttpResponse::Ok().json(value))
@@ -219,9 +226,7 @@ where
         let index = move |request: HttpRequest| -> FutureResponse {
             let handler = handler.clone();
             let context = request.state().clone();
-            request
-                .json()
-                .from_err()
+            json_body(&request)
                 .and_then(move |query: Q| {
                     handler(&context, query)
                         .map(|value| HttpResponse::Ok().json(value))
@@ -238,6 +243,28 @@ where
     }
 }
 
+/// Reads the JSON body of the request to a mutable endpoint. If the credentials of
+/// the request are bound to the body, the hash of the body is checked as well.
+pub(crate) fn json_body<Q: DeserializeOwned + 'static>(
+    request: &HttpRequest,
+) -> impl Future<Item = Q, Error = actix_web::Error> {
+    let content_hash = request
+        .extensions()
+        .get::<Principal>()
+        .and_then(|principal| principal.content_hash);
+    request
+        .body()
+        .from_err()
+        .and_then(move |body| -> result::Result<Q, actix_web::Error> {
+            if let Some(content_hash) = content_hash {
+                if crypto::hash(&body) != content_hash {
+                    return Err(ApiError::Unauthorized.into());
+                }
+            }
+            serde_json::from_slice(&body).map_err(|e| ApiError::BadRequest(e.to_string()).into())
+        })
+}
+
 /// Creates `actix_web::App` for the given aggregator and runtime configuration.
 pub(crate) fn create_app(aggregator: &ApiAggregator, runtime_config: ApiRuntimeConfig) -> App {
     let app_config = runtime_config.app_config;
@@ -0,0 +1,168 @@
+//! gRPC API backend.
+//!
+//! The backend serves unary gRPC methods over HTTP/2 with the
+//! [grpc-rust](https://github.com/stepancheg/grpc-rust) server. Requests and responses
+//! of the methods are protobuf messages, usually generated from the `.proto` files
+//! shared with the clients. Handlers take the same `ServiceApiState` as the handlers
+//! of the HTTP endpoints.
+//!
+//! The methods of an API scope added to the aggregator with the given prefix form
+//! the gRPC service `exonum.<prefix>`, where slashes of the prefix are replaced
+//! with dots. For example, the `GetBlock` method of the explorer is available at
+//! `/exonum.explorer/GetBlock`, and the methods of the `cryptocurrency` service are
+//! available at `/exonum.services.cryptocurrency/<method>`. The core methods are
+//! described in `proto/schema/exonum/api.proto`.
+//!
+//! Only the public API is served, since access control of the private API relies
+//! on HTTP authentication. Rate limits of the public API apply to gRPC methods as
+//! well; since the server does not expose the client address to the handlers, only
+//! the global limits are enforced. A single client can thus exhaust the budget of
+//! all the others, so the gRPC API should not be exposed to untrusted networks.
+//!
+//! The server is built only with the `grpc` feature of `exonum`. Without it, methods
+//! can still be added to API scopes, but they are not served.
+
+use protobuf::{self, Message};
+
+use std::{fmt, sync::Arc};
+
+use api::{error::Error as ApiError, Result, ServiceApiState};
+
+#[cfg(feature = "grpc")]
+pub use self::server::{GrpcServer, ServiceBuilder, Services};
+
+#[cfg(feature = "grpc")]
+mod server;
+
+/// Type alias for the inner gRPC method handler, which takes and returns serialized
+/// protobuf messages.
+pub type RawHandler = dyn Fn(&ServiceApiState, &[u8]) -> Result<Vec<u8>> + 'static + Send + Sync;
+
+/// Raw gRPC backend method handler.
+#[derive(Clone)]
+pub struct MethodHandler {
+    /// Method name.
+    pub name: String,
+    /// Whether the method modifies the node state, like transaction submission.
+    pub mutable: bool,
+    /// Inner handler.
+    pub inner: Arc<RawHandler>,
+}
+
+impl MethodHandler {
+    /// Creates a handler of the unary method with the given protobuf request and
+    /// response messages.
+    pub fn unary<Q, I, F>(name: &str, mutable: bool, handler: F) -> Self
+    where
+        Q: Message,
+        I: Message,
+        F: Fn(&ServiceApiState, Q) -> Result<I> + 'static + Send + Sync,
+    {
+        let inner = move |state: &ServiceApiState, body: &[u8]| -> Result<Vec<u8>> {
+            let request = protobuf::parse_from_bytes::<Q>(body)
+                .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+            let response = handler(state, request)?;
+            response
+                .write_to_bytes()
+                .map_err(|e| ApiError::InternalError(e.into()))
+        };
+
+        Self {
+            name: name.to_owned(),
+            mutable,
+            inner: Arc::new(inner),
+        }
+    }
+}
+
+impl fmt::Debug for MethodHandler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MethodHandler")
+            .field("name", &self.name)
+            .field("mutable", &self.mutable)
+            .finish()
+    }
+}
+
+/// API builder for the gRPC backend.
+#[derive(Debug, Clone, Default)]
+pub struct ApiBuilder {
+    handlers: Vec<MethodHandler>,
+}
+
+impl ApiBuilder {
+    /// Constructs a new backend builder instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the handlers added to the builder.
+    pub fn handlers(&self) -> &[MethodHandler] {
+        &self.handlers
+    }
+
+    /// Adds the method handler to the builder.
+    pub fn add_method(&mut self, handler: MethodHandler) -> &mut Self {
+        self.handlers.push(handler);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::sync::mpsc;
+    use protobuf::well_known_types::UInt64Value;
+
+    use super::*;
+    use blockchain::Blockchain;
+    use crypto::gen_keypair;
+    use node::ApiSender;
+    use storage::MemoryDB;
+
+    pub fn state() -> ServiceApiState {
+        let (public_key, secret_key) = gen_keypair();
+        let api_channel = mpsc::channel(1);
+        let blockchain = Blockchain::new(
+            MemoryDB::new(),
+            Vec::new(),
+            public_key,
+            secret_key,
+            ApiSender::new(api_channel.0),
+        );
+        ServiceApiState::new(blockchain)
+    }
+
+    #[test]
+    fn test_unary_method() {
+        let handler = MethodHandler::unary(
+            "Double",
+            false,
+            |_: &ServiceApiState, request: UInt64Value| -> Result<UInt64Value> {
+                if request.get_value() > 100 {
+                    return Err(ApiError::BadRequest("Value is too large".to_owned()));
+                }
+                let mut response = UInt64Value::new();
+                response.set_value(request.get_value() * 2);
+                Ok(response)
+            },
+        );
+        let state = state();
+
+        let mut request = UInt64Value::new();
+        request.set_value(21);
+        let response = (handler.inner)(&state, &request.write_to_bytes().unwrap()).unwrap();
+        let response = protobuf::parse_from_bytes::<UInt64Value>(&response).unwrap();
+        assert_eq!(response.get_value(), 42);
+
+        request.set_value(101);
+        match (handler.inner)(&state, &request.write_to_bytes().unwrap()) {
+            Err(ApiError::BadRequest(_)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        // Malformed requests are rejected before reaching the handler.
+        match (handler.inner)(&state, &[0xff, 0xff]) {
+            Err(ApiError::BadRequest(_)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,283 @@
+//! gRPC server of the public API methods.
+
+use bytes::Bytes;
+use failure;
+use grpc::{
+    self,
+    rt::{
+        GrpcStreaming, MethodDescriptor, MethodHandlerUnary, ServerMethod, ServerServiceDefinition,
+    },
+    GrpcMessageError, GrpcStatus, Marshaller, RequestOptions, SingleResponse,
+};
+
+use std::{fmt, net::SocketAddr, result, sync::Arc};
+
+use super::{ApiBuilder, MethodHandler};
+use api::{
+    error::Error as ApiError,
+    rate_limit::{Budget, RateLimiter},
+    ApiAccess, ApiAggregator, ExtendApiBackend, ServiceApiBackend, ServiceApiScope,
+    ServiceApiState,
+};
+
+impl ServiceApiBackend for ApiBuilder {
+    type Handler = MethodHandler;
+    type Backend = ServiceBuilder;
+
+    fn raw_handler(&mut self, handler: Self::Handler) -> &mut Self {
+        self.add_method(handler)
+    }
+
+    fn wire(&self, mut output: Self::Backend) -> Self::Backend {
+        for handler in self.handlers.clone() {
+            let method = output.server_method(handler);
+            output.methods.push(method);
+        }
+        output
+    }
+}
+
+/// Builder of the gRPC service, which serves the methods of an API scope.
+pub struct ServiceBuilder {
+    name: String,
+    state: ServiceApiState,
+    rate_limiter: Option<RateLimiter>,
+    methods: Vec<ServerMethod>,
+}
+
+impl ServiceBuilder {
+    fn new(prefix: &str, state: ServiceApiState, rate_limiter: Option<RateLimiter>) -> Self {
+        Self {
+            name: format!("exonum.{}", prefix.replace('/', ".")),
+            state,
+            rate_limiter,
+            methods: Vec::new(),
+        }
+    }
+
+    fn server_method(&self, handler: MethodHandler) -> ServerMethod {
+        let descriptor = MethodDescriptor {
+            name: format!("/{}/{}", self.name, handler.name),
+            streaming: GrpcStreaming::Unary,
+            req_marshaller: Box::new(RawMarshaller),
+            resp_marshaller: Box::new(RawMarshaller),
+        };
+        let budget = if handler.mutable {
+            Budget::Submit
+        } else {
+            Budget::Read
+        };
+        let state = self.state.clone();
+        let rate_limiter = self.rate_limiter.clone();
+        let inner = handler.inner;
+        let method = move |_options: RequestOptions, request: Bytes| {
+            if let Some(ref rate_limiter) = rate_limiter {
+                if rate_limiter.check(budget, None).is_err() {
+                    return SingleResponse::err(grpc_error(
+                        GrpcStatus::ResourceExhausted,
+                        "Too many requests".to_owned(),
+                    ));
+                }
+            }
+            match inner(&state, &request) {
+                Ok(response) => SingleResponse::completed(response),
+                Err(e) => SingleResponse::err(e.into()),
+            }
+        };
+        ServerMethod::new(Arc::new(descriptor), MethodHandlerUnary::new(method))
+    }
+}
+
+impl fmt::Debug for ServiceBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ServiceBuilder")
+            .field("name", &self.name)
+            .field("methods", &self.methods.len())
+            .finish()
+    }
+}
+
+/// gRPC services serving the API scopes.
+pub struct Services {
+    state: ServiceApiState,
+    rate_limiter: Option<RateLimiter>,
+    definitions: Vec<ServerServiceDefinition>,
+}
+
+impl Services {
+    /// Creates an empty set of services for the given state. Throttled requests are
+    /// rejected with the `RESOURCE_EXHAUSTED` status.
+    pub fn new(state: ServiceApiState, rate_limiter: Option<RateLimiter>) -> Self {
+        Self {
+            state,
+            rate_limiter,
+            definitions: Vec::new(),
+        }
+    }
+
+    /// Returns the number of the services.
+    pub fn len(&self) -> usize {
+        self.definitions.len()
+    }
+
+    /// Returns `true` if there are no services.
+    pub fn is_empty(&self) -> bool {
+        self.definitions.is_empty()
+    }
+}
+
+impl fmt::Debug for Services {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Services")
+            .field("definitions", &self.definitions.len())
+            .finish()
+    }
+}
+
+impl ExtendApiBackend for Services {
+    fn extend<'a, I>(mut self, items: I) -> Self
+    where
+        I: IntoIterator<Item = (&'a str, &'a ServiceApiScope)>,
+    {
+        for (prefix, scope) in items {
+            if scope.grpc_backend.handlers.is_empty() {
+                continue;
+            }
+            let service =
+                ServiceBuilder::new(prefix, self.state.clone(), self.rate_limiter.clone());
+            let service = scope.grpc_backend.wire(service);
+            self.definitions.push(ServerServiceDefinition::new(
+                &format!("/{}", service.name),
+                service.methods,
+            ));
+        }
+        self
+    }
+}
+
+/// Running gRPC server. The server is stopped when the handle is dropped.
+pub struct GrpcServer {
+    inner: grpc::Server,
+}
+
+impl GrpcServer {
+    /// Starts the gRPC server for the public API of the aggregator.
+    pub fn start(
+        aggregator: &ApiAggregator,
+        listen_address: SocketAddr,
+        rate_limiter: Option<RateLimiter>,
+    ) -> result::Result<Self, failure::Error> {
+        let state = ServiceApiState::new(aggregator.blockchain().clone());
+        let services =
+            aggregator.extend_backend(ApiAccess::Public, Services::new(state, rate_limiter));
+
+        let mut builder = grpc::ServerBuilder::new_plain();
+        builder
+            .http
+            .set_addr(listen_address)
+            .map_err(|e| format_err!("Unable to bind gRPC api to {}: {:?}", listen_address, e))?;
+        for definition in services.definitions {
+            builder.add_service(definition);
+        }
+        info!("Starting public gRPC api on {}", listen_address);
+        let inner = builder
+            .build()
+            .map_err(|e| format_err!("Unable to start gRPC api: {:?}", e))?;
+        Ok(Self { inner })
+    }
+
+    /// Stops the server.
+    pub fn stop(self) {
+        drop(self.inner);
+        info!("gRPC api stopped");
+    }
+}
+
+impl fmt::Debug for GrpcServer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GrpcServer").finish()
+    }
+}
+
+/// Passes serialized messages as is; the messages are parsed by the method handlers.
+struct RawMarshaller;
+
+impl Marshaller<Bytes> for RawMarshaller {
+    fn write(&self, message: &Bytes) -> grpc::Result<Vec<u8>> {
+        Ok(message.to_vec())
+    }
+
+    fn read(&self, bytes: Bytes) -> grpc::Result<Bytes> {
+        Ok(bytes)
+    }
+}
+
+impl Marshaller<Vec<u8>> for RawMarshaller {
+    fn write(&self, message: &Vec<u8>) -> grpc::Result<Vec<u8>> {
+        Ok(message.clone())
+    }
+
+    fn read(&self, bytes: Bytes) -> grpc::Result<Vec<u8>> {
+        Ok(bytes.to_vec())
+    }
+}
+
+fn grpc_error(status: GrpcStatus, message: String) -> grpc::Error {
+    grpc::Error::GrpcMessage(GrpcMessageError {
+        grpc_status: status as i32,
+        grpc_message: message,
+    })
+}
+
+impl From<ApiError> for grpc::Error {
+    fn from(e: ApiError) -> Self {
+        let status = match e {
+            ApiError::BadRequest(_) => GrpcStatus::InvalidArgument,
+            ApiError::NotFound(_) => GrpcStatus::NotFound,
+            ApiError::Unauthorized => GrpcStatus::Unauthenticated,
+            ApiError::Forbidden => GrpcStatus::PermissionDenied,
+            ApiError::Io(_) | ApiError::Storage(_) | ApiError::InternalError(_) => {
+                GrpcStatus::Internal
+            }
+        };
+        grpc_error(status, e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use protobuf::well_known_types::Empty;
+
+    use super::*;
+    use api::{backends::grpc::tests::state, Result};
+
+    #[test]
+    fn test_services() {
+        let mut scope = ServiceApiScope::new();
+        scope.grpc_method("Ping", |_: &ServiceApiState, _: Empty| -> Result<Empty> {
+            Ok(Empty::new())
+        });
+        let empty_scope = ServiceApiScope::new();
+
+        let services = Services::new(state(), None).extend(vec![
+            ("services/cryptocurrency", &scope),
+            ("system", &empty_scope),
+        ]);
+        // Scopes without gRPC methods are skipped.
+        assert_eq!(services.len(), 1);
+
+        let service = ServiceBuilder::new("services/cryptocurrency", state(), None);
+        assert_eq!(service.name, "exonum.services.cryptocurrency");
+    }
+
+    #[test]
+    fn test_error_status() {
+        let error = grpc::Error::from(ApiError::NotFound("Block not found".to_owned()));
+        match error {
+            grpc::Error::GrpcMessage(GrpcMessageError { grpc_status, .. }) => {
+                assert_eq!(grpc_status, GrpcStatus::NotFound as i32)
+            }
+            other => panic!("Unexpected error: {:?}", other),
+        }
+    }
+}
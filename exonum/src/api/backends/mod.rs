@@ -2,6 +2,8 @@
 //!
 //! Exonum API is abstract, its custom interlayer allows adding third-party
 //! backends, which are modules that implement API according to certain principles.
-//! Currently, only the Actix-web backend is available.
+//! The Actix-web backend serves the HTTP API, and the gRPC backend serves
+//! the public API methods registered with protobuf messages.
 
 pub mod actix;
+pub mod grpc;
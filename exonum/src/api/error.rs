@@ -39,6 +39,11 @@ pub enum Error {
     /// authentication credentials.
     #[fail(display = "Unauthorized")]
     Unauthorized,
+
+    /// Forbidden error. This error occurs when the authenticated author of
+    /// the request does not have the role required to call the endpoint.
+    #[fail(display = "Forbidden")]
+    Forbidden,
 }
 
 impl From<io::Error> for Error {
@@ -5,20 +5,30 @@ pub use self::{
     with::{FutureResult, Immutable, Mutable, NamedWith, Result, With},
 };
 
+use protobuf::Message;
 use serde::{de::DeserializeOwned, Serialize};
 
 use std::{collections::BTreeMap, fmt};
 
-use self::{backends::actix, node::public::ExplorerApi};
+use self::{
+    auth::ApiRole,
+    backends::{actix, grpc},
+    node::public::ExplorerApi,
+    openapi::{EndpointEntry, EndpointSpec, OPENAPI_PREFIX},
+};
 use blockchain::{Blockchain, SharedNodeState};
 use crypto::PublicKey;
 use node::ApiSender;
 
+pub mod auth;
 pub mod backends;
 pub mod error;
 pub mod node;
+pub mod openapi;
+pub mod pagination;
+pub mod rate_limit;
 mod state;
-pub(crate) mod websocket;
+pub mod websocket;
 mod with;
 
 /// Defines an object that could be used as an API backend.
@@ -73,6 +83,8 @@ pub trait ServiceApiBackend: Sized {
 #[derive(Debug, Clone, Default)]
 pub struct ServiceApiScope {
     pub(crate) actix_backend: actix::ApiBuilder,
+    pub(crate) grpc_backend: grpc::ApiBuilder,
+    pub(crate) endpoints: Vec<EndpointEntry>,
 }
 
 impl ServiceApiScope {
@@ -81,6 +93,12 @@ impl ServiceApiScope {
         Self::default()
     }
 
+    /// Returns the handlers of the endpoints added to the scope, which allows
+    /// calling them without an HTTP server.
+    pub fn handlers(&self) -> &[actix::RequestHandler] {
+        self.actix_backend.handlers()
+    }
+
     /// Adds the given endpoint handler to the API scope. These endpoints
     /// are designed for reading operations.
     ///
@@ -90,6 +108,24 @@ impl ServiceApiScope {
     ///   "first_param=value1&second_param=value2" form.
     /// - Response items should be encodable via `serde_json` crate.
     pub fn endpoint<Q, I, R, F, E>(&mut self, name: &'static str, endpoint: E) -> &mut Self
+    where
+        Q: DeserializeOwned + 'static,
+        I: Serialize + 'static,
+        F: for<'r> Fn(&'r ServiceApiState, Q) -> R + 'static + Clone,
+        E: Into<With<Q, I, R, F>>,
+        actix::RequestHandler: From<NamedWith<Q, I, R, F, Immutable>>,
+    {
+        self.endpoint_with_spec(name, EndpointSpec::default(), endpoint)
+    }
+
+    /// Adds the given endpoint handler to the API scope along with its description
+    /// for the [OpenAPI specification](openapi/index.html).
+    pub fn endpoint_with_spec<Q, I, R, F, E>(
+        &mut self,
+        name: &'static str,
+        spec: EndpointSpec,
+        endpoint: E,
+    ) -> &mut Self
     where
         Q: DeserializeOwned + 'static,
         I: Serialize + 'static,
@@ -98,6 +134,12 @@ impl ServiceApiScope {
         actix::RequestHandler: From<NamedWith<Q, I, R, F, Immutable>>,
     {
         self.actix_backend.endpoint(name, endpoint);
+        self.endpoints.push(EndpointEntry {
+            name: name.to_owned(),
+            mutable: false,
+            role: spec.role.unwrap_or(ApiRole::ReadOnly),
+            spec,
+        });
         self
     }
 
@@ -109,6 +151,24 @@ impl ServiceApiScope {
     /// - Query parameters should be decodable via `serde_json`.
     /// - Response items also should be encodable via `serde_json` crate.
     pub fn endpoint_mut<Q, I, R, F, E>(&mut self, name: &'static str, endpoint: E) -> &mut Self
+    where
+        Q: DeserializeOwned + 'static,
+        I: Serialize + 'static,
+        F: for<'r> Fn(&'r ServiceApiState, Q) -> R + 'static + Clone,
+        E: Into<With<Q, I, R, F>>,
+        actix::RequestHandler: From<NamedWith<Q, I, R, F, Mutable>>,
+    {
+        self.endpoint_mut_with_spec(name, EndpointSpec::default(), endpoint)
+    }
+
+    /// Adds the given mutable endpoint handler to the API scope along with
+    /// its description for the [OpenAPI specification](openapi/index.html).
+    pub fn endpoint_mut_with_spec<Q, I, R, F, E>(
+        &mut self,
+        name: &'static str,
+        spec: EndpointSpec,
+        endpoint: E,
+    ) -> &mut Self
     where
         Q: DeserializeOwned + 'static,
         I: Serialize + 'static,
@@ -117,6 +177,43 @@ impl ServiceApiScope {
         actix::RequestHandler: From<NamedWith<Q, I, R, F, Mutable>>,
     {
         self.actix_backend.endpoint_mut(name, endpoint);
+        self.endpoints.push(EndpointEntry {
+            name: name.to_owned(),
+            mutable: true,
+            role: spec.role.unwrap_or(ApiRole::Admin),
+            spec,
+        });
+        self
+    }
+
+    /// Adds the given unary gRPC method to the API scope. These methods are
+    /// designed for reading operations. Requests and responses are protobuf messages.
+    ///
+    /// Methods are served only for the public scope and only if `exonum` is built
+    /// with the `grpc` feature; see the [`grpc`] backend for the naming of the methods.
+    ///
+    /// [`grpc`]: backends/grpc/index.html
+    pub fn grpc_method<Q, I, F>(&mut self, name: &'static str, handler: F) -> &mut Self
+    where
+        Q: Message,
+        I: Message,
+        F: Fn(&ServiceApiState, Q) -> Result<I> + 'static + Send + Sync,
+    {
+        self.grpc_backend
+            .add_method(grpc::MethodHandler::unary(name, false, handler));
+        self
+    }
+
+    /// Adds the given unary gRPC method to the API scope. These methods are
+    /// designed for modification operations, such as transaction submission.
+    pub fn grpc_method_mut<Q, I, F>(&mut self, name: &'static str, handler: F) -> &mut Self
+    where
+        Q: Message,
+        I: Message,
+        F: Fn(&ServiceApiState, Q) -> Result<I> + 'static + Send + Sync,
+    {
+        self.grpc_backend
+            .add_method(grpc::MethodHandler::unary(name, true, handler));
         self
     }
 
@@ -124,6 +221,11 @@ impl ServiceApiScope {
     pub fn web_backend(&mut self) -> &mut actix::ApiBuilder {
         &mut self.actix_backend
     }
+
+    /// Returns a mutable reference to the underlying gRPC backend.
+    pub fn grpc_backend(&mut self) -> &mut grpc::ApiBuilder {
+        &mut self.grpc_backend
+    }
 }
 
 /// Service API builder, which is used to add service-specific endpoints to the node API.
@@ -285,7 +387,7 @@ pub trait ExtendApiBackend {
 }
 
 /// Exonum node API aggregator. This structure enables several API backends to
-/// operate simultaneously. Currently, only HTTP v1 backend is available.
+/// operate simultaneously: the HTTP v1 backend and the gRPC backend.
 #[derive(Debug, Clone)]
 pub struct ApiAggregator {
     blockchain: Blockchain,
@@ -328,19 +430,50 @@ impl ApiAggregator {
     }
 
     /// Extends the given API backend by handlers with the given access level.
+    /// If the `openapi` feature is enabled, the OpenAPI specification of these handlers
+    /// is served at `api/docs/v1/openapi`.
     pub fn extend_backend<B: ExtendApiBackend>(&self, access: ApiAccess, backend: B) -> B {
-        match access {
-            ApiAccess::Public => backend.extend(
-                self.inner
-                    .iter()
-                    .map(|(name, builder)| (name.as_ref(), &builder.public_scope)),
-            ),
-            ApiAccess::Private => backend.extend(
-                self.inner
-                    .iter()
-                    .map(|(name, builder)| (name.as_ref(), &builder.private_scope)),
-            ),
-        }
+        let docs = self.docs_scope(access);
+        let scopes = self
+            .scopes(access)
+            .chain(docs.as_ref().map(|docs| (OPENAPI_PREFIX, docs)));
+        backend.extend(scopes)
+    }
+
+    #[cfg(feature = "openapi")]
+    fn docs_scope(&self, access: ApiAccess) -> Option<ServiceApiScope> {
+        let spec = self.openapi_specification(access);
+        let mut docs = ServiceApiScope::new();
+        docs.endpoint_with_spec(
+            openapi::OPENAPI_ENDPOINT,
+            EndpointSpec::new().description("Returns the OpenAPI specification of the API"),
+            move |_state: &ServiceApiState, _query: ()| Ok(spec.clone()),
+        );
+        Some(docs)
+    }
+
+    #[cfg(not(feature = "openapi"))]
+    fn docs_scope(&self, _access: ApiAccess) -> Option<ServiceApiScope> {
+        None
+    }
+
+    /// Returns the OpenAPI specification of the handlers with the given access level.
+    #[cfg(feature = "openapi")]
+    pub fn openapi_specification(&self, access: ApiAccess) -> serde_json::Value {
+        openapi::specification(access, self.scopes(access))
+    }
+
+    pub(crate) fn scopes<'a>(
+        &'a self,
+        access: ApiAccess,
+    ) -> impl Iterator<Item = (&'a str, &'a ServiceApiScope)> + 'a {
+        self.inner.iter().map(move |(name, builder)| {
+            let scope = match access {
+                ApiAccess::Public => &builder.public_scope,
+                ApiAccess::Private => &builder.private_scope,
+            };
+            (name.as_ref(), scope)
+        })
     }
 
     /// Adds API factory with the given prefix to the aggregator.
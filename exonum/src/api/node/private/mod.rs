@@ -6,10 +6,11 @@
 use std::{collections::HashMap, net::SocketAddr};
 
 use api::{Error as ApiError, ServiceApiScope, ServiceApiState};
-use blockchain::{Service, SharedNodeState};
+use blockchain::{Schema, Service, SharedNodeState};
 use crypto::PublicKey;
+use events::CompressionStatsInfo;
 use messages::PROTOCOL_MAJOR_VERSION;
-use node::{ConnectInfo, ExternalMessage};
+use node::{state::SharedConnectList, ConnectInfo, ExternalMessage, PeerScoreInfo};
 
 /// Short information about the service.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -52,6 +53,33 @@ impl NodeInfo {
     }
 }
 
+/// Role of the peer from the connect list in the current configuration.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PeerRole {
+    /// Peer is a validator.
+    Validator,
+    /// Peer is an auditor.
+    Auditor,
+}
+
+/// Entry of the connect list.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ConnectListEntry {
+    /// Public key of the peer.
+    pub public_key: PublicKey,
+    /// Address of the peer.
+    pub address: String,
+    /// Is the peer pinned?
+    pub pinned: bool,
+    /// Role of the peer.
+    pub role: PeerRole,
+    /// Addresses of the peer in the order in which they are tried.
+    pub addresses: Vec<String>,
+    /// Was the peer discovered outside of the configured connect list?
+    pub discovered: bool,
+}
+
 #[derive(Serialize, Deserialize, Default)]
 struct ReconnectInfo {
     delay: u64,
@@ -80,6 +108,27 @@ struct IncomingConnection {
 struct PeersInfo {
     incoming_connections: Vec<ConnectInfo>,
     outgoing_connections: HashMap<SocketAddr, IncomingConnection>,
+    peer_scores: Vec<PeerScoreInfo>,
+    compression: CompressionInfo,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CompressionInfo {
+    stats: CompressionStatsInfo,
+    /// Compressed size of the sent messages divided by their raw size.
+    sent_ratio: Option<f64>,
+    /// Compressed size of the received messages divided by their raw size.
+    received_ratio: Option<f64>,
+}
+
+impl From<CompressionStatsInfo> for CompressionInfo {
+    fn from(stats: CompressionStatsInfo) -> Self {
+        Self {
+            sent_ratio: stats.sent_ratio(),
+            received_ratio: stats.received_ratio(),
+            stats,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -87,6 +136,38 @@ struct ConsensusEnabledQuery {
     enabled: bool,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct PeerQuery {
+    public_key: PublicKey,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct PeerPinQuery {
+    public_key: PublicKey,
+    pinned: bool,
+}
+
+// Checks that the peer can be modified through the API.
+fn check_peer(
+    connect_list: &SharedConnectList,
+    public_key: &PublicKey,
+    allow_pinned: bool,
+) -> Result<(), ApiError> {
+    if !connect_list.is_peer_allowed(public_key) {
+        return Err(ApiError::NotFound(format!(
+            "Peer {} is not in the connect list",
+            public_key
+        )));
+    }
+    if !allow_pinned && connect_list.is_pinned(public_key) {
+        return Err(ApiError::BadRequest(format!(
+            "Peer {} is pinned",
+            public_key
+        )));
+    }
+    Ok(())
+}
+
 /// Private system API.
 #[derive(Clone, Debug)]
 pub struct SystemApi {
@@ -107,7 +188,13 @@ impl SystemApi {
     pub fn wire(self, api_scope: &mut ServiceApiScope) -> &mut ServiceApiScope {
         self.handle_peers_info("v1/peers", api_scope)
             .handle_peer_add("v1/peers", api_scope)
+            .handle_connect_list("v1/connect_list", api_scope)
+            .handle_peer_add("v1/connect_list/add", api_scope)
+            .handle_peer_remove("v1/connect_list/remove", api_scope)
+            .handle_peer_update("v1/connect_list/update", api_scope)
+            .handle_peer_pin("v1/connect_list/pin", api_scope)
             .handle_network_info("v1/network", api_scope)
+            .handle_rate_limits("v1/rate_limits", api_scope)
             .handle_is_consensus_enabled("v1/consensus_enabled", api_scope)
             .handle_set_consensus_enabled("v1/consensus_enabled", api_scope)
             .handle_shutdown("v1/shutdown", api_scope)
@@ -140,22 +227,114 @@ impl SystemApi {
             Ok(PeersInfo {
                 incoming_connections: self.shared_api_state.incoming_connections(),
                 outgoing_connections,
+                peer_scores: self.shared_api_state.peer_scores(),
+                compression: self.shared_api_state.compression_stats().into(),
             })
         });
         self_
     }
 
     fn handle_peer_add(self, name: &'static str, api_scope: &mut ServiceApiScope) -> Self {
+        let self_ = self.clone();
         api_scope.endpoint_mut(
             name,
             move |state: &ServiceApiState, connect_info: ConnectInfo| {
+                let connect_list = self.shared_api_state.connect_list();
+                if connect_list.is_pinned(&connect_info.public_key) {
+                    return Err(ApiError::BadRequest(format!(
+                        "Peer {} is pinned",
+                        connect_info.public_key
+                    )));
+                }
                 state
                     .sender()
                     .peer_add(connect_info)
                     .map_err(ApiError::from)
             },
         );
-        self
+        self_
+    }
+
+    fn handle_connect_list(self, name: &'static str, api_scope: &mut ServiceApiScope) -> Self {
+        let self_ = self.clone();
+        api_scope.endpoint(name, move |state: &ServiceApiState, _query: ()| {
+            let snapshot = state.snapshot();
+            let validator_keys = Schema::new(&snapshot).actual_configuration().validator_keys;
+            let connect_list = self.shared_api_state.connect_list();
+            let pinned = connect_list.pinned();
+
+            let entries = connect_list
+                .peers()
+                .into_iter()
+                .map(|info| {
+                    let is_validator = validator_keys
+                        .iter()
+                        .any(|keys| keys.consensus_key == info.public_key);
+                    let (addresses, discovered) = connect_list
+                        .find_address_by_key(&info.public_key)
+                        .map_or_else(
+                            || (vec![info.address.clone()], false),
+                            |peer| (peer.addresses_by_priority(), peer.discovered),
+                        );
+                    ConnectListEntry {
+                        addresses,
+                        discovered,
+                        pinned: pinned.contains(&info.public_key),
+                        role: if is_validator {
+                            PeerRole::Validator
+                        } else {
+                            PeerRole::Auditor
+                        },
+                        public_key: info.public_key,
+                        address: info.address,
+                    }
+                })
+                .collect::<Vec<_>>();
+            Ok(entries)
+        });
+        self_
+    }
+
+    fn handle_peer_remove(self, name: &'static str, api_scope: &mut ServiceApiScope) -> Self {
+        let self_ = self.clone();
+        api_scope.endpoint_mut(name, move |state: &ServiceApiState, query: PeerQuery| {
+            let connect_list = self.shared_api_state.connect_list();
+            check_peer(&connect_list, &query.public_key, false)?;
+            state
+                .sender()
+                .peer_remove(query.public_key)
+                .map_err(ApiError::from)
+        });
+        self_
+    }
+
+    fn handle_peer_update(self, name: &'static str, api_scope: &mut ServiceApiScope) -> Self {
+        let self_ = self.clone();
+        api_scope.endpoint_mut(
+            name,
+            move |state: &ServiceApiState, connect_info: ConnectInfo| {
+                let connect_list = self.shared_api_state.connect_list();
+                check_peer(&connect_list, &connect_info.public_key, false)?;
+                state
+                    .sender()
+                    .peer_update(connect_info)
+                    .map_err(ApiError::from)
+            },
+        );
+        self_
+    }
+
+    fn handle_peer_pin(self, name: &'static str, api_scope: &mut ServiceApiScope) -> Self {
+        let self_ = self.clone();
+        api_scope.endpoint_mut(name, move |state: &ServiceApiState, query: PeerPinQuery| {
+            let connect_list = self.shared_api_state.connect_list();
+            check_peer(&connect_list, &query.public_key, true)?;
+            state
+                .sender()
+                .peer_pin(query.public_key, query.pinned)
+                .map_err(ApiError::from)
+        });
+        self_
     }
 
     fn handle_network_info(self, name: &'static str, api_scope: &mut ServiceApiScope) -> Self {
@@ -166,6 +345,14 @@ impl SystemApi {
         self_
     }
 
+    fn handle_rate_limits(self, name: &'static str, api_scope: &mut ServiceApiScope) -> Self {
+        let self_ = self.clone();
+        api_scope.endpoint(name, move |_state: &ServiceApiState, _query: ()| {
+            Ok(self.shared_api_state.throttled_requests())
+        });
+        self_
+    }
+
     fn handle_is_consensus_enabled(
         self,
         name: &'static str,
@@ -1,31 +1,45 @@
 //! Exonum blockchain explorer API.
 
 use actix::Arbiter;
-use actix_web::{http, ws};
+use actix_web::{http, ws, FromRequest, HttpResponse, Query};
+use bytes::Bytes;
 use chrono::{DateTime, Utc};
-use futures::IntoFuture;
+use failure;
+use futures::{stream, IntoFuture};
+#[cfg(feature = "openapi")]
+use schemars::JsonSchema;
 use serde_json;
 
 use std::ops::Range;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
 use api::{
     backends::actix::{self, FutureResponse, HttpRequest, RawHandler, RequestHandler},
+    openapi::EndpointSpec,
+    pagination::{Cursor, Page, PageRequest},
     websocket::{Server, Session},
     Error as ApiError, ServiceApiBackend, ServiceApiScope, ServiceApiState,
 };
-use blockchain::{Block, SharedNodeState};
-use crypto::Hash;
-use explorer::{self, BlockchainExplorer, TransactionInfo};
+use blockchain::{median_precommits_time, Block, Schema, SharedNodeState};
+use crypto::{self, Hash, PublicKey};
+use explorer::{
+    self, archive::ArchiveChunks, BlockchainExplorer, CommittedTransaction, DropReason,
+    DroppedTransaction, TransactionFilter, TransactionInfo, TransactionProof, TxOutcome,
+};
 use helpers::Height;
 use messages::{Message, Precommit, RawTransaction, Signed, SignedMessage};
+use proto::{schema::api as api_proto, ProtobufConvert};
 
 /// The maximum number of blocks to return per blocks request, in this way
 /// the parameter limits the maximum execution time for such requests.
 pub const MAX_BLOCKS_PER_REQUEST: usize = 1000;
 
+/// The maximum number of transactions returned by a transaction search.
+pub const MAX_TRANSACTIONS_PER_PAGE: usize = 100;
+
 /// Information on blocks coupled with the corresponding range in the blockchain.
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(JsonSchema))]
 pub struct BlocksRange {
     /// Exclusive range of blocks.
     pub range: Range<Height>,
@@ -33,10 +47,13 @@ pub struct BlocksRange {
     pub blocks: Vec<Block>,
     /// Optional median time from the corresponding blocks precommits.
     pub times: Option<Vec<DateTime<Utc>>>,
+    /// Cursor of the next page of blocks, or `None` if the genesis block is returned.
+    pub next_cursor: Option<Cursor>,
 }
 
 /// Information about a block in the blockchain.
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(JsonSchema))]
 pub struct BlockInfo {
     /// Block header as recorded in the blockchain.
     pub block: Block,
@@ -50,6 +67,7 @@ pub struct BlockInfo {
 
 /// Blocks in range parameters.
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[cfg_attr(feature = "openapi", derive(JsonSchema))]
 pub struct BlocksQuery {
     /// The number of blocks to return. Should not be greater than `MAX_BLOCKS_PER_REQUEST`.
     pub count: usize,
@@ -57,6 +75,11 @@ pub struct BlocksQuery {
     /// starting from the latest and at least up to the `latest` - `count` + 1.
     /// The default value is the height of the latest block in the blockchain.
     pub latest: Option<Height>,
+    /// Cursor returned in `next_cursor` of the previous page. If set, `latest`
+    /// is ignored and the blocks are returned starting from the cursor. Cursors
+    /// pointing beyond the latest block are rejected.
+    #[serde(default)]
+    pub cursor: Option<Cursor>,
     /// If true, then only non-empty blocks are returned. The default value is false.
     #[serde(default)]
     pub skip_empty_blocks: bool,
@@ -68,6 +91,7 @@ pub struct BlocksQuery {
 
 /// Block query parameters.
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(JsonSchema))]
 pub struct BlockQuery {
     /// The height of the desired block.
     pub height: Height,
@@ -80,8 +104,18 @@ impl BlockQuery {
     }
 }
 
+/// Archive export parameters.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct ArchiveQuery {
+    /// The height of the first exported block.
+    pub from: Height,
+    /// The number of blocks to export. Should not be greater than `MAX_BLOCKS_PER_REQUEST`.
+    pub count: usize,
+}
+
 /// Raw Transaction in hex representation.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(JsonSchema))]
 pub struct TransactionHex {
     /// The hex value of the transaction to be broadcasted.
     pub tx_body: String,
@@ -89,6 +123,7 @@ pub struct TransactionHex {
 
 /// Transaction response.
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(JsonSchema))]
 pub struct TransactionResponse {
     /// The hex value of the transaction to be broadcasted.
     pub tx_hash: Hash,
@@ -96,6 +131,7 @@ pub struct TransactionResponse {
 
 /// Transaction query parameters.
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(JsonSchema))]
 pub struct TransactionQuery {
     /// The hash of the transaction to be searched.
     pub hash: Hash,
@@ -108,6 +144,49 @@ impl TransactionQuery {
     }
 }
 
+/// Transaction search parameters.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[cfg_attr(feature = "openapi", derive(JsonSchema))]
+pub struct TransactionSearchQuery {
+    /// Public key of the transaction author.
+    #[serde(default)]
+    pub author: Option<PublicKey>,
+    /// Identifier of the service the transactions belong to. Either `author`
+    /// or `service_id` should be specified.
+    #[serde(default)]
+    pub service_id: Option<u16>,
+    /// Earliest median precommit time of the blocks containing the transactions,
+    /// inclusive.
+    #[serde(default)]
+    pub since: Option<DateTime<Utc>>,
+    /// Latest median precommit time of the blocks containing the transactions,
+    /// exclusive.
+    #[serde(default)]
+    pub until: Option<DateTime<Utc>>,
+    /// Execution outcome of the transactions.
+    #[serde(default)]
+    pub outcome: Option<TxOutcome>,
+    /// Cursor returned in `next_cursor` of the previous page.
+    #[serde(default)]
+    pub cursor: Option<Cursor>,
+    /// The number of transactions to return. Should not be greater than
+    /// `MAX_TRANSACTIONS_PER_PAGE`, which is the default value.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// Description of a transaction which is neither in the pool nor committed, returned
+/// with the `404 Not Found` status. The node remembers dropped transactions
+/// for a short time only, after which they become `unknown`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MissingTransaction {
+    /// The node has not seen the transaction recently.
+    Unknown,
+    /// The transaction has been dropped by the node.
+    Dropped(DroppedTransaction),
+}
+
 /// Exonum blockchain explorer API.
 #[derive(Debug, Clone, Copy)]
 pub struct ExplorerApi;
@@ -127,21 +206,35 @@ impl ExplorerApi {
             )));
         }
 
-        let (upper, blocks_iter) = if let Some(upper) = query.latest {
-            (upper, explorer.blocks(..upper.next()))
-        } else {
-            (explorer.height(), explorer.blocks(..))
+        let upper = match query.cursor {
+            Some(cursor) if cursor.position() > explorer.height().0 => {
+                return Err(ApiError::BadRequest(format!(
+                    "Cursor {} points beyond the latest block",
+                    cursor
+                )));
+            }
+            Some(cursor) => Height(cursor.position()),
+            None => query.latest.unwrap_or_else(|| explorer.height()),
         };
+        let page = PageRequest::new(query.cursor, query.count).collect(
+            explorer
+                .blocks(..upper.next())
+                .rev()
+                .filter(|block| !query.skip_empty_blocks || !block.is_empty())
+                .map(|block| (block.header().height().0, block)),
+        );
 
         let mut times = Vec::new();
 
-        let blocks: Vec<_> = blocks_iter
-            .rev()
-            .filter(|block| !query.skip_empty_blocks || !block.is_empty())
-            .take(query.count)
+        let blocks: Vec<_> = page
+            .items
+            .into_iter()
             .inspect(|block| {
                 if query.add_blocks_time {
-                    times.push(median_precommits_time(&block.precommits()));
+                    times.push(
+                        median_precommits_time(&block.precommits())
+                            .expect("Precommits cannot be empty"),
+                    );
                 }
             })
             .map(|block| block.into_header())
@@ -161,6 +254,7 @@ impl ExplorerApi {
             } else {
                 None
             },
+            next_cursor: page.next_cursor,
         })
     }
 
@@ -175,36 +269,220 @@ impl ExplorerApi {
     }
 
     /// Searches for a transaction, either committed or uncommitted, by the hash.
+    /// Transactions recently dropped by the node are reported with the reason
+    /// in the `NotFound` error, see `MissingTransaction`.
     pub fn transaction_info(
         state: &ServiceApiState,
+        node_state: &SharedNodeState,
         query: TransactionQuery,
     ) -> Result<TransactionInfo, ApiError> {
         BlockchainExplorer::new(state.blockchain())
             .transaction(&query.hash)
+            .ok_or_else(|| Self::missing_transaction(node_state, &query.hash))
+    }
+
+    fn missing_transaction(node_state: &SharedNodeState, tx_hash: &Hash) -> ApiError {
+        let missing = node_state
+            .dropped_transaction(tx_hash)
+            .map_or(MissingTransaction::Unknown, MissingTransaction::Dropped);
+        let description = serde_json::to_string(&missing).unwrap();
+        debug!("{}", description);
+        ApiError::NotFound(description)
+    }
+    /// Returns the proof that the transaction is committed with its execution result.
+    /// The proof can be verified offline, see `TransactionProof`.
+    pub fn transaction_proof(
+        state: &ServiceApiState,
+        query: TransactionQuery,
+    ) -> Result<TransactionProof, ApiError> {
+        BlockchainExplorer::new(state.blockchain())
+            .transaction_proof(&query.hash)
             .ok_or_else(|| {
-                let description = serde_json::to_string(&json!({ "type": "unknown" })).unwrap();
-                debug!("{}", description);
-                ApiError::NotFound(description)
+                ApiError::NotFound(format!("Transaction {:?} is not committed", query.hash))
             })
     }
+
+    /// Returns a page of the committed transactions matching the query, from
+    /// the newest to the oldest. The explorer indexes should be enabled for the node.
+    /// The page may hold fewer items than requested if the search has examined
+    /// the maximum number of index entries; the search continues with its cursor.
+    pub fn search_transactions(
+        state: &ServiceApiState,
+        query: TransactionSearchQuery,
+    ) -> Result<Page<CommittedTransaction>, ApiError> {
+        if !state.blockchain().explorer_indexes() {
+            return Err(ApiError::NotFound(
+                "Explorer indexes are disabled on this node".to_owned(),
+            ));
+        }
+        let request =
+            PageRequest::with_max_limit(query.cursor, query.limit, MAX_TRANSACTIONS_PER_PAGE)?;
+
+        let filter = TransactionFilter {
+            author: query.author,
+            service_id: query.service_id,
+            since: query.since,
+            until: query.until,
+            outcome: query.outcome,
+        };
+        BlockchainExplorer::new(state.blockchain())
+            .search_transactions(&filter, request)
+            .ok_or_else(|| {
+                ApiError::BadRequest("Either author or service_id should be specified".to_owned())
+            })
+    }
+
     /// Adds transaction into unconfirmed tx pool, and broadcast transaction to other nodes.
+    /// Transactions which cannot be decoded or passed to the node are recorded
+    /// as dropped.
     pub fn add_transaction(
         state: &ServiceApiState,
+        node_state: &SharedNodeState,
         query: TransactionHex,
     ) -> Result<TransactionResponse, ApiError> {
         use events::error::into_failure;
-        use messages::ProtocolMessage;
 
         let buf: Vec<u8> = ::hex::decode(query.tx_body).map_err(into_failure)?;
-        let signed = SignedMessage::from_raw_buffer(buf)?;
-        let tx_hash = signed.hash();
-        let signed = RawTransaction::try_from(Message::deserialize(signed)?)
-            .map_err(|_| format_err!("Couldn't deserialize transaction message."))?;
-        let _ = state
+        let tx_hash = Self::broadcast_transaction(state, node_state, buf)?;
+        Ok(TransactionResponse { tx_hash })
+    }
+
+    fn broadcast_transaction(
+        state: &ServiceApiState,
+        node_state: &SharedNodeState,
+        buf: Vec<u8>,
+    ) -> Result<Hash, ApiError> {
+        let tx_hash = crypto::hash(&buf);
+        let record = |reason: DropReason, e: failure::Error| {
+            node_state.record_dropped_transaction(tx_hash, reason, e.to_string());
+            ApiError::from(e)
+        };
+
+        let signed = Self::parse_transaction(buf).map_err(|e| record(DropReason::Invalid, e))?;
+        state
             .sender()
             .broadcast_transaction(signed)
-            .map_err(ApiError::from);
-        Ok(TransactionResponse { tx_hash })
+            .map_err(|e| record(DropReason::NodeUnavailable, e))?;
+        Ok(tx_hash)
+    }
+
+    fn parse_transaction(buf: Vec<u8>) -> Result<Signed<RawTransaction>, failure::Error> {
+        use messages::ProtocolMessage;
+
+        let signed = SignedMessage::from_raw_buffer(buf)?;
+        RawTransaction::try_from(Message::deserialize(signed)?)
+            .map_err(|_| format_err!("Couldn't deserialize transaction message."))
+    }
+
+    /// Returns the block at the given height. Serves the `GetBlock` gRPC method.
+    pub fn grpc_block(
+        state: &ServiceApiState,
+        request: api_proto::GetBlockRequest,
+    ) -> Result<api_proto::BlockInfo, ApiError> {
+        let info = Self::block(state, BlockQuery::new(Height(request.get_height())))?
+            .ok_or_else(|| ApiError::NotFound("Block not found".to_owned()))?;
+
+        let mut block = api_proto::BlockInfo::new();
+        block.set_block(info.block.to_pb());
+        block.set_precommits(
+            info.precommits
+                .into_iter()
+                .map(|precommit| precommit.serialize())
+                .collect(),
+        );
+        block.set_txs(info.txs.iter().map(ProtobufConvert::to_pb).collect());
+        block.set_time(info.time.to_pb());
+        Ok(block)
+    }
+
+    /// Searches for a transaction, either committed or uncommitted, by the hash.
+    /// Serves the `GetTransaction` gRPC method.
+    pub fn grpc_transaction(
+        state: &ServiceApiState,
+        node_state: &SharedNodeState,
+        mut request: api_proto::GetTransactionRequest,
+    ) -> Result<api_proto::TransactionInfo, ApiError> {
+        let tx_hash =
+            Hash::from_pb(request.take_hash()).map_err(|e| ApiError::BadRequest(e.to_string()))?;
+        let snapshot = state.snapshot();
+        let schema = Schema::new(&snapshot);
+        let message = schema
+            .transactions()
+            .get(&tx_hash)
+            .ok_or_else(|| Self::missing_transaction(node_state, &tx_hash))?;
+
+        let mut info = api_proto::TransactionInfo::new();
+        info.set_message(message.serialize());
+        if schema.transactions_pool().contains(&tx_hash) {
+            info.set_in_pool(true);
+        } else {
+            // Transactions out of the pool are committed.
+            let location = schema.transactions_locations().get(&tx_hash).unwrap();
+            let result = schema.transaction_results().get(&tx_hash).unwrap();
+            info.set_location(location.to_pb());
+            info.set_result(result.to_pb());
+        }
+        Ok(info)
+    }
+
+    /// Broadcasts the signed transaction. Serves the `SendTransaction` gRPC method.
+    pub fn grpc_send_transaction(
+        state: &ServiceApiState,
+        node_state: &SharedNodeState,
+        mut request: api_proto::SendTransactionRequest,
+    ) -> Result<api_proto::SendTransactionResponse, ApiError> {
+        let tx_hash = Self::broadcast_transaction(state, node_state, request.take_tx_body())?;
+        let mut response = api_proto::SendTransactionResponse::new();
+        response.set_tx_hash(tx_hash.to_pb());
+        Ok(response)
+    }
+
+    /// Returns the archive with the committed blocks starting from the given height, in the
+    /// format described in the [`archive`] module, in chunks of one block. The archive is cut
+    /// at the latest block.
+    ///
+    /// [`archive`]: ../../../../explorer/archive/index.html
+    pub fn archive(
+        state: &ServiceApiState,
+        query: ArchiveQuery,
+    ) -> Result<ArchiveChunks, ApiError> {
+        if query.count > MAX_BLOCKS_PER_REQUEST {
+            return Err(ApiError::BadRequest(format!(
+                "Max block count per request exceeded ({})",
+                MAX_BLOCKS_PER_REQUEST
+            )));
+        }
+
+        let to = query
+            .from
+            .0
+            .checked_add(query.count as u64)
+            .map(Height)
+            .ok_or_else(|| ApiError::BadRequest("Block height overflow".to_owned()))?;
+        Ok(ArchiveChunks::new(state.snapshot(), query.from, to))
+    }
+
+    /// Serves the archive as a binary file, which is streamed a block at a time.
+    pub fn handle_archive(name: &'static str, backend: &mut actix::ApiBuilder) {
+        let index = move |request: HttpRequest| -> FutureResponse {
+            let future = Query::from_request(&request, &Default::default())
+                .map(|query: Query<ArchiveQuery>| query.into_inner())
+                .and_then(|query| Self::archive(request.state(), query).map_err(From::from))
+                .map(|chunks| {
+                    let chunks = chunks.map(|chunk| chunk.map(Bytes::from));
+                    HttpResponse::Ok()
+                        .content_type("application/octet-stream")
+                        .streaming(stream::iter_result(chunks))
+                })
+                .into_future();
+            Box::new(future)
+        };
+
+        backend.raw_handler(RequestHandler {
+            name: name.to_owned(),
+            method: http::Method::GET,
+            inner: Arc::from(index) as Arc<RawHandler>,
+        });
     }
 
     /// Subscribes to block commits events.
@@ -214,20 +492,37 @@ impl ExplorerApi {
         service_api_state: ServiceApiState,
         shared_node_state: SharedNodeState,
     ) {
-        let server = Arc::new(Mutex::new(None));
+        Self::handle_websocket(name, backend, service_api_state, shared_node_state, true);
+    }
+
+    /// Subscribes to the events selected by the client, see the `websocket` module
+    /// for the protocol.
+    pub fn handle_events(
+        name: &'static str,
+        backend: &mut actix::ApiBuilder,
+        service_api_state: ServiceApiState,
+        shared_node_state: SharedNodeState,
+    ) {
+        Self::handle_websocket(name, backend, service_api_state, shared_node_state, false);
+    }
+
+    fn handle_websocket(
+        name: &'static str,
+        backend: &mut actix::ApiBuilder,
+        service_api_state: ServiceApiState,
+        shared_node_state: SharedNodeState,
+        legacy: bool,
+    ) {
         let service_api_state = Arc::new(service_api_state);
 
         let index = move |req: HttpRequest| -> FutureResponse {
-            let server = server.clone();
             let service_api_state = service_api_state.clone();
-            let mut address = server.lock().expect("Expected mutex lock");
-            if address.is_none() {
-                *address = Some(Arbiter::start(|_| Server::new(service_api_state)));
+            let node_state = shared_node_state.clone();
+            let address = shared_node_state.broadcast_server_address(|| {
+                Arbiter::start(move |_| Server::new(service_api_state, node_state))
+            });
 
-                shared_node_state.set_broadcast_server_address(address.to_owned().unwrap());
-            }
-
-            Box::new(ws::start(&req, Session::new(address.to_owned().unwrap())).into_future())
+            Box::new(ws::start(&req, Session::new(address, legacy)).into_future())
         };
 
         backend.raw_handler(RequestHandler {
@@ -246,14 +541,91 @@ impl ExplorerApi {
         Self::handle_subscribe(
             "v1/blocks/subscribe",
             api_scope.web_backend(),
+            service_api_state.clone(),
+            shared_node_state.clone(),
+        );
+        Self::handle_events(
+            "v1/ws",
+            api_scope.web_backend(),
             service_api_state,
-            shared_node_state,
+            shared_node_state.clone(),
         );
+        Self::handle_archive("v1/blocks/archive", api_scope.web_backend());
+
+        let node_state = shared_node_state.clone();
+        let get_transaction =
+            move |state: &ServiceApiState, request: api_proto::GetTransactionRequest| {
+                Self::grpc_transaction(state, &node_state, request)
+            };
+        let node_state = shared_node_state.clone();
+        let send_transaction =
+            move |state: &ServiceApiState, request: api_proto::SendTransactionRequest| {
+                Self::grpc_send_transaction(state, &node_state, request)
+            };
+        let node_state = shared_node_state.clone();
+        let transaction_info = move |state: &ServiceApiState, query: TransactionQuery| {
+            Self::transaction_info(state, &node_state, query)
+        };
+        let add_transaction = move |state: &ServiceApiState, query: TransactionHex| {
+            Self::add_transaction(state, &shared_node_state, query)
+        };
+
         api_scope
-            .endpoint("v1/blocks", Self::blocks)
-            .endpoint("v1/block", Self::block)
-            .endpoint("v1/transactions", Self::transaction_info)
-            .endpoint_mut("v1/transactions", Self::add_transaction)
+            .grpc_method("GetBlock", Self::grpc_block)
+            .grpc_method("GetTransaction", get_transaction)
+            .grpc_method_mut("SendTransaction", send_transaction)
+            .endpoint_with_spec(
+                "v1/blocks",
+                EndpointSpec::new()
+                    .description("Returns a page of block headers, starting from the latest block")
+                    .query::<BlocksQuery>()
+                    .response::<BlocksRange>(),
+                Self::blocks,
+            )
+            .endpoint_with_spec(
+                "v1/block",
+                EndpointSpec::new()
+                    .description("Returns the block at the given height")
+                    .query::<BlockQuery>()
+                    .response::<Option<BlockInfo>>(),
+                Self::block,
+            )
+            .endpoint_with_spec(
+                "v1/transactions",
+                EndpointSpec::new()
+                    .description("Searches for a committed or an uncommitted transaction")
+                    .query::<TransactionQuery>()
+                    .response::<TransactionInfo>(),
+                transaction_info,
+            )
+            .endpoint_with_spec(
+                "v1/transactions/search",
+                EndpointSpec::new()
+                    .description(
+                        "Returns a page of committed transactions by the author or the service",
+                    )
+                    .query::<TransactionSearchQuery>()
+                    .response::<Page<CommittedTransaction>>(),
+                Self::search_transactions,
+            )
+            .endpoint_with_spec(
+                "v1/transactions/proof",
+                EndpointSpec::new()
+                    .description(
+                        "Returns a proof that the committed transaction has the given result",
+                    )
+                    .query::<TransactionQuery>()
+                    .response::<TransactionProof>(),
+                Self::transaction_proof,
+            )
+            .endpoint_mut_with_spec(
+                "v1/transactions",
+                EndpointSpec::new()
+                    .description("Broadcasts the signed transaction")
+                    .query::<TransactionHex>()
+                    .response::<TransactionResponse>(),
+                add_transaction,
+            )
     }
 }
 
@@ -263,14 +635,7 @@ impl<'a> From<explorer::BlockInfo<'a>> for BlockInfo {
             block: inner.header().clone(),
             precommits: inner.precommits().to_vec(),
             txs: inner.transaction_hashes().to_vec(),
-            time: median_precommits_time(&inner.precommits()),
+            time: median_precommits_time(&inner.precommits()).expect("Precommits cannot be empty"),
         }
     }
 }
-
-fn median_precommits_time(precommits: &[Signed<Precommit>]) -> DateTime<Utc> {
-    debug_assert!(!precommits.is_empty(), "Precommits cannot be empty");
-    let mut times: Vec<_> = precommits.iter().map(|p| p.time()).collect();
-    times.sort();
-    times[times.len() / 2]
-}
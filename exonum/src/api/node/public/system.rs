@@ -1,11 +1,20 @@
 //! Public system API.
 
-use api::{ServiceApiScope, ServiceApiState};
+use protobuf::well_known_types::Empty;
+#[cfg(feature = "openapi")]
+use schemars::JsonSchema;
+
+use api::{openapi::EndpointSpec, ServiceApiScope, ServiceApiState};
 use blockchain::{Schema, SharedNodeState};
 use helpers::user_agent;
+use proto::schema::api::{
+    HealthCheckInfo as HealthCheckInfoProto, HealthCheckInfo_ConsensusStatus, MempoolInfo,
+    UserAgentInfo,
+};
 
 /// Information about the current state of the node memory pool.
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(JsonSchema))]
 pub struct MemPoolInfo {
     /// Total number of uncommitted transactions.
     pub size: u64,
@@ -13,6 +22,7 @@ pub struct MemPoolInfo {
 
 /// Information about the amount of peers connected to the node.
 #[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(JsonSchema))]
 pub struct PeersAmount {
     /// Amount of connected peers.
     pub amount: usize,
@@ -20,6 +30,7 @@ pub struct PeersAmount {
 
 /// Information about whether the node is connected to other peers.
 #[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(JsonSchema))]
 pub enum ConnectivityStatus {
     /// The node has no connected peers.
     NotConnected,
@@ -30,6 +41,7 @@ pub enum ConnectivityStatus {
 /// Information about whether it is possible to achieve the consensus between
 /// validators in the current state.
 #[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(JsonSchema))]
 pub enum ConsensusStatus {
     /// Consensus disabled on this node.
     Disabled,
@@ -42,6 +54,7 @@ pub enum ConsensusStatus {
 /// Information about whether the node is connected to other peers and
 /// its consensus status.
 #[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(JsonSchema))]
 pub struct HealthCheckInfo {
     /// Consensus status.
     pub consensus_status: ConsensusStatus,
@@ -62,7 +75,10 @@ impl SystemApi {
     }
 
     fn handle_mempool_info(self, name: &'static str, api_scope: &mut ServiceApiScope) -> Self {
-        api_scope.endpoint(name, move |state: &ServiceApiState, _query: ()| {
+        let spec = EndpointSpec::new()
+            .description("Returns the number of transactions in the pool")
+            .response::<MemPoolInfo>();
+        api_scope.endpoint_with_spec(name, spec, move |state: &ServiceApiState, _query: ()| {
             let snapshot = state.snapshot();
             let schema = Schema::new(&snapshot);
             Ok(MemPoolInfo {
@@ -73,7 +89,10 @@ impl SystemApi {
     }
 
     fn handle_user_agent_info(self, name: &'static str, api_scope: &mut ServiceApiScope) -> Self {
-        api_scope.endpoint(name, move |_state: &ServiceApiState, _query: ()| {
+        let spec = EndpointSpec::new()
+            .description("Returns the version of the node software")
+            .response::<String>();
+        api_scope.endpoint_with_spec(name, spec, move |_state: &ServiceApiState, _query: ()| {
             Ok(user_agent::get())
         });
         self
@@ -81,7 +100,10 @@ impl SystemApi {
 
     fn handle_healthcheck_info(self, name: &'static str, api_scope: &mut ServiceApiScope) -> Self {
         let self_ = self.clone();
-        api_scope.endpoint(name, move |_state: &ServiceApiState, _query: ()| {
+        let spec = EndpointSpec::new()
+            .description("Returns the consensus and connectivity status of the node")
+            .response::<HealthCheckInfo>();
+        api_scope.endpoint_with_spec(name, spec, move |_state: &ServiceApiState, _query: ()| {
             Ok(HealthCheckInfo {
                 consensus_status: self.get_consensus_status(),
                 connectivity: self.get_connectivity_status(),
@@ -90,6 +112,44 @@ impl SystemApi {
         self_
     }
 
+    fn handle_grpc_methods(self, api_scope: &mut ServiceApiScope) -> Self {
+        let self_ = self.clone();
+        api_scope
+            .grpc_method("GetMempool", |state: &ServiceApiState, _request: Empty| {
+                let snapshot = state.snapshot();
+                let mut info = MempoolInfo::new();
+                info.set_size(Schema::new(&snapshot).transactions_pool_len());
+                Ok(info)
+            })
+            .grpc_method(
+                "GetUserAgent",
+                |_state: &ServiceApiState, _request: Empty| {
+                    let mut info = UserAgentInfo::new();
+                    info.set_user_agent(user_agent::get());
+                    Ok(info)
+                },
+            )
+            .grpc_method(
+                "GetHealthcheck",
+                move |_state: &ServiceApiState, _request: Empty| {
+                    let consensus_status = match self.get_consensus_status() {
+                        ConsensusStatus::Disabled => HealthCheckInfo_ConsensusStatus::DISABLED,
+                        ConsensusStatus::Enabled => HealthCheckInfo_ConsensusStatus::ENABLED,
+                        ConsensusStatus::Active => HealthCheckInfo_ConsensusStatus::ACTIVE,
+                    };
+                    let connected_peers = match self.get_connectivity_status() {
+                        ConnectivityStatus::NotConnected => 0,
+                        ConnectivityStatus::Connected(peers) => peers.amount as u64,
+                    };
+                    let mut info = HealthCheckInfoProto::new();
+                    info.set_consensus_status(consensus_status);
+                    info.set_connected_peers(connected_peers);
+                    Ok(info)
+                },
+            );
+        self_
+    }
+
     fn get_connectivity_status(&self) -> ConnectivityStatus {
         let in_conn = self.shared_api_state.incoming_connections().len();
         let out_conn = self.shared_api_state.outgoing_connections().len();
@@ -119,7 +179,8 @@ impl SystemApi {
     pub fn wire(self, api_scope: &mut ServiceApiScope) -> &mut ServiceApiScope {
         self.handle_mempool_info("v1/mempool", api_scope)
             .handle_healthcheck_info("v1/healthcheck", api_scope)
-            .handle_user_agent_info("v1/user_agent", api_scope);
+            .handle_user_agent_info("v1/user_agent", api_scope)
+            .handle_grpc_methods(api_scope);
         api_scope
     }
 }
@@ -0,0 +1,341 @@
+//! Machine-readable description of the HTTP API in the [OpenAPI 3] format.
+//!
+//! The specification is built only if the `openapi` feature of the crate is enabled.
+//! Otherwise, the schemas set in an `EndpointSpec` are ignored, and the query and
+//! response types are not required to implement `JsonSchema`.
+//!
+//! Every endpoint added to a `ServiceApiScope` is listed in the specification with
+//! its path and HTTP method. Endpoints added with `endpoint_with_spec` and
+//! `endpoint_mut_with_spec` may also carry a description and the schemas of
+//! their query and response, which are derived from the serde representation
+//! of the types with [`schemars`]:
+//!
+//! ```rust
+//! #[macro_use] extern crate serde_derive;
+//! extern crate exonum;
+//! #[cfg(feature = "openapi")]
+//! extern crate schemars;
+//!
+//! #[cfg(feature = "openapi")]
+//! use schemars::JsonSchema;
+//!
+//! use exonum::api::{self, openapi::EndpointSpec, ServiceApiBuilder, ServiceApiState};
+//!
+//! /// Query for the balance of a wallet.
+//! #[derive(Deserialize)]
+//! #[cfg_attr(feature = "openapi", derive(JsonSchema))]
+//! pub struct BalanceQuery {
+//!     /// Name of the wallet.
+//!     pub name: String,
+//! }
+//!
+//! fn balance(_state: &ServiceApiState, _query: BalanceQuery) -> api::Result<u64> {
+//!     Ok(0)
+//! }
+//!
+//! # fn main() {
+//! let mut builder = ServiceApiBuilder::new();
+//! builder.public_scope().endpoint_with_spec(
+//!     "v1/balance",
+//!     EndpointSpec::new()
+//!         .description("Returns the balance of the wallet")
+//!         .query::<BalanceQuery>()
+//!         .response::<u64>(),
+//!     balance,
+//! );
+//! # }
+//! ```
+//!
+//! `ApiAggregator` serves the specification of the public API and the specification
+//! of the private API, which cover the core endpoints and the endpoints of all
+//! services, at `api/docs/v1/openapi` of the corresponding address. Handlers added
+//! directly to the web backend, such as the WebSocket endpoints, are not included.
+//!
+//! Besides the successful response, operations list the errors the API may return:
+//! `400 Bad Request` for a malformed query, `404 Not Found` and `500 Internal Server
+//! Error`. Operations of the public API may also be throttled with `429 Too Many
+//! Requests`. Operations of the private API may be rejected with `401 Unauthorized`
+//! and `403 Forbidden` when authentication is enabled, and specify the role required
+//! to call them in the `x-exonum-role` field.
+//!
+//! [OpenAPI 3]: https://swagger.io/specification/
+//! [`schemars`]: https://docs.rs/schemars
+
+#[cfg(feature = "openapi")]
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    schema::{ObjectValidation, Schema, SchemaObject},
+    JsonSchema,
+};
+#[cfg(feature = "openapi")]
+use serde_json::{Map, Value};
+
+#[cfg(feature = "openapi")]
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::auth::ApiRole;
+#[cfg(feature = "openapi")]
+use super::{ApiAccess, ServiceApiScope};
+
+/// Prefix of the endpoint serving the specification.
+pub const OPENAPI_PREFIX: &str = "docs";
+/// Name of the endpoint serving the specification.
+pub const OPENAPI_ENDPOINT: &str = "v1/openapi";
+
+#[cfg(feature = "openapi")]
+type SchemaFn = fn(&mut SchemaGenerator) -> Schema;
+
+/// Description of an endpoint in the API specification.
+#[derive(Clone, Default)]
+pub struct EndpointSpec {
+    description: Option<String>,
+    #[cfg(feature = "openapi")]
+    query: Option<SchemaFn>,
+    #[cfg(feature = "openapi")]
+    response: Option<SchemaFn>,
+    pub(crate) role: Option<ApiRole>,
+}
+
+impl EndpointSpec {
+    /// Creates an empty description.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the human-readable description of the endpoint.
+    pub fn description<S: Into<String>>(mut self, description: S) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Sets the type of the query. Fields of the query of an immutable endpoint are
+    /// described as query parameters, the query of a mutable endpoint is described
+    /// as the JSON request body.
+    #[cfg(feature = "openapi")]
+    pub fn query<Q: JsonSchema>(mut self) -> Self {
+        self.query = Some(Q::json_schema as SchemaFn);
+        self
+    }
+
+    /// Sets the type of the query. The type is ignored without the `openapi` feature.
+    #[cfg(not(feature = "openapi"))]
+    pub fn query<Q>(self) -> Self {
+        self
+    }
+
+    /// Sets the type of the response.
+    #[cfg(feature = "openapi")]
+    pub fn response<R: JsonSchema>(mut self) -> Self {
+        self.response = Some(R::json_schema as SchemaFn);
+        self
+    }
+
+    /// Sets the type of the response. The type is ignored without the `openapi` feature.
+    #[cfg(not(feature = "openapi"))]
+    pub fn response<R>(self) -> Self {
+        self
+    }
+
+    /// Sets the role required to call the endpoint of the private API when
+    /// [authentication](../auth/index.html) is enabled. By default, immutable endpoints
+    /// require the `read_only` role and mutable endpoints require the `admin` role.
+    pub fn role(mut self, role: ApiRole) -> Self {
+        self.role = Some(role);
+        self
+    }
+
+    #[cfg(feature = "openapi")]
+    fn operation(&self, tag: &str, mutable: bool, gen: &mut SchemaGenerator) -> Value {
+        let mut operation = json!({ "tags": [tag] });
+        if let Some(ref description) = self.description {
+            operation["description"] = json!(description);
+        }
+        if let Some(query) = self.query {
+            let schema = query(gen);
+            if mutable {
+                operation["requestBody"] = json!({
+                    "required": true,
+                    "content": { "application/json": { "schema": schema } }
+                });
+            } else {
+                operation["parameters"] = Value::Array(query_parameters(schema));
+            }
+        }
+
+        let mut response = json!({ "description": "Successful response" });
+        if let Some(schema) = self.response {
+            response["content"] = json!({ "application/json": { "schema": schema(gen) } });
+        }
+        operation["responses"] = json!({
+            "200": response,
+            "400": { "description": "Malformed query or request body" },
+            "404": { "description": "Requested resource is not found" },
+            "500": { "description": "Internal server error" }
+        });
+        operation
+    }
+}
+
+impl fmt::Debug for EndpointSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EndpointSpec")
+            .field("description", &self.description)
+            .field("role", &self.role)
+            .finish()
+    }
+}
+
+/// Endpoint of a `ServiceApiScope`, as listed in the specification.
+#[derive(Debug, Clone)]
+pub(crate) struct EndpointEntry {
+    pub name: String,
+    pub mutable: bool,
+    pub role: ApiRole,
+    pub spec: EndpointSpec,
+}
+
+/// Describes the fields of an object as query parameters.
+#[cfg(feature = "openapi")]
+fn query_parameters(schema: Schema) -> Vec<Value> {
+    let ObjectValidation {
+        properties,
+        required,
+        ..
+    } = match schema {
+        Schema::Object(SchemaObject {
+            object: Some(object),
+            ..
+        }) => *object,
+        _ => return Vec::new(),
+    };
+    properties
+        .into_iter()
+        .map(|(name, schema)| {
+            let is_required = required.contains(&name);
+            json!({
+                "name": name,
+                "in": "query",
+                "required": is_required,
+                "schema": schema
+            })
+        })
+        .collect()
+}
+
+/// Creates the specification of the endpoints of the given scopes with the given
+/// access level.
+#[cfg(feature = "openapi")]
+pub(crate) fn specification<'a, I>(access: ApiAccess, scopes: I) -> Value
+where
+    I: IntoIterator<Item = (&'a str, &'a ServiceApiScope)>,
+{
+    let mut gen = SchemaGenerator::new(SchemaSettings::openapi3());
+    let mut paths = BTreeMap::new();
+    for (prefix, scope) in scopes {
+        for endpoint in &scope.endpoints {
+            let path = format!("/api/{}/{}", prefix, endpoint.name);
+            let method = if endpoint.mutable { "post" } else { "get" };
+            let mut operation = endpoint.spec.operation(prefix, endpoint.mutable, &mut gen);
+            if access == ApiAccess::Private {
+                operation["x-exonum-role"] = json!(endpoint.role);
+                operation["responses"]["401"] =
+                    json!({ "description": "Missing or invalid request signature" });
+                operation["responses"]["403"] =
+                    json!({ "description": "Role of the request author is not sufficient" });
+            } else {
+                operation["responses"]["429"] = json!({ "description": "Too many requests" });
+            }
+            paths
+                .entry(path)
+                .or_insert_with(Map::new)
+                .insert(method.to_owned(), operation);
+        }
+    }
+
+    json!({
+        "openapi": "3.0.0",
+        "info": {
+            "title": format!("Exonum node {} API", access),
+            "version": env!("CARGO_PKG_VERSION")
+        },
+        "paths": paths,
+        "components": { "schemas": gen.definitions() }
+    })
+}
+
+#[cfg(all(test, feature = "openapi"))]
+mod tests {
+    use super::*;
+    use api::{node::public::explorer::BlocksQuery, Result as ApiResult, ServiceApiState};
+
+    #[test]
+    fn test_specification() {
+        let mut scope = ServiceApiScope::new();
+        scope
+            .endpoint("v1/ping", |_: &ServiceApiState, _: ()| -> ApiResult<()> {
+                Ok(())
+            })
+            .endpoint_with_spec(
+                "v1/blocks",
+                EndpointSpec::new()
+                    .description("Returns blocks")
+                    .query::<BlocksQuery>()
+                    .response::<u64>(),
+                |_: &ServiceApiState, _: BlocksQuery| -> ApiResult<u64> { Ok(0) },
+            )
+            .endpoint_mut_with_spec(
+                "v1/blocks",
+                EndpointSpec::new().query::<BlocksQuery>(),
+                |_: &ServiceApiState, _: BlocksQuery| -> ApiResult<()> { Ok(()) },
+            );
+
+        let spec = specification(ApiAccess::Public, vec![("explorer", &scope)]);
+        assert_eq!(spec["info"]["title"], "Exonum node public API");
+        assert_eq!(
+            spec["paths"]["/api/explorer/v1/ping"]["get"]["responses"]["200"],
+            json!({ "description": "Successful response" })
+        );
+
+        assert!(spec["paths"]["/api/explorer/v1/ping"]["get"]["x-exonum-role"].is_null());
+        let responses = &spec["paths"]["/api/explorer/v1/ping"]["get"]["responses"];
+        for status in &["400", "404", "429", "500"] {
+            assert!(responses[status].is_object());
+        }
+        assert!(responses["403"].is_null());
+
+        let private_spec = specification(ApiAccess::Private, vec![("explorer", &scope)]);
+        let private_blocks = &private_spec["paths"]["/api/explorer/v1/blocks"];
+        assert_eq!(private_blocks["get"]["x-exonum-role"], "read_only");
+        assert_eq!(private_blocks["post"]["x-exonum-role"], "admin");
+        let responses = &private_blocks["get"]["responses"];
+        for status in &["400", "401", "403", "404", "500"] {
+            assert!(responses[status].is_object());
+        }
+        assert!(responses["429"].is_null());
+
+        let blocks = &spec["paths"]["/api/explorer/v1/blocks"];
+        assert_eq!(blocks["get"]["description"], "Returns blocks");
+        let parameters = blocks["get"]["parameters"].as_array().unwrap();
+        let count = parameters
+            .iter()
+            .find(|parameter| parameter["name"] == "count")
+            .unwrap();
+        assert_eq!(count["in"], "query");
+        assert_eq!(count["required"], true);
+        let latest = parameters
+            .iter()
+            .find(|parameter| parameter["name"] == "latest")
+            .unwrap();
+        assert_eq!(latest["required"], false);
+        assert_eq!(
+            blocks["get"]["responses"]["200"]["content"]["application/json"]["schema"]["type"],
+            "integer"
+        );
+        assert!(
+            blocks["post"]["requestBody"]["content"]["application/json"]["schema"]["properties"]
+                ["skip_empty_blocks"]
+                .is_object()
+        );
+    }
+}
@@ -0,0 +1,263 @@
+//! Cursor-based pagination of the lists returned by the API.
+//!
+//! A client requests a page with an optional cursor and a limit on the number
+//! of items, and receives the items together with the cursor of the next page.
+//! Cursors are opaque for clients; internally, a cursor is the position of
+//! the first item of the page in an append-only collection, such as a `ListIndex`,
+//! a `ProofListIndex` or the chain of blocks. Committing new blocks only appends
+//! items to such collections, so a cursor stays valid, and paging through
+//! a collection neither skips nor repeats items.
+//!
+//! # Examples
+//!
+//! ```
+//! use exonum::api::pagination::PageRequest;
+//! use exonum::storage::{Database, ListIndex, MemoryDB};
+//!
+//! let db = MemoryDB::new();
+//! let mut fork = db.fork();
+//! let mut list = ListIndex::new("list", &mut fork);
+//! list.extend(vec![1_u32, 2, 3, 4, 5]);
+//!
+//! let request = PageRequest::new(None, 2);
+//! let page = request.collect((request.start(0)..).zip(list.iter_from(request.start(0))));
+//! assert_eq!(page.items, vec![1, 2]);
+//!
+//! let request = PageRequest::new(page.next_cursor, 2);
+//! let page = request.collect((request.start(0)..).zip(list.iter_from(request.start(0))));
+//! assert_eq!(page.items, vec![3, 4]);
+//! assert!(page.next_cursor.is_some());
+//! ```
+
+use byteorder::{BigEndian, ByteOrder};
+use failure;
+use hex::{self, FromHex};
+#[cfg(feature = "openapi")]
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::{
+    de::{self, Deserialize, Deserializer},
+    ser::{Serialize, Serializer},
+};
+
+use std::{fmt, str::FromStr};
+
+use super::Error as ApiError;
+
+/// Opaque position of a page in a collection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Cursor(u64);
+
+impl Cursor {
+    /// Creates a cursor pointing to the item at the given position.
+    pub fn new(position: u64) -> Self {
+        Cursor(position)
+    }
+
+    /// Returns the position of the item the cursor points to.
+    pub fn position(self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut bytes = [0; 8];
+        BigEndian::write_u64(&mut bytes, self.0);
+        f.write_str(&hex::encode(bytes))
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = Vec::<u8>::from_hex(s)?;
+        ensure!(bytes.len() == 8, "Cursor should be 8 bytes long");
+        Ok(Cursor(BigEndian::read_u64(&bytes)))
+    }
+}
+
+impl Serialize for Cursor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Cursor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse()
+            .map_err(|e| de::Error::custom(format!("Invalid cursor: {}", e)))
+    }
+}
+
+#[cfg(feature = "openapi")]
+impl JsonSchema for Cursor {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        "Cursor".to_owned()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let mut schema = String::json_schema(gen).into_object();
+        schema.metadata().description = Some("Opaque cursor of a page".to_owned());
+        schema.into()
+    }
+}
+
+/// Page of a collection.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(JsonSchema))]
+pub struct Page<T> {
+    /// Items of the page.
+    pub items: Vec<T>,
+    /// Cursor of the next page, or `None` if this page is the last one.
+    pub next_cursor: Option<Cursor>,
+}
+
+/// Request of a page of a collection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PageRequest {
+    cursor: Option<Cursor>,
+    limit: usize,
+}
+
+impl PageRequest {
+    /// Creates a request of at most `limit` items starting at the cursor, or
+    /// at the beginning of the collection if the cursor is not set.
+    pub fn new(cursor: Option<Cursor>, limit: usize) -> Self {
+        Self { cursor, limit }
+    }
+
+    /// Creates a request with the limit set by a client. If the limit is not set,
+    /// `max_limit` is used; zero and greater limits are rejected.
+    pub fn with_max_limit(
+        cursor: Option<Cursor>,
+        limit: Option<usize>,
+        max_limit: usize,
+    ) -> Result<Self, ApiError> {
+        match limit {
+            Some(0) => Err(ApiError::BadRequest(
+                "Page size should be positive".to_owned(),
+            )),
+            Some(limit) if limit > max_limit => Err(ApiError::BadRequest(format!(
+                "Max page size exceeded ({})",
+                max_limit
+            ))),
+            limit => Ok(Self::new(cursor, limit.unwrap_or(max_limit))),
+        }
+    }
+
+    /// Returns the cursor of the requested page.
+    pub fn cursor(&self) -> Option<Cursor> {
+        self.cursor
+    }
+
+    /// Returns the maximum number of items on the page.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Returns the position of the first item of the page, or `first` if
+    /// the cursor is not set.
+    pub fn start(&self, first: u64) -> u64 {
+        self.cursor.map_or(first, Cursor::position)
+    }
+
+    /// Collects the page from the items and their positions. Items must be
+    /// iterated starting from the position returned by `start`, in the order of
+    /// the pages; items filtered out by the caller may be skipped.
+    pub fn collect<I, T>(&self, items: I) -> Page<T>
+    where
+        I: IntoIterator<Item = (u64, T)>,
+    {
+        let mut items = items.into_iter();
+        let page_items = items
+            .by_ref()
+            .take(self.limit)
+            .map(|(_, item)| item)
+            .collect();
+        Page {
+            items: page_items,
+            next_cursor: items.next().map(|(position, _)| Cursor(position)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use storage::{Database, ListIndex, MemoryDB};
+
+    #[test]
+    fn test_cursor_format() {
+        let cursor = Cursor::new(258);
+        assert_eq!(cursor.to_string(), "0000000000000102");
+        assert_eq!("0000000000000102".parse::<Cursor>().unwrap(), cursor);
+        assert!("0102".parse::<Cursor>().is_err());
+
+        let json = ::serde_json::to_string(&cursor).unwrap();
+        assert_eq!(json, "\"0000000000000102\"");
+        assert_eq!(::serde_json::from_str::<Cursor>(&json).unwrap(), cursor);
+    }
+
+    #[test]
+    fn test_pages_are_stable_against_appends() {
+        let db = MemoryDB::new();
+        let mut fork = db.fork();
+        let mut list = ListIndex::new("list", &mut fork);
+        list.extend(0..5_u64);
+
+        let mut items = Vec::new();
+        let mut cursor = None;
+        loop {
+            let request = PageRequest::new(cursor, 2);
+            let start = request.start(0);
+            let page = request.collect((start..).zip(list.iter_from(start)));
+            items.extend(page.items);
+            if items.len() == 2 {
+                // Items appended between the requests are returned on the later pages.
+                list.push(5);
+            }
+            cursor = page.next_cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        assert_eq!(items, (0..6).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_filtered_descending_pages() {
+        let request = PageRequest::new(Some(Cursor::new(8)), 2);
+        let start = request.start(100);
+        let page = request.collect((0..start + 1).rev().filter(|i| i % 3 == 0).map(|i| (i, i)));
+        assert_eq!(page.items, vec![6, 3]);
+        assert_eq!(page.next_cursor, Some(Cursor::new(0)));
+
+        let request = PageRequest::new(page.next_cursor, 2);
+        let start = request.start(100);
+        let page = request.collect((0..start + 1).rev().map(|i| (i, i)));
+        assert_eq!(page.items, vec![0]);
+        assert_eq!(page.next_cursor, None);
+    }
+
+    #[test]
+    fn test_max_limit() {
+        assert_eq!(
+            PageRequest::with_max_limit(None, None, 10).unwrap().limit(),
+            10
+        );
+        assert_eq!(
+            PageRequest::with_max_limit(None, Some(3), 10)
+                .unwrap()
+                .limit(),
+            3
+        );
+        assert!(PageRequest::with_max_limit(None, Some(11), 10).is_err());
+        assert!(PageRequest::with_max_limit(None, Some(0), 10).is_err());
+    }
+}
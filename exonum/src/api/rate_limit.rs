@@ -0,0 +1,511 @@
+//! Rate limiting of the public API.
+//!
+//! Requests are divided into two budgets: read requests (`GET` and other methods
+//! which do not modify the node state) and transaction submission (`POST`). Each
+//! budget may limit the requests of a single client IP address and the requests
+//! of all clients together. Limits are token buckets: a client may send `burst`
+//! requests at once and then `requests_per_second` requests on average.
+//!
+//! Throttled requests are answered with `429 Too Many Requests` and the
+//! `Retry-After` header containing the number of seconds after which the request
+//! may succeed. Throttled gRPC requests are rejected with the `RESOURCE_EXHAUSTED`
+//! status. The numbers of throttled requests are available at
+//! `api/system/v1/rate_limits` of the private API.
+
+use actix_web::{
+    http::{header, Method},
+    middleware::{Middleware, Started},
+    HttpRequest, HttpResponse, Result as ActixResult,
+};
+
+use std::{
+    collections::HashMap,
+    mem,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use super::ServiceApiState;
+use blockchain::SharedNodeState;
+
+/// Maximum number of client addresses tracked by a budget. When it is exceeded,
+/// the least recently seen address is forgotten.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// Limit of the request rate.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Quota {
+    /// Average number of requests per second.
+    pub requests_per_second: u32,
+    /// Maximum number of requests sent at once.
+    pub burst: u32,
+}
+
+impl Quota {
+    fn capacity(self) -> f64 {
+        f64::from(self.burst.max(1))
+    }
+
+    fn rate(self) -> f64 {
+        f64::from(self.requests_per_second)
+    }
+}
+
+/// Limits of a budget of requests. Absent limits are not enforced.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct BudgetConfig {
+    /// Limit for a single client IP address.
+    #[serde(default)]
+    pub per_ip: Option<Quota>,
+    /// Limit for all clients together.
+    #[serde(default)]
+    pub global: Option<Quota>,
+}
+
+/// Rate limits of the public API.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    /// Limits of the read requests.
+    #[serde(default)]
+    pub read: BudgetConfig,
+    /// Limits of the transaction submission.
+    #[serde(default)]
+    pub submit: BudgetConfig,
+}
+
+/// Budget of requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Budget {
+    /// Requests reading the blockchain state.
+    Read,
+    /// Requests submitting transactions.
+    Submit,
+}
+
+impl Budget {
+    fn of(method: &Method) -> Self {
+        if *method == Method::POST {
+            Budget::Submit
+        } else {
+            Budget::Read
+        }
+    }
+}
+
+/// Numbers of the requests throttled since the start of the node.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ThrottledRequestsInfo {
+    /// Throttled read requests.
+    pub read: u64,
+    /// Throttled transaction submissions.
+    pub submit: u64,
+}
+
+impl ThrottledRequestsInfo {
+    pub(crate) fn record(&mut self, budget: Budget) {
+        match budget {
+            Budget::Read => self.read += 1,
+            Budget::Submit => self.submit += 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn full(quota: Quota, now: Instant) -> Self {
+        Self {
+            tokens: quota.capacity(),
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, quota: Quota, now: Instant) {
+        if now > self.updated {
+            let elapsed = now - self.updated;
+            let elapsed = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+            self.tokens = (self.tokens + elapsed * quota.rate()).min(quota.capacity());
+            self.updated = now;
+        }
+    }
+
+    /// Returns the time after which a token will be available, or `None` if it is
+    /// available now.
+    fn wait_time(&mut self, quota: Quota, now: Instant) -> Option<Duration> {
+        self.refill(quota, now);
+        if self.tokens >= 1.0 {
+            return None;
+        }
+        let seconds = if quota.requests_per_second > 0 {
+            (1.0 - self.tokens) / quota.rate()
+        } else {
+            60.0
+        };
+        Some(Duration::from_millis((seconds * 1000.0).ceil() as u64))
+    }
+
+    fn take(&mut self) {
+        self.tokens -= 1.0;
+    }
+}
+
+#[derive(Debug)]
+struct ClientEntry {
+    client: IpAddr,
+    bucket: TokenBucket,
+    /// More recently seen neighbor.
+    prev: Option<usize>,
+    /// Less recently seen neighbor.
+    next: Option<usize>,
+}
+
+/// Buckets of the client addresses with a bounded number of entries. The entries
+/// form a list from the most to the least recently seen client, so the client to
+/// be forgotten is found in constant time.
+#[derive(Debug)]
+struct ClientBuckets {
+    positions: HashMap<IpAddr, usize>,
+    entries: Vec<ClientEntry>,
+    head: Option<usize>,
+    tail: Option<usize>,
+    capacity: usize,
+}
+
+impl Default for ClientBuckets {
+    fn default() -> Self {
+        Self::new(MAX_TRACKED_CLIENTS)
+    }
+}
+
+impl ClientBuckets {
+    fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "Capacity should be positive");
+        Self {
+            positions: HashMap::new(),
+            entries: Vec::new(),
+            head: None,
+            tail: None,
+            capacity,
+        }
+    }
+
+    /// Returns the bucket of the client, inserting a full bucket if the client is
+    /// not tracked. The least recently seen client is forgotten if the capacity
+    /// is exceeded.
+    fn get_or_insert(&mut self, client: IpAddr, quota: Quota, now: Instant) -> &mut TokenBucket {
+        let position = match self.positions.get(&client).cloned() {
+            Some(position) => {
+                self.unlink(position);
+                position
+            }
+            None => {
+                let entry = ClientEntry {
+                    client,
+                    bucket: TokenBucket::full(quota, now),
+                    prev: None,
+                    next: None,
+                };
+                let position = if self.entries.len() < self.capacity {
+                    self.entries.push(entry);
+                    self.entries.len() - 1
+                } else {
+                    let position = self.tail.expect("Buckets without entries");
+                    self.unlink(position);
+                    let forgotten = mem::replace(&mut self.entries[position], entry);
+                    self.positions.remove(&forgotten.client);
+                    position
+                };
+                self.positions.insert(client, position);
+                position
+            }
+        };
+        self.push_front(position);
+        &mut self.entries[position].bucket
+    }
+
+    fn unlink(&mut self, position: usize) {
+        let (prev, next) = {
+            let entry = &mut self.entries[position];
+            (entry.prev.take(), entry.next.take())
+        };
+        match prev {
+            Some(prev) => self.entries[prev].next = next,
+            None => self.head = next,
+        }
+        match next {
+            Some(next) => self.entries[next].prev = prev,
+            None => self.tail = prev,
+        }
+    }
+
+    fn push_front(&mut self, position: usize) {
+        self.entries[position].next = self.head;
+        match self.head {
+            Some(head) => self.entries[head].prev = Some(position),
+            None => self.tail = Some(position),
+        }
+        self.head = Some(position);
+    }
+}
+
+#[derive(Debug, Default)]
+struct BudgetState {
+    global: Option<TokenBucket>,
+    per_ip: ClientBuckets,
+}
+
+impl BudgetState {
+    /// Takes a token for the request, or returns the time after which the request
+    /// may be retried.
+    fn check(
+        &mut self,
+        config: BudgetConfig,
+        client: Option<IpAddr>,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        let BudgetState {
+            ref mut global,
+            ref mut per_ip,
+        } = *self;
+        let mut global = match config.global {
+            Some(quota) => Some((
+                quota,
+                global.get_or_insert_with(|| TokenBucket::full(quota, now)),
+            )),
+            None => None,
+        };
+        let mut per_ip = match (config.per_ip, client) {
+            (Some(quota), Some(client)) => Some((quota, per_ip.get_or_insert(client, quota, now))),
+            _ => None,
+        };
+
+        let wait_time = global
+            .iter_mut()
+            .chain(per_ip.iter_mut())
+            .filter_map(|&mut (quota, ref mut bucket)| bucket.wait_time(quota, now))
+            .max();
+        if let Some(wait_time) = wait_time {
+            return Err(wait_time);
+        }
+        for (_, bucket) in global.into_iter().chain(per_ip) {
+            bucket.take();
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+struct LimiterState {
+    read: BudgetState,
+    submit: BudgetState,
+}
+
+/// Middleware enforcing the rate limits of the public API.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    state: Arc<Mutex<LimiterState>>,
+    node_state: SharedNodeState,
+}
+
+impl RateLimiter {
+    /// Creates a rate limiter which records the throttled requests in the node state.
+    pub fn new(config: RateLimitConfig, node_state: SharedNodeState) -> Self {
+        Self {
+            config,
+            state: Arc::default(),
+            node_state,
+        }
+    }
+
+    /// Takes a token for the request of the client, or returns the time after
+    /// which the request may be retried.
+    pub(crate) fn check(&self, budget: Budget, client: Option<IpAddr>) -> Result<(), Duration> {
+        self.check_at(budget, client, Instant::now())
+    }
+
+    fn check_at(
+        &self,
+        budget: Budget,
+        client: Option<IpAddr>,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        let mut state = self.state.lock().expect("Rate limiter lock");
+        let result = match budget {
+            Budget::Read => state.read.check(self.config.read, client, now),
+            Budget::Submit => state.submit.check(self.config.submit, client, now),
+        };
+        if result.is_err() {
+            self.node_state.record_throttled_request(budget);
+            metric!("api.throttled_requests", 1);
+        }
+        result
+    }
+}
+
+impl Middleware<ServiceApiState> for RateLimiter {
+    fn start(&self, request: &HttpRequest<ServiceApiState>) -> ActixResult<Started> {
+        let budget = Budget::of(request.method());
+        let client = request.peer_addr().map(|address| address.ip());
+        match self.check(budget, client) {
+            Ok(()) => Ok(Started::Done),
+            Err(wait_time) => {
+                let retry_after = wait_time.as_secs() + u64::from(wait_time.subsec_nanos() > 0);
+                let response = HttpResponse::TooManyRequests()
+                    .header(header::RETRY_AFTER, retry_after.max(1).to_string())
+                    .body("Too many requests");
+                Ok(Started::Response(response))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(config: RateLimitConfig) -> RateLimiter {
+        RateLimiter::new(config, SharedNodeState::new(1000))
+    }
+
+    fn quota(requests_per_second: u32, burst: u32) -> Option<Quota> {
+        Some(Quota {
+            requests_per_second,
+            burst,
+        })
+    }
+
+    #[test]
+    fn test_per_ip_limit() {
+        let limiter = limiter(RateLimitConfig {
+            read: BudgetConfig {
+                per_ip: quota(2, 3),
+                global: None,
+            },
+            ..Default::default()
+        });
+        let alice = Some("10.0.0.1".parse().unwrap());
+        let bob = Some("10.0.0.2".parse().unwrap());
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            assert_eq!(limiter.check_at(Budget::Read, alice, start), Ok(()));
+        }
+        assert_eq!(
+            limiter.check_at(Budget::Read, alice, start),
+            Err(Duration::from_millis(500))
+        );
+        // Other clients and other budgets are not affected.
+        assert_eq!(limiter.check_at(Budget::Read, bob, start), Ok(()));
+        assert_eq!(limiter.check_at(Budget::Submit, alice, start), Ok(()));
+
+        let later = start + Duration::from_millis(500);
+        assert_eq!(limiter.check_at(Budget::Read, alice, later), Ok(()));
+        assert!(limiter.check_at(Budget::Read, alice, later).is_err());
+
+        assert_eq!(
+            limiter.node_state.throttled_requests(),
+            ThrottledRequestsInfo { read: 2, submit: 0 }
+        );
+    }
+
+    #[test]
+    fn test_global_limit() {
+        let limiter = limiter(RateLimitConfig {
+            submit: BudgetConfig {
+                per_ip: quota(10, 10),
+                global: quota(1, 2),
+            },
+            ..Default::default()
+        });
+        let start = Instant::now();
+        let clients: Vec<IpAddr> = vec!["10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap()];
+
+        assert_eq!(
+            limiter.check_at(Budget::Submit, Some(clients[0]), start),
+            Ok(())
+        );
+        assert_eq!(
+            limiter.check_at(Budget::Submit, Some(clients[1]), start),
+            Ok(())
+        );
+        assert_eq!(
+            limiter.check_at(Budget::Submit, None, start),
+            Err(Duration::from_secs(1))
+        );
+        assert_eq!(
+            limiter.node_state.throttled_requests(),
+            ThrottledRequestsInfo { read: 0, submit: 1 }
+        );
+    }
+
+    #[test]
+    fn test_throttled_request_does_not_consume_tokens() {
+        let limiter = limiter(RateLimitConfig {
+            read: BudgetConfig {
+                per_ip: quota(1, 5),
+                global: quota(1, 1),
+            },
+            ..Default::default()
+        });
+        let client = Some("10.0.0.1".parse().unwrap());
+        let start = Instant::now();
+
+        assert_eq!(limiter.check_at(Budget::Read, client, start), Ok(()));
+        for _ in 0..10 {
+            assert!(limiter.check_at(Budget::Read, client, start).is_err());
+        }
+        // The per-IP bucket still has tokens after the global limit is lifted.
+        let later = start + Duration::from_secs(1);
+        assert_eq!(limiter.check_at(Budget::Read, client, later), Ok(()));
+    }
+
+    #[test]
+    fn test_least_recently_seen_client_is_forgotten() {
+        let quota = quota(1, 2).unwrap();
+        let clients: Vec<IpAddr> = (1..4)
+            .map(|i| format!("10.0.0.{}", i).parse().unwrap())
+            .collect();
+        let start = Instant::now();
+        let mut buckets = ClientBuckets::new(2);
+
+        buckets.get_or_insert(clients[0], quota, start).take();
+        buckets.get_or_insert(clients[1], quota, start).take();
+        // The first client is seen again, so the second one is forgotten.
+        buckets.get_or_insert(clients[0], quota, start).take();
+        buckets.get_or_insert(clients[2], quota, start);
+
+        assert_eq!(buckets.entries.len(), 2);
+        assert!(!buckets.positions.contains_key(&clients[1]));
+        assert!(buckets
+            .get_or_insert(clients[0], quota, start)
+            .wait_time(quota, start)
+            .is_some());
+        // The forgotten client starts with a full bucket.
+        assert_eq!(
+            buckets.get_or_insert(clients[1], quota, start).tokens,
+            quota.capacity()
+        );
+        assert!(!buckets.positions.contains_key(&clients[2]));
+    }
+
+    #[test]
+    fn test_config_format() {
+        let config: RateLimitConfig = ::toml::from_str(
+            r#"
+            [read.per_ip]
+            requests_per_second = 20
+            burst = 40
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.read.per_ip, quota(20, 40));
+        assert_eq!(config.read.global, None);
+        assert_eq!(config.submit, BudgetConfig::default());
+    }
+}
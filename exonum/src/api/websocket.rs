@@ -1,100 +1,918 @@
 //! WebSocket API.
+//!
+//! The explorer provides two WebSocket endpoints:
+//!
+//! - `api/explorer/v1/blocks/subscribe` sends the header of each committed block
+//!   as a JSON object. Messages from the client are ignored.
+//! - `api/explorer/v1/ws` sends notifications on the subscriptions made by the
+//!   client with `IncomingMessage`s.
+//!
+//! All messages of the `v1/ws` endpoint are JSON objects with the `type` field.
+//! A client subscribes to events with a filter, which is one of
+//!
+//! - `{ "type": "blocks" }`: committed blocks;
+//! - `{ "type": "transaction", "tx_hash": "..." }`: the commit of the transaction
+//!   with the given hash; the subscription is removed after the notification;
+//! - `{ "type": "transactions", "service_id": 128, "transaction_id": 0 }`: committed
+//!   transactions, optionally filtered by the service and the transaction type;
+//! - `{ "type": "pool", "service_id": 128 }`: transactions admitted to the pool of
+//!   unconfirmed transactions, with the same optional filters;
+//! - `{ "type": "dropped" }`: transactions dropped by the node, see below.
+//!
+//! For example, the client sends
+//!
+//! ```text
+//! { "type": "subscribe", "filter": { "type": "transactions", "service_id": 128 } }
+//! ```
+//!
+//! and the server acknowledges the subscription with
+//! `{ "type": "subscribed", "id": 1 }`. The notifications on the subscription contain
+//! its `subscription_id`, for example:
+//!
+//! ```text
+//! {
+//!   "type": "transaction",
+//!   "subscription_id": 1,
+//!   "tx_hash": "...",
+//!   "service_id": 128,
+//!   "transaction_id": 0,
+//!   "location": { "block_height": 5, "position_in_block": 0 },
+//!   "status": { "type": "success" }
+//! }
+//! ```
+//!
+//! `{ "type": "unsubscribe", "id": 1 }` removes the subscription. Malformed requests
+//! are answered with `{ "type": "error", "description": "..." }`.
+//!
+//! Clients may also submit signed transactions as hex strings, in the same format
+//! as for the `v1/transactions` endpoint:
+//!
+//! ```text
+//! { "type": "transaction", "tx_body": "..." }
+//! ```
+//!
+//! A valid transaction is passed to the node, and the server replies with
+//! `{ "type": "transaction_accepted", "tx_hash": "...", "subscription_id": 2 }`.
+//! The reply subscribes the client to the commit of the transaction, so the
+//! `transaction` notification with its location, including the height of the block,
+//! and its execution status follows once the transaction is committed. These
+//! subscriptions do not count against the limit of subscriptions of a client. They are
+//! removed with `{ "type": "unsubscribed", "id": 2 }` if the transaction is not
+//! committed within 10 minutes. Invalid
+//! transactions are answered with
+//! `{ "type": "transaction_rejected", "tx_hash": "...", "description": "..." }`,
+//! where `tx_hash` is `null` if the transaction cannot be decoded.
+//!
+//! Transactions which are dropped by the node instead of being admitted to the pool
+//! are announced with
+//!
+//! ```text
+//! {
+//!   "type": "transaction_dropped",
+//!   "subscription_id": 3,
+//!   "tx_hash": "...",
+//!   "reason": "invalid",
+//!   "description": "..."
+//! }
+//! ```
+//!
+//! to the `dropped` subscriptions and to the subscriptions to the commit of the dropped
+//! transaction. The latter are removed after the notification. The reasons are
+//! described by [`DropReason`].
+//!
+//! [`DropReason`]: ../../explorer/dropped/enum.DropReason.html
+//!
+//! Notifications for a client which does not keep up with them are dropped. The server
+//! keeps track of the messages written to the connection of each client until they are
+//! flushed to its socket, and drops notifications while these messages take more than
+//! 1 MiB. The client is told how many notifications it has missed with a `lagged`
+//! message once it catches up. A client which misses too many notifications in a row
+//! is disconnected.
 
 use actix::*;
 use actix_web::ws;
 use serde_json;
 
+use failure;
 use rand::{self, rngs::ThreadRng, Rng};
 
-use std::{cell::RefCell, collections::HashMap, sync::Arc};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use api::ServiceApiState;
-use blockchain::Schema;
-use crypto::Hash;
+use blockchain::{Block, Schema, SharedNodeState, TransactionResult, TxLocation};
+use crypto::{self, Hash};
+use explorer::{DropReason, DroppedTransaction, TxStatus};
+use messages::{self, ProtocolMessage, RawTransaction, Signed};
+use storage::Snapshot;
 
-/// WebSocket message for communication between clients(`Session`) and server(`Server`).
+/// Maximum number of subscriptions of a single client.
+const MAX_SUBSCRIPTIONS: usize = 64;
+/// Maximum number of the submitted transactions a single client waits for.
+const MAX_PENDING_TRANSACTIONS: usize = 1024;
+/// Time in seconds a client waits for the commit of a submitted transaction.
+const PENDING_TRANSACTION_TIMEOUT: u64 = 600;
+/// Interval in seconds between checks of the pending transactions.
+const PENDING_TRANSACTIONS_CHECK_INTERVAL: u64 = 10;
+/// Number of notifications in a row a client may miss before it is disconnected.
+const MAX_MISSED_NOTIFICATIONS: usize = 256;
+/// Maximum size of the messages written to the connection of a client but not yet
+/// flushed to its socket, in bytes.
+const MAX_PENDING_BYTES: usize = 1 << 20;
+
+/// Request of a client of the `v1/ws` endpoint.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum IncomingMessage {
+    /// Subscribes to the events matching the filter.
+    Subscribe {
+        /// Filter of the events.
+        filter: SubscriptionType,
+    },
+    /// Removes the subscription with the given identifier.
+    Unsubscribe {
+        /// Identifier of the subscription.
+        id: u64,
+    },
+    /// Submits a signed transaction and subscribes to its commit.
+    Transaction {
+        /// Hex of the serialized signed transaction.
+        tx_body: String,
+    },
+}
+
+/// Events a client can subscribe to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SubscriptionType {
+    /// Committed blocks.
+    Blocks,
+    /// Commit of the transaction with the given hash.
+    Transaction {
+        /// Hash of the transaction.
+        tx_hash: Hash,
+    },
+    /// Committed transactions.
+    Transactions(TransactionFilter),
+    /// Transactions admitted to the pool of unconfirmed transactions.
+    Pool(TransactionFilter),
+    /// Transactions dropped by the node.
+    Dropped,
+}
+
+/// Filter of transactions by their type. Empty fields match any transaction.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TransactionFilter {
+    /// Identifier of the service.
+    pub service_id: Option<u16>,
+    /// Identifier of the transaction within the service. Requires `service_id`.
+    pub transaction_id: Option<u16>,
+}
+
+impl TransactionFilter {
+    fn matches(&self, service_id: u16, transaction_id: u16) -> bool {
+        self.service_id.map_or(true, |id| id == service_id)
+            && self.transaction_id.map_or(true, |id| id == transaction_id)
+    }
+}
+
+/// Message sent to a client of the `v1/ws` endpoint.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OutgoingMessage {
+    /// The subscription has been made.
+    Subscribed {
+        /// Identifier of the subscription.
+        id: u64,
+    },
+    /// The subscription has been removed.
+    Unsubscribed {
+        /// Identifier of the subscription.
+        id: u64,
+    },
+    /// The request of the client is invalid.
+    Error {
+        /// Description of the error.
+        description: String,
+    },
+    /// The client has missed notifications because it has not kept up with them.
+    Lagged {
+        /// Number of the missed notifications.
+        missed: usize,
+    },
+    /// A block has been committed.
+    Block {
+        /// Identifier of the subscription.
+        subscription_id: u64,
+        /// Header of the block.
+        block: Block,
+    },
+    /// A transaction has been committed.
+    Transaction {
+        /// Identifier of the subscription.
+        subscription_id: u64,
+        /// Hash of the transaction.
+        tx_hash: Hash,
+        /// Identifier of the service.
+        service_id: u16,
+        /// Identifier of the transaction within the service.
+        transaction_id: u16,
+        /// Location of the transaction in the blockchain.
+        location: TxLocation,
+        /// Result of the transaction execution.
+        #[serde(with = "TxStatus")]
+        status: TransactionResult,
+    },
+    /// A transaction has been admitted to the pool.
+    PoolAdmission {
+        /// Identifier of the subscription.
+        subscription_id: u64,
+        /// Hash of the transaction.
+        tx_hash: Hash,
+        /// Identifier of the service.
+        service_id: u16,
+        /// Identifier of the transaction within the service.
+        transaction_id: u16,
+    },
+    /// A transaction has been dropped by the node.
+    TransactionDropped {
+        /// Identifier of the subscription.
+        subscription_id: u64,
+        /// Hash of the transaction.
+        tx_hash: Hash,
+        /// Reason why the transaction has been dropped.
+        reason: DropReason,
+        /// Description of the error.
+        description: String,
+    },
+    /// The submitted transaction has been passed to the node.
+    TransactionAccepted {
+        /// Hash of the transaction.
+        tx_hash: Hash,
+        /// Identifier of the subscription to the commit of the transaction.
+        subscription_id: u64,
+    },
+    /// The submitted transaction is invalid or cannot be passed to the node.
+    TransactionRejected {
+        /// Hash of the transaction, if it could be decoded.
+        tx_hash: Option<Hash>,
+        /// Description of the error.
+        description: String,
+    },
+}
+
+/// Text sent from the server to the client of a session.
 #[derive(Message, Debug)]
 pub(crate) struct Message(pub String);
 
 #[derive(Message)]
 #[rtype(usize)]
-pub(crate) struct Subscribe {
+pub(crate) struct Connect {
     pub address: Recipient<Message>,
+    pub legacy: bool,
 }
 
 #[derive(Message)]
-pub(crate) struct Unsubscribe {
+pub(crate) struct Disconnect {
     pub id: usize,
 }
 
+#[derive(Message)]
+pub(crate) struct Request {
+    pub id: usize,
+    pub message: IncomingMessage,
+}
+
 #[derive(Message)]
 pub(crate) struct Broadcast {
     pub block_hash: Hash,
 }
 
+#[derive(Message)]
+pub(crate) struct PoolAdmission {
+    pub tx_hash: Hash,
+    pub service_id: u16,
+    pub transaction_id: u16,
+}
+
+#[derive(Message)]
+pub(crate) struct TransactionDropped(pub DroppedTransaction);
+
+/// Committed transaction, as seen by the subscriptions.
+#[derive(Debug, Clone, Copy)]
+struct TransactionEvent<'a> {
+    tx_hash: Hash,
+    service_id: u16,
+    transaction_id: u16,
+    location: &'a TxLocation,
+    status: &'a TransactionResult,
+}
+
+/// Subscriptions of a single client.
+#[derive(Debug, Default)]
+struct Subscriptions {
+    items: BTreeMap<u64, SubscriptionType>,
+    /// Deadlines of the subscriptions to the commit of the submitted transactions.
+    pending: BTreeMap<u64, Instant>,
+    last_id: u64,
+}
+
+impl Subscriptions {
+    fn subscribe(&mut self, filter: SubscriptionType) -> Result<u64, String> {
+        if self.items.len() - self.pending.len() >= MAX_SUBSCRIPTIONS {
+            return Err(format!(
+                "The number of subscriptions is limited to {}",
+                MAX_SUBSCRIPTIONS
+            ));
+        }
+        if let SubscriptionType::Transactions(ref tx_filter)
+        | SubscriptionType::Pool(ref tx_filter) = filter
+        {
+            if tx_filter.transaction_id.is_some() && tx_filter.service_id.is_none() {
+                return Err("`transaction_id` requires `service_id`".to_owned());
+            }
+        }
+
+        self.last_id += 1;
+        self.items.insert(self.last_id, filter);
+        Ok(self.last_id)
+    }
+
+    /// Subscribes to the commit of the submitted transaction until the deadline.
+    fn watch(&mut self, tx_hash: Hash, deadline: Instant) -> Result<u64, String> {
+        if self.pending.len() >= MAX_PENDING_TRANSACTIONS {
+            return Err(format!(
+                "The number of pending transactions is limited to {}",
+                MAX_PENDING_TRANSACTIONS
+            ));
+        }
+        self.last_id += 1;
+        self.items
+            .insert(self.last_id, SubscriptionType::Transaction { tx_hash });
+        self.pending.insert(self.last_id, deadline);
+        Ok(self.last_id)
+    }
+
+    fn remove(&mut self, id: u64) -> Option<SubscriptionType> {
+        self.pending.remove(&id);
+        self.items.remove(&id)
+    }
+
+    fn unsubscribe(&mut self, id: u64) -> Result<(), String> {
+        self.remove(id)
+            .map(drop)
+            .ok_or_else(|| format!("Unknown subscription {}", id))
+    }
+
+    /// Removes the subscriptions to the submitted transactions which have not been
+    /// committed before their deadlines and returns the notifications on the removal.
+    fn expire(&mut self, now: Instant) -> Vec<OutgoingMessage> {
+        let expired = self
+            .pending
+            .iter()
+            .filter(|&(_, &deadline)| deadline <= now)
+            .map(|(&id, _)| id)
+            .collect::<Vec<_>>();
+        expired
+            .into_iter()
+            .map(|id| {
+                self.remove(id);
+                OutgoingMessage::Unsubscribed { id }
+            })
+            .collect()
+    }
+
+    fn has_transaction_subscriptions(&self) -> bool {
+        self.items.values().any(|filter| match *filter {
+            SubscriptionType::Transaction { .. } | SubscriptionType::Transactions(_) => true,
+            _ => false,
+        })
+    }
+
+    /// Returns the notifications on the committed block. Subscriptions to single
+    /// transactions included in the block are removed.
+    fn on_block(&mut self, block: &Block, txs: &[TransactionEvent]) -> Vec<OutgoingMessage> {
+        let mut notifications = Vec::new();
+        let mut completed = Vec::new();
+        for (&subscription_id, filter) in &self.items {
+            match *filter {
+                SubscriptionType::Blocks => notifications.push(OutgoingMessage::Block {
+                    subscription_id,
+                    block: block.clone(),
+                }),
+                SubscriptionType::Transaction { ref tx_hash } => {
+                    if let Some(tx) = txs.iter().find(|tx| tx.tx_hash == *tx_hash) {
+                        notifications.push(tx.notification(subscription_id));
+                        completed.push(subscription_id);
+                    }
+                }
+                SubscriptionType::Transactions(ref tx_filter) => notifications.extend(
+                    txs.iter()
+                        .filter(|tx| tx_filter.matches(tx.service_id, tx.transaction_id))
+                        .map(|tx| tx.notification(subscription_id)),
+                ),
+                SubscriptionType::Pool(_) | SubscriptionType::Dropped => {}
+            }
+        }
+        for id in completed {
+            self.remove(id);
+        }
+        notifications
+    }
+
+    fn on_pool_admission(&self, event: &PoolAdmission) -> Vec<OutgoingMessage> {
+        self.items
+            .iter()
+            .filter_map(|(&subscription_id, filter)| match *filter {
+                SubscriptionType::Pool(ref tx_filter)
+                    if tx_filter.matches(event.service_id, event.transaction_id) =>
+                {
+                    Some(OutgoingMessage::PoolAdmission {
+                        subscription_id,
+                        tx_hash: event.tx_hash,
+                        service_id: event.service_id,
+                        transaction_id: event.transaction_id,
+                    })
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the notifications on the dropped transaction. Subscriptions to the commit
+    /// of the transaction are removed.
+    fn on_dropped(&mut self, tx: &DroppedTransaction) -> Vec<OutgoingMessage> {
+        let mut notifications = Vec::new();
+        let mut completed = Vec::new();
+        for (&subscription_id, filter) in &self.items {
+            match *filter {
+                SubscriptionType::Transaction { ref tx_hash } if *tx_hash == tx.tx_hash => {
+                    completed.push(subscription_id);
+                }
+                SubscriptionType::Dropped => {}
+                _ => continue,
+            }
+            notifications.push(OutgoingMessage::TransactionDropped {
+                subscription_id,
+                tx_hash: tx.tx_hash,
+                reason: tx.reason,
+                description: tx.description.clone(),
+            });
+        }
+        for id in completed {
+            self.remove(id);
+        }
+        notifications
+    }
+}
+
+impl<'a> TransactionEvent<'a> {
+    fn notification(&self, subscription_id: u64) -> OutgoingMessage {
+        OutgoingMessage::Transaction {
+            subscription_id,
+            tx_hash: self.tx_hash,
+            service_id: self.service_id,
+            transaction_id: self.transaction_id,
+            location: self.location.clone(),
+            status: self.status.clone(),
+        }
+    }
+}
+
+/// Client of the server.
+struct Subscriber {
+    address: Recipient<Message>,
+    /// Clients of the `v1/blocks/subscribe` endpoint receive block headers only.
+    legacy: bool,
+    subscriptions: Subscriptions,
+}
+
 pub(crate) struct Server {
-    pub subscribers: HashMap<usize, Recipient<Message>>,
+    subscribers: HashMap<usize, Subscriber>,
     service_api_state: Arc<ServiceApiState>,
+    node_state: SharedNodeState,
     rng: RefCell<ThreadRng>,
 }
 
 impl Server {
-    pub fn new(service_api_state: Arc<ServiceApiState>) -> Self {
+    pub fn new(service_api_state: Arc<ServiceApiState>, node_state: SharedNodeState) -> Self {
         Self {
             subscribers: HashMap::new(),
             service_api_state,
+            node_state,
             rng: RefCell::new(rand::thread_rng()),
         }
     }
+
+    fn send_all(&mut self, id: usize, notifications: &[OutgoingMessage]) {
+        for notification in notifications {
+            let text = serde_json::to_string(notification).expect("Cannot serialize message");
+            self.send(id, text);
+        }
+    }
+
+    /// Returns the subscriptions of a connected client.
+    fn subscriptions(&mut self, id: usize) -> &mut Subscriptions {
+        &mut self
+            .subscribers
+            .get_mut(&id)
+            .expect("Unknown subscriber")
+            .subscriptions
+    }
+
+    fn subscribe(&mut self, id: usize, filter: SubscriptionType) -> Vec<OutgoingMessage> {
+        let subscription = self.subscriptions(id).subscribe(filter);
+        match subscription {
+            Ok(subscription_id) => {
+                let mut notifications = vec![OutgoingMessage::Subscribed {
+                    id: subscription_id,
+                }];
+                notifications.extend(self.committed_notification(id, subscription_id));
+                notifications
+            }
+            Err(description) => vec![OutgoingMessage::Error { description }],
+        }
+    }
+
+    /// Checks whether the transaction the subscription waits for has been committed
+    /// before the subscription. If so, removes the subscription and returns
+    /// the notification on the commit.
+    fn committed_notification(
+        &mut self,
+        id: usize,
+        subscription_id: u64,
+    ) -> Option<OutgoingMessage> {
+        let snapshot = self.service_api_state.snapshot();
+        let schema = Schema::new(&snapshot);
+        let subscriptions = self.subscriptions(id);
+        let tx_hash = match subscriptions.items[&subscription_id] {
+            SubscriptionType::Transaction { tx_hash } => tx_hash,
+            _ => return None,
+        };
+        let tx = committed_transaction(&schema, tx_hash)?;
+        subscriptions.remove(subscription_id);
+        Some(tx.event().notification(subscription_id))
+    }
+
+    /// Passes the transaction submitted by the client to the node and subscribes
+    /// the client to its commit.
+    fn submit_transaction(&mut self, id: usize, tx_body: &str) -> Vec<OutgoingMessage> {
+        let buffer = match ::hex::decode(tx_body) {
+            Ok(buffer) => buffer,
+            Err(e) => {
+                return vec![OutgoingMessage::TransactionRejected {
+                    tx_hash: None,
+                    description: format!("Invalid transaction hex: {}", e),
+                }]
+            }
+        };
+        let tx_hash = crypto::hash(&buffer);
+        let rejected = move |description: String| OutgoingMessage::TransactionRejected {
+            tx_hash: Some(tx_hash),
+            description,
+        };
+
+        let tx = match self.parse_transaction(buffer) {
+            Ok(tx) => tx,
+            Err(e) => {
+                let description = e.to_string();
+                self.node_state.record_dropped_transaction(
+                    tx_hash,
+                    DropReason::Invalid,
+                    description.clone(),
+                );
+                return vec![rejected(description)];
+            }
+        };
+        let deadline = Instant::now() + Duration::from_secs(PENDING_TRANSACTION_TIMEOUT);
+        let subscription = self.subscriptions(id).watch(tx_hash, deadline);
+        let subscription_id = match subscription {
+            Ok(subscription_id) => subscription_id,
+            Err(description) => return vec![rejected(description)],
+        };
+        let accepted = OutgoingMessage::TransactionAccepted {
+            tx_hash,
+            subscription_id,
+        };
+        if let Some(notification) = self.committed_notification(id, subscription_id) {
+            return vec![accepted, notification];
+        }
+        if let Err(e) = self.service_api_state.sender().broadcast_transaction(tx) {
+            self.subscriptions(id).remove(subscription_id);
+            let description = format!("Cannot pass the transaction to the node: {}", e);
+            self.node_state.record_dropped_transaction(
+                tx_hash,
+                DropReason::NodeUnavailable,
+                description.clone(),
+            );
+            return vec![rejected(description)];
+        }
+        vec![accepted]
+    }
+
+    /// Verifies the signature of the transaction and checks that it can be executed
+    /// by one of the services.
+    fn parse_transaction(&self, buffer: Vec<u8>) -> Result<Signed<RawTransaction>, failure::Error> {
+        let message = messages::Message::from_raw_buffer(buffer)?;
+        let tx = RawTransaction::try_from(message)
+            .map_err(|_| format_err!("The message is not a transaction"))?;
+        self.service_api_state
+            .blockchain()
+            .tx_from_raw(tx.payload().clone())?;
+        Ok(tx)
+    }
+
+    /// Stops waiting for the submitted transactions which have not been committed in time.
+    fn expire_pending_transactions(&mut self) {
+        let now = Instant::now();
+        let notifications = self
+            .subscribers
+            .iter_mut()
+            .map(|(&id, subscriber)| (id, subscriber.subscriptions.expire(now)))
+            .filter(|&(_, ref notifications)| !notifications.is_empty())
+            .collect::<Vec<_>>();
+        for (id, notifications) in notifications {
+            self.send_all(id, &notifications);
+        }
+    }
+
+    /// Passes the text to the session of the client. The session drops the notifications
+    /// the client does not keep up with.
+    fn send(&mut self, id: usize, text: String) {
+        let closed = match self.subscribers.get(&id) {
+            Some(subscriber) => subscriber.address.do_send(Message(text)).is_err(),
+            None => return,
+        };
+        if closed {
+            self.subscribers.remove(&id);
+        }
+    }
 }
 
 impl Actor for Server {
     type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        let interval = Duration::from_secs(PENDING_TRANSACTIONS_CHECK_INTERVAL);
+        ctx.run_interval(interval, |server, _| server.expire_pending_transactions());
+    }
 }
 
-impl Handler<Subscribe> for Server {
+impl Handler<Connect> for Server {
     type Result = usize;
 
-    fn handle(&mut self, Subscribe { address }: Subscribe, _ctx: &mut Self::Context) -> usize {
+    fn handle(&mut self, Connect { address, legacy }: Connect, _ctx: &mut Self::Context) -> usize {
         let id = self.rng.borrow_mut().gen::<usize>();
-        self.subscribers.insert(id, address);
+        let mut subscriptions = Subscriptions::default();
+        if legacy {
+            subscriptions
+                .subscribe(SubscriptionType::Blocks)
+                .expect("Cannot subscribe to blocks");
+        }
+        self.subscribers.insert(
+            id,
+            Subscriber {
+                address,
+                legacy,
+                subscriptions,
+            },
+        );
 
         id
     }
 }
 
-impl Handler<Unsubscribe> for Server {
+impl Handler<Disconnect> for Server {
     type Result = ();
 
-    fn handle(&mut self, Unsubscribe { id }: Unsubscribe, _ctx: &mut Self::Context) {
+    fn handle(&mut self, Disconnect { id }: Disconnect, _ctx: &mut Self::Context) {
         self.subscribers.remove(&id);
     }
 }
 
+impl Handler<Request> for Server {
+    type Result = ();
+
+    fn handle(&mut self, Request { id, message }: Request, _ctx: &mut Self::Context) {
+        match self.subscribers.get(&id) {
+            Some(subscriber) if !subscriber.legacy => {}
+            _ => return,
+        }
+        let notifications = match message {
+            IncomingMessage::Subscribe { filter } => self.subscribe(id, filter),
+            IncomingMessage::Unsubscribe {
+                id: subscription_id,
+            } => {
+                let response = self
+                    .subscriptions(id)
+                    .unsubscribe(subscription_id)
+                    .map(|()| OutgoingMessage::Unsubscribed {
+                        id: subscription_id,
+                    })
+                    .unwrap_or_else(|description| OutgoingMessage::Error { description });
+                vec![response]
+            }
+            IncomingMessage::Transaction { tx_body } => self.submit_transaction(id, &tx_body),
+        };
+        self.send_all(id, &notifications);
+    }
+}
+
 impl Handler<Broadcast> for Server {
     type Result = ();
 
     fn handle(&mut self, Broadcast { block_hash }: Broadcast, _ctx: &mut Self::Context) {
         let snapshot = self.service_api_state.snapshot();
-        let schema = Schema::new(snapshot);
-        let block_header = schema.blocks().get(&block_hash);
-        let block_header_json = serde_json::to_value(block_header).unwrap().to_string();
-        for address in self.subscribers.values() {
-            let _ = address.do_send(Message(block_header_json.clone()));
+        let schema = Schema::new(&snapshot);
+        let block = match schema.blocks().get(&block_hash) {
+            Some(block) => block,
+            None => return,
+        };
+
+        let needs_transactions = self
+            .subscribers
+            .values()
+            .any(|subscriber| subscriber.subscriptions.has_transaction_subscriptions());
+        let txs = if needs_transactions {
+            schema
+                .block_transactions(block.height())
+                .iter()
+                .filter_map(|tx_hash| committed_transaction(&schema, tx_hash))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let events = txs
+            .iter()
+            .map(CommittedTransaction::event)
+            .collect::<Vec<_>>();
+
+        let header = serde_json::to_string(&block).expect("Cannot serialize block");
+        let ids = self.subscribers.keys().cloned().collect::<Vec<_>>();
+        for id in ids {
+            let (legacy, notifications) = {
+                let subscriber = self.subscribers.get_mut(&id).unwrap();
+                let notifications = subscriber.subscriptions.on_block(&block, &events);
+                (subscriber.legacy, notifications)
+            };
+            if legacy {
+                self.send(id, header.clone());
+            } else {
+                self.send_all(id, &notifications);
+            }
         }
     }
 }
 
+impl Handler<PoolAdmission> for Server {
+    type Result = ();
+
+    fn handle(&mut self, event: PoolAdmission, _ctx: &mut Self::Context) {
+        let notifications = self
+            .subscribers
+            .iter()
+            .map(|(&id, subscriber)| (id, subscriber.subscriptions.on_pool_admission(&event)))
+            .filter(|&(_, ref notifications)| !notifications.is_empty())
+            .collect::<Vec<_>>();
+        for (id, notifications) in notifications {
+            self.send_all(id, &notifications);
+        }
+    }
+}
+
+impl Handler<TransactionDropped> for Server {
+    type Result = ();
+
+    fn handle(&mut self, TransactionDropped(tx): TransactionDropped, _ctx: &mut Self::Context) {
+        let notifications = self
+            .subscribers
+            .iter_mut()
+            .filter(|&(_, ref subscriber)| !subscriber.legacy)
+            .map(|(&id, subscriber)| (id, subscriber.subscriptions.on_dropped(&tx)))
+            .filter(|&(_, ref notifications)| !notifications.is_empty())
+            .collect::<Vec<_>>();
+        for (id, notifications) in notifications {
+            self.send_all(id, &notifications);
+        }
+    }
+}
+
+/// Committed transaction read from the blockchain.
+struct CommittedTransaction {
+    tx_hash: Hash,
+    service_id: u16,
+    transaction_id: u16,
+    location: TxLocation,
+    status: TransactionResult,
+}
+
+impl CommittedTransaction {
+    fn event(&self) -> TransactionEvent {
+        TransactionEvent {
+            tx_hash: self.tx_hash,
+            service_id: self.service_id,
+            transaction_id: self.transaction_id,
+            location: &self.location,
+            status: &self.status,
+        }
+    }
+}
+
+fn committed_transaction<T: AsRef<dyn Snapshot>>(
+    schema: &Schema<T>,
+    tx_hash: Hash,
+) -> Option<CommittedTransaction> {
+    let location = schema.transactions_locations().get(&tx_hash)?;
+    let status = schema.transaction_results().get(&tx_hash)?;
+    let tx = schema.transactions().get(&tx_hash)?;
+    Some(CommittedTransaction {
+        tx_hash,
+        service_id: tx.payload().service_id(),
+        transaction_id: tx.payload().transaction_id(),
+        location,
+        status,
+    })
+}
+
 pub(crate) struct Session {
     pub id: usize,
     pub server_address: Addr<Server>,
+    legacy: bool,
+    /// Size of the messages written to the connection but not yet flushed to the socket.
+    pending_bytes: usize,
+    /// Number of notifications missed in a row.
+    missed: usize,
 }
 
 impl Session {
-    pub fn new(server_address: Addr<Server>) -> Self {
+    /// Creates a session of the `v1/ws` endpoint, or of the `v1/blocks/subscribe`
+    /// endpoint if `legacy` is set.
+    pub fn new(server_address: Addr<Server>, legacy: bool) -> Self {
         Self {
             id: 0,
             server_address,
+            legacy,
+            pending_bytes: 0,
+            missed: 0,
+        }
+    }
+
+    /// Sends the text to the client unless the messages waiting to be flushed to its
+    /// socket take too much space. A client which has missed notifications is told about
+    /// them first, and a client which misses too many of them in a row is disconnected.
+    fn send(&mut self, text: String, ctx: &mut <Self as Actor>::Context) {
+        if self.pending_bytes + text.len() > MAX_PENDING_BYTES {
+            self.missed += 1;
+            if self.missed > MAX_MISSED_NOTIFICATIONS {
+                ctx.close(Some(ws::CloseReason {
+                    code: ws::CloseCode::Policy,
+                    description: Some("Too many missed notifications".to_owned()),
+                }));
+                ctx.stop();
+            }
+            return;
+        }
+        if self.missed > 0 && !self.legacy {
+            let lagged = OutgoingMessage::Lagged {
+                missed: self.missed,
+            };
+            let lagged = serde_json::to_string(&lagged).expect("Cannot serialize message");
+            self.write(lagged, ctx);
+        }
+        self.missed = 0;
+        self.write(text, ctx);
+    }
+
+    /// Writes the text to the connection and keeps track of it until it is flushed.
+    fn write(&mut self, text: String, ctx: &mut <Self as Actor>::Context) {
+        let len = text.len();
+        self.pending_bytes += len;
+        ctx.text(text);
+        ctx.drain()
+            .map(move |(), session, _| session.pending_bytes -= len)
+            .spawn(ctx);
+    }
+
+    fn handle_text(&mut self, text: &str, ctx: &mut <Self as Actor>::Context) {
+        if self.legacy {
+            return;
+        }
+        match serde_json::from_str::<IncomingMessage>(text) {
+            Ok(message) => self.server_address.do_send(Request {
+                id: self.id,
+                message,
+            }),
+            Err(e) => {
+                let error = OutgoingMessage::Error {
+                    description: format!("Invalid request: {}", e),
+                };
+                let error = serde_json::to_string(&error).expect("Cannot serialize message");
+                self.send(error, ctx);
+            }
         }
     }
 }
@@ -105,8 +923,9 @@ impl Actor for Session {
     fn started(&mut self, ctx: &mut Self::Context) {
         let address: Addr<_> = ctx.address();
         self.server_address
-            .send(Subscribe {
+            .send(Connect {
                 address: address.clone().recipient(),
+                legacy: self.legacy,
             })
             .into_actor(self)
             .then(|response, actor, context| {
@@ -122,7 +941,7 @@ impl Actor for Session {
     }
 
     fn stopping(&mut self, _ctx: &mut <Self as Actor>::Context) -> Running {
-        self.server_address.do_send(Unsubscribe { id: self.id });
+        self.server_address.do_send(Disconnect { id: self.id });
         Running::Stop
     }
 }
@@ -130,8 +949,8 @@ impl Actor for Session {
 impl Handler<Message> for Session {
     type Result = ();
 
-    fn handle(&mut self, msg: Message, ctx: &mut Self::Context) {
-        ctx.text(msg.0);
+    fn handle(&mut self, Message(text): Message, ctx: &mut Self::Context) {
+        self.send(text, ctx);
     }
 }
 
@@ -139,6 +958,7 @@ impl StreamHandler<ws::Message, ws::ProtocolError> for Session {
     fn handle(&mut self, msg: ws::Message, ctx: &mut Self::Context) {
         match msg {
             ws::Message::Ping(msg) => ctx.pong(&msg),
+            ws::Message::Text(text) => self.handle_text(&text, ctx),
             ws::Message::Close(_) => {
                 ctx.stop();
             }
@@ -146,3 +966,415 @@ impl StreamHandler<ws::Message, ws::ProtocolError> for Session {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{server::HttpServer, App};
+    use futures::sync::mpsc;
+
+    use std::{
+        io::{Read, Write},
+        net::{SocketAddr, TcpStream},
+        panic::{self, AssertUnwindSafe},
+        thread,
+        time::Duration,
+    };
+
+    use super::*;
+    use blockchain::{Blockchain, TransactionError};
+    use crypto::{gen_keypair, hash};
+    use helpers::{Height, ValidatorId};
+    use node::ApiSender;
+    use storage::MemoryDB;
+
+    fn block() -> Block {
+        Block::new(
+            ValidatorId(0),
+            Height(5),
+            2,
+            &hash(&[0]),
+            &hash(&[1]),
+            &hash(&[2]),
+        )
+    }
+
+    #[test]
+    fn test_protocol_format() {
+        let request: IncomingMessage = serde_json::from_str(
+            r#"{ "type": "subscribe", "filter": { "type": "pool", "service_id": 128 } }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            request,
+            IncomingMessage::Subscribe {
+                filter: SubscriptionType::Pool(TransactionFilter {
+                    service_id: Some(128),
+                    transaction_id: None,
+                }),
+            }
+        );
+        let request: IncomingMessage =
+            serde_json::from_str(r#"{ "type": "unsubscribe", "id": 3 }"#).unwrap();
+        assert_eq!(request, IncomingMessage::Unsubscribe { id: 3 });
+
+        let response = OutgoingMessage::Subscribed { id: 1 };
+        assert_eq!(
+            serde_json::to_value(&response).unwrap(),
+            json!({ "type": "subscribed", "id": 1 })
+        );
+
+        let request: IncomingMessage =
+            serde_json::from_str(r#"{ "type": "transaction", "tx_body": "00ff" }"#).unwrap();
+        assert_eq!(
+            request,
+            IncomingMessage::Transaction {
+                tx_body: "00ff".to_owned(),
+            }
+        );
+        let response = OutgoingMessage::TransactionAccepted {
+            tx_hash: hash(&[0]),
+            subscription_id: 2,
+        };
+        assert_eq!(
+            serde_json::to_value(&response).unwrap(),
+            json!({ "type": "transaction_accepted", "tx_hash": hash(&[0]), "subscription_id": 2 })
+        );
+        let response = OutgoingMessage::TransactionRejected {
+            tx_hash: None,
+            description: "Invalid transaction hex".to_owned(),
+        };
+        assert_eq!(
+            serde_json::to_value(&response).unwrap(),
+            json!({
+                "type": "transaction_rejected",
+                "tx_hash": null,
+                "description": "Invalid transaction hex"
+            })
+        );
+    }
+
+    #[test]
+    fn test_subscription_errors() {
+        let mut subscriptions = Subscriptions::default();
+        let filter = SubscriptionType::Transactions(TransactionFilter {
+            service_id: None,
+            transaction_id: Some(1),
+        });
+        assert!(subscriptions.subscribe(filter).is_err());
+        assert!(subscriptions.unsubscribe(1).is_err());
+
+        for i in 0..MAX_SUBSCRIPTIONS {
+            assert_eq!(
+                subscriptions.subscribe(SubscriptionType::Blocks),
+                Ok(i as u64 + 1)
+            );
+        }
+        assert!(subscriptions.subscribe(SubscriptionType::Blocks).is_err());
+        assert_eq!(subscriptions.unsubscribe(1), Ok(()));
+        assert!(subscriptions.subscribe(SubscriptionType::Blocks).is_ok());
+    }
+
+    #[test]
+    fn test_pending_transactions() {
+        let mut subscriptions = Subscriptions::default();
+        let now = Instant::now();
+        let deadline = now + Duration::from_secs(PENDING_TRANSACTION_TIMEOUT);
+        for _ in 0..MAX_SUBSCRIPTIONS - 1 {
+            subscriptions.subscribe(SubscriptionType::Blocks).unwrap();
+        }
+        let first = subscriptions.watch(hash(&[0]), now).unwrap();
+        for i in 1..MAX_PENDING_TRANSACTIONS {
+            subscriptions.watch(hash(&[i as u8]), deadline).unwrap();
+        }
+        assert!(subscriptions.watch(hash(&[1]), deadline).is_err());
+        // Pending transactions do not count against the limit of subscriptions.
+        assert!(subscriptions.subscribe(SubscriptionType::Blocks).is_ok());
+        assert!(subscriptions.subscribe(SubscriptionType::Blocks).is_err());
+
+        assert_eq!(
+            subscriptions.expire(now),
+            vec![OutgoingMessage::Unsubscribed { id: first }]
+        );
+        assert!(subscriptions.unsubscribe(first).is_err());
+        assert!(subscriptions.expire(now).is_empty());
+        let second = subscriptions.watch(hash(&[0]), deadline).unwrap();
+        assert_eq!(
+            subscriptions.expire(deadline).len(),
+            MAX_PENDING_TRANSACTIONS
+        );
+        assert!(subscriptions.unsubscribe(second).is_err());
+        assert!(subscriptions.watch(hash(&[0]), deadline).is_ok());
+    }
+
+    #[test]
+    fn test_block_notifications() {
+        let mut subscriptions = Subscriptions::default();
+        let blocks = subscriptions.subscribe(SubscriptionType::Blocks).unwrap();
+        let single = subscriptions
+            .subscribe(SubscriptionType::Transaction {
+                tx_hash: hash(&[10]),
+            })
+            .unwrap();
+        let by_type = subscriptions
+            .subscribe(SubscriptionType::Transactions(TransactionFilter {
+                service_id: Some(128),
+                transaction_id: Some(1),
+            }))
+            .unwrap();
+        subscriptions
+            .subscribe(SubscriptionType::Pool(TransactionFilter::default()))
+            .unwrap();
+        assert!(subscriptions.has_transaction_subscriptions());
+
+        let locations = [TxLocation::new(Height(5), 0), TxLocation::new(Height(5), 1)];
+        let success = TransactionResult(Ok(()));
+        let failure = TransactionResult(Err(TransactionError::code(1, None)));
+        let txs = [
+            TransactionEvent {
+                tx_hash: hash(&[10]),
+                service_id: 128,
+                transaction_id: 0,
+                location: &locations[0],
+                status: &success,
+            },
+            TransactionEvent {
+                tx_hash: hash(&[11]),
+                service_id: 128,
+                transaction_id: 1,
+                location: &locations[1],
+                status: &failure,
+            },
+        ];
+
+        let notifications = subscriptions.on_block(&block(), &txs);
+        assert_eq!(
+            notifications,
+            vec![
+                OutgoingMessage::Block {
+                    subscription_id: blocks,
+                    block: block(),
+                },
+                txs[0].notification(single),
+                txs[1].notification(by_type),
+            ]
+        );
+        // The subscription to the single transaction is complete.
+        let notifications = subscriptions.on_block(&block(), &txs);
+        assert_eq!(notifications.len(), 2);
+        assert!(subscriptions.unsubscribe(single).is_err());
+
+        let json = serde_json::to_value(&txs[1].notification(by_type)).unwrap();
+        assert_eq!(
+            json["status"],
+            json!({ "type": "error", "code": 1, "description": "" })
+        );
+    }
+
+    #[test]
+    fn test_pool_notifications() {
+        let mut subscriptions = Subscriptions::default();
+        let all = subscriptions
+            .subscribe(SubscriptionType::Pool(TransactionFilter::default()))
+            .unwrap();
+        let by_service = subscriptions
+            .subscribe(SubscriptionType::Pool(TransactionFilter {
+                service_id: Some(1),
+                transaction_id: None,
+            }))
+            .unwrap();
+
+        let event = PoolAdmission {
+            tx_hash: hash(&[1]),
+            service_id: 2,
+            transaction_id: 0,
+        };
+        let notifications = subscriptions.on_pool_admission(&event);
+        assert_eq!(
+            notifications,
+            vec![OutgoingMessage::PoolAdmission {
+                subscription_id: all,
+                tx_hash: hash(&[1]),
+                service_id: 2,
+                transaction_id: 0,
+            }]
+        );
+
+        let event = PoolAdmission {
+            service_id: 1,
+            ..event
+        };
+        let ids = subscriptions
+            .on_pool_admission(&event)
+            .into_iter()
+            .map(|notification| match notification {
+                OutgoingMessage::PoolAdmission {
+                    subscription_id, ..
+                } => subscription_id,
+                other => panic!("Unexpected notification {:?}", other),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![all, by_service]);
+        assert!(subscriptions.on_block(&block(), &[]).is_empty());
+    }
+
+    #[test]
+    fn test_dropped_notifications() {
+        let mut subscriptions = Subscriptions::default();
+        let dropped = subscriptions.subscribe(SubscriptionType::Dropped).unwrap();
+        let single = subscriptions
+            .subscribe(SubscriptionType::Transaction {
+                tx_hash: hash(&[1]),
+            })
+            .unwrap();
+        subscriptions
+            .subscribe(SubscriptionType::Transaction {
+                tx_hash: hash(&[2]),
+            })
+            .unwrap();
+
+        let tx = DroppedTransaction {
+            tx_hash: hash(&[1]),
+            reason: DropReason::Invalid,
+            description: "Invalid signature".to_owned(),
+            time: ::chrono::Utc::now(),
+        };
+        let notification = |subscription_id| OutgoingMessage::TransactionDropped {
+            subscription_id,
+            tx_hash: hash(&[1]),
+            reason: DropReason::Invalid,
+            description: "Invalid signature".to_owned(),
+        };
+        assert_eq!(
+            subscriptions.on_dropped(&tx),
+            vec![notification(dropped), notification(single)]
+        );
+        // The subscription to the commit of the dropped transaction is complete.
+        assert_eq!(subscriptions.on_dropped(&tx), vec![notification(dropped)]);
+        assert!(subscriptions.unsubscribe(single).is_err());
+
+        assert_eq!(
+            serde_json::to_value(&notification(dropped)).unwrap(),
+            json!({
+                "type": "transaction_dropped",
+                "subscription_id": dropped,
+                "tx_hash": hash(&[1]),
+                "reason": "invalid",
+                "description": "Invalid signature"
+            })
+        );
+    }
+
+    fn api_state() -> ServiceApiState {
+        let (public_key, secret_key) = gen_keypair();
+        let api_channel = mpsc::channel(1);
+        let blockchain = Blockchain::new(
+            MemoryDB::new(),
+            Vec::new(),
+            public_key,
+            secret_key,
+            ApiSender::new(api_channel.0),
+        );
+        ServiceApiState::new(blockchain)
+    }
+
+    /// Reads from the stream until the received bytes contain the pattern.
+    fn read_until(stream: &mut TcpStream, received: &mut Vec<u8>, pattern: &[u8]) {
+        let mut chunk = [0; 4096];
+        let mut start = 0;
+        while !received[start..]
+            .windows(pattern.len())
+            .any(|window| window == pattern)
+        {
+            start = received.len().saturating_sub(pattern.len());
+            let len = stream.read(&mut chunk).expect("Cannot read from socket");
+            assert!(len > 0, "Connection closed");
+            received.extend_from_slice(&chunk[..len]);
+        }
+    }
+
+    /// Connects to the `v1/ws` endpoint and subscribes to the pool. The client then stops
+    /// reading its socket until the server has sent the notifications on `count`
+    /// pool admissions. Returns everything the client has received.
+    fn flood_client(address: SocketAddr, server: &Addr<Server>, count: usize) -> Vec<u8> {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(30)))
+            .unwrap();
+        stream
+            .write_all(
+                b"GET /ws HTTP/1.1\r\n\
+                  Host: localhost\r\n\
+                  Upgrade: websocket\r\n\
+                  Connection: Upgrade\r\n\
+                  Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+                  Sec-WebSocket-Version: 13\r\n\r\n",
+            )
+            .unwrap();
+        let mut received = Vec::new();
+        read_until(&mut stream, &mut received, b"\r\n\r\n");
+
+        // Frames of a client are masked; the zero mask leaves the payload intact.
+        let request = br#"{ "type": "subscribe", "filter": { "type": "pool" } }"#;
+        let mut frame = vec![0x81, 0x80 | request.len() as u8, 0, 0, 0, 0];
+        frame.extend_from_slice(request);
+        stream.write_all(&frame).unwrap();
+        read_until(&mut stream, &mut received, b"subscribed");
+
+        for _ in 0..count {
+            server.do_send(PoolAdmission {
+                tx_hash: Hash::zero(),
+                service_id: 128,
+                transaction_id: 0,
+            });
+        }
+        thread::sleep(Duration::from_secs(2));
+        read_until(&mut stream, &mut received, b"Too many missed notifications");
+        received
+    }
+
+    #[test]
+    fn test_client_not_reading_socket() {
+        // Notifications take tens of megabytes, more than the socket buffers can hold.
+        const NOTIFICATIONS: usize = 200_000;
+
+        let system = System::new("websocket-test");
+        let state = api_state();
+        let server = Server::new(Arc::new(state.clone()), SharedNodeState::new(5000)).start();
+        let session_server = server.clone();
+        let http_server = HttpServer::new(move || {
+            let server = session_server.clone();
+            App::with_state(state.clone()).resource("/ws", move |r| {
+                r.f(move |request| ws::start(request, Session::new(server.clone(), false)))
+            })
+        })
+        .workers(1)
+        .bind("127.0.0.1:0")
+        .unwrap();
+        let address = http_server.addrs()[0];
+        http_server.start();
+
+        let system_handle = System::current();
+        let client = thread::spawn(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                flood_client(address, &server, NOTIFICATIONS)
+            }));
+            system_handle.stop();
+            result
+        });
+        system.run();
+        let received = client
+            .join()
+            .unwrap()
+            .unwrap_or_else(|e| panic::resume_unwind(e));
+
+        // The session has dropped the notifications the client has not kept up with
+        // instead of buffering them.
+        let pattern: &[u8] = b"pool_admission";
+        let notifications = received
+            .windows(pattern.len())
+            .filter(|&window| window == pattern)
+            .count();
+        assert!(notifications > 0);
+        assert!(notifications < NOTIFICATIONS);
+    }
+}
@@ -2,6 +2,8 @@ use crypto::Hash;
 use helpers::{Height, ValidatorId};
 use messages::{Precommit, Signed};
 use proto;
+#[cfg(feature = "openapi")]
+use schemars::JsonSchema;
 
 /// Exonum block header data structure.
 ///
@@ -12,6 +14,7 @@ use proto;
 /// The header only contains the amount of transactions and the transactions root hash as well as
 /// other information, but not the transactions themselves.
 #[derive(Clone, PartialEq, Eq, Ord, PartialOrd, Debug, Serialize, Deserialize, ProtobufConvert)]
+#[cfg_attr(feature = "openapi", derive(JsonSchema))]
 #[exonum(pb = "proto::Block", crate = "crate")]
 pub struct Block {
     /// Identifier of the leader node which has proposed the block.
@@ -81,6 +84,7 @@ impl Block {
 /// a block. It consists of the block itself and the `Precommit`
 /// messages related to this block.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(JsonSchema))]
 pub struct BlockProof {
     /// Block header containing such information as the ID of the node which
     /// proposed the block, the height of the block, the number of transactions
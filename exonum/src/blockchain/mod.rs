@@ -21,7 +21,7 @@ pub use self::{
     block::{Block, BlockProof},
     config::{ConsensusConfig, StoredConfiguration, ValidatorKeys},
     genesis::GenesisConfig,
-    schema::{Schema, TxLocation},
+    schema::{Schema, TxLocation, CONFIGS_TABLE, TRANSACTION_RESULTS_TABLE},
     service::{Service, ServiceContext, SharedNodeState},
     transaction::{
         ExecutionError, ExecutionResult, Transaction, TransactionContext, TransactionError,
@@ -32,6 +32,7 @@ pub use self::{
 pub mod config;
 
 use byteorder::{ByteOrder, LittleEndian};
+use chrono::{DateTime, Utc};
 use failure;
 
 use std::{
@@ -68,6 +69,7 @@ pub struct Blockchain {
     #[doc(hidden)]
     pub service_keypair: (PublicKey, SecretKey),
     pub(crate) api_sender: ApiSender,
+    explorer_indexes: bool,
 }
 
 impl Blockchain {
@@ -96,6 +98,7 @@ impl Blockchain {
             service_map: Arc::new(service_map),
             service_keypair: (service_public_key, service_secret_key),
             api_sender,
+            explorer_indexes: false,
         }
     }
 
@@ -108,6 +111,19 @@ impl Blockchain {
         }
     }
 
+    /// Enables or disables the explorer indexes, which are updated on each commit:
+    /// the committed transactions by author and by service, and the block times.
+    /// The indexes are not a part of the blockchain state; blocks committed
+    /// while the indexes are disabled are not indexed.
+    pub fn set_explorer_indexes(&mut self, enabled: bool) {
+        self.explorer_indexes = enabled;
+    }
+
+    /// Returns `true` if the explorer indexes are enabled.
+    pub fn explorer_indexes(&self) -> bool {
+        self.explorer_indexes
+    }
+
     /// Returns mapping from the service identifier (`u16`) to service (`Box<dyn Service>`) for
     /// all services.
     pub fn service_map(&self) -> &Arc<HashMap<u16, Box<dyn Service>>> {
@@ -481,8 +497,13 @@ impl Blockchain {
 
             {
                 let mut schema = Schema::new(&mut fork);
-                for precommit in precommits {
-                    schema.precommits_mut(&block_hash).push(precommit.clone());
+                let precommits: Vec<_> = precommits.collect();
+                schema
+                    .precommits_mut(&block_hash)
+                    .extend(precommits.iter().cloned());
+                if self.explorer_indexes {
+                    let height = schema.height();
+                    schema.index_block(height, &precommits);
                 }
 
                 // Consensus messages cache is useful only during one height, so it should be
@@ -572,6 +593,13 @@ impl Blockchain {
     }
 }
 
+/// Returns the median time of the precommits, or `None` if there are no precommits.
+pub(crate) fn median_precommits_time(precommits: &[Signed<Precommit>]) -> Option<DateTime<Utc>> {
+    let mut times: Vec<_> = precommits.iter().map(|p| p.time()).collect();
+    times.sort();
+    times.get(times.len() / 2).cloned()
+}
+
 fn before_commit(service: &dyn Service, fork: &mut Fork) {
     fork.checkpoint();
     match panic::catch_unwind(panic::AssertUnwindSafe(|| service.before_commit(fork))) {
@@ -604,6 +632,7 @@ impl Clone for Blockchain {
             service_map: Arc::clone(&self.service_map),
             api_sender: self.api_sender.clone(),
             service_keypair: self.service_keypair.clone(),
+            explorer_indexes: self.explorer_indexes,
         }
     }
 }
@@ -1,8 +1,15 @@
-use super::{config::StoredConfiguration, Block, BlockProof, Blockchain, TransactionResult};
+use chrono::{DateTime, Utc};
+
+use super::{
+    config::StoredConfiguration, median_precommits_time, Block, BlockProof, Blockchain,
+    TransactionResult,
+};
 use crypto::{CryptoHash, Hash, PublicKey};
 use helpers::{Height, Round};
 use messages::{Connect, Message, Precommit, RawTransaction, Signed};
 use proto;
+#[cfg(feature = "openapi")]
+use schemars::JsonSchema;
 use storage::{
     Entry, Fork, KeySetIndex, ListIndex, MapIndex, MapProof, ProofListIndex, ProofMapIndex,
     Snapshot,
@@ -35,8 +42,19 @@ define_names!(
     PEERS_CACHE => "peers_cache";
     CONSENSUS_MESSAGES_CACHE => "consensus_messages_cache";
     CONSENSUS_ROUND => "consensus_round";
+    TRANSACTIONS_BY_AUTHOR => "transactions_by_author";
+    TRANSACTIONS_BY_SERVICE => "transactions_by_service";
+    BLOCK_TIMES => "block_times";
 );
 
+/// Index of the `configs` table among the core tables aggregated into the state hash.
+pub const CONFIGS_TABLE: usize = 0;
+/// Index of the `transaction_results` table among the core tables aggregated into
+/// the state hash.
+pub const TRANSACTION_RESULTS_TABLE: usize = 1;
+/// Number of the core tables aggregated into the state hash.
+const CORE_TABLES_COUNT: usize = 2;
+
 /// Configuration index.
 #[derive(Debug, Serialize, Deserialize, ProtobufConvert)]
 #[exonum(pb = "proto::ConfigReference", crate = "crate")]
@@ -70,7 +88,8 @@ impl ConfigReference {
 /// Transaction location in a block.
 /// The given entity defines the block where the transaction was
 /// included and the position of this transaction in that block.
-#[derive(Debug, Serialize, Deserialize, PartialEq, ProtobufConvert)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ProtobufConvert)]
+#[cfg_attr(feature = "openapi", derive(JsonSchema))]
 #[exonum(pb = "proto::TxLocation", crate = "crate")]
 pub struct TxLocation {
     /// Height of the block where the transaction was included.
@@ -227,6 +246,30 @@ where
             .unwrap_or_else(Round::first)
     }
 
+    /// Returns a table that keeps the hashes of the committed transactions signed
+    /// by the given author, in the order of their commitment.
+    ///
+    /// The table is filled only if the explorer indexes are enabled for the node.
+    pub fn transactions_by_author(&self, author: &PublicKey) -> ListIndex<&T, Hash> {
+        ListIndex::new_in_family(TRANSACTIONS_BY_AUTHOR, author, &self.view)
+    }
+
+    /// Returns a table that keeps the hashes of the committed transactions of
+    /// the given service, in the order of their commitment.
+    ///
+    /// The table is filled only if the explorer indexes are enabled for the node.
+    pub fn transactions_by_service(&self, service_id: u16) -> ListIndex<&T, Hash> {
+        ListIndex::new_in_family(TRANSACTIONS_BY_SERVICE, &service_id, &self.view)
+    }
+
+    /// Returns a table that keeps the median time of the block precommits for
+    /// every block height.
+    ///
+    /// The table is filled only if the explorer indexes are enabled for the node.
+    pub fn block_times(&self) -> MapIndex<&T, u64, DateTime<Utc>> {
+        MapIndex::new(BLOCK_TIMES, &self.view)
+    }
+
     /// Returns the block hash for the given height.
     pub fn block_hash_by_height(&self, height: Height) -> Option<Hash> {
         self.block_hashes_by_height().get(height.into())
@@ -339,10 +382,10 @@ where
 
     /// Returns the `state_hash` table for core tables.
     pub fn core_state_hash(&self) -> Vec<Hash> {
-        vec![
-            self.configs().merkle_root(),
-            self.transaction_results().merkle_root(),
-        ]
+        let mut hashes = vec![Hash::zero(); CORE_TABLES_COUNT];
+        hashes[CONFIGS_TABLE] = self.configs().merkle_root();
+        hashes[TRANSACTION_RESULTS_TABLE] = self.transaction_results().merkle_root();
+        hashes
     }
 
     /// Constructs a proof of inclusion of a root hash of a specific service
@@ -513,6 +556,51 @@ impl<'a> Schema<&'a mut Fork> {
         entry.set(round);
     }
 
+    /// Mutable reference to the [`transactions_by_author`][1] index.
+    ///
+    /// [1]: struct.Schema.html#method.transactions_by_author
+    pub(crate) fn transactions_by_author_mut(
+        &mut self,
+        author: &PublicKey,
+    ) -> ListIndex<&mut Fork, Hash> {
+        ListIndex::new_in_family(TRANSACTIONS_BY_AUTHOR, author, self.view)
+    }
+
+    /// Mutable reference to the [`transactions_by_service`][1] index.
+    ///
+    /// [1]: struct.Schema.html#method.transactions_by_service
+    pub(crate) fn transactions_by_service_mut(
+        &mut self,
+        service_id: u16,
+    ) -> ListIndex<&mut Fork, Hash> {
+        ListIndex::new_in_family(TRANSACTIONS_BY_SERVICE, &service_id, self.view)
+    }
+
+    /// Mutable reference to the [`block_times`][1] index.
+    ///
+    /// [1]: struct.Schema.html#method.block_times
+    pub(crate) fn block_times_mut(&mut self) -> MapIndex<&mut Fork, u64, DateTime<Utc>> {
+        MapIndex::new(BLOCK_TIMES, self.view)
+    }
+
+    /// Adds the transactions of the block at the given height to the explorer
+    /// indexes and records the median time of the block precommits.
+    pub(crate) fn index_block(&mut self, height: Height, precommits: &[Signed<Precommit>]) {
+        let tx_hashes: Vec<Hash> = self.block_transactions(height).iter().collect();
+        for tx_hash in tx_hashes {
+            let tx = self
+                .transactions()
+                .get(&tx_hash)
+                .expect("BUG: Committed transaction is not found");
+            self.transactions_by_author_mut(&tx.author()).push(tx_hash);
+            self.transactions_by_service_mut(tx.service_id())
+                .push(tx_hash);
+        }
+        if let Some(time) = median_precommits_time(precommits) {
+            self.block_times_mut().put(&height.0, time);
+        }
+    }
+
     /// Adds a new configuration to the blockchain, which will become actual at
     /// the `actual_from` height in `config_data`.
     pub fn commit_configuration(&mut self, config_data: StoredConfiguration) {
@@ -2,6 +2,7 @@
 //! blockchain platforms, Exonum services encapsulate business logic of the blockchain application.
 
 use actix::Addr;
+use chrono::Utc;
 use serde_json::Value;
 
 use std::{
@@ -12,13 +13,17 @@ use std::{
 };
 
 use super::transaction::Transaction;
-use api::{websocket, ServiceApiBuilder};
+use api::{
+    rate_limit::{Budget, ThrottledRequestsInfo},
+    websocket, ServiceApiBuilder,
+};
 use blockchain::{ConsensusConfig, Schema, StoredConfiguration, ValidatorKeys};
 use crypto::{Hash, PublicKey, SecretKey};
-use events::network::ConnectedPeerAddr;
+use events::{network::ConnectedPeerAddr, CompressionStatsInfo};
+use explorer::{dropped::DroppedTransactions, DropReason, DroppedTransaction};
 use helpers::{Height, Milliseconds, ValidatorId};
 use messages::{Message, RawTransaction, ServiceTransaction, Signed};
-use node::{ApiSender, ConnectInfo, NodeRole, State};
+use node::{state::SharedConnectList, ApiSender, ConnectInfo, NodeRole, PeerScoreInfo, State};
 use storage::{Fork, Snapshot};
 
 /// A trait that describes the business logic of a certain service.
@@ -335,7 +340,12 @@ pub struct ApiNodeState {
     node_role: NodeRole,
     majority_count: usize,
     validators: Vec<ValidatorKeys>,
+    peer_scores: Vec<PeerScoreInfo>,
+    compression_stats: CompressionStatsInfo,
+    throttled_requests: ThrottledRequestsInfo,
+    connect_list: SharedConnectList,
     broadcast_server_address: Option<Addr<websocket::Server>>,
+    dropped_transactions: DroppedTransactions,
 }
 
 impl fmt::Debug for ApiNodeState {
@@ -348,6 +358,10 @@ impl fmt::Debug for ApiNodeState {
             .field("node_role", &self.node_role)
             .field("majority_count", &self.majority_count)
             .field("validators", &self.validators)
+            .field("peer_scores", &self.peer_scores)
+            .field("compression_stats", &self.compression_stats)
+            .field("throttled_requests", &self.throttled_requests)
+            .field("connect_list", &self.connect_list)
             .finish()
     }
 }
@@ -413,6 +427,61 @@ impl SharedNodeState {
             .collect()
     }
 
+    /// Returns misbehavior scores of the peers known to the node.
+    pub fn peer_scores(&self) -> Vec<PeerScoreInfo> {
+        self.state
+            .read()
+            .expect("Expected read lock.")
+            .peer_scores
+            .clone()
+    }
+
+    /// Returns the connect list of the node.
+    pub(crate) fn connect_list(&self) -> SharedConnectList {
+        self.state
+            .read()
+            .expect("Expected read lock.")
+            .connect_list
+            .clone()
+    }
+
+    pub(crate) fn set_connect_list(&self, connect_list: SharedConnectList) {
+        let mut state = self.state.write().expect("Expected write lock.");
+        state.connect_list = connect_list;
+    }
+
+    pub(crate) fn set_peer_scores(&self, peer_scores: Vec<PeerScoreInfo>) {
+        let mut state = self.state.write().expect("Expected write lock.");
+        state.peer_scores = peer_scores;
+    }
+
+    /// Returns the amounts of bytes passed through the connections which use compression.
+    pub fn compression_stats(&self) -> CompressionStatsInfo {
+        self.state
+            .read()
+            .expect("Expected read lock.")
+            .compression_stats
+            .clone()
+    }
+
+    pub(crate) fn set_compression_stats(&self, compression_stats: CompressionStatsInfo) {
+        let mut state = self.state.write().expect("Expected write lock.");
+        state.compression_stats = compression_stats;
+    }
+
+    /// Returns the numbers of the public API requests throttled by the rate limits.
+    pub fn throttled_requests(&self) -> ThrottledRequestsInfo {
+        self.state
+            .read()
+            .expect("Expected read lock.")
+            .throttled_requests
+    }
+
+    pub(crate) fn record_throttled_request(&self, budget: Budget) {
+        let mut state = self.state.write().expect("Expected write lock.");
+        state.throttled_requests.record(budget);
+    }
+
     /// Updates internal state, from `State` of a blockchain node.
     pub fn update_node_state(&self, state: &State) {
         let mut lock = self.state.write().expect("Expected write lock.");
@@ -514,9 +583,17 @@ impl SharedNodeState {
             .remove(addr)
     }
 
-    pub(crate) fn set_broadcast_server_address(&self, address: Addr<websocket::Server>) {
+    /// Returns the address of the WebSocket server, starting the server if it is not
+    /// running yet.
+    pub(crate) fn broadcast_server_address<F>(&self, start: F) -> Addr<websocket::Server>
+    where
+        F: FnOnce() -> Addr<websocket::Server>,
+    {
         let mut state = self.state.write().expect("Expected write lock");
-        state.broadcast_server_address = Some(address);
+        state
+            .broadcast_server_address
+            .get_or_insert_with(start)
+            .clone()
     }
 
     /// Broadcast message to all subscribers.
@@ -532,6 +609,58 @@ impl SharedNodeState {
             })
         }
     }
+
+    /// Returns the record of the transaction dropped by the node, if the transaction
+    /// has been dropped recently.
+    pub fn dropped_transaction(&self, tx_hash: &Hash) -> Option<DroppedTransaction> {
+        self.state
+            .read()
+            .expect("Expected read lock")
+            .dropped_transactions
+            .get(tx_hash, Utc::now())
+            .cloned()
+    }
+
+    /// Records the transaction dropped by the node and notifies the subscribers about it.
+    pub(crate) fn record_dropped_transaction(
+        &self,
+        tx_hash: Hash,
+        reason: DropReason,
+        description: String,
+    ) {
+        let tx = DroppedTransaction {
+            tx_hash,
+            reason,
+            description,
+            time: Utc::now(),
+        };
+        let mut state = self.state.write().expect("Expected write lock");
+        state.dropped_transactions.insert(tx.clone());
+        if let Some(ref address) = state.broadcast_server_address {
+            address.do_send(websocket::TransactionDropped(tx))
+        }
+    }
+
+    /// Notifies the subscribers about the transaction admitted to the pool.
+    pub(crate) fn broadcast_pool_admission(
+        &self,
+        tx_hash: Hash,
+        service_id: u16,
+        transaction_id: u16,
+    ) {
+        if let Some(ref address) = self
+            .state
+            .read()
+            .expect("Expected read lock")
+            .broadcast_server_address
+        {
+            address.do_send(websocket::PoolAdmission {
+                tx_hash,
+                service_id,
+                transaction_id,
+            })
+        }
+    }
 }
 
 impl<'a, S: Service> From<S> for Box<dyn Service + 'a> {
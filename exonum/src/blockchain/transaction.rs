@@ -2,6 +2,12 @@
 
 use failure;
 use protobuf::Message;
+#[cfg(feature = "openapi")]
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{InstanceType, Schema, SchemaObject},
+    JsonSchema,
+};
 use serde::{de::DeserializeOwned, Serialize};
 
 use std::{any::Any, borrow::Cow, convert::Into, error::Error, fmt, u8};
@@ -57,6 +63,34 @@ impl ::std::fmt::Debug for TransactionMessage {
     }
 }
 
+#[cfg(feature = "openapi")]
+impl JsonSchema for TransactionMessage {
+    fn schema_name() -> String {
+        "TransactionMessage".to_owned()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let mut debug = SchemaObject::default();
+        debug.metadata().description =
+            Some("Transaction as JSON, if it is known to the node".to_owned());
+
+        let mut schema = SchemaObject {
+            instance_type: Some(InstanceType::Object.into()),
+            ..Default::default()
+        };
+        {
+            let object = schema.object();
+            object.properties.insert("debug".to_owned(), debug.into());
+            object.properties.insert(
+                "message".to_owned(),
+                gen.subschema_for::<Signed<RawTransaction>>(),
+            );
+            object.required.insert("message".to_owned());
+        }
+        schema.into()
+    }
+}
+
 impl TransactionMessage {
     /// Returns `SignedMessage`.
     pub fn signed_message(&self) -> &SignedMessage {
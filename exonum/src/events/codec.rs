@@ -18,15 +18,27 @@ use failure;
 use std::mem;
 use tokio_io::codec::{Decoder, Encoder};
 
-use events::noise::{NoiseWrapper, HEADER_LENGTH as NOISE_HEADER_LENGTH};
+use events::{
+    compression::MessageCompressor,
+    noise::{NoiseWrapper, Rekeying, HEADER_LENGTH as NOISE_HEADER_LENGTH, TRANSPORT_KEY_LENGTH},
+};
 use messages::{SignedMessage, EMPTY_SIGNED_MESSAGE_SIZE};
 
+// If the connection uses compression or rekeying, each frame starts with its kind.
+const MESSAGE_FRAME: u8 = 0;
+const COMPRESSED_MESSAGE_FRAME: u8 = 1;
+const REKEY_FRAME: u8 = 2;
+
 #[derive(Debug)]
 pub struct MessagesCodec {
     /// Maximum message length (in bytes), gets populated from `ConsensusConfig`.
     max_message_len: u32,
     /// Noise session to encrypt/decrypt messages.
     session: NoiseWrapper,
+    /// Compression negotiated with the peer, applied before encryption.
+    compressor: Option<MessageCompressor>,
+    /// Usage of the outgoing key, if rekeying is negotiated with the peer.
+    rekeying: Option<Rekeying>,
 }
 
 impl MessagesCodec {
@@ -34,45 +46,145 @@ impl MessagesCodec {
         Self {
             max_message_len,
             session,
+            compressor: None,
+            rekeying: None,
         }
     }
-}
 
-impl Decoder for MessagesCodec {
-    type Item = Vec<u8>;
-    type Error = failure::Error;
+    pub fn with_compressor(mut self, compressor: MessageCompressor) -> Self {
+        self.compressor = Some(compressor);
+        self
+    }
 
-    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        // Framing level
-        if buf.len() < mem::size_of::<u32>() {
-            return Ok(None);
-        }
+    pub fn with_rekeying(mut self, rekeying: Rekeying) -> Self {
+        self.rekeying = Some(rekeying);
+        self
+    }
 
-        let len = LittleEndian::read_u32(buf) as usize;
+    fn has_frame_kinds(&self) -> bool {
+        self.compressor.is_some() || self.rekeying.is_some()
+    }
 
-        if buf.len() < NOISE_HEADER_LENGTH + len {
-            return Ok(None);
+    // Returns the message contained in the frame, or `None` for service frames.
+    fn read_frame(&mut self, frame: &[u8]) -> Result<Option<Vec<u8>>, failure::Error> {
+        let (&kind, data) = frame
+            .split_first()
+            .ok_or_else(|| format_err!("Received empty frame"))?;
+
+        match kind {
+            MESSAGE_FRAME => {
+                if let Some(ref compressor) = self.compressor {
+                    compressor.record_received(data.len(), frame.len());
+                }
+                Ok(Some(data.to_vec()))
+            }
+            COMPRESSED_MESSAGE_FRAME => {
+                let compressor = self.compressor.as_ref().ok_or_else(|| {
+                    format_err!("Received compressed message without compression")
+                })?;
+                let message = compressor.decompress(data, self.max_message_len as usize)?;
+                compressor.record_received(message.len(), frame.len());
+                Ok(Some(message))
+            }
+            REKEY_FRAME if self.rekeying.is_some() => {
+                ensure!(
+                    data.len() == TRANSPORT_KEY_LENGTH,
+                    "Received key of wrong length: {}",
+                    data.len()
+                );
+                self.session.set_incoming_key(data)?;
+                Ok(None)
+            }
+            other => bail!("Received frame of unknown kind: {}", other),
         }
+    }
 
-        let buf = self.session.decrypt_msg(len, buf)?;
+    fn write_frame(&self, raw: &[u8]) -> Result<Vec<u8>, failure::Error> {
+        let compressed = match self.compressor {
+            Some(ref compressor) => compressor.compress(raw)?,
+            None => None,
+        };
 
-        if buf.len() > self.max_message_len as usize {
-            bail!(
-                "Received message is too long: received_len = {}, allowed_len = {}",
-                buf.len(),
-                self.max_message_len
-            )
+        let mut frame = Vec::with_capacity(raw.len() + 1);
+        match compressed {
+            Some(compressed) => {
+                frame.push(COMPRESSED_MESSAGE_FRAME);
+                frame.extend_from_slice(&compressed);
+            }
+            None => {
+                frame.push(MESSAGE_FRAME);
+                frame.extend_from_slice(raw);
+            }
         }
 
-        if buf.len() <= EMPTY_SIGNED_MESSAGE_SIZE {
-            bail!(
-                "Received malicious message with wrong length: received_len = {}, min_len = {}",
-                buf.len(),
-                EMPTY_SIGNED_MESSAGE_SIZE
-            )
+        if let Some(ref compressor) = self.compressor {
+            compressor.record_sent(raw.len(), frame.len());
         }
+        Ok(frame)
+    }
 
-        Ok(Some(buf.to_vec()))
+    // Sends a new outgoing key encrypted with the current one and starts using it.
+    fn rekey(&mut self, buf: &mut BytesMut) -> Result<(), failure::Error> {
+        let key = NoiseWrapper::generate_transport_key();
+        let mut frame = Vec::with_capacity(key.len() + 1);
+        frame.push(REKEY_FRAME);
+        frame.extend_from_slice(&key);
+
+        self.session.encrypt_msg(&frame, buf)?;
+        self.session.set_outgoing_key(&key)?;
+        if let Some(ref mut rekeying) = self.rekeying {
+            rekeying.reset();
+        }
+        Ok(())
+    }
+}
+
+impl Decoder for MessagesCodec {
+    type Item = Vec<u8>;
+    type Error = failure::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        loop {
+            // Framing level
+            if buf.len() < mem::size_of::<u32>() {
+                return Ok(None);
+            }
+
+            let len = LittleEndian::read_u32(buf) as usize;
+
+            if buf.len() < NOISE_HEADER_LENGTH + len {
+                return Ok(None);
+            }
+
+            let frame = self.session.decrypt_msg(len, buf)?;
+            let message = if self.has_frame_kinds() {
+                match self.read_frame(&frame)? {
+                    Some(message) => message,
+                    // The next frame may be in the buffer already.
+                    None => continue,
+                }
+            } else {
+                frame.to_vec()
+            };
+
+            if message.len() > self.max_message_len as usize {
+                bail!(
+                    "Received message is too long: received_len = {}, allowed_len = {}",
+                    message.len(),
+                    self.max_message_len
+                )
+            }
+
+            if message.len() <= EMPTY_SIGNED_MESSAGE_SIZE {
+                bail!(
+                    "Received malicious message with wrong length: received_len = {}, min_len = {}",
+                    message.len(),
+                    EMPTY_SIGNED_MESSAGE_SIZE
+                )
+            }
+
+            return Ok(Some(message));
+        }
     }
 }
 
@@ -81,7 +193,21 @@ impl Encoder for MessagesCodec {
     type Error = failure::Error;
 
     fn encode(&mut self, msg: Self::Item, buf: &mut BytesMut) -> Result<(), Self::Error> {
-        self.session.encrypt_msg(msg.raw(), buf)?;
+        if self.rekeying.as_ref().map_or(false, Rekeying::is_due) {
+            self.rekey(buf)?;
+        }
+
+        let start = buf.len();
+        if self.has_frame_kinds() {
+            let frame = self.write_frame(msg.raw())?;
+            self.session.encrypt_msg(&frame, buf)?;
+        } else {
+            self.session.encrypt_msg(msg.raw(), buf)?;
+        }
+
+        if let Some(ref mut rekeying) = self.rekeying {
+            rekeying.record(buf.len() - start);
+        }
         Ok(())
     }
 }
@@ -93,7 +219,10 @@ mod test {
     use tokio_io::codec::{Decoder, Encoder};
 
     use super::MessagesCodec;
-    use events::noise::{HandshakeParams, NoiseWrapper};
+    use events::{
+        compression::{Compression, CompressionConfig, MessageCompressor, SharedCompressionStats},
+        noise::{HandshakeParams, NoiseWrapper, RekeyConfig, Rekeying},
+    };
     use messages::{SignedMessage, EMPTY_SIGNED_MESSAGE_SIZE};
 
     pub fn raw_message(val: Vec<u8>) -> SignedMessage {
@@ -142,6 +271,80 @@ mod test {
         assert!(responder.decode_eof(&mut bytes).unwrap().is_none());
     }
 
+    #[test]
+    fn decode_compressed_message() {
+        let stats = SharedCompressionStats::new();
+        let (responder, initiator) = create_encrypted_codecs();
+        let compressor = MessageCompressor::new(
+            Compression::Zstd,
+            &CompressionConfig::default(),
+            stats.clone(),
+        );
+        let mut responder = responder.with_compressor(compressor.clone());
+        let mut initiator = initiator.with_compressor(compressor);
+
+        let data = vec![1_u8; 5000];
+        let mut bytes = BytesMut::new();
+        initiator
+            .encode(raw_message(data.clone()), &mut bytes)
+            .unwrap();
+        assert!(bytes.len() < data.len());
+
+        match responder.decode(&mut bytes) {
+            Ok(Some(ref message)) if *message == &data[..] => {}
+            _ => panic!("Wrong input"),
+        };
+        assert_eq!(stats.info().raw_bytes_received, 5000);
+    }
+
+    #[test]
+    #[should_panic(expected = "Received message is too long after decompression")]
+    fn decode_compressed_message_too_long() {
+        let (responder, initiator) = create_encrypted_codecs();
+        let compressor = MessageCompressor::new(
+            Compression::Zstd,
+            &CompressionConfig::default(),
+            SharedCompressionStats::new(),
+        );
+        let mut responder = responder.with_compressor(compressor.clone());
+        let mut initiator = initiator.with_compressor(compressor);
+
+        // The compressed message fits the limit, but the original one does not.
+        let mut bytes = BytesMut::new();
+        initiator
+            .encode(raw_message(vec![1_u8; 100_000]), &mut bytes)
+            .unwrap();
+        responder.decode(&mut bytes).unwrap();
+    }
+
+    #[test]
+    fn decode_messages_after_rekeying() {
+        let config = RekeyConfig {
+            enabled: true,
+            max_bytes: 1,
+            ..RekeyConfig::default()
+        };
+        let (responder, initiator) = create_encrypted_codecs();
+        let mut responder = responder.with_rekeying(Rekeying::new(&config));
+        let mut initiator = initiator.with_rekeying(Rekeying::new(&config));
+
+        // Every message except the first one is preceded by a new key.
+        let data = vec![1_u8; EMPTY_SIGNED_MESSAGE_SIZE + 10];
+        let mut bytes = BytesMut::new();
+        for _ in 0..3 {
+            initiator
+                .encode(raw_message(data.clone()), &mut bytes)
+                .unwrap();
+        }
+        for _ in 0..3 {
+            match responder.decode(&mut bytes) {
+                Ok(Some(ref message)) if *message == &data[..] => {}
+                _ => panic!("Wrong input"),
+            };
+        }
+        assert!(bytes.is_empty());
+    }
+
     fn get_decoded_message(data: &[u8]) -> Result<Option<Vec<u8>>, failure::Error> {
         let (ref mut responder, ref mut initiator) = create_encrypted_codecs();
         let raw = raw_message(data.to_vec());
@@ -191,11 +394,15 @@ mod test {
         let responder_codec = MessagesCodec {
             max_message_len: 10000,
             session: initiator,
+            compressor: None,
+            rekeying: None,
         };
 
         let initiator_codec = MessagesCodec {
             max_message_len: 10000,
             session: responder,
+            compressor: None,
+            rekeying: None,
         };
 
         (responder_codec, initiator_codec)
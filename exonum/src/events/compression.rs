@@ -0,0 +1,311 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compression of messages sent over connections which have negotiated it
+//! during the handshake.
+
+use failure;
+use zstd;
+
+use std::{
+    io::Read,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+/// Compression algorithms supported by the node, in the order of preference.
+const SUPPORTED_ALGORITHMS: &[Compression] = &[Compression::Zstd];
+
+/// Message compression configuration.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct CompressionConfig {
+    /// Whether the node offers compression to its peers. Compression is used
+    /// with a peer only if both sides offer the same algorithm.
+    pub enabled: bool,
+    /// Compression level.
+    pub level: i32,
+    /// Messages shorter than this amount of bytes are sent uncompressed.
+    pub min_size: usize,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            level: 3,
+            min_size: 256,
+        }
+    }
+}
+
+impl CompressionConfig {
+    /// Returns names of the algorithms advertised in the `Connect` message.
+    pub fn offer(&self) -> Vec<String> {
+        if !self.enabled {
+            return Vec::new();
+        }
+        SUPPORTED_ALGORITHMS
+            .iter()
+            .map(|algorithm| algorithm.name().to_owned())
+            .collect()
+    }
+}
+
+/// Compression algorithm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Zstandard.
+    Zstd,
+}
+
+impl Compression {
+    /// Returns the name of the algorithm used in the `Connect` message.
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    /// Selects the most preferred algorithm offered by both peers. The choice
+    /// does not depend on the order of the offers, so that both sides of
+    /// the connection select the same algorithm.
+    pub fn negotiate(ours: &[String], theirs: &[String]) -> Option<Self> {
+        SUPPORTED_ALGORITHMS.iter().cloned().find(|algorithm| {
+            let name = algorithm.name();
+            ours.iter().any(|n| n == name) && theirs.iter().any(|n| n == name)
+        })
+    }
+}
+
+/// Amounts of bytes passed through the connections which use compression.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct CompressionStatsInfo {
+    /// Size of the sent messages before compression.
+    pub raw_bytes_sent: u64,
+    /// Size of the sent messages after compression.
+    pub compressed_bytes_sent: u64,
+    /// Size of the received messages after decompression.
+    pub raw_bytes_received: u64,
+    /// Size of the received messages before decompression.
+    pub compressed_bytes_received: u64,
+}
+
+impl CompressionStatsInfo {
+    /// Ratio of the compressed size of the sent messages to their raw size.
+    pub fn sent_ratio(&self) -> Option<f64> {
+        ratio(self.compressed_bytes_sent, self.raw_bytes_sent)
+    }
+
+    /// Ratio of the compressed size of the received messages to their raw size.
+    pub fn received_ratio(&self) -> Option<f64> {
+        ratio(self.compressed_bytes_received, self.raw_bytes_received)
+    }
+}
+
+fn ratio(compressed: u64, raw: u64) -> Option<f64> {
+    if raw == 0 {
+        None
+    } else {
+        Some(compressed as f64 / raw as f64)
+    }
+}
+
+#[derive(Debug, Default)]
+struct CompressionCounters {
+    raw_sent: AtomicUsize,
+    compressed_sent: AtomicUsize,
+    raw_received: AtomicUsize,
+    compressed_received: AtomicUsize,
+}
+
+/// Compression counters shared between the connections and the node.
+#[derive(Debug, Clone, Default)]
+pub struct SharedCompressionStats {
+    inner: Arc<CompressionCounters>,
+}
+
+impl SharedCompressionStats {
+    /// Creates zeroed counters.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the current values of the counters.
+    pub fn info(&self) -> CompressionStatsInfo {
+        let load = |counter: &AtomicUsize| counter.load(Ordering::Relaxed) as u64;
+        CompressionStatsInfo {
+            raw_bytes_sent: load(&self.inner.raw_sent),
+            compressed_bytes_sent: load(&self.inner.compressed_sent),
+            raw_bytes_received: load(&self.inner.raw_received),
+            compressed_bytes_received: load(&self.inner.compressed_received),
+        }
+    }
+
+    fn record_sent(&self, raw: usize, compressed: usize) {
+        self.inner.raw_sent.fetch_add(raw, Ordering::Relaxed);
+        self.inner
+            .compressed_sent
+            .fetch_add(compressed, Ordering::Relaxed);
+    }
+
+    fn record_received(&self, raw: usize, compressed: usize) {
+        self.inner.raw_received.fetch_add(raw, Ordering::Relaxed);
+        self.inner
+            .compressed_received
+            .fetch_add(compressed, Ordering::Relaxed);
+    }
+}
+
+/// Compresses and decompresses messages of a single connection.
+#[derive(Debug, Clone)]
+pub struct MessageCompressor {
+    algorithm: Compression,
+    level: i32,
+    min_size: usize,
+    stats: SharedCompressionStats,
+}
+
+impl MessageCompressor {
+    /// Creates a compressor for the negotiated algorithm.
+    pub fn new(
+        algorithm: Compression,
+        config: &CompressionConfig,
+        stats: SharedCompressionStats,
+    ) -> Self {
+        Self {
+            algorithm,
+            level: config.level,
+            min_size: config.min_size,
+            stats,
+        }
+    }
+
+    /// Returns the negotiated algorithm.
+    pub fn algorithm(&self) -> Compression {
+        self.algorithm
+    }
+
+    /// Compresses the message. Returns `None` if the message is too short or
+    /// compression does not reduce its size, so it should be sent as is.
+    pub fn compress(&self, raw: &[u8]) -> Result<Option<Vec<u8>>, failure::Error> {
+        if raw.len() < self.min_size {
+            return Ok(None);
+        }
+        let compressed = match self.algorithm {
+            Compression::Zstd => zstd::stream::encode_all(raw, self.level)?,
+        };
+        if compressed.len() < raw.len() {
+            Ok(Some(compressed))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Decompresses the message. Fails if the message is longer than `max_len` bytes
+    /// after decompression; the rest of the data is not decompressed in this case.
+    pub fn decompress(&self, data: &[u8], max_len: usize) -> Result<Vec<u8>, failure::Error> {
+        let mut raw = Vec::new();
+        match self.algorithm {
+            Compression::Zstd => zstd::stream::Decoder::new(data)?
+                .take(max_len as u64 + 1)
+                .read_to_end(&mut raw)?,
+        };
+
+        if raw.len() > max_len {
+            bail!(
+                "Received message is too long after decompression: allowed_len = {}",
+                max_len
+            )
+        }
+        Ok(raw)
+    }
+
+    /// Records the size of the sent message before and after compression.
+    pub fn record_sent(&self, raw: usize, compressed: usize) {
+        self.stats.record_sent(raw, compressed);
+    }
+
+    /// Records the size of the received message before and after decompression.
+    pub fn record_received(&self, raw: usize, compressed: usize) {
+        self.stats.record_received(raw, compressed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compressor(stats: &SharedCompressionStats) -> MessageCompressor {
+        MessageCompressor::new(
+            Compression::Zstd,
+            &CompressionConfig::default(),
+            stats.clone(),
+        )
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let zstd = vec!["zstd".to_owned()];
+        let unknown = vec!["lz4".to_owned(), "zstd".to_owned()];
+
+        assert_eq!(
+            Compression::negotiate(&zstd, &zstd),
+            Some(Compression::Zstd)
+        );
+        assert_eq!(
+            Compression::negotiate(&zstd, &unknown),
+            Some(Compression::Zstd)
+        );
+        assert_eq!(Compression::negotiate(&zstd, &[]), None);
+        assert_eq!(Compression::negotiate(&[], &zstd), None);
+        assert!(CompressionConfig::default().offer().is_empty());
+    }
+
+    #[test]
+    fn test_compress_roundtrip() {
+        let stats = SharedCompressionStats::new();
+        let compressor = compressor(&stats);
+
+        assert!(compressor.compress(&[1_u8; 10]).unwrap().is_none());
+
+        let long = vec![1_u8; 10_000];
+        let compressed = compressor.compress(&long).unwrap().unwrap();
+        assert!(compressed.len() < long.len());
+        assert_eq!(compressor.decompress(&compressed, 10_000).unwrap(), long);
+
+        compressor.record_sent(long.len(), compressed.len());
+        let info = stats.info();
+        assert_eq!(info.raw_bytes_sent, 10_000);
+        assert_eq!(info.raw_bytes_received, 0);
+        assert!(info.sent_ratio().unwrap() < 0.1);
+        assert_eq!(info.received_ratio(), None);
+    }
+
+    #[test]
+    fn test_decompression_bomb() {
+        let compressor = compressor(&SharedCompressionStats::new());
+
+        let compressed = compressor
+            .compress(&vec![0_u8; 1_000_000])
+            .unwrap()
+            .unwrap();
+        assert!(compressed.len() < 1_000);
+        let err = compressor.decompress(&compressed, 10_000).unwrap_err();
+        assert!(err.to_string().contains("too long after decompression"));
+
+        assert!(compressor.decompress(&[1, 2, 3], 10_000).is_err());
+    }
+}
@@ -23,6 +23,7 @@ use tokio_core::reactor::{Handle, Timeout};
 use std::time::{Duration, SystemTime};
 
 use super::{InternalEvent, InternalRequest, TimeoutRequest};
+use crypto::PublicKey;
 use messages::{Message, SignedMessage};
 
 #[derive(Debug)]
@@ -47,15 +48,20 @@ impl InternalPart {
     }
 
     fn verify_message(
+        peer: PublicKey,
         raw: Vec<u8>,
         internal_tx: mpsc::Sender<InternalEvent>,
     ) -> impl Future<Item = (), Error = ()> {
-        future::lazy(|| SignedMessage::from_raw_buffer(raw).and_then(Message::deserialize))
-            .map_err(drop)
-            .and_then(|protocol| {
-                let event = future::ok(InternalEvent::MessageVerified(Box::new(protocol)));
-                Self::send_event(event, internal_tx)
-            })
+        future::lazy(move || {
+            let event = match SignedMessage::from_raw_buffer(raw).and_then(Message::deserialize) {
+                Ok(protocol) => InternalEvent::MessageVerified(Box::new(protocol)),
+                Err(e) => {
+                    trace!("Invalid message from peer {}: {}", peer, e);
+                    InternalEvent::InvalidMessage(peer)
+                }
+            };
+            Self::send_event(future::ok(event), internal_tx)
+        })
     }
 
     /// Represents a task that processes Internal Requests and produces Internal Events.
@@ -70,8 +76,8 @@ impl InternalPart {
         self.internal_requests_rx
             .map(move |request| {
                 let event = match request {
-                    InternalRequest::VerifyMessage(tx) => {
-                        let fut = Self::verify_message(tx, internal_tx.clone());
+                    InternalRequest::VerifyMessage(peer, tx) => {
+                        let fut = Self::verify_message(peer, tx, internal_tx.clone());
                         verify_executor
                             .execute(Box::new(fut))
                             .expect("cannot schedule message verification");
@@ -118,7 +124,7 @@ mod tests {
     use super::*;
     use crypto::{gen_keypair, Signature};
 
-    fn verify_message(msg: Vec<u8>) -> Option<InternalEvent> {
+    fn verify_message(peer: PublicKey, msg: Vec<u8>) -> Option<InternalEvent> {
         let (internal_tx, internal_rx) = mpsc::channel(16);
         let (internal_requests_tx, internal_requests_rx) = mpsc::channel(16);
 
@@ -140,7 +146,7 @@ mod tests {
             core.run(task).unwrap()
         });
 
-        let request = InternalRequest::VerifyMessage(msg);
+        let request = InternalRequest::VerifyMessage(peer, msg);
         internal_requests_tx.wait().send(request).unwrap();
         thread.join().unwrap()
     }
@@ -152,7 +158,7 @@ mod tests {
 
         let expected_event =
             InternalEvent::MessageVerified(Box::new(Message::deserialize(tx.clone()).unwrap()));
-        let event = verify_message(tx.raw().to_vec());
+        let event = verify_message(pk, tx.raw().to_vec());
         assert_eq!(event, Some(expected_event));
     }
 
@@ -161,7 +167,7 @@ mod tests {
         let (pk, _) = gen_keypair();
         let tx = SignedMessage::new_with_signature(0, 0, &vec![0; 200], pk, Signature::zero());
 
-        let event = verify_message(tx.raw().to_vec());
-        assert_eq!(event, None);
+        let event = verify_message(pk, tx.raw().to_vec());
+        assert_eq!(event, Some(InternalEvent::InvalidMessage(pk)));
     }
 }
@@ -14,10 +14,12 @@
 
 #![allow(missing_debug_implementations, missing_docs)]
 
+pub use self::compression::{CompressionConfig, CompressionStatsInfo, SharedCompressionStats};
 pub use self::internal::InternalPart;
 pub use self::network::{NetworkConfiguration, NetworkEvent, NetworkPart, NetworkRequest};
 
 pub mod codec;
+pub mod compression;
 pub mod error;
 pub mod internal;
 pub mod network;
@@ -31,6 +33,7 @@ use futures::{
 
 use std::{cmp::Ordering, time::SystemTime};
 
+use crypto::PublicKey;
 use helpers::{Height, Round};
 use messages::Message;
 use node::{ExternalMessage, NodeTimeout};
@@ -55,6 +58,8 @@ pub enum InternalEvent {
     /// Message has been successfully verified.
     /// Message is boxed here so that enum variants have similar size.
     MessageVerified(Box<Message>),
+    /// Message received from the peer has failed verification.
+    InvalidMessage(PublicKey),
 }
 
 #[derive(Debug)]
@@ -63,8 +68,8 @@ pub enum InternalRequest {
     Timeout(TimeoutRequest),
     JumpToRound(Height, Round),
     Shutdown,
-    /// Async request to verify a message in the thread pool.
-    VerifyMessage(Vec<u8>),
+    /// Async request to verify a message received from the peer in the thread pool.
+    VerifyMessage(PublicKey, Vec<u8>),
 }
 
 #[derive(Debug, PartialEq, Eq)]
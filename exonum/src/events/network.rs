@@ -29,22 +29,28 @@ use tokio_retry::{
     Retry,
 };
 
-use std::{cell::RefCell, collections::HashMap, net::SocketAddr, rc::Rc, time::Duration};
+use std::{cell::RefCell, collections::HashMap, io, net::SocketAddr, rc::Rc, time::Duration};
 
 use super::{error::log_error, to_box};
-use crypto::PublicKey;
+use crypto::{PublicKey, SecretKey};
 use events::{
     codec::MessagesCodec,
+    compression::CompressionConfig,
     error::into_failure,
-    noise::{Handshake, HandshakeParams, NoiseHandshake},
+    noise::{Handshake, HandshakeParams, NoiseHandshake, RekeyConfig},
 };
 use helpers::Milliseconds;
 use messages::{Connect, Message, Service, Signed, SignedMessage};
-use node::state::SharedConnectList;
+use node::{
+    state::SharedConnectList, BlockSyncConfig, Misbehavior, PeerDiscoveryConfig, PeerScoringConfig,
+    SharedPeerScores,
+};
 
 const OUTGOING_CHANNEL_SIZE: usize = 10;
 
-#[derive(Debug, Clone)]
+type ConnectFuture = Box<dyn Future<Item = (TcpStream, String), Error = io::Error>>;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ConnectedPeerAddr {
     In(SocketAddr),
     Out(String, SocketAddr),
@@ -61,7 +67,7 @@ impl ConnectedPeerAddr {
 
 #[derive(Debug)]
 pub enum NetworkEvent {
-    MessageReceived(Vec<u8>),
+    MessageReceived(PublicKey, Vec<u8>),
     PeerConnected(ConnectedPeerAddr, Signed<Connect>),
     PeerDisconnected(PublicKey),
     UnableConnectToPeer(PublicKey),
@@ -71,6 +77,7 @@ pub enum NetworkEvent {
 pub enum NetworkRequest {
     SendMessage(PublicKey, SignedMessage),
     DisconnectWithPeer(PublicKey),
+    UpdateConsensusKeys(PublicKey, SecretKey, Signed<Connect>),
     Shutdown,
 }
 
@@ -83,6 +90,16 @@ pub struct NetworkConfiguration {
     pub tcp_keep_alive: Option<u64>,
     pub tcp_connect_retry_timeout: Milliseconds,
     pub tcp_connect_max_retries: u64,
+    #[serde(default)]
+    pub peer_scoring: PeerScoringConfig,
+    #[serde(default)]
+    pub block_sync: BlockSyncConfig,
+    #[serde(default)]
+    pub compression: CompressionConfig,
+    #[serde(default)]
+    pub rekeying: RekeyConfig,
+    #[serde(default)]
+    pub discovery: PeerDiscoveryConfig,
 }
 
 impl Default for NetworkConfiguration {
@@ -94,6 +111,11 @@ impl Default for NetworkConfiguration {
             tcp_nodelay: true,
             tcp_connect_retry_timeout: 15_000,
             tcp_connect_max_retries: 10,
+            peer_scoring: PeerScoringConfig::default(),
+            block_sync: BlockSyncConfig::default(),
+            compression: CompressionConfig::default(),
+            rekeying: RekeyConfig::default(),
+            discovery: PeerDiscoveryConfig::default(),
         }
     }
 }
@@ -107,6 +129,7 @@ pub struct NetworkPart {
     pub network_requests: (mpsc::Sender<NetworkRequest>, mpsc::Receiver<NetworkRequest>),
     pub network_tx: mpsc::Sender<NetworkEvent>,
     pub connect_list: SharedConnectList,
+    pub peer_scores: SharedPeerScores,
 }
 
 #[derive(Clone, Debug)]
@@ -244,8 +267,11 @@ struct NetworkHandler {
     handle: Handle,
     network_config: NetworkConfiguration,
     network_tx: mpsc::Sender<NetworkEvent>,
-    handshake_params: HandshakeParams,
+    // Shared between the listener and outgoing connections, so that the consensus key
+    // can be replaced while the node is running.
+    handshake_params: Rc<RefCell<HandshakeParams>>,
     connect_list: SharedConnectList,
+    peer_scores: SharedPeerScores,
 }
 
 impl NetworkHandler {
@@ -257,6 +283,7 @@ impl NetworkHandler {
         network_tx: mpsc::Sender<NetworkEvent>,
         handshake_params: HandshakeParams,
         connect_list: SharedConnectList,
+        peer_scores: SharedPeerScores,
     ) -> Self {
         NetworkHandler {
             handle,
@@ -264,8 +291,9 @@ impl NetworkHandler {
             pool: connection_pool,
             network_config,
             network_tx,
-            handshake_params,
+            handshake_params: Rc::new(RefCell::new(handshake_params)),
             connect_list,
+            peer_scores,
         }
     }
 
@@ -294,7 +322,8 @@ impl NetworkHandler {
                 let network_tx = network_tx.clone();
                 let handle = handle.clone();
 
-                let handshake = NoiseHandshake::responder(&handshake_params, &listen_address);
+                let handshake =
+                    NoiseHandshake::responder(&handshake_params.borrow(), &listen_address);
                 let holder = incoming_connections_counter.clone();
                 // Check incoming connections count
                 let connections_count = Rc::strong_count(&incoming_connections_counter) - 1;
@@ -308,12 +337,21 @@ impl NetworkHandler {
                 }
 
                 let connect_list = self.connect_list.clone();
+                let peer_scores = self.peer_scores.clone();
                 let listener = handshake
                     .listen(incoming_connection)
                     .and_then(move |(socket, raw)| (Ok(socket), Self::parse_connect_msg(Some(raw))))
                     .and_then(move |(socket, message)| {
                         if pool.contains(&message.author()) {
                             Box::new(future::ok(()))
+                        } else if peer_scores.is_banned(&message.author()) {
+                            warn!(
+                                "Rejecting incoming connection with peer={} public_key={}, \
+                                 peer is banned",
+                                address,
+                                message.author()
+                            );
+                            Box::new(future::ok(()))
                         } else if connect_list.is_peer_allowed(&message.author()) {
                             let receiver_rx =
                                 pool.add_incoming_address(&message.author(), &conn_addr);
@@ -329,6 +367,7 @@ impl NetworkHandler {
                                 message,
                                 pool,
                                 &network_tx,
+                                peer_scores,
                             ))
                         } else {
                             warn!( "Rejecting incoming connection with peer={} public_key={}, peer is not in the ConnectList",
@@ -363,28 +402,32 @@ impl NetworkHandler {
             .map(jitter)
             .take(max_tries);
 
-        let unresolved_address = self
+        let addresses = self
             .connect_list
             .find_address_by_key(&key)
-            .map(|a| a.address.clone());
+            .map(|a| a.addresses_by_priority());
 
-        if let Some(unresolved_address) = unresolved_address {
-            let action = {
-                let unresolved_address = unresolved_address.clone();
-                move || tokio_dns::TcpStream::connect(unresolved_address.as_str())
-            };
+        if let Some(addresses) = addresses {
+            let action = move || Self::connect_by_priority(addresses.clone());
 
             let (sender_tx, receiver_rx) = mpsc::channel::<SignedMessage>(OUTGOING_CHANNEL_SIZE);
             let pool = self.pool.clone();
+            let peer_scores = self.peer_scores.clone();
             Either::A(
                 Retry::spawn(strategy, action)
                     .map_err(into_failure)
-                    .and_then(move |socket| Self::configure_socket(socket, network_config))
-                    .and_then(move |outgoing_connection| {
+                    .and_then(move |(socket, address)| {
+                        Self::configure_socket(socket, network_config)
+                            .map(|socket| (socket, address))
+                    })
+                    .and_then(move |(outgoing_connection, address)| {
                         Self::build_handshake_initiator(outgoing_connection, key, &handshake_params)
+                            .map(|(socket, raw)| (socket, raw, address))
                     })
-                    .and_then(move |(socket, raw)| (Ok(socket), Self::parse_connect_msg(Some(raw))))
-                    .and_then(move |(socket, message)| {
+                    .and_then(move |(socket, raw, address)| {
+                        (Ok(socket), Self::parse_connect_msg(Some(raw)), Ok(address))
+                    })
+                    .and_then(move |(socket, message, unresolved_address)| {
                         let connection_limit_reached = pool.count_outgoing() >= max_connections;
                         if pool.contains(&message.author()) || connection_limit_reached {
                             Box::new(future::ok(()))
@@ -413,6 +456,7 @@ impl NetworkHandler {
                                 message,
                                 pool,
                                 &network_tx,
+                                peer_scores,
                             ))
                         }
                     })
@@ -426,11 +470,29 @@ impl NetworkHandler {
         }
     }
 
+    /// Connects to the first reachable address of the peer, trying the addresses
+    /// in the given order. Returns the socket and the address it is connected to.
+    fn connect_by_priority(addresses: Vec<String>) -> ConnectFuture {
+        let no_addresses: ConnectFuture = Box::new(err(io::Error::new(
+            io::ErrorKind::AddrNotAvailable,
+            "Peer has no addresses",
+        )));
+        addresses
+            .into_iter()
+            .fold(no_addresses, |previous, address| -> ConnectFuture {
+                Box::new(previous.or_else(move |_| {
+                    let connection = tokio_dns::TcpStream::connect(address.as_str());
+                    connection.map(move |socket| (socket, address))
+                }))
+            })
+    }
+
     fn process_messages(
         pool: &ConnectionPool,
         handle: &Handle,
         connection: Connection,
         network_tx: &mpsc::Sender<NetworkEvent>,
+        peer_scores: SharedPeerScores,
     ) -> Result<(), failure::Error> {
         let (sink, stream) = connection.socket.split();
 
@@ -439,6 +501,7 @@ impl NetworkHandler {
             pool.clone(),
             &connection.key,
             network_tx.clone(),
+            peer_scores,
         );
 
         let outgoing = Self::process_outgoing_messages(sink, connection.receiver_rx);
@@ -469,15 +532,31 @@ impl NetworkHandler {
         pool: ConnectionPool,
         key: &PublicKey,
         network_tx: mpsc::Sender<NetworkEvent>,
+        peer_scores: SharedPeerScores,
     ) -> impl Future<Item = (), Error = ()>
     where
         S: Stream<Item = Vec<u8>, Error = failure::Error>,
     {
         let key = *key;
+        let ban_scores = peer_scores.clone();
+        let malformed_scores = peer_scores.clone();
+        let messages = stream
+            .map_err(move |e| {
+                // I/O errors are caused by the connection itself, other ones mean
+                // that the peer has sent data which cannot be decrypted or decoded.
+                if e.downcast_ref::<io::Error>().is_none() {
+                    malformed_scores.report(&key, Misbehavior::MalformedMessage);
+                }
+                e
+            })
+            .take_while(move |_| Ok(!ban_scores.is_banned(&key)))
+            .filter(move |_| peer_scores.check_message(&key))
+            .map(move |raw| NetworkEvent::MessageReceived(key, raw));
+
         network_tx
             .clone()
             .sink_map_err(into_failure)
-            .send_all(stream.map(NetworkEvent::MessageReceived))
+            .send_all(messages)
             .then(move |_| pool.disconnect_with_peer(&key, &network_tx))
             .map_err(|e| {
                 error!("Connection terminated: {}: {}", e, e.find_root_cause());
@@ -499,11 +578,14 @@ impl NetworkHandler {
         message: Signed<Connect>,
         pool: ConnectionPool,
         network_tx: &mpsc::Sender<NetworkEvent>,
+        peer_scores: SharedPeerScores,
     ) -> impl Future<Item = (), Error = failure::Error> {
         trace!("Established connection with peer={:?}", connection.address);
         let handle = connection.handle.clone();
         Self::send_peer_connected_event(&connection.address, message, &network_tx).and_then(
-            move |network_tx| Self::process_messages(&pool, &handle, connection, &network_tx),
+            move |network_tx| {
+                Self::process_messages(&pool, &handle, connection, &network_tx, peer_scores)
+            },
         )
     }
 
@@ -535,6 +617,12 @@ impl NetworkHandler {
                 NetworkRequest::DisconnectWithPeer(peer) => {
                     to_box(self.pool.disconnect_with_peer(&peer, &self.network_tx))
                 }
+                NetworkRequest::UpdateConsensusKeys(public_key, secret_key, connect) => {
+                    self.handshake_params
+                        .borrow_mut()
+                        .set_keys(public_key, secret_key, connect);
+                    to_box(future::ok(()))
+                }
                 NetworkRequest::Shutdown => to_box(
                     cancel_sender
                         .take()
@@ -558,7 +646,10 @@ impl NetworkHandler {
     ) -> impl Future<Item = (), Error = failure::Error> {
         let pool = self.pool.clone();
 
-        if pool.contains(address) {
+        if self.peer_scores.is_banned(address) {
+            trace!("Dropping message to the banned peer {}", address);
+            to_box(future::ok(()))
+        } else if pool.contains(address) {
             to_box(pool.send_message(address, message))
         } else if self.can_create_connections() {
             to_box(self.create_new_connection(*address, message))
@@ -573,15 +664,15 @@ impl NetworkHandler {
         message: SignedMessage,
     ) -> impl Future<Item = (), Error = failure::Error> {
         let pool = self.pool.clone();
-        let connect = self.handshake_params.connect.clone();
-        self.connect(key, &self.handshake_params)
-            .and_then(move |_| {
-                if &message == connect.signed_message() {
-                    Either::A(future::ok(()))
-                } else {
-                    Either::B(pool.send_message(&key, message))
-                }
-            })
+        let handshake_params = self.handshake_params.borrow().clone();
+        let connect = handshake_params.connect.clone();
+        self.connect(key, &handshake_params).and_then(move |_| {
+            if &message == connect.signed_message() {
+                Either::A(future::ok(()))
+            } else {
+                Either::B(pool.send_message(&key, message))
+            }
+        })
     }
 
     fn send_peer_connected_event(
@@ -644,6 +735,7 @@ impl NetworkPart {
             self.network_tx.clone(),
             handshake_params.clone(),
             self.connect_list.clone(),
+            self.peer_scores.clone(),
         );
 
         let listener = handler.clone().listener();
@@ -1,13 +1,14 @@
 // spell-checker:ignore uint
 use failure;
 
+pub use self::rekey::{RekeyConfig, Rekeying};
 #[cfg(feature = "sodiumoxide-crypto")]
 #[doc(inline)]
 pub use self::wrappers::sodium_wrapper::{
     handshake::{HandshakeParams, NoiseHandshake},
     wrapper::{
         NoiseWrapper, HANDSHAKE_HEADER_LENGTH, MAX_HANDSHAKE_MESSAGE_LENGTH,
-        MIN_HANDSHAKE_MESSAGE_LENGTH,
+        MIN_HANDSHAKE_MESSAGE_LENGTH, TRANSPORT_KEY_LENGTH,
     },
 };
 
@@ -22,6 +23,7 @@ use tokio_io::{
 use events::{codec::MessagesCodec, error::into_failure};
 
 pub mod error;
+pub mod rekey;
 pub mod wrappers;
 
 #[cfg(test)]
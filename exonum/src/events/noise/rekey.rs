@@ -0,0 +1,80 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Periodic replacement of the keys used to encrypt messages of an established connection.
+
+use std::time::{Duration, Instant};
+
+use helpers::Milliseconds;
+
+/// Rekeying configuration.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct RekeyConfig {
+    /// Whether the node offers rekeying to its peers. Rekeying is used with a peer
+    /// only if both sides support it.
+    pub enabled: bool,
+    /// Amount of bytes encrypted with a single key.
+    pub max_bytes: u64,
+    /// Maximum time during which a single key is used.
+    pub interval: Milliseconds,
+}
+
+impl Default for RekeyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_bytes: 1 << 30,
+            interval: 3_600_000,
+        }
+    }
+}
+
+/// Tracks the usage of the key encrypting outgoing messages of a connection.
+/// Each side of the connection replaces its own outgoing key, sending the new key
+/// encrypted with the current one.
+#[derive(Debug)]
+pub struct Rekeying {
+    max_bytes: u64,
+    interval: Duration,
+    encrypted_bytes: u64,
+    key_created: Instant,
+}
+
+impl Rekeying {
+    /// Starts tracking the key established by the handshake.
+    pub fn new(config: &RekeyConfig) -> Self {
+        Self {
+            max_bytes: config.max_bytes,
+            interval: Duration::from_millis(config.interval),
+            encrypted_bytes: 0,
+            key_created: Instant::now(),
+        }
+    }
+
+    /// Returns `true` if the outgoing key should be replaced.
+    pub fn is_due(&self) -> bool {
+        self.encrypted_bytes >= self.max_bytes || self.key_created.elapsed() >= self.interval
+    }
+
+    /// Records the amount of bytes encrypted with the current key.
+    pub fn record(&mut self, len: usize) {
+        self.encrypted_bytes += len as u64;
+    }
+
+    /// Starts tracking a new key.
+    pub fn reset(&mut self) {
+        self.encrypted_bytes = 0;
+        self.key_created = Instant::now();
+    }
+}
@@ -12,9 +12,10 @@ use crypto::{
 };
 use events::{
     codec::MessagesCodec,
-    noise::{Handshake, HandshakeRawMessage, HandshakeResult},
+    compression::{Compression, CompressionConfig, MessageCompressor, SharedCompressionStats},
+    noise::{Handshake, HandshakeRawMessage, HandshakeResult, RekeyConfig, Rekeying},
 };
-use messages::{Connect, Signed};
+use messages::{Connect, Message, Service, Signed};
 use node::state::SharedConnectList;
 use storage::StorageValue;
 
@@ -27,6 +28,9 @@ pub struct HandshakeParams {
     pub connect_list: SharedConnectList,
     pub connect: Signed<Connect>,
     max_message_len: u32,
+    compression: CompressionConfig,
+    compression_stats: SharedCompressionStats,
+    rekeying: RekeyConfig,
 }
 
 impl HandshakeParams {
@@ -46,9 +50,39 @@ impl HandshakeParams {
             remote_key: None,
             connect,
             connect_list,
+            compression: CompressionConfig::default(),
+            compression_stats: SharedCompressionStats::default(),
+            rekeying: RekeyConfig::default(),
         }
     }
 
+    /// Sets parameters of the compression used with peers which support it. Compression is
+    /// offered to peers through the `Connect` message passed to the constructor.
+    pub fn set_compression(&mut self, config: CompressionConfig, stats: SharedCompressionStats) {
+        self.compression = config;
+        self.compression_stats = stats;
+    }
+
+    /// Sets parameters of the rekeying used with peers which support it. Rekeying is
+    /// offered to peers through the `Connect` message passed to the constructor.
+    pub fn set_rekeying(&mut self, config: RekeyConfig) {
+        self.rekeying = config;
+    }
+
+    /// Replaces the static key of the node and its `Connect` message, which are
+    /// used for the connections established afterwards.
+    pub fn set_keys(
+        &mut self,
+        public_key: PublicKey,
+        secret_key: SecretKey,
+        connect: Signed<Connect>,
+    ) {
+        let (public_key, secret_key) = into_x25519_keypair(public_key, secret_key).unwrap();
+        self.public_key = public_key;
+        self.secret_key = secret_key;
+        self.connect = connect;
+    }
+
     pub fn set_remote_key(&mut self, remote_key: PublicKey) {
         self.remote_key = Some(into_x25519_public_key(remote_key));
     }
@@ -61,6 +95,9 @@ pub struct NoiseHandshake {
     max_message_len: u32,
     connect_list: SharedConnectList,
     connect: Signed<Connect>,
+    compression: CompressionConfig,
+    compression_stats: SharedCompressionStats,
+    rekeying: RekeyConfig,
 }
 
 impl NoiseHandshake {
@@ -72,6 +109,9 @@ impl NoiseHandshake {
             max_message_len: params.max_message_len,
             connect_list: params.connect_list.clone(),
             connect: params.connect.clone(),
+            compression: params.compression,
+            compression_stats: params.compression_stats.clone(),
+            rekeying: params.rekeying,
         }
     }
 
@@ -83,6 +123,9 @@ impl NoiseHandshake {
             max_message_len: params.max_message_len,
             connect_list: params.connect_list.clone(),
             connect: params.connect.clone(),
+            compression: params.compression,
+            compression_stats: params.compression_stats.clone(),
+            rekeying: params.rekeying,
         }
     }
 
@@ -127,16 +170,55 @@ impl NoiseHandshake {
             bail!("peer is not in ConnectList")
         }
 
+        // Both sides of the connection know each other's `Connect` messages at this point,
+        // so they agree on the connection features without additional round trips.
+        let peer_connect = match Message::from_raw_buffer(message.clone()) {
+            Ok(Message::Service(Service::Connect(connect))) => Some(connect),
+            // The message is rejected later on.
+            _ => None,
+        };
+
+        let compressor = peer_connect
+            .as_ref()
+            .and_then(|connect| self.negotiate_compression(connect));
+        let rekeying = peer_connect
+            .as_ref()
+            .filter(|connect| self.connect.rekeying() && connect.rekeying())
+            .map(|_| Rekeying::new(&self.rekeying));
+
         let noise = self.noise.into_transport_mode()?;
-        let framed = MessagesCodec::new(self.max_message_len, noise).framed(stream);
-        Ok((framed, message))
+        let mut codec = MessagesCodec::new(self.max_message_len, noise);
+        if let Some(compressor) = compressor {
+            codec = codec.with_compressor(compressor);
+        }
+        if let Some(rekeying) = rekeying {
+            codec = codec.with_rekeying(rekeying);
+        }
+        Ok((codec.framed(stream), message))
+    }
+
+    fn negotiate_compression(&self, peer_connect: &Connect) -> Option<MessageCompressor> {
+        let algorithm =
+            Compression::negotiate(self.connect.compression(), peer_connect.compression())?;
+        trace!(
+            "Using {} compression with peer={}",
+            algorithm.name(),
+            self.peer_address
+        );
+        Some(MessageCompressor::new(
+            algorithm,
+            &self.compression,
+            self.compression_stats.clone(),
+        ))
     }
 
+    // Previous keys of the peers which have rotated their consensus keys
+    // are accepted during the transition window.
     fn is_peer_allowed(&self, remote_static_key: &x25519::PublicKey) -> bool {
         self.connect_list
-            .peers()
-            .iter()
-            .map(|info| into_x25519_public_key(info.public_key))
+            .allowed_keys()
+            .into_iter()
+            .map(into_x25519_public_key)
             .any(|key| remote_static_key == &key)
     }
 }
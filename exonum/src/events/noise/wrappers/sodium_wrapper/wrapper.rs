@@ -5,6 +5,7 @@
 use byteorder::{ByteOrder, LittleEndian};
 use bytes::BytesMut;
 use failure;
+use rand::{thread_rng, RngCore};
 use snow::{Builder, Session};
 
 use std::fmt::{self, Error, Formatter};
@@ -17,6 +18,8 @@ use events::noise::{error::NoiseError, HEADER_LENGTH, MAX_MESSAGE_LENGTH, TAG_LE
 pub const HANDSHAKE_HEADER_LENGTH: usize = 2;
 pub const MAX_HANDSHAKE_MESSAGE_LENGTH: usize = 65535;
 pub const MIN_HANDSHAKE_MESSAGE_LENGTH: usize = 32;
+/// Length of the keys used by the session in transport mode.
+pub const TRANSPORT_KEY_LENGTH: usize = 32;
 
 // We choose XK pattern since it provides mutual authentication,
 // transmission of static public keys and requires pre-defined remote public
@@ -137,6 +140,33 @@ impl NoiseWrapper {
         Ok(())
     }
 
+    /// Generates a random key for the session in transport mode.
+    pub fn generate_transport_key() -> Vec<u8> {
+        let mut key = vec![0_u8; TRANSPORT_KEY_LENGTH];
+        thread_rng().fill_bytes(&mut key);
+        key
+    }
+
+    /// Replaces the key used to encrypt outgoing messages. The nonce is reset as well.
+    pub fn set_outgoing_key(&mut self, key: &[u8]) -> Result<(), NoiseError> {
+        if self.session.is_initiator() {
+            self.session.rekey(Some(key), None)?;
+        } else {
+            self.session.rekey(None, Some(key))?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the key used to decrypt incoming messages. The nonce is reset as well.
+    pub fn set_incoming_key(&mut self, key: &[u8]) -> Result<(), NoiseError> {
+        if self.session.is_initiator() {
+            self.session.rekey(None, Some(key))?;
+        } else {
+            self.session.rekey(Some(key), None)?;
+        }
+        Ok(())
+    }
+
     fn read(&mut self, input: &[u8], buf: &mut [u8]) -> Result<usize, NoiseError> {
         let len = self.session.read_message(input, buf)?;
         Ok(len)
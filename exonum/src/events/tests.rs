@@ -33,7 +33,10 @@ use events::{
 };
 use helpers::user_agent;
 use messages::{Connect, Message, Signed, SignedMessage};
-use node::{state::SharedConnectList, ConnectInfo, ConnectList, EventsPoolCapacity, NodeChannel};
+use node::{
+    state::SharedConnectList, ConnectInfo, ConnectList, EventsPoolCapacity, NodeChannel,
+    SharedPeerScores,
+};
 
 #[derive(Debug)]
 pub struct TestHandler {
@@ -111,7 +114,7 @@ impl TestHandler {
 
     pub fn wait_for_message(&mut self) -> SignedMessage {
         match self.wait_for_event() {
-            Ok(NetworkEvent::MessageReceived(msg)) => SignedMessage::from_vec_unchecked(msg),
+            Ok(NetworkEvent::MessageReceived(_, msg)) => SignedMessage::from_vec_unchecked(msg),
             Ok(other) => panic!("Unexpected message received, {:?}", other),
             Err(e) => panic!("An error during wait for message occurred, {:?}", e),
         }
@@ -183,6 +186,7 @@ impl TestEvents {
             network_requests: channel.network_requests,
             network_tx: network_tx.clone(),
             connect_list: self.connect_list,
+            peer_scores: SharedPeerScores::new(network_config.peer_scoring),
         };
 
         let handler_part = TestHandler::new(self.listen_address, network_requests_tx, network_rx);
@@ -0,0 +1,709 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Archive of committed blocks for offline analytics and cold storage.
+//!
+//! # File format
+//!
+//! An archive is an append-only sequence of records following a header. All integers
+//! are little-endian.
+//!
+//! ```text
+//! archive  = header record*
+//! header   = magic:   8 bytes, "EXNMARCH"
+//!            version: u32, currently 1
+//! record   = kind:     u8
+//!            length:   u32, at most `MAX_RECORD_SIZE`
+//!            payload:  `length` bytes
+//!            checksum: 32 bytes, SHA-256 hash of `kind`, `length` and `payload`
+//! item     = length: u32, followed by `length` bytes
+//! ```
+//!
+//! Records are of two kinds:
+//!
+//! - `1`, configuration: the payload is a `StoredConfiguration` in its storage form.
+//!   The record precedes the first block at which the configuration becomes actual.
+//! - `2`, block: the payload is a sequence of items. The block header goes first,
+//!   followed by the number of precommits as a `u32` and the precommit messages.
+//!   Then go the number of transactions as a `u32` and, for every transaction of the
+//!   block, the transaction message and its execution result.
+//!
+//! Headers and execution results are encoded with protobuf, as they are stored in the
+//! database; messages are stored with their signatures.
+//!
+//! # Import
+//!
+//! `ArchiveImporter` rebuilds the block-history indexes of the core schema: blocks with
+//! their precommits, transactions with their locations and results, and configurations.
+//! Every block must follow the previously imported one, its precommits must be signed by
+//! the majority of the validators of the actual configuration and its transactions must
+//! match the transactions hash of the header. The archive carries no service state, so
+//! state hashes are not checked, and configurations are only checked to form a chain.
+//! The imported history is thus as trustworthy as its first configuration.
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use std::{
+    collections::HashSet,
+    fmt,
+    io::{self, Read, Write},
+    mem,
+};
+
+use blockchain::{Block, Schema, StoredConfiguration, TransactionResult, TxLocation};
+use crypto::{self, CryptoHash, Hash, HASH_SIZE};
+use helpers::Height;
+use messages::{BinaryForm, Message, Precommit, ProtocolMessage, RawTransaction, Signed};
+use node::state::State;
+use proto::ProtobufConvert;
+use storage::{Database, Error as StorageError, Snapshot, StorageValue};
+
+/// Magic bytes starting an archive.
+pub const ARCHIVE_MAGIC: &[u8; 8] = b"EXNMARCH";
+/// Version of the archive format.
+pub const ARCHIVE_VERSION: u32 = 1;
+/// Maximum length of the record payload in bytes.
+pub const MAX_RECORD_SIZE: u32 = 128 * 1024 * 1024;
+
+const CONFIGURATION_RECORD: u8 = 1;
+const BLOCK_RECORD: u8 = 2;
+
+/// Errors occurring while reading or importing an archive.
+#[derive(Debug, Fail)]
+pub enum ArchiveError {
+    /// I/O error.
+    #[fail(display = "I/O error: {}", _0)]
+    Io(#[cause] io::Error),
+
+    /// The archive does not start with the expected header.
+    #[fail(display = "Invalid archive header")]
+    InvalidHeader,
+
+    /// The archive has a format version unknown to this node.
+    #[fail(display = "Unsupported archive version {}", _0)]
+    UnsupportedVersion(u32),
+
+    /// The record is damaged.
+    #[fail(display = "Checksum mismatch in the record at offset {}", _0)]
+    ChecksumMismatch(u64),
+
+    /// The record cannot be decoded.
+    #[fail(display = "Malformed record: {}", _0)]
+    Malformed(String),
+
+    /// The block does not follow the last imported block.
+    #[fail(display = "Block at height {} does not follow the imported blocks", _0)]
+    UnexpectedBlock(Height),
+
+    /// The configuration does not follow the last imported configuration.
+    #[fail(
+        display = "Configuration {:?} does not follow the imported configurations",
+        _0
+    )]
+    UnexpectedConfiguration(Hash),
+
+    /// The block precedes any configuration.
+    #[fail(display = "No configuration is known at height {}", _0)]
+    MissingConfiguration(Height),
+
+    /// Precommits of the block are invalid.
+    #[fail(display = "Invalid precommits of the block at height {}: {}", _0, _1)]
+    InvalidPrecommits(Height, String),
+
+    /// Transactions of the block do not match its header.
+    #[fail(display = "Transactions do not match the block at height {}", _0)]
+    TransactionsMismatch(Height),
+
+    /// The database cannot be updated.
+    #[fail(display = "Storage error: {}", _0)]
+    Storage(#[cause] StorageError),
+}
+
+impl From<io::Error> for ArchiveError {
+    fn from(e: io::Error) -> Self {
+        ArchiveError::Io(e)
+    }
+}
+
+impl From<StorageError> for ArchiveError {
+    fn from(e: StorageError) -> Self {
+        ArchiveError::Storage(e)
+    }
+}
+
+/// Committed block with everything needed to restore its history.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchivedBlock {
+    /// Block header.
+    pub block: Block,
+    /// Precommits of the validators for the block.
+    pub precommits: Vec<Signed<Precommit>>,
+    /// Transactions of the block in the order of execution with their results.
+    pub transactions: Vec<(Signed<RawTransaction>, TransactionResult)>,
+}
+
+impl ArchivedBlock {
+    /// Reads the block at the given height from the database.
+    pub fn from_snapshot(snapshot: &dyn Snapshot, height: Height) -> Option<Self> {
+        let schema = Schema::new(snapshot);
+        let proof = schema.block_and_precommits(height)?;
+        let transactions = schema
+            .block_transactions(height)
+            .iter()
+            .map(|hash| {
+                let tx = schema
+                    .transactions()
+                    .get(&hash)
+                    .expect("BUG: missing transaction");
+                let result = schema
+                    .transaction_results()
+                    .get(&hash)
+                    .expect("BUG: missing transaction result");
+                (tx, result)
+            })
+            .collect();
+
+        Some(Self {
+            block: proof.block,
+            precommits: proof.precommits,
+            transactions,
+        })
+    }
+}
+
+/// Record of an archive.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArchiveRecord {
+    /// Configuration which becomes actual at the next block.
+    Configuration(StoredConfiguration),
+    /// Committed block.
+    Block(ArchivedBlock),
+}
+
+/// Writes records to an archive.
+#[derive(Debug)]
+pub struct ArchiveWriter<W> {
+    inner: W,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    /// Starts a new archive by writing its header.
+    pub fn new(mut inner: W) -> io::Result<Self> {
+        inner.write_all(ARCHIVE_MAGIC)?;
+        inner.write_u32::<LittleEndian>(ARCHIVE_VERSION)?;
+        Ok(Self { inner })
+    }
+
+    /// Continues the archive written before; `inner` must be positioned at its end.
+    pub fn append(inner: W) -> Self {
+        Self { inner }
+    }
+
+    /// Writes the configuration record.
+    pub fn write_configuration(&mut self, config: &StoredConfiguration) -> io::Result<()> {
+        self.write_record(CONFIGURATION_RECORD, &config.clone().into_bytes())
+    }
+
+    /// Writes the block record.
+    pub fn write_block(&mut self, block: &ArchivedBlock) -> io::Result<()> {
+        let mut payload = Vec::new();
+        write_item(&mut payload, &block.block.clone().into_bytes())?;
+        payload.write_u32::<LittleEndian>(block.precommits.len() as u32)?;
+        for precommit in &block.precommits {
+            write_item(&mut payload, &precommit.clone().into_bytes())?;
+        }
+        payload.write_u32::<LittleEndian>(block.transactions.len() as u32)?;
+        for (tx, result) in &block.transactions {
+            write_item(&mut payload, &tx.clone().into_bytes())?;
+            write_item(&mut payload, &result.clone().into_bytes())?;
+        }
+        self.write_record(BLOCK_RECORD, &payload)
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    fn write_record(&mut self, kind: u8, payload: &[u8]) -> io::Result<()> {
+        if payload.len() > MAX_RECORD_SIZE as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Record of {} bytes exceeds the size limit", payload.len()),
+            ));
+        }
+        let mut record = Vec::with_capacity(payload.len() + 5);
+        record.write_u8(kind)?;
+        record.write_u32::<LittleEndian>(payload.len() as u32)?;
+        record.extend_from_slice(payload);
+        self.inner.write_all(&record)?;
+        self.inner.write_all(crypto::hash(&record).as_ref())?;
+        self.inner.flush()
+    }
+}
+
+fn write_item(buffer: &mut Vec<u8>, item: &[u8]) -> io::Result<()> {
+    buffer.write_u32::<LittleEndian>(item.len() as u32)?;
+    buffer.extend_from_slice(item);
+    Ok(())
+}
+
+/// Blocks in a range of heights with the configurations which become actual among them.
+#[derive(Debug)]
+struct ExportRange {
+    from: Height,
+    to: Height,
+    /// Heights at which configurations become actual, with the hashes of the configurations.
+    config_changes: Vec<(Height, Hash)>,
+}
+
+impl ExportRange {
+    /// Cuts the exclusive range of heights at the current height of the blockchain.
+    fn new(snapshot: &dyn Snapshot, from: Height, to: Height) -> Self {
+        let schema = Schema::new(snapshot);
+        let to = Height(to.0.min(schema.height().next().0));
+        let config_changes = schema
+            .configs_actual_from()
+            .iter()
+            .filter(|reference| reference.actual_from() > from && reference.actual_from() < to)
+            .map(|reference| (reference.actual_from(), *reference.cfg_hash()))
+            .collect();
+        Self {
+            from,
+            to,
+            config_changes,
+        }
+    }
+
+    /// Writes the block at the given height, preceded by the configuration which becomes
+    /// actual at it. The first block of the range is preceded by its actual configuration.
+    fn write_block<W: Write>(
+        &self,
+        snapshot: &dyn Snapshot,
+        height: Height,
+        writer: &mut ArchiveWriter<W>,
+    ) -> io::Result<()> {
+        let schema = Schema::new(snapshot);
+        if height == self.from {
+            writer.write_configuration(&schema.configuration_by_height(height))?;
+        } else if let Some(&(_, ref cfg_hash)) = self
+            .config_changes
+            .iter()
+            .find(|&&(actual_from, _)| actual_from == height)
+        {
+            let config = schema
+                .configs()
+                .get(cfg_hash)
+                .expect("BUG: missing configuration");
+            writer.write_configuration(&config)?;
+        }
+        let block = ArchivedBlock::from_snapshot(snapshot, height).expect("BUG: missing block");
+        writer.write_block(&block)
+    }
+}
+
+/// Writes blocks in the given range of heights to the archive, preceded by the
+/// configurations which become actual in that range. The range is exclusive and is
+/// cut at the current height of the blockchain. Returns the number of written blocks.
+pub fn export_blocks<W: Write>(
+    snapshot: &dyn Snapshot,
+    from: Height,
+    to: Height,
+    writer: &mut ArchiveWriter<W>,
+) -> io::Result<u64> {
+    let range = ExportRange::new(snapshot, from, to);
+    let mut count = 0;
+    for height in range.from.0..range.to.0 {
+        range.write_block(snapshot, Height(height), writer)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Archive of the blocks in a range of heights, produced one block at a time.
+///
+/// Each item is the next chunk of the archive, which holds a block with the preceding
+/// configuration record, if any. The first chunk also holds the header of the archive.
+/// The chunks are read from the snapshot passed on creation, so the archive is consistent
+/// even if new blocks are committed meanwhile.
+pub struct ArchiveChunks {
+    snapshot: Box<dyn Snapshot>,
+    range: ExportRange,
+    next: Height,
+    writer: ArchiveWriter<Vec<u8>>,
+}
+
+impl fmt::Debug for ArchiveChunks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ArchiveChunks")
+            .field("range", &self.range)
+            .field("next", &self.next)
+            .finish()
+    }
+}
+
+impl ArchiveChunks {
+    /// Starts the archive of the blocks in the given range of heights. The range is
+    /// exclusive and is cut at the current height of the blockchain.
+    pub fn new(snapshot: Box<dyn Snapshot>, from: Height, to: Height) -> Self {
+        let range = ExportRange::new(snapshot.as_ref(), from, to);
+        let writer = ArchiveWriter::new(Vec::new()).expect("Cannot write archive header");
+        Self {
+            snapshot,
+            range,
+            next: from,
+            writer,
+        }
+    }
+}
+
+impl Iterator for ArchiveChunks {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next < self.range.to {
+            let height = self.next;
+            self.next = height.next();
+            if let Err(e) = self
+                .range
+                .write_block(self.snapshot.as_ref(), height, &mut self.writer)
+            {
+                return Some(Err(e));
+            }
+        }
+        if self.writer.inner.is_empty() {
+            None
+        } else {
+            Some(Ok(mem::replace(&mut self.writer.inner, Vec::new())))
+        }
+    }
+}
+
+/// Reads records from an archive.
+#[derive(Debug)]
+pub struct ArchiveReader<R> {
+    inner: R,
+    offset: u64,
+}
+
+impl<R: Read> ArchiveReader<R> {
+    /// Checks the header of the archive and creates a reader of its records.
+    pub fn new(mut inner: R) -> Result<Self, ArchiveError> {
+        let mut magic = [0; 8];
+        inner.read_exact(&mut magic)?;
+        if &magic != ARCHIVE_MAGIC {
+            return Err(ArchiveError::InvalidHeader);
+        }
+        let version = inner.read_u32::<LittleEndian>()?;
+        if version != ARCHIVE_VERSION {
+            return Err(ArchiveError::UnsupportedVersion(version));
+        }
+        Ok(Self {
+            inner,
+            offset: (ARCHIVE_MAGIC.len() + 4) as u64,
+        })
+    }
+
+    /// Reads the next record. Returns `None` at the end of the archive.
+    pub fn read_record(&mut self) -> Result<Option<ArchiveRecord>, ArchiveError> {
+        let kind = match self.inner.read_u8() {
+            Ok(kind) => kind,
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let length = self.inner.read_u32::<LittleEndian>()?;
+        if length > MAX_RECORD_SIZE {
+            return Err(ArchiveError::Malformed(format!(
+                "record length {} exceeds the limit of {} bytes",
+                length, MAX_RECORD_SIZE
+            )));
+        }
+        // The length is not trusted until the checksum is verified, so the payload
+        // is read without reserving the space for it.
+        let mut record = Vec::new();
+        record.write_u8(kind)?;
+        record.write_u32::<LittleEndian>(length)?;
+        (&mut self.inner)
+            .take(u64::from(length))
+            .read_to_end(&mut record)?;
+        if record.len() != length as usize + 5 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        let mut checksum = [0; HASH_SIZE];
+        self.inner.read_exact(&mut checksum)?;
+        if crypto::hash(&record).as_ref() != &checksum[..] {
+            return Err(ArchiveError::ChecksumMismatch(self.offset));
+        }
+        self.offset += (record.len() + HASH_SIZE) as u64;
+
+        let payload = &record[5..];
+        let record = match kind {
+            CONFIGURATION_RECORD => ArchiveRecord::Configuration(
+                StoredConfiguration::try_deserialize(payload).map_err(malformed)?,
+            ),
+            BLOCK_RECORD => ArchiveRecord::Block(decode_block(payload)?),
+            kind => {
+                return Err(ArchiveError::Malformed(format!(
+                    "unknown record kind {}",
+                    kind
+                )))
+            }
+        };
+        Ok(Some(record))
+    }
+}
+
+impl<R: Read> Iterator for ArchiveReader<R> {
+    type Item = Result<ArchiveRecord, ArchiveError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.read_record() {
+            Ok(record) => record.map(Ok),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+fn malformed<E: ToString>(e: E) -> ArchiveError {
+    ArchiveError::Malformed(e.to_string())
+}
+
+fn read_item<'a>(payload: &mut &'a [u8]) -> Result<&'a [u8], ArchiveError> {
+    let length = payload.read_u32::<LittleEndian>()? as usize;
+    if payload.len() < length {
+        return Err(ArchiveError::Malformed(
+            "item exceeds the record".to_owned(),
+        ));
+    }
+    let data: &'a [u8] = *payload;
+    let (item, rest) = data.split_at(length);
+    *payload = rest;
+    Ok(item)
+}
+
+fn read_message<T: ProtocolMessage>(payload: &mut &[u8]) -> Result<Signed<T>, ArchiveError> {
+    let message = Message::from_raw_buffer(read_item(payload)?.to_vec()).map_err(malformed)?;
+    T::try_from(message).map_err(|message| malformed(format!("unexpected message {:?}", message)))
+}
+
+fn decode_block(mut payload: &[u8]) -> Result<ArchivedBlock, ArchiveError> {
+    let payload = &mut payload;
+    let block = Block::decode(read_item(payload)?).map_err(malformed)?;
+
+    let precommits_count = payload.read_u32::<LittleEndian>()?;
+    let mut precommits = Vec::new();
+    for _ in 0..precommits_count {
+        precommits.push(read_message::<Precommit>(payload)?);
+    }
+
+    let transactions_count = payload.read_u32::<LittleEndian>()?;
+    let mut transactions = Vec::new();
+    for _ in 0..transactions_count {
+        let tx = read_message::<RawTransaction>(payload)?;
+        let result =
+            <TransactionResult as ProtobufConvert>::ProtoStruct::decode(read_item(payload)?)
+                .and_then(TransactionResult::from_pb)
+                .map_err(malformed)?;
+        transactions.push((tx, result));
+    }
+
+    if !payload.is_empty() {
+        return Err(ArchiveError::Malformed(
+            "unexpected data after the block".to_owned(),
+        ));
+    }
+    Ok(ArchivedBlock {
+        block,
+        precommits,
+        transactions,
+    })
+}
+
+/// Imports verified archive records into the database.
+///
+/// The importer continues the history stored in the database, which is empty
+/// for a fresh database.
+pub struct ArchiveImporter<'a> {
+    db: &'a dyn Database,
+    last_block: Option<Block>,
+    config: Option<StoredConfiguration>,
+}
+
+impl<'a> ArchiveImporter<'a> {
+    /// Creates an importer continuing the history stored in the database.
+    pub fn new(db: &'a dyn Database) -> Self {
+        let snapshot = db.snapshot();
+        let schema = Schema::new(&snapshot);
+        let last_block = if schema.block_hashes_by_height().is_empty() {
+            None
+        } else {
+            Some(schema.last_block())
+        };
+        let config = schema.configs_actual_from().last().map(|reference| {
+            schema
+                .configs()
+                .get(reference.cfg_hash())
+                .expect("BUG: missing configuration")
+        });
+
+        Self {
+            db,
+            last_block,
+            config,
+        }
+    }
+
+    /// Returns the last imported block, if any.
+    pub fn last_block(&self) -> Option<&Block> {
+        self.last_block.as_ref()
+    }
+
+    /// Imports all the records of the archive. Returns the number of imported blocks.
+    pub fn import<R: Read>(&mut self, reader: ArchiveReader<R>) -> Result<u64, ArchiveError> {
+        let mut count = 0;
+        for record in reader {
+            match record? {
+                ArchiveRecord::Configuration(config) => self.import_configuration(config)?,
+                ArchiveRecord::Block(block) => {
+                    self.import_block(block)?;
+                    count += 1;
+                }
+            }
+        }
+        Ok(count)
+    }
+
+    /// Imports the configuration which becomes actual at the next block.
+    /// The configuration already known to the importer is skipped.
+    pub fn import_configuration(
+        &mut self,
+        config: StoredConfiguration,
+    ) -> Result<(), ArchiveError> {
+        let next_height = self.next_height();
+        let previous_hash = match self.config {
+            Some(ref current) if current.hash() == config.hash() => return Ok(()),
+            Some(ref current) => current.hash(),
+            None => Hash::zero(),
+        };
+        if config.previous_cfg_hash != previous_hash || config.actual_from != next_height {
+            return Err(ArchiveError::UnexpectedConfiguration(config.hash()));
+        }
+
+        let mut fork = self.db.fork();
+        Schema::new(&mut fork).commit_configuration(config.clone());
+        self.db.merge(fork.into_patch())?;
+        self.config = Some(config);
+        Ok(())
+    }
+
+    /// Verifies the block and adds it to the history.
+    pub fn import_block(&mut self, archived: ArchivedBlock) -> Result<(), ArchiveError> {
+        let block = archived.block;
+        let height = block.height();
+        let last_hash = self
+            .last_block
+            .as_ref()
+            .map_or_else(Hash::zero, CryptoHash::hash);
+        if height != self.next_height() || *block.prev_hash() != last_hash {
+            return Err(ArchiveError::UnexpectedBlock(height));
+        }
+        let block_hash = block.hash();
+        match self.config {
+            Some(ref config) if config.actual_from <= height => {
+                if height > Height::zero() {
+                    verify_precommits(config, &block_hash, height, &archived.precommits)?;
+                }
+            }
+            _ => return Err(ArchiveError::MissingConfiguration(height)),
+        }
+        if archived.transactions.len() != block.tx_count() as usize {
+            return Err(ArchiveError::TransactionsMismatch(height));
+        }
+
+        let mut fork = self.db.fork();
+        {
+            let mut schema = Schema::new(&mut fork);
+            for (index, (tx, result)) in archived.transactions.into_iter().enumerate() {
+                let tx_hash = tx.hash();
+                schema.transactions_mut().put(&tx_hash, tx);
+                schema.transaction_results_mut().put(&tx_hash, result);
+                schema.block_transactions_mut(height).push(tx_hash);
+                let location = TxLocation::new(height, index as u64);
+                schema.transactions_locations_mut().put(&tx_hash, location);
+            }
+            if schema.block_transactions(height).merkle_root() != *block.tx_hash() {
+                return Err(ArchiveError::TransactionsMismatch(height));
+            }
+
+            for precommit in archived.precommits {
+                schema.precommits_mut(&block_hash).push(precommit);
+            }
+            schema.block_hashes_by_height_mut().push(block_hash);
+            schema.blocks_mut().put(&block_hash, block.clone());
+        }
+        self.db.merge(fork.into_patch())?;
+        self.last_block = Some(block);
+        Ok(())
+    }
+
+    fn next_height(&self) -> Height {
+        self.last_block
+            .as_ref()
+            .map_or_else(Height::zero, |block| block.height().next())
+    }
+}
+
+impl<'a> fmt::Debug for ArchiveImporter<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ArchiveImporter")
+            .field("last_block", &self.last_block)
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+fn verify_precommits(
+    config: &StoredConfiguration,
+    block_hash: &Hash,
+    height: Height,
+    precommits: &[Signed<Precommit>],
+) -> Result<(), ArchiveError> {
+    let invalid = |reason: &str| Err(ArchiveError::InvalidPrecommits(height, reason.to_owned()));
+    let validators = &config.validator_keys;
+    if precommits.len() < State::byzantine_majority_count(validators.len()) {
+        return invalid("not enough precommits");
+    }
+
+    let round = precommits[0].round();
+    let mut voted = HashSet::with_capacity(precommits.len());
+    for precommit in precommits {
+        let keys = match validators.get(precommit.validator().0 as usize) {
+            Some(keys) => keys,
+            None => return invalid("unknown validator"),
+        };
+        if keys.consensus_key != precommit.author() {
+            return invalid("precommit is not signed by the validator");
+        }
+        if precommit.block_hash() != block_hash
+            || precommit.height() != height
+            || precommit.round() != round
+        {
+            return invalid("precommit does not match the block");
+        }
+        if !voted.insert(precommit.validator()) {
+            return invalid("several precommits from one validator");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,223 @@
+//! Short-lived record of the transactions which did not reach the pool.
+//!
+//! The explorer cannot tell a transaction that was never submitted from one that
+//! was submitted but dropped, since dropped transactions are not persisted. The node
+//! therefore remembers the recent drops with their reasons. The record is bounded
+//! both in size and in time: entries older than `DROPPED_TRANSACTION_TTL_SECS` are
+//! forgotten, and when the record is full the oldest entries are forgotten first.
+
+use chrono::{DateTime, Duration, Utc};
+
+use std::collections::{HashMap, VecDeque};
+
+use crypto::Hash;
+
+/// Maximum number of the dropped transactions remembered by the node.
+pub const MAX_DROPPED_TRANSACTIONS: usize = 10_000;
+/// Number of seconds a dropped transaction is remembered by the node.
+pub const DROPPED_TRANSACTION_TTL_SECS: i64 = 600;
+
+/// Reason why a transaction was dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DropReason {
+    /// The transaction could not be passed to the node, for example, because
+    /// the node is shutting down.
+    NodeUnavailable,
+    /// The transaction cannot be decoded, has an invalid signature or does not
+    /// belong to any service of the node.
+    Invalid,
+    /// The node has rejected the transaction because it is already committed.
+    Rejected,
+}
+
+/// Transaction dropped by the node.
+///
+/// # JSON presentation
+///
+/// ```text
+/// {
+///   "tx_hash": "...",
+///   "reason": "invalid",
+///   "description": "Couldn't deserialize transaction message.",
+///   "time": "2019-01-01T00:00:00Z"
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DroppedTransaction {
+    /// Hash of the transaction.
+    pub tx_hash: Hash,
+    /// Reason why the transaction was dropped.
+    pub reason: DropReason,
+    /// Human-readable description of the error.
+    pub description: String,
+    /// Time when the transaction was dropped.
+    pub time: DateTime<Utc>,
+}
+
+/// Bounded record of the recently dropped transactions.
+#[derive(Debug)]
+pub(crate) struct DroppedTransactions {
+    items: HashMap<Hash, DroppedTransaction>,
+    /// Hashes with the times of their records, from the oldest to the newest.
+    /// A hash recorded several times occurs here once per record, so the capacity
+    /// bounds the length of `order` rather than the number of records.
+    order: VecDeque<(Hash, DateTime<Utc>)>,
+    capacity: usize,
+    ttl: Duration,
+}
+
+impl Default for DroppedTransactions {
+    fn default() -> Self {
+        Self::new(
+            MAX_DROPPED_TRANSACTIONS,
+            Duration::seconds(DROPPED_TRANSACTION_TTL_SECS),
+        )
+    }
+}
+
+impl DroppedTransactions {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            items: HashMap::new(),
+            order: VecDeque::new(),
+            capacity,
+            ttl,
+        }
+    }
+
+    /// Records the transaction, replacing the previous record with the same hash.
+    pub fn insert(&mut self, tx: DroppedTransaction) {
+        self.prune(tx.time);
+        while self.order.len() >= self.capacity {
+            if !self.pop_oldest() {
+                break;
+            }
+        }
+        self.order.push_back((tx.tx_hash, tx.time));
+        self.items.insert(tx.tx_hash, tx);
+    }
+
+    /// Returns the record of the transaction, if it has not expired by `now`.
+    pub fn get(&self, tx_hash: &Hash, now: DateTime<Utc>) -> Option<&DroppedTransaction> {
+        self.items
+            .get(tx_hash)
+            .filter(|tx| now - tx.time < self.ttl)
+    }
+
+    /// Forgets the records which have expired by `now`.
+    fn prune(&mut self, now: DateTime<Utc>) {
+        while let Some(&(_, time)) = self.order.front() {
+            if now - time < self.ttl {
+                break;
+            }
+            self.pop_oldest();
+        }
+    }
+
+    /// Removes the oldest entry of `order` and the record it refers to, unless the record
+    /// has been replaced since. Returns `false` if `order` is empty.
+    fn pop_oldest(&mut self) -> bool {
+        let (tx_hash, time) = match self.order.pop_front() {
+            Some(entry) => entry,
+            None => return false,
+        };
+        if self.items.get(&tx_hash).map_or(false, |tx| tx.time == time) {
+            self.items.remove(&tx_hash);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto;
+
+    fn dropped(n: u8, reason: DropReason, time: DateTime<Utc>) -> DroppedTransaction {
+        DroppedTransaction {
+            tx_hash: crypto::hash(&[n]),
+            reason,
+            description: format!("transaction {}", n),
+            time,
+        }
+    }
+
+    #[test]
+    fn test_dropped_transactions_expire() {
+        let start = Utc::now();
+        let mut record = DroppedTransactions::new(10, Duration::seconds(60));
+        record.insert(dropped(0, DropReason::Invalid, start));
+        let tx_hash = crypto::hash(&[0]);
+
+        let found = record.get(&tx_hash, start + Duration::seconds(59)).unwrap();
+        assert_eq!(found.reason, DropReason::Invalid);
+        assert!(record
+            .get(&tx_hash, start + Duration::seconds(60))
+            .is_none());
+
+        record.insert(dropped(
+            1,
+            DropReason::NodeUnavailable,
+            start + Duration::seconds(61),
+        ));
+        assert!(!record.items.contains_key(&tx_hash));
+        assert_eq!(record.order.len(), 1);
+    }
+
+    #[test]
+    fn test_dropped_transactions_capacity() {
+        let start = Utc::now();
+        let mut record = DroppedTransactions::new(2, Duration::seconds(60));
+        for n in 0..3 {
+            record.insert(dropped(n, DropReason::Invalid, start));
+        }
+        assert!(record.get(&crypto::hash(&[0]), start).is_none());
+        assert!(record.get(&crypto::hash(&[1]), start).is_some());
+        assert!(record.get(&crypto::hash(&[2]), start).is_some());
+    }
+
+    #[test]
+    fn test_dropped_transactions_replace() {
+        let start = Utc::now();
+        let later = start + Duration::seconds(30);
+        let mut record = DroppedTransactions::new(3, Duration::seconds(60));
+        record.insert(dropped(0, DropReason::Invalid, start));
+        record.insert(dropped(0, DropReason::NodeUnavailable, later));
+        record.insert(dropped(1, DropReason::Invalid, later));
+
+        let tx_hash = crypto::hash(&[0]);
+        assert_eq!(
+            record.get(&tx_hash, later).unwrap().reason,
+            DropReason::NodeUnavailable
+        );
+        assert!(record.get(&crypto::hash(&[1]), later).is_some());
+
+        // The replaced record outlives the time of the original one.
+        record.insert(dropped(
+            2,
+            DropReason::Invalid,
+            start + Duration::seconds(70),
+        ));
+        assert!(record
+            .get(&tx_hash, start + Duration::seconds(70))
+            .is_some());
+        assert_eq!(record.order.len(), 3);
+    }
+
+    #[test]
+    fn test_dropped_transactions_resubmitted() {
+        let start = Utc::now();
+        let mut record = DroppedTransactions::new(3, Duration::seconds(60));
+        record.insert(dropped(1, DropReason::Invalid, start));
+        for i in 0..10 {
+            let time = start + Duration::milliseconds(i);
+            record.insert(dropped(0, DropReason::Invalid, time));
+        }
+
+        assert_eq!(record.order.len(), 3);
+        assert_eq!(record.items.len(), 1);
+        assert!(record.get(&crypto::hash(&[0]), start).is_some());
+        assert!(record.get(&crypto::hash(&[1]), start).is_none());
+    }
+}
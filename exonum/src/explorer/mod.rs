@@ -3,9 +3,13 @@
 //!
 //! See the `explorer` example in the crate for examples of usage.
 
+use chrono::{DateTime, Utc};
+#[cfg(feature = "openapi")]
+use schemars::{gen::SchemaGenerator, schema::Schema as JsonSchemaObject, JsonSchema};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use std::{
+    borrow::Cow,
     cell::{Ref, RefCell},
     collections::Bound,
     fmt,
@@ -13,14 +17,20 @@ use std::{
     slice,
 };
 
+use api::pagination::{Cursor, Page, PageRequest};
 use blockchain::{
-    Block, Blockchain, Schema, TransactionError, TransactionErrorType, TransactionMessage,
-    TransactionResult, TxLocation,
+    Block, BlockProof, Blockchain, Schema, TransactionError, TransactionErrorType,
+    TransactionMessage, TransactionResult, TxLocation, CORE_SERVICE, TRANSACTION_RESULTS_TABLE,
 };
-use crypto::{CryptoHash, Hash};
+use crypto::{CryptoHash, Hash, PublicKey};
 use helpers::Height;
 use messages::{Precommit, RawTransaction, Signed};
-use storage::{ListProof, Snapshot};
+use storage::{ListProof, MapProof, Snapshot};
+
+pub use self::dropped::{DropReason, DroppedTransaction};
+
+pub mod archive;
+pub mod dropped;
 
 /// Transaction parsing result.
 type ParseResult = Result<TransactionMessage, failure::Error>;
@@ -420,25 +430,28 @@ impl<'a> IntoIterator for &'a BlockWithTransactions {
 /// # } // main
 /// ```
 #[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(JsonSchema))]
 pub struct CommittedTransaction {
     content: TransactionMessage,
     location: TxLocation,
     location_proof: ListProof<Hash>,
     #[serde(with = "TxStatus")]
+    #[cfg_attr(feature = "openapi", schemars(with = "TxStatus<'static>"))]
     status: TransactionResult,
 }
 
 /// Transaction execution status. Simplified version of `TransactionResult`.
 #[serde(tag = "type", rename_all = "kebab-case")]
 #[derive(Debug, Serialize, Deserialize)]
-enum TxStatus<'a> {
+#[cfg_attr(feature = "openapi", derive(JsonSchema))]
+pub(crate) enum TxStatus<'a> {
     Success,
-    Panic { description: &'a str },
-    Error { code: u8, description: &'a str },
+    Panic { description: Cow<'a, str> },
+    Error { code: u8, description: Cow<'a, str> },
 }
 
 impl<'a> TxStatus<'a> {
-    fn serialize<S>(result: &TransactionResult, serializer: S) -> Result<S::Ok, S::Error>
+    pub(crate) fn serialize<S>(result: &TransactionResult, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
@@ -446,9 +459,9 @@ impl<'a> TxStatus<'a> {
         status.serialize(serializer)
     }
 
-    fn deserialize<D>(deserializer: D) -> Result<TransactionResult, D::Error>
+    pub(crate) fn deserialize<'de, D>(deserializer: D) -> Result<TransactionResult, D::Error>
     where
-        D: Deserializer<'a>,
+        D: Deserializer<'de>,
     {
         let tx_status = <Self as Deserialize>::deserialize(deserializer)?;
         Ok(TransactionResult::from(tx_status))
@@ -462,7 +475,7 @@ impl<'a> From<&'a TransactionResult> for TxStatus<'a> {
         match (*result).0 {
             Ok(()) => TxStatus::Success,
             Err(ref e) => {
-                let description = e.description().unwrap_or_default();
+                let description = Cow::Borrowed(e.description().unwrap_or_default());
                 match e.error_type() {
                     Panic => TxStatus::Panic { description },
                     Code(code) => TxStatus::Error { code, description },
@@ -484,14 +497,53 @@ impl<'a> From<TxStatus<'a>> for TransactionResult {
 
         TransactionResult(match status {
             TxStatus::Success => Ok(()),
-            TxStatus::Panic { description } => Err(TransactionError::panic(to_option(description))),
+            TxStatus::Panic { description } => {
+                Err(TransactionError::panic(to_option(&description)))
+            }
             TxStatus::Error { code, description } => {
-                Err(TransactionError::code(code, to_option(description)))
+                Err(TransactionError::code(code, to_option(&description)))
             }
         })
     }
 }
 
+/// Transaction results are serialized in the same way as the `status` of
+/// a [`CommittedTransaction`], e.g., within the proofs of the `transaction_results` table.
+///
+/// [`CommittedTransaction`]: struct.CommittedTransaction.html#json-presentation
+impl Serialize for TransactionResult {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        TxStatus::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for TransactionResult {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        TxStatus::deserialize(deserializer)
+    }
+}
+
+#[cfg(feature = "openapi")]
+impl JsonSchema for TransactionResult {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        "TransactionResult".to_owned()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> JsonSchemaObject {
+        TxStatus::json_schema(gen)
+    }
+}
+
 impl CommittedTransaction {
     /// Returns the content of the transaction.
     pub fn content(&self) -> &TransactionMessage {
@@ -514,6 +566,40 @@ impl CommittedTransaction {
     }
 }
 
+/// Proof that a transaction is committed with the given execution result.
+///
+/// The proof can be verified offline against the blocks signed by the validators:
+///
+/// - `location_proof` proves the transaction hash at `location` against the `tx_hash`
+///   of the block in `block_proof`;
+/// - `to_table` and `to_result` prove the execution result against the `state_hash`
+///   of the block in `state_block_proof`. The `transaction_results` table is aggregated
+///   into the state hash at the [`TRANSACTION_RESULTS_TABLE`] index among the core tables,
+///   see [`Schema::core_state_hash`].
+///
+/// Transaction results cannot change once committed, so the result is proven
+/// in the state of the latest block, which is referred to by `state_block_proof`.
+/// For transactions of the latest block, both block proofs are the same.
+///
+/// [`TRANSACTION_RESULTS_TABLE`]: ../blockchain/constant.TRANSACTION_RESULTS_TABLE.html
+/// [`Schema::core_state_hash`]: ../blockchain/struct.Schema.html#method.core_state_hash
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(JsonSchema))]
+pub struct TransactionProof {
+    /// Block containing the transaction, with its precommits.
+    pub block_proof: BlockProof,
+    /// Location of the transaction in the block.
+    pub location: TxLocation,
+    /// Proof of the transaction hash in the list of the block transactions.
+    pub location_proof: ListProof<Hash>,
+    /// Latest block of the blockchain, with its precommits.
+    pub state_block_proof: BlockProof,
+    /// Proof of the `transaction_results` table in the state of the latest block.
+    pub to_table: MapProof<Hash, Hash>,
+    /// Proof of the transaction result in the `transaction_results` table.
+    pub to_result: MapProof<Hash, TransactionResult>,
+}
+
 /// Information about the transaction.
 ///
 /// Values of this type are returned by the [`transaction()`] method of the `BlockchainExplorer`.
@@ -590,6 +676,7 @@ impl CommittedTransaction {
 /// # } // main
 /// ```
 #[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(JsonSchema))]
 #[serde(tag = "type", rename_all = "kebab-case")]
 pub enum TransactionInfo {
     /// Transaction is in the memory pool, but not yet committed to the blockchain.
@@ -637,6 +724,37 @@ impl TransactionInfo {
     }
 }
 
+/// Execution outcome of a committed transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum TxOutcome {
+    /// The transaction was executed successfully.
+    Success,
+    /// The transaction returned an error or panicked.
+    Failure,
+}
+
+/// Maximum number of the index entries examined by a single transaction search.
+pub const MAX_SCANNED_TRANSACTIONS: usize = 10_000;
+
+/// Filter of the transactions searched with the explorer indexes.
+///
+/// At least one of `author` and `service_id` should be specified.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TransactionFilter {
+    /// Author of the transactions.
+    pub author: Option<PublicKey>,
+    /// Identifier of the service the transactions belong to.
+    pub service_id: Option<u16>,
+    /// Earliest time of the blocks containing the transactions, inclusive.
+    pub since: Option<DateTime<Utc>>,
+    /// Latest time of the blocks containing the transactions, exclusive.
+    pub until: Option<DateTime<Utc>>,
+    /// Execution outcome of the transactions.
+    pub outcome: Option<TxOutcome>,
+}
+
 /// Blockchain explorer.
 ///
 /// # Notes
@@ -680,6 +798,29 @@ impl<'a> BlockchainExplorer<'a> {
         Some(TransactionInfo::Committed(tx))
     }
 
+    /// Returns the proof that the transaction is committed with its execution result,
+    /// or `None` if the transaction is not committed.
+    pub fn transaction_proof(&self, tx_hash: &Hash) -> Option<TransactionProof> {
+        let schema = Schema::new(&self.snapshot);
+        let location = schema.transactions_locations().get(tx_hash)?;
+        let block_proof = schema.block_and_precommits(location.block_height())?;
+        let location_proof = schema
+            .block_transactions(location.block_height())
+            .get_proof(location.position_in_block());
+        let state_block_proof = schema
+            .block_and_precommits(schema.height())
+            .expect("Latest block is absent");
+
+        Some(TransactionProof {
+            block_proof,
+            location,
+            location_proof,
+            state_block_proof,
+            to_table: schema.get_proof_to_service_table(CORE_SERVICE, TRANSACTION_RESULTS_TABLE),
+            to_result: schema.transaction_results().get_proof(*tx_hash),
+        })
+    }
+
     /// Returns transaction message without proof.
     pub fn transaction_without_proof(&self, tx_hash: &Hash) -> Option<TransactionMessage> {
         let schema = Schema::new(&self.snapshot);
@@ -774,6 +915,118 @@ impl<'a> BlockchainExplorer<'a> {
         })
     }
 
+    /// Returns the median time of the block precommits, or `None` if the block
+    /// is not recorded in the explorer indexes.
+    pub fn block_time(&self, height: Height) -> Option<DateTime<Utc>> {
+        Schema::new(&self.snapshot).block_times().get(&height.0)
+    }
+
+    /// Returns a page of the committed transactions matching the filter, from
+    /// the newest to the oldest. Block times are taken from the explorer indexes,
+    /// so transactions in the blocks missing from the indexes never match
+    /// a time range.
+    ///
+    /// Returns `None` if the filter specifies neither the author nor the service.
+    ///
+    /// # Notes
+    ///
+    /// Transactions are found with the explorer indexes, which should be enabled
+    /// with [`Blockchain::set_explorer_indexes`].
+    ///
+    /// At most [`MAX_SCANNED_TRANSACTIONS`] entries of the index are examined per call.
+    /// If the page is not full by then, it holds fewer items than requested, possibly
+    /// none, and its cursor points to the first entry that has not been examined.
+    ///
+    /// [`Blockchain::set_explorer_indexes`]: ../blockchain/struct.Blockchain.html#method.set_explorer_indexes
+    /// [`MAX_SCANNED_TRANSACTIONS`]: constant.MAX_SCANNED_TRANSACTIONS.html
+    pub fn search_transactions(
+        &self,
+        filter: &TransactionFilter,
+        request: PageRequest,
+    ) -> Option<Page<CommittedTransaction>> {
+        self.search_transactions_scanning(filter, request, MAX_SCANNED_TRANSACTIONS)
+    }
+
+    /// Searches the transactions examining at most `max_scanned` entries of the index.
+    pub(crate) fn search_transactions_scanning(
+        &self,
+        filter: &TransactionFilter,
+        request: PageRequest,
+        max_scanned: usize,
+    ) -> Option<Page<CommittedTransaction>> {
+        let schema = Schema::new(&self.snapshot);
+        let tx_hashes = match (filter.author, filter.service_id) {
+            (Some(ref author), _) => schema.transactions_by_author(author),
+            (None, Some(service_id)) => schema.transactions_by_service(service_id),
+            (None, None) => return None,
+        };
+        if tx_hashes.is_empty() {
+            return Some(Page {
+                items: Vec::new(),
+                next_cursor: None,
+            });
+        }
+
+        let transactions = schema.transactions();
+        let locations = schema.transactions_locations();
+        let results = schema.transaction_results();
+        let block_times = schema.block_times();
+        let matches = |tx_hash: &Hash| -> bool {
+            if let (Some(_), Some(service_id)) = (filter.author, filter.service_id) {
+                let tx = transactions.get(tx_hash).unwrap();
+                if tx.service_id() != service_id {
+                    return false;
+                }
+            }
+            if filter.since.is_some() || filter.until.is_some() {
+                let height = locations.get(tx_hash).unwrap().block_height();
+                let time = match block_times.get(&height.0) {
+                    Some(time) => time,
+                    None => return false,
+                };
+                if filter.since.map_or(false, |since| time < since)
+                    || filter.until.map_or(false, |until| time >= until)
+                {
+                    return false;
+                }
+            }
+            if let Some(outcome) = filter.outcome {
+                let success = results.get(tx_hash).unwrap().0.is_ok();
+                if success != (outcome == TxOutcome::Success) {
+                    return false;
+                }
+            }
+            true
+        };
+
+        let last = tx_hashes.len() - 1;
+        let start = request.start(last).min(last);
+        let page = request.collect(
+            (0..start + 1)
+                .rev()
+                .take(max_scanned)
+                .map(|position| (position, tx_hashes.get(position).unwrap()))
+                .filter(|&(_, ref tx_hash)| matches(tx_hash)),
+        );
+        // Continue after the examined entries if they are not exhausted.
+        let next_cursor = page.next_cursor.or_else(|| {
+            let max_scanned = max_scanned as u64;
+            if start >= max_scanned {
+                Some(Cursor::new(start - max_scanned))
+            } else {
+                None
+            }
+        });
+        Some(Page {
+            items: page
+                .items
+                .iter()
+                .map(|tx_hash| self.committed_transaction(tx_hash, None))
+                .collect(),
+            next_cursor,
+        })
+    }
+
     /// Iterates over blocks in the blockchain.
     pub fn blocks<R: Into<HeightRange>>(&self, heights: R) -> Blocks {
         use std::cmp::max;
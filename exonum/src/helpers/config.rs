@@ -13,6 +13,7 @@ use std::{
     thread,
 };
 
+use crypto::{PublicKey, SecretKey};
 use node::{ConnectListConfig, NodeConfig};
 
 /// Implements loading and saving TOML-encoded configurations.
@@ -50,13 +51,26 @@ fn do_load<T: DeserializeOwned>(path: &Path) -> Result<T, Error> {
     Ok(toml::de::from_str(&toml)?)
 }
 
+// The value is written into a temporary file next to the target one, which then replaces
+// the target. Thus, readers never observe a partially written config.
 fn do_save<T: Serialize>(value: &T, path: &Path) -> Result<(), Error> {
     if let Some(dir) = path.parent() {
         fs::create_dir_all(dir)?;
     }
-    let mut file = File::create(path)?;
     let value_toml = toml::Value::try_from(value)?;
+
+    let mut tmp_name = path
+        .file_name()
+        .ok_or_else(|| format_err!("config path {} is not a file", path.display()))?
+        .to_owned();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let mut file = File::create(&tmp_path)?;
     file.write_all(value_toml.to_string().as_bytes())?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&tmp_path, path)?;
     Ok(())
 }
 
@@ -72,6 +86,8 @@ pub struct ConfigManager {
 pub enum ConfigRequest {
     /// Request for connect list update in config file.
     UpdateConnectList(ConnectListConfig),
+    /// Request for replacing consensus keys with the next ones in config file.
+    UpdateConsensusKeys(PublicKey, SecretKey),
 }
 
 impl ConfigManager {
@@ -90,6 +106,15 @@ impl ConfigManager {
 
                         let res = Self::update_connect_list(connect_list, &path);
 
+                        if let Err(ref error) = res {
+                            error!("Unable to update config: {}", error);
+                        }
+                    }
+                    ConfigRequest::UpdateConsensusKeys(public_key, secret_key) => {
+                        info!("Updating consensus keys. New public key: {}", public_key);
+
+                        let res = Self::update_consensus_keys(public_key, secret_key, &path);
+
                         if let Err(ref error) = res {
                             error!("Unable to update config: {}", error);
                         }
@@ -109,6 +134,13 @@ impl ConfigManager {
             .expect("Can't message to ConfigManager thread");
     }
 
+    /// Stores new consensus keys at file system.
+    pub fn store_consensus_keys(&self, public_key: PublicKey, secret_key: SecretKey) {
+        self.tx
+            .send(ConfigRequest::UpdateConsensusKeys(public_key, secret_key))
+            .expect("Can't message to ConfigManager thread");
+    }
+
     /// Stops `ConfigManager`.
     pub fn stop(self) {
         drop(self.tx);
@@ -128,4 +160,72 @@ impl ConfigManager {
 
         Ok(())
     }
+
+    // Replaces consensus keys on file system synchronously. The next keys
+    // in the key rotation settings are cleared.
+    #[doc(hidden)]
+    pub fn update_consensus_keys<P>(
+        public_key: PublicKey,
+        secret_key: SecretKey,
+        path: &P,
+    ) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let mut current_config: NodeConfig = ConfigFile::load(path)?;
+        current_config.consensus_public_key = public_key;
+        current_config.consensus_secret_key = secret_key;
+        current_config.key_rotation.next_consensus_public_key = None;
+        current_config.key_rotation.next_consensus_secret_key = None;
+        ConfigFile::save(&current_config, path)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+    use crypto::gen_keypair;
+    use helpers::generate_testnet_config;
+
+    #[test]
+    fn test_update_connect_list() {
+        let dir = TempDir::new("exonum_config").unwrap();
+        let path = dir.path().join("node.toml");
+        let configs = generate_testnet_config(2, 16_700);
+        ConfigFile::save(&configs[0], &path).unwrap();
+
+        let mut connect_list = configs[0].connect_list.clone();
+        connect_list.peers.remove(0);
+        connect_list.peers[0].pinned = true;
+        ConfigManager::update_connect_list(connect_list.clone(), &path).unwrap();
+
+        let config: NodeConfig = ConfigFile::load(&path).unwrap();
+        assert_eq!(config.connect_list, connect_list);
+        assert_eq!(config.consensus_public_key, configs[0].consensus_public_key);
+        // No temporary files are left after the update.
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_update_consensus_keys() {
+        let dir = TempDir::new("exonum_config").unwrap();
+        let path = dir.path().join("node.toml");
+        let mut config = generate_testnet_config(1, 16_700).remove(0);
+        let (public_key, secret_key) = gen_keypair();
+        config.key_rotation.next_consensus_public_key = Some(public_key);
+        config.key_rotation.next_consensus_secret_key = Some(secret_key.clone());
+        ConfigFile::save(&config, &path).unwrap();
+
+        ConfigManager::update_consensus_keys(public_key, secret_key.clone(), &path).unwrap();
+
+        let config: NodeConfig = ConfigFile::load(&path).unwrap();
+        assert_eq!(config.consensus_public_key, public_key);
+        assert_eq!(config.consensus_secret_key, secret_key);
+        assert_eq!(config.key_rotation.next_consensus_public_key, None);
+        assert_eq!(config.key_rotation.next_consensus_secret_key, None);
+    }
 }
@@ -7,7 +7,10 @@ use std::{
 
 use super::{
     clap_backend::ClapBackend,
-    details::{Finalize, GenerateCommonConfig, GenerateNodeConfig, GenerateTestnet, Run, RunDev},
+    details::{
+        Finalize, GenerateCommonConfig, GenerateNodeConfig, GenerateTestnet, Run, RunDev,
+        RunReplica,
+    },
     info::Info,
     internal::{CollectedCommand, Command, Feedback},
     keys,
@@ -73,6 +76,15 @@ impl NodeBuilder {
                 let node = Node::new(db, services, config, config_file_path);
                 Some(node)
             }
+            Feedback::ReplayTrace(ref ctx) => {
+                let services: Vec<Box<dyn Service>> = self
+                    .service_factories
+                    .into_iter()
+                    .map(|mut factory| factory.make_service(ctx))
+                    .collect();
+                Maintenance::replay_trace(ctx, services);
+                None
+            }
             _ => None,
         }
     }
@@ -125,6 +137,7 @@ impl NodeBuilder {
             Box::new(GenerateTestnet) as Box<dyn Command>,
             Box::new(Run),
             Box::new(RunDev),
+            Box::new(RunReplica),
             Box::new(GenerateNodeConfig),
             Box::new(GenerateCommonConfig),
             Box::new(Finalize),
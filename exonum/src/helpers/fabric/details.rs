@@ -24,7 +24,7 @@ use super::{
 use api::backends::actix::AllowOrigin;
 use blockchain::{config::ValidatorKeys, GenesisConfig};
 use helpers::{config::ConfigFile, generate_testnet_config};
-use node::{ConnectListConfig, NodeApiConfig, NodeConfig};
+use node::{ConnectListConfig, NodeApiConfig, NodeConfig, ReplicaConfig};
 use storage::{Database, DbOptions, RocksDB};
 
 const DATABASE_PATH: &str = "DATABASE_PATH";
@@ -32,6 +32,7 @@ const OUTPUT_DIR: &str = "OUTPUT_DIR";
 const PEER_ADDRESS: &str = "PEER_ADDRESS";
 const LISTEN_ADDRESS: &str = "LISTEN_ADDRESS";
 const NODE_CONFIG_PATH: &str = "NODE_CONFIG_PATH";
+const REPLICA_CONFIG_PATH: &str = "REPLICA_CONFIG_PATH";
 const PUBLIC_API_ADDRESS: &str = "PUBLIC_API_ADDRESS";
 const PRIVATE_API_ADDRESS: &str = "PRIVATE_API_ADDRESS";
 const PUBLIC_ALLOW_ORIGIN: &str = "PUBLIC_ALLOW_ORIGIN";
@@ -146,6 +147,83 @@ impl Command for Run {
     }
 }
 
+/// Command for running a read replica, which follows the blockchain from
+/// the trusted nodes without taking part in consensus.
+pub struct RunReplica;
+
+impl Command for RunReplica {
+    fn args(&self) -> Vec<Argument> {
+        vec![
+            Argument::new_named(
+                REPLICA_CONFIG_PATH,
+                true,
+                "Path to replica configuration file.",
+                "c",
+                "replica-config",
+                false,
+            ),
+            Argument::new_named(
+                DATABASE_PATH,
+                true,
+                "Use database with the given path.",
+                "d",
+                "db-path",
+                false,
+            ),
+            Argument::new_named(
+                PUBLIC_API_ADDRESS,
+                false,
+                "Listen address for public api.",
+                None,
+                "public-api-address",
+                false,
+            ),
+            Argument::new_named(
+                PRIVATE_API_ADDRESS,
+                false,
+                "Listen address for private api.",
+                None,
+                "private-api-address",
+                false,
+            ),
+        ]
+    }
+
+    fn name(&self) -> CommandName {
+        "run-replica"
+    }
+
+    fn about(&self) -> &str {
+        "Run read replica"
+    }
+
+    fn execute(
+        &self,
+        _commands: &HashMap<CommandName, CollectedCommand>,
+        mut context: Context,
+        exts: &dyn Fn(Context) -> Context,
+    ) -> Feedback {
+        let config_path = context
+            .arg::<String>(REPLICA_CONFIG_PATH)
+            .unwrap_or_else(|_| panic!("{} not found.", REPLICA_CONFIG_PATH));
+        let replica_config: ReplicaConfig =
+            ConfigFile::load(config_path).expect("Can't load replica config file");
+        let mut config = replica_config
+            .into_node_config()
+            .expect("Invalid replica config");
+
+        if let Some(public_addr) = Run::public_api_address(&context) {
+            config.api.public_api_address = Some(public_addr);
+        }
+        if let Some(private_addr) = Run::private_api_address(&context) {
+            config.api.private_api_address = Some(private_addr);
+        }
+
+        context.set(keys::NODE_CONFIG, config);
+        Feedback::RunNode(exts(context))
+    }
+}
+
 /// Command for running service in dev mode.
 pub struct RunDev;
 
@@ -667,6 +745,11 @@ impl Command for Finalize {
                 database: Default::default(),
                 connect_list,
                 thread_pool_size: Default::default(),
+                consensus_trace: None,
+                key_rotation: Default::default(),
+                external_addresses: Vec::new(),
+                replica: false,
+                explorer_indexes: false,
             }
         };
 
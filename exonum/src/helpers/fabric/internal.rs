@@ -9,6 +9,8 @@ use super::{Argument, CommandExtension, CommandName, Context};
 pub enum Feedback {
     /// Run node with current context.
     RunNode(Context),
+    /// Replay the consensus trace with the services of the node and current context.
+    ReplayTrace(Context),
     /// Do nothing
     None,
 }
@@ -1,14 +1,22 @@
 //! This module implements node maintenance actions.
 
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::BufReader,
+    path::Path,
+};
 
 use super::{
     internal::{CollectedCommand, Command, Feedback},
     Argument, CommandName, Context,
 };
-use blockchain::Schema;
-use helpers::config::ConfigFile;
-use node::NodeConfig;
+use blockchain::{Schema, Service};
+use explorer::archive::{
+    export_blocks, ArchiveImporter, ArchiveReader, ArchiveRecord, ArchiveWriter,
+};
+use helpers::{config::ConfigFile, Height};
+use node::{ConsensusTrace, NodeConfig, TraceReplayer};
 use storage::{Database, DbOptions, RocksDB};
 
 // Context entry for the path to the node config.
@@ -17,10 +25,20 @@ const NODE_CONFIG_PATH: &str = "NODE_CONFIG_PATH";
 const DATABASE_PATH: &str = "DATABASE_PATH";
 // Context entry for the type of action to be performed.
 const MAINTENANCE_ACTION_PATH: &str = "MAINTENANCE_ACTION_PATH";
+// Context entry for the path to the blocks archive.
+const ARCHIVE_PATH: &str = "ARCHIVE_PATH";
+// Context entry for the path to the consensus trace.
+const TRACE_PATH: &str = "TRACE_PATH";
 
 /// Maintenance command. Supported actions:
 ///
 /// - `clear-cache` - clear message cache.
+/// - `export-archive` - write committed blocks to the archive, appending the blocks
+///   missing from it if the archive exists.
+/// - `import-archive` - verify blocks from the archive and add them to the block history
+///   of the database.
+/// - `replay-trace` - replay the consensus trace recorded by the node on a copy of its
+///   database taken before the trace has been started.
 #[derive(Debug)]
 pub struct Maintenance;
 
@@ -53,6 +71,98 @@ impl Maintenance {
 
         info!("Cache cleared successfully");
     }
+
+    fn archive_path(ctx: &Context) -> String {
+        ctx.arg::<String>(ARCHIVE_PATH)
+            .unwrap_or_else(|_| panic!("{} not found.", ARCHIVE_PATH))
+    }
+
+    fn export_archive(context: &Context) {
+        let config = Self::node_config(context);
+        let db = Self::database(context, &config.database);
+        let path = Self::archive_path(context);
+
+        let (mut writer, from) = if Path::new(&path).exists() {
+            let file = File::open(&path).expect("Can't open archive file");
+            let reader = ArchiveReader::new(BufReader::new(file)).expect("Can't read archive");
+            let mut from = Height::zero();
+            for record in reader {
+                if let ArchiveRecord::Block(block) = record.expect("Archive is damaged") {
+                    from = block.block.height().next();
+                }
+            }
+            let file = OpenOptions::new()
+                .append(true)
+                .open(&path)
+                .expect("Can't open archive file");
+            (ArchiveWriter::append(file), from)
+        } else {
+            let file = File::create(&path).expect("Can't create archive file");
+            let writer = ArchiveWriter::new(file).expect("Can't write archive header");
+            (writer, Height::zero())
+        };
+
+        info!("Exporting blocks starting from height {}", from);
+        let snapshot = db.snapshot();
+        let count = export_blocks(
+            snapshot.as_ref(),
+            from,
+            Height(u64::max_value()),
+            &mut writer,
+        )
+        .expect("Can't export blocks");
+        info!("Exported {} blocks", count);
+    }
+
+    fn import_archive(context: &Context) {
+        let config = Self::node_config(context);
+        let db = Self::database(context, &config.database);
+        let path = Self::archive_path(context);
+
+        let file = File::open(&path).expect("Can't open archive file");
+        let reader = ArchiveReader::new(BufReader::new(file)).expect("Can't read archive");
+        let mut importer = ArchiveImporter::new(&*db);
+        let count = importer
+            .import(reader)
+            .unwrap_or_else(|e| panic!("Can't import archive: {}", e));
+        info!(
+            "Imported {} blocks, the last block is {:?}",
+            count,
+            importer.last_block()
+        );
+    }
+
+    /// Replays the consensus trace with the given services. The database is modified by
+    /// the replay, so it should be a copy of the node database.
+    pub(crate) fn replay_trace(context: &Context, services: Vec<Box<dyn Service>>) {
+        let config = Self::node_config(context);
+        let db = Self::database(context, &config.database);
+        let path = context
+            .arg::<String>(TRACE_PATH)
+            .unwrap_or_else(|_| panic!("{} not found.", TRACE_PATH));
+
+        let file = File::open(&path).expect("Can't open trace file");
+        let entries = ConsensusTrace::read(BufReader::new(file)).expect("Can't read trace");
+        let start = match entries.first() {
+            Some(entry) => entry.time,
+            None => {
+                info!("Trace is empty");
+                return;
+            }
+        };
+
+        let mut replayer = TraceReplayer::new(db, services, config, start);
+        let count = replayer
+            .replay(entries)
+            .unwrap_or_else(|e| panic!("Replay has diverged: {}", e));
+        info!(
+            "Replayed {} events, the node is at height {}, round {} with the last block {:?}",
+            count,
+            replayer.handler().state().height(),
+            replayer.handler().state().round(),
+            replayer.handler().last_block_hash()
+        );
+    }
 }
 
 impl Command for Maintenance {
@@ -82,6 +192,22 @@ impl Command for Maintenance {
                 "action",
                 false,
             ),
+            Argument::new_named(
+                ARCHIVE_PATH,
+                false,
+                "Path to the blocks archive.",
+                None,
+                "archive",
+                false,
+            ),
+            Argument::new_named(
+                TRACE_PATH,
+                false,
+                "Path to the consensus trace.",
+                None,
+                "trace",
+                false,
+            ),
         ]
     }
 
@@ -90,7 +216,8 @@ impl Command for Maintenance {
     }
 
     fn about(&self) -> &str {
-        "Maintenance module. Available actions: clear-cache."
+        "Maintenance module. Available actions: clear-cache, export-archive, import-archive, \
+         replay-trace."
     }
 
     fn execute(
@@ -103,10 +230,13 @@ impl Command for Maintenance {
             .arg::<String>(MAINTENANCE_ACTION_PATH)
             .unwrap_or_else(|_| panic!("{} not found.", MAINTENANCE_ACTION_PATH));
 
-        if action == "clear-cache" {
-            Self::clear_cache(&context);
-        } else {
-            println!("Unsupported maintenance action: {}", action);
+        match action.as_str() {
+            "clear-cache" => Self::clear_cache(&context),
+            "export-archive" => Self::export_archive(&context),
+            "import-archive" => Self::import_archive(&context),
+            // Services are available only to the node builder.
+            "replay-trace" => return Feedback::ReplayTrace(context),
+            _ => println!("Unsupported maintenance action: {}", action),
         }
 
         Feedback::None
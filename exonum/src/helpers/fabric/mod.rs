@@ -3,7 +3,10 @@
 pub use self::{
     builder::NodeBuilder,
     context_key::ContextKey,
-    details::{Finalize, GenerateCommonConfig, GenerateNodeConfig, GenerateTestnet, Run, RunDev},
+    details::{
+        Finalize, GenerateCommonConfig, GenerateNodeConfig, GenerateTestnet, Run, RunDev,
+        RunReplica,
+    },
     internal::Command,
     maintenance::Maintenance,
     shared::{AbstractConfig, CommonConfigTemplate, NodePrivateConfig, NodePublicConfig},
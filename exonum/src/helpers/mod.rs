@@ -61,6 +61,11 @@ pub fn generate_testnet_config(count: u16, start_port: u16) -> Vec<NodeConfig> {
             services_configs: Default::default(),
             database: Default::default(),
             thread_pool_size: Default::default(),
+            consensus_trace: None,
+            key_rotation: Default::default(),
+            external_addresses: Vec::new(),
+            replica: false,
+            explorer_indexes: false,
         })
         .collect::<Vec<_>>()
 }
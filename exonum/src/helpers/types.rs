@@ -3,6 +3,8 @@
 use std::{fmt, num::ParseIntError, str::FromStr};
 
 use crypto::{CryptoHash, Hash};
+#[cfg(feature = "openapi")]
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 /// Number of milliseconds.
@@ -224,6 +226,7 @@ impl Round {
 
 /// Validators identifier.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(JsonSchema))]
 pub struct ValidatorId(pub u16);
 
 impl ValidatorId {
@@ -316,6 +319,21 @@ impl<'de> Deserialize<'de> for Height {
     }
 }
 
+#[cfg(feature = "openapi")]
+impl JsonSchema for Height {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        "Height".to_owned()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        u64::json_schema(gen)
+    }
+}
+
 impl FromStr for Height {
     type Err = ParseIntError;
 
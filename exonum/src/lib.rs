@@ -53,6 +53,8 @@ extern crate exonum_sodiumoxide as sodiumoxide;
 #[macro_use]
 extern crate failure;
 extern crate futures;
+#[cfg(feature = "grpc")]
+extern crate grpc;
 extern crate hex;
 #[macro_use]
 extern crate log;
@@ -60,6 +62,8 @@ extern crate os_info;
 extern crate rand;
 extern crate rand_xorshift;
 extern crate rust_decimal;
+#[cfg(feature = "openapi")]
+extern crate schemars;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
@@ -78,6 +82,7 @@ extern crate tokio_retry;
 extern crate tokio_threadpool;
 extern crate toml;
 extern crate uuid;
+extern crate zstd;
 
 // Test dependencies.
 #[cfg(test)]
@@ -20,6 +20,8 @@
 use byteorder::{ByteOrder, LittleEndian};
 use failure::Error;
 use hex::{FromHex, ToHex};
+#[cfg(feature = "openapi")]
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
 use serde::de::{self, Deserialize, Deserializer};
 use serde::ser::{Serialize, Serializer};
 
@@ -95,6 +97,11 @@ impl RawTransaction {
     pub fn service_id(&self) -> u16 {
         self.service_id
     }
+
+    /// Returns the identifier of the transaction within the service.
+    pub fn transaction_id(&self) -> u16 {
+        self.service_transaction.transaction_id
+    }
 }
 
 impl BinaryForm for RawTransaction {
@@ -301,3 +308,20 @@ where
             })
     }
 }
+
+#[cfg(feature = "openapi")]
+impl<T> JsonSchema for Signed<T> {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        "SignedMessage".to_owned()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let mut schema = String::json_schema(gen).into_object();
+        schema.metadata().description = Some("Hex of the signed message".to_owned());
+        schema.into()
+    }
+}
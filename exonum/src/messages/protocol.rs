@@ -13,14 +13,17 @@
 //!     * generation - in which cases message is generated
 
 use bit_vec::BitVec;
+use byteorder::{ByteOrder, LittleEndian};
 use chrono::{DateTime, Utc};
 use failure;
 
-use std::{borrow::Cow, fmt::Debug, mem};
+use std::{borrow::Cow, cmp, fmt::Debug, mem};
 
 use super::{BinaryForm, RawTransaction, ServiceTransaction, Signed, SignedMessage};
 use blockchain;
-use crypto::{CryptoHash, Hash, PublicKey, SecretKey, PUBLIC_KEY_LENGTH, SIGNATURE_LENGTH};
+use crypto::{
+    self, CryptoHash, Hash, PublicKey, SecretKey, HASH_SIZE, PUBLIC_KEY_LENGTH, SIGNATURE_LENGTH,
+};
 use helpers::{Height, Round, ValidatorId};
 use proto;
 use storage::{proof_list_index as merkle, StorageValue};
@@ -35,6 +38,19 @@ pub const EMPTY_SIGNED_MESSAGE_SIZE: usize =
 pub const TRANSACTION_RESPONSE_EMPTY_SIZE: usize =
     EMPTY_SIGNED_MESSAGE_SIZE + PUBLIC_KEY_LENGTH + mem::size_of::<u8>() * 4;
 
+/// `Signed<BlocksResponse>` size without blocks inside.
+#[doc(hidden)]
+pub const BLOCKS_RESPONSE_EMPTY_SIZE: usize =
+    EMPTY_SIGNED_MESSAGE_SIZE + PUBLIC_KEY_LENGTH + mem::size_of::<u8>() * 4;
+
+/// Maximal size of the field header of a single block inside `BlocksResponse`.
+#[doc(hidden)]
+pub const BLOCKS_RESPONSE_BLOCK_HEADER: usize = mem::size_of::<u8>() + mem::size_of::<u32>() + 1;
+
+/// Size of a single transaction hash inside `TransactionsAnnounce`.
+#[doc(hidden)]
+pub const TRANSACTIONS_ANNOUNCE_HASH_SIZE: usize = HASH_SIZE + mem::size_of::<u8>() * 4;
+
 /// `Signed<RawTransaction>` size with empty transaction inside.
 pub const RAW_TRANSACTION_EMPTY_SIZE: usize = EMPTY_SIGNED_MESSAGE_SIZE + mem::size_of::<u16>() * 2;
 
@@ -60,6 +76,12 @@ pub struct Connect {
     time: DateTime<Utc>,
     /// String containing information about this node including Exonum, Rust and OS versions.
     user_agent: String,
+    /// Message compression algorithms supported by the node.
+    compression: Vec<String>,
+    /// Whether the node supports rekeying of established connections.
+    rekeying: bool,
+    /// Additional addresses of the node with their priorities.
+    addresses: Vec<PeerAddressRecord>,
 }
 
 impl Connect {
@@ -69,9 +91,32 @@ impl Connect {
             pub_addr: addr.to_owned(),
             time,
             user_agent: user_agent.to_owned(),
+            compression: Vec::new(),
+            rekeying: false,
+            addresses: Vec::new(),
         }
     }
 
+    /// Advertises message compression algorithms supported by the node. Peers
+    /// which do not know this field ignore it and communicate without compression.
+    pub fn with_compression(mut self, compression: Vec<String>) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Advertises support of rekeying of established connections.
+    pub fn with_rekeying(mut self, rekeying: bool) -> Self {
+        self.rekeying = rekeying;
+        self
+    }
+
+    /// Advertises additional addresses at which the node can be reached. Peers
+    /// which do not know this field use only `pub_addr`.
+    pub fn with_addresses(mut self, addresses: Vec<PeerAddressRecord>) -> Self {
+        self.addresses = addresses;
+        self
+    }
+
     /// The node's address.
     pub fn pub_addr(&self) -> &str {
         &self.pub_addr
@@ -86,6 +131,41 @@ impl Connect {
     pub fn user_agent(&self) -> &str {
         &self.user_agent
     }
+
+    /// Message compression algorithms supported by the node.
+    pub fn compression(&self) -> &[String] {
+        &self.compression
+    }
+
+    /// Whether the node supports rekeying of established connections.
+    pub fn rekeying(&self) -> bool {
+        self.rekeying
+    }
+
+    /// Additional addresses of the node with their priorities.
+    pub fn addresses(&self) -> &[PeerAddressRecord] {
+        &self.addresses
+    }
+}
+
+/// Network address of a node advertised in its `Connect` message.
+#[derive(Clone, PartialEq, Eq, Ord, PartialOrd, Debug, Serialize, Deserialize, ProtobufConvert)]
+#[exonum(pb = "proto::PeerAddressRecord", crate = "crate")]
+pub struct PeerAddressRecord {
+    /// External address of the node, hostname:port.
+    pub address: String,
+    /// Priority of the address. Addresses with lower values are tried first.
+    pub priority: u32,
+}
+
+impl PeerAddressRecord {
+    /// Creates a new address record.
+    pub fn new(address: &str, priority: u32) -> Self {
+        Self {
+            address: address.to_owned(),
+            priority,
+        }
+    }
 }
 
 /// Current node status.
@@ -131,6 +211,39 @@ impl Status {
     }
 }
 
+/// Announcement of transactions known to the sender.
+///
+/// ### Validation
+/// The message is ignored if its author is not in the connect list.
+///
+/// ### Processing
+/// Hashes of transactions that are not known to the node are requested
+/// from the author with `TransactionsRequest`.
+///
+/// ### Generation
+/// The message is broadcast instead of transaction bodies when the number
+/// of peers exceeds `node::TxGossipConfig::flood_peers_threshold`. Hashes of
+/// new transactions are accumulated and sent after
+/// `node::TxGossipConfig::announce_interval`.
+#[derive(Clone, PartialEq, Eq, Ord, PartialOrd, Debug, ProtobufConvert)]
+#[exonum(pb = "proto::TransactionsAnnounce", crate = "crate")]
+pub struct TransactionsAnnounce {
+    /// The list of the transaction hashes.
+    txs: Vec<Hash>,
+}
+
+impl TransactionsAnnounce {
+    /// Create new `TransactionsAnnounce` message.
+    pub fn new(txs: &[Hash]) -> Self {
+        Self { txs: txs.to_vec() }
+    }
+
+    /// The list of the transaction hashes.
+    pub fn txs(&self) -> &[Hash] {
+        &self.txs
+    }
+}
+
 /// Proposal for a new block.
 ///
 /// ### Validation
@@ -416,6 +529,131 @@ impl BlockResponse {
     }
 }
 
+/// Information about a block with transactions identified by short ids.
+///
+/// ### Validation
+/// The message is ignored if
+///     * its `to` field corresponds to a different node
+///     * the `block`, `transactions` and `precommits` fields cannot be
+///     parsed or verified
+///
+/// ### Processing
+/// Prefilled transactions are added to the pool, then transaction hashes
+/// are restored from the short ids of the transactions in the pool. If all
+/// hashes are restored and match the block, the block is added to the
+/// blockchain. Otherwise `BlockRequest` is sent to the author.
+///
+/// ### Generation
+/// The message is sent as response to `CompactBlockRequest`. Transactions
+/// which the author itself had to request while processing the block are
+/// prefilled, as the recipient is likely to lack them as well.
+#[derive(Clone, PartialEq, Eq, Ord, PartialOrd, Debug, ProtobufConvert)]
+#[exonum(pb = "proto::CompactBlockResponse", crate = "crate")]
+pub struct CompactBlockResponse {
+    /// Public key of the recipient.
+    to: PublicKey,
+    /// Block header.
+    block: blockchain::Block,
+    /// List of pre-commits.
+    precommits: Vec<Vec<u8>>,
+    /// Short ids of the block transactions.
+    short_ids: Vec<u64>,
+    /// Prefilled transactions.
+    transactions: Vec<Vec<u8>>,
+}
+
+impl CompactBlockResponse {
+    /// Create new `CompactBlockResponse` message.
+    pub fn new(
+        to: &PublicKey,
+        block: blockchain::Block,
+        precommits: Vec<Vec<u8>>,
+        short_ids: Vec<u64>,
+        transactions: Vec<Vec<u8>>,
+    ) -> Self {
+        Self {
+            to: *to,
+            block,
+            precommits,
+            short_ids,
+            transactions,
+        }
+    }
+
+    /// Returns the short id of the transaction in the block with the given hash.
+    ///
+    /// Short ids are salted with the block hash, so that transactions with colliding
+    /// ids cannot be crafted before the block is created.
+    pub fn short_tx_id(block_hash: &Hash, tx_hash: &Hash) -> u64 {
+        let mut buffer = Vec::with_capacity(HASH_SIZE * 2);
+        buffer.extend_from_slice(block_hash.as_ref());
+        buffer.extend_from_slice(tx_hash.as_ref());
+        LittleEndian::read_u64(&crypto::hash(&buffer).as_ref()[..8])
+    }
+
+    /// Public key of the recipient.
+    pub fn to(&self) -> &PublicKey {
+        &self.to
+    }
+    /// Block header.
+    pub fn block(&self) -> blockchain::Block {
+        self.block.clone()
+    }
+    /// List of pre-commits.
+    pub fn precommits(&self) -> Vec<Vec<u8>> {
+        self.precommits.clone()
+    }
+    /// Short ids of the block transactions.
+    pub fn short_ids(&self) -> &[u64] {
+        &self.short_ids
+    }
+    /// Prefilled transactions.
+    pub fn transactions(&self) -> Vec<Vec<u8>> {
+        self.transactions.clone()
+    }
+}
+
+/// Information about several consecutive blocks.
+///
+/// ### Validation
+/// The message is ignored if
+///     * its `to` field corresponds to a different node
+///     * the `blocks` field cannot be parsed or contains blocks signed by
+///     another node
+///
+/// ### Processing
+/// Blocks are buffered and added to the blockchain in the order of their heights,
+/// each one is processed as a separate `BlockResponse`.
+///
+/// ### Generation
+/// The message is sent as response to `BlockRequest` for several blocks. Blocks
+/// are included while the message fits into `max_message_len`, so the response
+/// may contain fewer blocks than requested.
+#[derive(Clone, PartialEq, Eq, Ord, PartialOrd, Debug, ProtobufConvert)]
+#[exonum(pb = "proto::BlocksResponse", crate = "crate")]
+pub struct BlocksResponse {
+    /// Public key of the recipient.
+    to: PublicKey,
+    /// List of the signed `BlockResponse` messages.
+    blocks: Vec<Vec<u8>>,
+}
+
+impl BlocksResponse {
+    /// Create new `BlocksResponse` message.
+    pub fn new(to: &PublicKey, blocks: Vec<Vec<u8>>) -> Self {
+        Self { to: *to, blocks }
+    }
+
+    /// Public key of the recipient.
+    pub fn to(&self) -> &PublicKey {
+        &self.to
+    }
+    /// List of the signed `BlockResponse` messages.
+    pub fn blocks(&self) -> Vec<Vec<u8>> {
+        self.blocks.clone()
+    }
+}
+
 /// Information about the transactions.
 ///
 /// ### Validation
@@ -612,7 +850,9 @@ impl PrevotesRequest {
 /// level corresponds to the `from` field.
 ///
 /// ### Processing
-/// Peer `Connect` messages are sent to the recipient.
+/// Peer `Connect` messages are sent to the recipient. `Connect` messages of
+/// the peers discovered outside of the connect list are sent only if `discovery`
+/// is set, since other nodes do not accept them.
 ///
 /// ### Generation
 /// `PeersRequest` message is sent regularly with the timeout controlled by
@@ -622,29 +862,49 @@ impl PrevotesRequest {
 pub struct PeersRequest {
     /// Public key of the recipient.
     to: PublicKey,
+    /// Whether the sender discovers peers outside of its connect list.
+    discovery: bool,
 }
 
 impl PeersRequest {
     /// Create new `PeersRequest`.
     pub fn new(to: &PublicKey) -> Self {
-        Self { to: *to }
+        Self {
+            to: *to,
+            discovery: false,
+        }
+    }
+
+    /// Requests `Connect` messages of the peers which the recipient has discovered
+    /// outside of its connect list.
+    pub fn with_discovery(mut self, discovery: bool) -> Self {
+        self.discovery = discovery;
+        self
     }
+
     /// Public key of the recipient.
     pub fn to(&self) -> &PublicKey {
         &self.to
     }
+
+    /// Whether the sender discovers peers outside of its connect list.
+    pub fn discovery(&self) -> bool {
+        self.discovery
+    }
 }
 
-/// Request for the block with the given `height`.
+/// Request for `count` consecutive blocks starting from the given `height`.
 ///
 /// ### Validation
 /// The message is ignored if its `height` is bigger than the node's one.
 ///
 /// ### Processing
-/// `BlockResponse` message is sent as the response.
+/// `BlockResponse` message is sent as the response to the request of a single
+/// block, `BlocksResponse` is sent if several blocks are requested.
 ///
 /// ### Generation
-/// This message can be sent during `Status` processing.
+/// This message can be sent during `Status` processing. Requests for several
+/// blocks are sent if the node lags behind its peers by more than one block.
 #[derive(Clone, PartialEq, Eq, Ord, PartialOrd, Debug, ProtobufConvert)]
 #[exonum(pb = "proto::BlockRequest", crate = "crate")]
 pub struct BlockRequest {
@@ -652,10 +912,61 @@ pub struct BlockRequest {
     to: PublicKey,
     /// The height to which the message is related.
     height: Height,
+    /// Number of the requested blocks.
+    count: u32,
 }
 
 impl BlockRequest {
-    /// Create new `BlockRequest`.
+    /// Create new `BlockRequest` for a single block.
+    pub fn new(to: &PublicKey, height: Height) -> Self {
+        Self::range(to, height, 1)
+    }
+    /// Create new `BlockRequest` for `count` blocks starting from `height`.
+    pub fn range(to: &PublicKey, height: Height, count: u32) -> Self {
+        Self {
+            to: *to,
+            height,
+            count,
+        }
+    }
+    /// Public key of the recipient.
+    pub fn to(&self) -> &PublicKey {
+        &self.to
+    }
+    /// The height to which the message is related.
+    pub fn height(&self) -> Height {
+        self.height
+    }
+    /// Number of the requested blocks. Requests without the count, sent by nodes
+    /// of previous versions, ask for a single block.
+    pub fn count(&self) -> u32 {
+        cmp::max(self.count, 1)
+    }
+}
+
+/// Request for the block with the given `height` in the compact form.
+///
+/// ### Validation
+/// The message is ignored if its `height` is bigger than the node's one.
+///
+/// ### Processing
+/// `CompactBlockResponse` message is sent as the response.
+///
+/// ### Generation
+/// This message is sent instead of `BlockRequest` if compact blocks are
+/// enabled, the node lags slightly behind the peer and has transactions
+/// in the pool.
+#[derive(Clone, PartialEq, Eq, Ord, PartialOrd, Debug, ProtobufConvert)]
+#[exonum(pb = "proto::CompactBlockRequest", crate = "crate")]
+pub struct CompactBlockRequest {
+    /// Public key of the recipient.
+    to: PublicKey,
+    /// The height to which the message is related.
+    height: Height,
+}
+
+impl CompactBlockRequest {
+    /// Create new `CompactBlockRequest`.
     pub fn new(to: &PublicKey, height: Height) -> Self {
         Self { to: *to, height }
     }
@@ -676,6 +987,38 @@ impl BlockResponse {
     }
 }
 
+impl CompactBlockResponse {
+    /// Verify prefilled transactions and return their safer wrappers.
+    pub(crate) fn verify_transactions(
+        &self,
+    ) -> Result<Vec<Signed<RawTransaction>>, ::failure::Error> {
+        self.transactions()
+            .into_iter()
+            .map(|buffer| {
+                let signed = SignedMessage::from_raw_buffer(buffer)?;
+                let protocol = Message::deserialize(signed)?;
+                ProtocolMessage::try_from(protocol)
+                    .map_err(|_| format_err!("Couldn't verify transaction from message"))
+            })
+            .collect()
+    }
+}
+
+impl BlocksResponse {
+    /// Verify blocks and return their safer wrappers.
+    pub(crate) fn verify_blocks(&self) -> Result<Vec<Signed<BlockResponse>>, ::failure::Error> {
+        self.blocks()
+            .into_iter()
+            .map(|buffer| {
+                let signed = SignedMessage::from_raw_buffer(buffer)?;
+                let protocol = Message::deserialize(signed)?;
+                ProtocolMessage::try_from(protocol)
+                    .map_err(|_| format_err!("Couldn't verify block from message"))
+            })
+            .collect()
+    }
+}
+
 impl Precommit {
     /// Verify precommits signature and return it's safer wrapper
     pub(crate) fn verify_precommit(buffer: Vec<u8>) -> Result<Signed<Precommit>, ::failure::Error> {
@@ -833,6 +1176,8 @@ impl_protocol! {
             Connect = 1,
             /// `Status` information of other node.
             Status = 2,
+            /// Hashes of transactions known to other node.
+            TransactionsAnnounce = 3,
         },
         /// Exonum consensus specific node messages.
         1 => Consensus {
@@ -849,6 +1194,10 @@ impl_protocol! {
             TransactionsResponse = 0,
             /// Information about block, that sent as response to `BlockRequest`.
             BlockResponse = 1,
+            /// Information about block, that sent as response to `CompactBlockRequest`.
+            CompactBlockResponse = 2,
+            /// Information about several blocks, that sent as response to `BlockRequest`.
+            BlocksResponse = 3,
         },
         /// Exonum node requests.
         3 => Requests {
@@ -862,6 +1211,8 @@ impl_protocol! {
             PeersRequest = 3,
             /// Request of some future block.
             BlockRequest = 4,
+            /// Request of some future block in the compact form.
+            CompactBlockRequest = 5,
         },
 
     }
@@ -933,6 +1284,7 @@ impl Requests {
             Requests::PrevotesRequest(ref msg) => msg.to(),
             Requests::PeersRequest(ref msg) => msg.to(),
             Requests::BlockRequest(ref msg) => msg.to(),
+            Requests::CompactBlockRequest(ref msg) => msg.to(),
         }
     }
 
@@ -944,6 +1296,7 @@ impl Requests {
             Requests::PrevotesRequest(ref msg) => msg.author(),
             Requests::PeersRequest(ref msg) => msg.author(),
             Requests::BlockRequest(ref msg) => msg.author(),
+            Requests::CompactBlockRequest(ref msg) => msg.author(),
         }
     }
 }
@@ -3,9 +3,11 @@ use hex::{self, FromHex};
 use serde_json;
 
 use super::{
-    BinaryForm, BlockResponse, Message, Precommit, ProtocolMessage, RawTransaction,
-    ServiceTransaction, Signed, SignedMessage, Status, TransactionsResponse,
-    RAW_TRANSACTION_EMPTY_SIZE, TRANSACTION_RESPONSE_EMPTY_SIZE,
+    BinaryForm, BlockRequest, BlockResponse, BlocksResponse, Message, Precommit, ProtocolMessage,
+    RawTransaction, ServiceTransaction, Signed, SignedMessage, Status, TransactionsAnnounce,
+    TransactionsResponse, BLOCKS_RESPONSE_BLOCK_HEADER, BLOCKS_RESPONSE_EMPTY_SIZE,
+    EMPTY_SIGNED_MESSAGE_SIZE, RAW_TRANSACTION_EMPTY_SIZE, TRANSACTIONS_ANNOUNCE_HASH_SIZE,
+    TRANSACTION_RESPONSE_EMPTY_SIZE,
 };
 use blockchain::{Block, BlockProof};
 use crypto::{gen_keypair, hash, PublicKey, SecretKey};
@@ -24,6 +26,46 @@ fn test_block_response_empty_size() {
     )
 }
 
+#[test]
+fn test_blocks_response_size() {
+    let (public_key, secret_key) = gen_keypair();
+    let msg = BlocksResponse::new(&public_key, vec![]);
+    let msg = Message::concrete(msg, public_key, &secret_key);
+    assert_eq!(BLOCKS_RESPONSE_EMPTY_SIZE, msg.signed_message().raw().len());
+
+    let blocks = vec![vec![0; 100], vec![0; 10_000]];
+    let msg = BlocksResponse::new(&public_key, blocks.clone());
+    let msg = Message::concrete(msg, public_key, &secret_key);
+    let max_size = blocks
+        .iter()
+        .fold(BLOCKS_RESPONSE_EMPTY_SIZE, |size, block| {
+            size + block.len() + BLOCKS_RESPONSE_BLOCK_HEADER
+        });
+    assert!(msg.signed_message().raw().len() <= max_size);
+}
+
+#[test]
+fn test_block_request_count() {
+    let (public_key, _) = gen_keypair();
+    let request = BlockRequest::range(&public_key, Height(1), 0);
+    assert_eq!(request.count(), 1);
+    let request = BlockRequest::range(&public_key, Height(1), 10);
+    assert_eq!(request.count(), 10);
+    assert_eq!(BlockRequest::new(&public_key, Height(1)).count(), 1);
+}
+
+#[test]
+fn test_transactions_announce_size() {
+    let (public_key, secret_key) = gen_keypair();
+    let txs = (0..10u8).map(|i| hash(&[i])).collect::<Vec<_>>();
+    let msg = TransactionsAnnounce::new(&txs);
+    let msg = Message::concrete(msg, public_key, &secret_key);
+    assert_eq!(
+        EMPTY_SIGNED_MESSAGE_SIZE + txs.len() * TRANSACTIONS_ANNOUNCE_HASH_SIZE,
+        msg.signed_message().raw().len()
+    )
+}
+
 #[derive(Clone, PartialEq, Eq, Ord, PartialOrd, Debug, Serialize, Deserialize, ProtobufConvert)]
 #[exonum(pb = "proto::schema::tests::CreateWallet", crate = "crate")]
 struct CreateWallet {
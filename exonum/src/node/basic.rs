@@ -1,6 +1,6 @@
 use rand::{self, Rng};
 
-use super::{NodeHandler, NodeRole, RequestData};
+use super::{NodeHandler, NodeRole};
 use crypto::PublicKey;
 use events::error::LogError;
 use events::network::ConnectedPeerAddr;
@@ -9,18 +9,32 @@ use messages::{Connect, Message, PeersRequest, Responses, Service, Signed, Statu
 
 impl NodeHandler {
     /// Redirects message to the corresponding `handle_...` function.
+    /// Replicas ignore consensus messages.
     pub fn handle_message(&mut self, msg: Message) {
         match msg {
-            Message::Consensus(msg) => self.handle_consensus(msg),
+            Message::Consensus(msg) => {
+                if self.replica {
+                    trace!("Replica ignores consensus message {:?}", msg);
+                } else {
+                    self.handle_consensus(msg)
+                }
+            }
             Message::Requests(ref msg) => self.handle_request(msg),
 
             Message::Service(Service::Connect(msg)) => self.handle_connect(msg),
             Message::Service(Service::Status(msg)) => self.handle_status(&msg),
+            Message::Service(Service::TransactionsAnnounce(msg)) => self.handle_txs_announce(&msg),
             // ignore tx duplication error,
             Message::Service(Service::RawTransaction(msg)) => drop(self.handle_tx(msg)),
             Message::Responses(Responses::BlockResponse(msg)) => {
                 self.handle_block(&msg).log_error()
             }
+            Message::Responses(Responses::BlocksResponse(msg)) => {
+                self.handle_blocks(&msg).log_error()
+            }
+            Message::Responses(Responses::CompactBlockResponse(msg)) => {
+                self.handle_compact_block(&msg).log_error()
+            }
             Message::Responses(Responses::TransactionsResponse(msg)) => {
                 self.handle_txs_batch(&msg).log_error()
             }
@@ -51,13 +65,14 @@ impl NodeHandler {
     }
 
     /// Removes peer from the state and from the cache. Node will try to connect to that address
-    /// again if it was in the validators list.
+    /// again if it was in the validators list or is pinned in the connect list.
     fn remove_peer_with_addr(&mut self, key: PublicKey) {
         self.state.remove_peer_with_pubkey(&key);
         self.blockchain.remove_peer_with_pubkey(&key);
         let is_validator = self.state.peer_is_validator(&key);
         let in_connect_list = self.state.peer_in_connect_list(&key);
-        if is_validator && in_connect_list {
+        let is_pinned = self.state.connect_list().is_pinned(&key);
+        if (is_validator && in_connect_list) || is_pinned {
             self.connect(key);
         }
     }
@@ -78,7 +93,8 @@ impl NodeHandler {
             return;
         }
 
-        if !self.state.connect_list().is_peer_allowed(&public_key) {
+        if !self.state.connect_list().is_peer_allowed(&public_key) && !self.discover_peer(&message)
+        {
             error!(
                 "Received connect message from {:?} peer which not in ConnectList.",
                 public_key
@@ -100,7 +116,8 @@ impl NodeHandler {
                 error!("Received weird Connect message from {}", address);
                 return;
             }
-            if saved_message.pub_addr() != message.pub_addr() {
+            let is_pinned = self.state.connect_list().is_pinned(&public_key);
+            if saved_message.pub_addr() != message.pub_addr() && !is_pinned {
                 info!(
                     "Updating connect list for peer: {} with new addr: {}",
                     public_key,
@@ -111,6 +128,9 @@ impl NodeHandler {
                     .update_peer(&public_key, message.pub_addr().to_string())
             }
         }
+        self.state
+            .connect_list()
+            .update_addresses(&public_key, message.addresses());
         self.state.add_peer(public_key, message.clone());
         info!(
             "Received Connect message from {}. Need to connect: {}",
@@ -125,6 +145,33 @@ impl NodeHandler {
         }
     }
 
+    /// Adds the author of the `Connect` message to the connect list if the node discovers
+    /// peers outside of its connect list. Returns `true` if the peer has been added.
+    fn discover_peer(&mut self, message: &Signed<Connect>) -> bool {
+        if !self.is_discovering_peers() {
+            return false;
+        }
+        let is_added = self.state.connect_list().add_discovered(
+            message.author(),
+            message.pub_addr().to_owned(),
+            self.discovery.max_peers,
+        );
+        if is_added {
+            info!(
+                "Discovered peer {} with address {}",
+                message.author(),
+                message.pub_addr()
+            );
+        }
+        is_added
+    }
+
+    /// Returns `true` if the node is an auditor discovering peers outside of its
+    /// connect list.
+    fn is_discovering_peers(&self) -> bool {
+        self.discovery.enabled && self.state.validator_state().is_none()
+    }
+
     /// Handles the `Status` message. Node sends `BlockRequest` as response if height in the
     /// message is higher than node's height.
     pub fn handle_status(&mut self, msg: &Signed<Status>) {
@@ -153,15 +200,23 @@ impl NodeHandler {
                 self.state.set_node_height(peer, msg.height());
             }
 
-            // Request block
-            self.request(RequestData::Block(height), peer);
+            // Request blocks
+            self.request_next_block();
         }
     }
 
     /// Handles the `PeersRequest` message. Node sends `Connect` messages of other peers as result.
+    /// Peers discovered outside of the connect list are sent only in response to the discovery
+    /// requests.
     pub fn handle_request_peers(&mut self, msg: &Signed<PeersRequest>) {
-        let peers: Vec<Signed<Connect>> =
-            self.state.peers().iter().map(|(_, b)| b.clone()).collect();
+        let connect_list = self.state.connect_list();
+        let peers: Vec<Signed<Connect>> = self
+            .state
+            .peers()
+            .iter()
+            .filter(|(key, _)| msg.discovery() || !connect_list.is_discovered(key))
+            .map(|(_, b)| b.clone())
+            .collect();
         trace!(
             "HANDLE REQUEST PEERS: Sending {:?} peers to {:?}",
             peers,
@@ -198,7 +253,7 @@ impl NodeHandler {
                 .nth(gen_peer_id())
                 .unwrap();
             let peer = peer.clone();
-            let msg = PeersRequest::new(&peer.author());
+            let msg = PeersRequest::new(&peer.author()).with_discovery(self.is_discovering_peers());
             trace!("Request peers from peer with addr {:?}", peer.pub_addr());
             let message = self.sign_message(msg);
             self.send_to_peer(peer.author(), message);
@@ -209,6 +264,10 @@ impl NodeHandler {
     /// Node update internal `ApiState` and `NodeRole`.
     pub fn handle_update_api_state_timeout(&mut self) {
         self.api_state.update_node_state(&self.state);
+        self.api_state
+            .set_peer_scores(self.peer_scores.peers_info());
+        self.api_state
+            .set_compression_stats(self.compression_stats.info());
         self.node_role = NodeRole::new(self.state.validator_id());
         self.add_update_api_state_timeout();
     }
@@ -0,0 +1,347 @@
+//! Blocks requested in parallel from several peers while the node catches up with them.
+
+use std::{cmp, collections::BTreeMap};
+
+use crypto::PublicKey;
+use helpers::Height;
+use messages::{BlockResponse, Signed};
+
+/// Block synchronization configuration.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct BlockSyncConfig {
+    /// Maximum number of heights, starting from the current one, for which blocks are
+    /// requested or kept received at once.
+    pub window_size: u64,
+    /// Maximum number of blocks requested with a single `BlockRequest`. The node also sends
+    /// at most this amount of blocks in response to requests of other nodes.
+    pub blocks_per_request: u32,
+}
+
+impl Default for BlockSyncConfig {
+    fn default() -> Self {
+        Self {
+            window_size: 64,
+            blocks_per_request: 16,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BlockRange {
+    count: u32,
+    peer: PublicKey,
+}
+
+/// Requested ranges of heights and received blocks waiting for the previous ones
+/// to be committed.
+#[derive(Debug)]
+pub struct BlockSync {
+    config: BlockSyncConfig,
+    // Requested ranges by their first height.
+    ranges: BTreeMap<Height, BlockRange>,
+    // Blocks received ahead of the current height.
+    blocks: BTreeMap<Height, Signed<BlockResponse>>,
+    is_applying: bool,
+}
+
+impl BlockSync {
+    /// Creates an empty state with the given configuration.
+    pub fn new(config: BlockSyncConfig) -> Self {
+        Self {
+            config,
+            ranges: BTreeMap::new(),
+            blocks: BTreeMap::new(),
+            is_applying: false,
+        }
+    }
+
+    /// Returns the configuration.
+    pub fn config(&self) -> &BlockSyncConfig {
+        &self.config
+    }
+
+    /// Returns the first range of heights within the window starting from `height` and
+    /// below `limit` which is neither requested nor received.
+    pub fn next_range(&self, height: Height, limit: Height) -> Option<(Height, u32)> {
+        let end = cmp::min(limit.0, height.0.saturating_add(self.config.window_size));
+        let max_count = cmp::max(self.config.blocks_per_request, 1);
+
+        let mut start = height.0;
+        while start < end {
+            if let Some((range_start, range)) = self.range_containing(Height(start)) {
+                start = range_start.0 + u64::from(range.count);
+                continue;
+            }
+            if self.blocks.contains_key(&Height(start)) {
+                start += 1;
+                continue;
+            }
+
+            let mut count = 1;
+            while count < max_count
+                && start + u64::from(count) < end
+                && !self.is_covered(Height(start + u64::from(count)))
+            {
+                count += 1;
+            }
+            return Some((Height(start), count));
+        }
+        None
+    }
+
+    /// Remembers that `count` blocks starting from `height` are requested from the peer.
+    pub fn start_range(&mut self, height: Height, count: u32, peer: PublicKey) {
+        self.ranges.insert(height, BlockRange { count, peer });
+    }
+
+    /// Returns the number of blocks requested starting from `height`, or `1` if
+    /// the range is unknown.
+    pub fn range_len(&self, height: Height) -> u32 {
+        self.ranges.get(&height).map_or(1, |range| range.count)
+    }
+
+    /// Updates the peer from which the range starting at `height` is requested.
+    pub fn set_peer(&mut self, height: Height, peer: PublicKey) {
+        if let Some(range) = self.ranges.get_mut(&height) {
+            range.peer = peer;
+        }
+    }
+
+    /// Forgets the range containing `height`. Blocks of the range that have not been
+    /// received can be requested again. Returns the first height of the range.
+    pub fn finish_range(&mut self, height: Height) -> Option<Height> {
+        let (start, _) = self.range_containing(height)?;
+        self.ranges.remove(&start);
+        Some(start)
+    }
+
+    /// Returns the number of ranges requested from the peer.
+    pub fn peer_load(&self, peer: &PublicKey) -> usize {
+        self.ranges
+            .values()
+            .filter(|range| range.peer == *peer)
+            .count()
+    }
+
+    /// Selects the peer with the least number of requested ranges among the peers
+    /// having the block at the given height. Peers are given with their heights.
+    pub fn select_peer(
+        &self,
+        height: Height,
+        peers: &[(PublicKey, Height)],
+    ) -> Option<(PublicKey, Height)> {
+        peers
+            .iter()
+            .filter(|&&(_, peer_height)| peer_height > height)
+            .min_by_key(|&&(ref peer, _)| self.peer_load(peer))
+            .cloned()
+    }
+
+    /// Keeps the block received from the peer until the previous ones are committed.
+    /// Returns `false` if the block has not been requested from this peer, has already
+    /// been received or `window_size` blocks are kept already.
+    pub fn add_block(&mut self, peer: &PublicKey, block: Signed<BlockResponse>) -> bool {
+        let height = block.block().height();
+        let is_requested = self
+            .range_containing(height)
+            .map_or(false, |(_, range)| range.peer == *peer);
+        if !is_requested
+            || self.blocks.contains_key(&height)
+            || self.blocks.len() as u64 >= self.config.window_size
+        {
+            return false;
+        }
+        self.blocks.insert(height, block);
+        true
+    }
+
+    /// Takes the received block at the given height.
+    pub fn take_block(&mut self, height: Height) -> Option<Signed<BlockResponse>> {
+        self.blocks.remove(&height)
+    }
+
+    /// Forgets blocks below `height` and the ranges which lie entirely below it.
+    /// Returns the first heights of the forgotten ranges.
+    pub fn prune(&mut self, height: Height) -> Vec<Height> {
+        self.blocks = self.blocks.split_off(&height);
+        let outdated = self
+            .ranges
+            .iter()
+            .filter(|&(start, range)| start.0 + u64::from(range.count) <= height.0)
+            .map(|(start, _)| *start)
+            .collect::<Vec<_>>();
+        for start in &outdated {
+            self.ranges.remove(start);
+        }
+        outdated
+    }
+
+    /// Returns `true` if received blocks are being committed.
+    pub fn is_applying(&self) -> bool {
+        self.is_applying
+    }
+
+    /// Marks the start or the end of committing received blocks.
+    pub fn set_applying(&mut self, is_applying: bool) {
+        self.is_applying = is_applying;
+    }
+
+    fn range_containing(&self, height: Height) -> Option<(Height, BlockRange)> {
+        self.ranges
+            .range(..=height)
+            .next_back()
+            .filter(|&(start, range)| height.0 < start.0 + u64::from(range.count))
+            .map(|(start, range)| (*start, *range))
+    }
+
+    fn is_covered(&self, height: Height) -> bool {
+        self.blocks.contains_key(&height) || self.range_containing(height).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockchain::Block;
+    use crypto::{gen_keypair, Hash};
+    use helpers::ValidatorId;
+    use messages::Message;
+
+    fn config() -> BlockSyncConfig {
+        BlockSyncConfig {
+            window_size: 10,
+            blocks_per_request: 3,
+        }
+    }
+
+    fn block_response(height: Height) -> Signed<BlockResponse> {
+        let (public_key, secret_key) = gen_keypair();
+        let block = Block::new(
+            ValidatorId(0),
+            height,
+            0,
+            &Hash::zero(),
+            &Hash::zero(),
+            &Hash::zero(),
+        );
+        Message::concrete(
+            BlockResponse::new(&public_key, block, vec![], &[]),
+            public_key,
+            &secret_key,
+        )
+    }
+
+    #[test]
+    fn test_next_range() {
+        let (peer, _) = gen_keypair();
+        let mut sync = BlockSync::new(config());
+
+        assert_eq!(sync.next_range(Height(1), Height(1)), None);
+        assert_eq!(sync.next_range(Height(1), Height(3)), Some((Height(1), 2)));
+        assert_eq!(
+            sync.next_range(Height(1), Height(100)),
+            Some((Height(1), 3))
+        );
+
+        sync.start_range(Height(1), 3, peer);
+        assert_eq!(
+            sync.next_range(Height(1), Height(100)),
+            Some((Height(4), 3))
+        );
+        sync.start_range(Height(5), 1, peer);
+        assert_eq!(
+            sync.next_range(Height(1), Height(100)),
+            Some((Height(4), 1))
+        );
+        sync.start_range(Height(4), 1, peer);
+        assert_eq!(
+            sync.next_range(Height(1), Height(100)),
+            Some((Height(6), 3))
+        );
+        sync.start_range(Height(6), 3, peer);
+        // The window ends at height 11.
+        assert_eq!(
+            sync.next_range(Height(1), Height(100)),
+            Some((Height(9), 2))
+        );
+        sync.start_range(Height(9), 2, peer);
+        assert_eq!(sync.next_range(Height(1), Height(100)), None);
+
+        assert_eq!(sync.peer_load(&peer), 5);
+        assert_eq!(sync.range_len(Height(6)), 3);
+        assert_eq!(sync.range_len(Height(7)), 1);
+
+        // A range is finished by any of its heights.
+        assert_eq!(sync.finish_range(Height(7)), Some(Height(6)));
+        assert_eq!(sync.finish_range(Height(7)), None);
+        assert_eq!(
+            sync.next_range(Height(1), Height(100)),
+            Some((Height(6), 3))
+        );
+    }
+
+    #[test]
+    fn test_select_peer() {
+        let (first, _) = gen_keypair();
+        let (second, _) = gen_keypair();
+        let peers = [(first, Height(3)), (second, Height(10))];
+        let mut sync = BlockSync::new(config());
+
+        assert_eq!(sync.select_peer(Height(1), &peers), Some(peers[0]));
+        sync.start_range(Height(1), 2, first);
+        assert_eq!(sync.select_peer(Height(1), &peers), Some(peers[1]));
+        sync.start_range(Height(3), 3, second);
+        assert_eq!(sync.select_peer(Height(2), &peers), Some(peers[0]));
+        // Only the second peer has blocks above its height.
+        assert_eq!(sync.select_peer(Height(6), &peers), Some(peers[1]));
+        assert_eq!(sync.select_peer(Height(10), &peers), None);
+    }
+
+    #[test]
+    fn test_received_blocks() {
+        let (peer, _) = gen_keypair();
+        let (other_peer, _) = gen_keypair();
+        let mut sync = BlockSync::new(config());
+
+        // Blocks which have not been requested are rejected.
+        assert!(!sync.add_block(&peer, block_response(Height(2))));
+
+        sync.start_range(Height(1), 3, peer);
+        // Blocks are accepted only from the peer they are requested from.
+        assert!(!sync.add_block(&other_peer, block_response(Height(2))));
+        assert!(sync.add_block(&peer, block_response(Height(2))));
+        assert!(!sync.add_block(&peer, block_response(Height(2))));
+        assert!(sync.add_block(&peer, block_response(Height(3))));
+        assert_eq!(sync.finish_range(Height(1)), Some(Height(1)));
+
+        // Received blocks are not requested again.
+        assert_eq!(
+            sync.next_range(Height(1), Height(100)),
+            Some((Height(1), 1))
+        );
+        assert!(sync.take_block(Height(1)).is_none());
+        assert!(sync.take_block(Height(2)).is_some());
+        assert!(sync.take_block(Height(2)).is_none());
+
+        sync.start_range(Height(4), 2, peer);
+        sync.start_range(Height(6), 2, peer);
+        assert_eq!(sync.prune(Height(6)), vec![Height(4)]);
+        assert!(sync.take_block(Height(3)).is_none());
+        assert_eq!(sync.peer_load(&peer), 1);
+    }
+    #[test]
+    fn test_received_blocks_limit() {
+        let (peer, _) = gen_keypair();
+        let mut sync = BlockSync::new(BlockSyncConfig {
+            window_size: 2,
+            blocks_per_request: 3,
+        });
+
+        sync.start_range(Height(1), 3, peer);
+        assert!(sync.add_block(&peer, block_response(Height(2))));
+        assert!(sync.add_block(&peer, block_response(Height(3))));
+        assert!(!sync.add_block(&peer, block_response(Height(1))));
+        assert!(sync.take_block(Height(2)).is_some());
+        assert!(sync.add_block(&peer, block_response(Height(1))));
+    }
+}
@@ -3,28 +3,100 @@
 use std::collections::BTreeMap;
 
 use crypto::PublicKey;
-use node::{ConnectInfo, ConnectListConfig};
+use helpers::Height;
+use messages::PeerAddressRecord;
+use node::{ConnectInfo, ConnectListConfig, ConnectListPeer};
+
+/// Maximal number of additional addresses accepted from the `Connect` message of a peer.
+pub const MAX_PEER_ADDRESSES: usize = 16;
+
+/// Discovery of the peers outside of the connect list.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct PeerDiscoveryConfig {
+    /// Whether an auditor node connects to the peers learned from the `Connect` messages
+    /// of other peers even if they are not in its connect list. Validators ignore this flag.
+    pub enabled: bool,
+    /// Maximal number of discovered peers added to the connect list.
+    pub max_peers: usize,
+}
+
+impl Default for PeerDiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_peers: 64,
+        }
+    }
+}
 
 /// Network address of the peer.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PeerAddress {
     /// External address of the peer hostname:port.
     pub address: String,
+    /// Pinned peers cannot be removed from the list, and their address is not updated
+    /// from the `Connect` messages. The node keeps reconnecting to pinned peers.
+    #[serde(default)]
+    pub pinned: bool,
+    /// Additional addresses advertised by the peer in its `Connect` message.
+    #[serde(default)]
+    pub addresses: Vec<PeerAddressRecord>,
+    /// Whether the peer has been discovered outside of the configured connect list.
+    /// Discovered peers are not stored in the node config.
+    #[serde(default)]
+    pub discovered: bool,
 }
 
 impl PeerAddress {
     /// New unresolved address.
     pub fn new(address: String) -> Self {
-        PeerAddress { address }
+        PeerAddress {
+            address,
+            pinned: false,
+            addresses: Vec::new(),
+            discovered: false,
+        }
+    }
+
+    /// Returns addresses of the peer in the order in which they should be tried.
+    /// Advertised addresses go first, sorted by their priority; the main address
+    /// is tried last unless it is advertised too. Only the main address of a pinned
+    /// peer is used.
+    pub fn addresses_by_priority(&self) -> Vec<String> {
+        if self.pinned {
+            return vec![self.address.clone()];
+        }
+        let mut records = self.addresses.iter().collect::<Vec<_>>();
+        records.sort_by_key(|record| record.priority);
+        let mut addresses = Vec::with_capacity(records.len() + 1);
+        for record in records {
+            if !addresses.contains(&record.address) {
+                addresses.push(record.address.clone());
+            }
+        }
+        if !addresses.contains(&self.address) {
+            addresses.push(self.address.clone());
+        }
+        addresses
     }
 }
 
+/// Previous consensus key of a peer which has rotated it.
+#[derive(Debug, Clone)]
+struct RetiredKey {
+    address: PeerAddress,
+    until: Height,
+}
+
 /// `ConnectList` stores mapping between IP-addresses and public keys.
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ConnectList {
     /// Peers to which we can connect.
     #[serde(default)]
     pub peers: BTreeMap<PublicKey, PeerAddress>,
+    /// Previous keys of the peers, still accepted during the key transition window.
+    #[serde(skip)]
+    retired: BTreeMap<PublicKey, RetiredKey>,
 }
 
 impl ConnectList {
@@ -33,15 +105,48 @@ impl ConnectList {
         let peers: BTreeMap<PublicKey, PeerAddress> = config
             .peers
             .into_iter()
-            .map(|peer| (peer.public_key, PeerAddress::new(peer.address)))
+            .map(|peer| {
+                let mut address = PeerAddress::new(peer.address);
+                address.pinned = peer.pinned;
+                (peer.public_key, address)
+            })
             .collect();
 
-        ConnectList { peers }
+        ConnectList {
+            peers,
+            retired: BTreeMap::new(),
+        }
+    }
+
+    /// Creates `ConnectListConfig` from the peers of the ConnectList, except
+    /// the discovered ones.
+    pub fn to_config(&self) -> ConnectListConfig {
+        let peers = self
+            .peers
+            .iter()
+            .filter(|(_, peer)| !peer.discovered)
+            .map(|(public_key, peer)| ConnectListPeer {
+                address: peer.address.clone(),
+                public_key: *public_key,
+                pinned: peer.pinned,
+            })
+            .collect();
+        ConnectListConfig { peers }
     }
 
     /// Returns `true` if a peer with the given public key can connect.
     pub fn is_peer_allowed(&self, peer: &PublicKey) -> bool {
-        self.peers.contains_key(peer)
+        self.peers.contains_key(peer) || self.retired.contains_key(peer)
+    }
+
+    /// Returns keys of the peers which can connect, including the previous keys
+    /// of the peers in the key transition window.
+    pub fn allowed_keys(&self) -> Vec<PublicKey> {
+        self.peers
+            .keys()
+            .chain(self.retired.keys())
+            .cloned()
+            .collect()
     }
 
     /// Check if we allow to connect to `address`.
@@ -51,18 +156,140 @@ impl ConnectList {
 
     /// Get peer address with public key.
     pub fn find_address_by_pubkey(&self, key: &PublicKey) -> Option<&PeerAddress> {
-        self.peers.get(key)
+        self.peers
+            .get(key)
+            .or_else(|| self.retired.get(key).map(|retired| &retired.address))
     }
 
-    /// Adds peer to the ConnectList.
-    pub fn add(&mut self, peer: ConnectInfo) {
-        self.peers
-            .insert(peer.public_key, PeerAddress::new(peer.address));
+    /// Adds peer to the ConnectList. If the peer is already in the list, its address
+    /// is updated. Pinned peers are left intact; returns `false` in this case.
+    pub fn add(&mut self, peer: ConnectInfo) -> bool {
+        if self.is_pinned(&peer.public_key) {
+            return false;
+        }
+        let address = self
+            .peers
+            .entry(peer.public_key)
+            .or_insert_with(|| PeerAddress::new(String::new()));
+        address.address = peer.address;
+        address.discovered = false;
+        true
     }
 
-    /// Update peer address.
+    /// Update peer address. The pinned flag of the peer is preserved.
     pub fn update_peer(&mut self, public_key: &PublicKey, address: String) {
-        self.peers.insert(*public_key, PeerAddress::new(address));
+        self.peers
+            .entry(*public_key)
+            .or_insert_with(|| PeerAddress::new(String::new()))
+            .address = address;
+    }
+
+    /// Replaces the additional addresses of the peer. Addresses of pinned peers
+    /// are not updated. Returns `true` if the addresses have been updated.
+    pub fn update_addresses(
+        &mut self,
+        public_key: &PublicKey,
+        addresses: &[PeerAddressRecord],
+    ) -> bool {
+        match self.peers.get_mut(public_key) {
+            Some(peer) => {
+                if peer.pinned {
+                    return false;
+                }
+                peer.addresses = addresses.iter().take(MAX_PEER_ADDRESSES).cloned().collect();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Adds a peer discovered outside of the configured connect list. Returns `false`
+    /// if the peer is already in the list or `max_peers` discovered peers are known.
+    pub fn add_discovered(
+        &mut self,
+        public_key: PublicKey,
+        address: String,
+        max_peers: usize,
+    ) -> bool {
+        let discovered = self.peers.values().filter(|peer| peer.discovered).count();
+        if discovered >= max_peers || self.peers.contains_key(&public_key) {
+            return false;
+        }
+        let mut peer = PeerAddress::new(address);
+        peer.discovered = true;
+        self.peers.insert(public_key, peer);
+        true
+    }
+
+    /// Returns `true` if the peer has been discovered outside of the configured
+    /// connect list.
+    pub fn is_discovered(&self, public_key: &PublicKey) -> bool {
+        self.peers
+            .get(public_key)
+            .map_or(false, |peer| peer.discovered)
+    }
+
+    /// Removes peer from the ConnectList. Returns the address of the removed peer.
+    pub fn remove(&mut self, public_key: &PublicKey) -> Option<PeerAddress> {
+        self.peers.remove(public_key)
+    }
+
+    /// Replaces the key of the peer, keeping its address. The previous key is accepted
+    /// until `until` height. Returns `false` if the peer is not in the ConnectList.
+    pub fn rotate_key(&mut self, old_key: &PublicKey, new_key: PublicKey, until: Height) -> bool {
+        let address = match self.peers.remove(old_key) {
+            Some(address) => address,
+            None => return false,
+        };
+        self.retired.insert(
+            *old_key,
+            RetiredKey {
+                address: address.clone(),
+                until,
+            },
+        );
+        self.peers.insert(new_key, address);
+        true
+    }
+
+    /// Forgets the previous keys of the peers whose transition window ends at
+    /// the given height. Returns the forgotten keys.
+    pub fn remove_retired(&mut self, height: Height) -> Vec<PublicKey> {
+        let expired = self
+            .retired
+            .iter()
+            .filter(|(_, retired)| retired.until <= height)
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+        for key in &expired {
+            self.retired.remove(key);
+        }
+        expired
+    }
+
+    /// Pins or unpins the peer. Returns `false` if the peer is not in the ConnectList.
+    pub fn set_pinned(&mut self, public_key: &PublicKey, pinned: bool) -> bool {
+        match self.peers.get_mut(public_key) {
+            Some(peer) => {
+                peer.pinned = pinned;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns `true` if the peer is pinned.
+    pub fn is_pinned(&self, public_key: &PublicKey) -> bool {
+        self.peers.get(public_key).map_or(false, |peer| peer.pinned)
+    }
+
+    /// Returns public keys of the pinned peers.
+    pub fn pinned(&self) -> Vec<PublicKey> {
+        self.peers
+            .iter()
+            .filter(|(_, peer)| peer.pinned)
+            .map(|(public_key, _)| *public_key)
+            .collect()
     }
 }
 
@@ -146,7 +373,7 @@ mod test {
             connect_list.add(ConnectInfo {
                 public_key: *peer,
                 address: address.clone(),
-            })
+            });
         }
     }
 
@@ -181,4 +408,144 @@ mod test {
         assert!(connect_list.is_address_allowed(&address));
     }
 
+    #[test]
+    fn test_remove_and_pin() {
+        let peers = make_keys(REGULAR_PEERS, 3);
+        let mut connect_list = ConnectList::default();
+        add_to_connect_list(&mut connect_list, &peers);
+
+        assert!(connect_list.set_pinned(&peers[0], true));
+        assert!(!connect_list.set_pinned(&make_keys(VALIDATORS[0], 1)[0], true));
+        assert!(connect_list.is_pinned(&peers[0]));
+        assert_eq!(connect_list.pinned(), vec![peers[0]]);
+
+        // Address updates keep the pinned flag.
+        connect_list.update_peer(&peers[0], "127.0.0.1:81".to_owned());
+        assert!(connect_list.is_pinned(&peers[0]));
+        assert_eq!(
+            connect_list
+                .find_address_by_pubkey(&peers[0])
+                .unwrap()
+                .address,
+            "127.0.0.1:81"
+        );
+
+        // Adding a pinned peer keeps its address and the pinned flag.
+        assert!(!connect_list.add(ConnectInfo {
+            public_key: peers[0],
+            address: "127.0.0.1:82".to_owned(),
+        }));
+        assert!(connect_list.is_pinned(&peers[0]));
+        assert_eq!(
+            connect_list
+                .find_address_by_pubkey(&peers[0])
+                .unwrap()
+                .address,
+            "127.0.0.1:81"
+        );
+
+        assert!(connect_list.remove(&peers[1]).is_some());
+        assert!(connect_list.remove(&peers[1]).is_none());
+        check_in_connect_list(&connect_list, &peers, &[0, 2], &[1]);
+
+        assert!(connect_list.set_pinned(&peers[0], false));
+        assert!(connect_list.pinned().is_empty());
+    }
+
+    #[test]
+    fn test_pinned_from_config() {
+        let mut peers = make_keys(REGULAR_PEERS, 2);
+        peers.sort();
+        let config = ConnectListConfig {
+            peers: peers
+                .iter()
+                .enumerate()
+                .map(|(i, public_key)| ConnectListPeer {
+                    public_key: *public_key,
+                    address: "127.0.0.1:80".to_owned(),
+                    pinned: i == 1,
+                })
+                .collect(),
+        };
+
+        let mut connect_list = ConnectList::from_config(config.clone());
+        assert!(!connect_list.is_pinned(&peers[0]));
+        assert!(connect_list.is_pinned(&peers[1]));
+        assert_eq!(connect_list.pinned(), vec![peers[1]]);
+
+        // Discovered peers are not stored in the config.
+        assert!(connect_list.add_discovered(gen_keypair().0, "127.0.0.1:81".to_owned(), 1));
+        assert_eq!(connect_list.to_config(), config);
+    }
+
+    #[test]
+    fn test_rotate_key() {
+        let peers = make_keys(REGULAR_PEERS, 2);
+        let (new_key, _) = gen_keypair();
+        let mut connect_list = ConnectList::default();
+        add_to_connect_list(&mut connect_list, &peers);
+        connect_list.set_pinned(&peers[0], true);
+
+        assert!(!connect_list.rotate_key(&new_key, peers[1], Height(10)));
+        assert!(connect_list.rotate_key(&peers[0], new_key, Height(10)));
+        assert!(connect_list.is_pinned(&new_key));
+        assert!(!connect_list.peers.contains_key(&peers[0]));
+        assert_eq!(connect_list.allowed_keys().len(), 3);
+
+        // The previous key is accepted during the transition window.
+        check_in_connect_list(&connect_list, &peers, &[0, 1], &[]);
+        assert!(connect_list.is_peer_allowed(&new_key));
+        assert!(connect_list.find_address_by_pubkey(&peers[0]).is_some());
+
+        assert!(connect_list.remove_retired(Height(9)).is_empty());
+        assert_eq!(connect_list.remove_retired(Height(10)), vec![peers[0]]);
+        check_in_connect_list(&connect_list, &peers, &[1], &[0]);
+        assert!(connect_list.find_address_by_pubkey(&peers[0]).is_none());
+    }
+
+    #[test]
+    fn test_addresses_by_priority() {
+        let peers = make_keys(REGULAR_PEERS, 2);
+        let mut connect_list = ConnectList::default();
+        add_to_connect_list(&mut connect_list, &peers);
+
+        let records = vec![
+            PeerAddressRecord::new("10.0.0.1:80", 2),
+            PeerAddressRecord::new("127.0.0.1:80", 1),
+            PeerAddressRecord::new("192.168.0.1:80", 0),
+        ];
+        assert!(connect_list.update_addresses(&peers[0], &records));
+        let address = connect_list.find_address_by_pubkey(&peers[0]).unwrap();
+        assert_eq!(
+            address.addresses_by_priority(),
+            vec!["192.168.0.1:80", "127.0.0.1:80", "10.0.0.1:80"]
+        );
+
+        connect_list.set_pinned(&peers[1], true);
+        assert!(!connect_list.update_addresses(&peers[1], &records));
+        let address = connect_list.find_address_by_pubkey(&peers[1]).unwrap();
+        assert_eq!(address.addresses_by_priority(), vec!["127.0.0.1:80"]);
+    }
+
+    #[test]
+    fn test_add_discovered() {
+        let peers = make_keys(REGULAR_PEERS, 3);
+        let mut connect_list = ConnectList::default();
+        add_to_connect_list(&mut connect_list, &peers[..1]);
+
+        assert!(!connect_list.add_discovered(peers[0], "127.0.0.1:80".to_owned(), 1));
+        assert!(connect_list.add_discovered(peers[1], "127.0.0.1:81".to_owned(), 1));
+        assert!(!connect_list.add_discovered(peers[2], "127.0.0.1:82".to_owned(), 1));
+        check_in_connect_list(&connect_list, &peers, &[0, 1], &[2]);
+        assert!(!connect_list.is_discovered(&peers[0]));
+        assert!(connect_list.is_discovered(&peers[1]));
+
+        // A discovered peer added explicitly becomes a configured one.
+        connect_list.add(ConnectInfo {
+            public_key: peers[1],
+            address: "127.0.0.1:81".to_owned(),
+        });
+        assert!(!connect_list.is_discovered(&peers[1]));
+        assert!(connect_list.add_discovered(peers[2], "127.0.0.1:82".to_owned(), 1));
+    }
 }
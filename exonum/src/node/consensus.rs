@@ -1,17 +1,22 @@
-use std::collections::HashSet;
+use std::{
+    cmp,
+    collections::{HashMap, HashSet},
+};
 
-use blockchain::Schema;
-use crypto::{CryptoHash, Hash, PublicKey};
+use blockchain::{Block, Schema};
+use crypto::{CryptoHash, Hash, PublicKey, PUBLIC_KEY_LENGTH};
 use events::InternalRequest;
+use explorer::DropReason;
 use failure;
 use helpers::{Height, Round, ValidatorId};
 use messages::{
-    BlockRequest, BlockResponse, Consensus as ConsensusMessage, Precommit, Prevote,
-    PrevotesRequest, Propose, ProposeRequest, RawTransaction, Signed, SignedMessage,
-    TransactionsRequest, TransactionsResponse,
+    BlockRequest, BlockResponse, BlocksResponse, CompactBlockRequest, CompactBlockResponse,
+    Consensus as ConsensusMessage, Precommit, Prevote, PrevotesRequest, Propose, ProposeRequest,
+    RawTransaction, Signed, SignedMessage, TransactionsAnnounce, TransactionsRequest,
+    TransactionsResponse, EMPTY_SIGNED_MESSAGE_SIZE, TRANSACTIONS_ANNOUNCE_HASH_SIZE,
 };
-use node::{NodeHandler, RequestData};
-use storage::Patch;
+use node::{Misbehavior, NodeHandler, RequestData};
+use storage::{proof_list_index as merkle, Patch};
 
 // TODO Reduce view invocations. (ECR-171)
 impl NodeHandler {
@@ -94,6 +99,7 @@ impl NodeHandler {
                 msg.validator(),
                 self.state.leader(msg.round())
             );
+            self.report_misbehavior(&from, Misbehavior::InvalidPropose);
             return;
         }
 
@@ -101,6 +107,8 @@ impl NodeHandler {
 
         let snapshot = self.blockchain.snapshot();
         let schema = Schema::new(snapshot);
+        // Duplicate proposes are expected, e.g. when they are requested from several peers.
+        let is_known = self.state.propose(&msg.hash()).is_some();
         //TODO: Remove this match after errors refactor. (ECR-979)
         let has_unknown_txs = match self.state.add_propose(
             msg.clone(),
@@ -110,6 +118,9 @@ impl NodeHandler {
             Ok(state) => state.has_unknown_txs(),
             Err(err) => {
                 warn!("{}, msg={:?}", err, msg);
+                if !is_known {
+                    self.report_misbehavior(&from, Misbehavior::InvalidPropose);
+                }
                 return;
             }
         };
@@ -132,27 +143,40 @@ impl NodeHandler {
     }
 
     fn validate_block_response(&self, msg: &Signed<BlockResponse>) -> Result<(), failure::Error> {
-        if msg.to() != self.state.consensus_public_key() {
+        self.validate_received_block(&msg.author(), msg.to(), &msg.block(), msg.precommits())?;
+        if !msg.verify_tx_hash() {
+            bail!("Received block has invalid tx_hash, msg={:?}", msg);
+        }
+        Ok(())
+    }
+
+    /// Checks the recipient, the header and the precommits of the block received
+    /// from `author`.
+    fn validate_received_block(
+        &self,
+        author: &PublicKey,
+        to: &PublicKey,
+        block: &Block,
+        precommits: Vec<Vec<u8>>,
+    ) -> Result<(), failure::Error> {
+        if to != self.state.consensus_public_key() {
             bail!(
                 "Received block intended for another peer, to={}, from={}",
-                msg.to().to_hex(),
-                msg.author().to_hex()
+                to.to_hex(),
+                author.to_hex()
             );
         }
 
-        if !self.state.connect_list().is_peer_allowed(&msg.author()) {
+        if !self.state.connect_list().is_peer_allowed(author) {
             bail!(
                 "Received request message from peer = {} which not in ConnectList.",
-                msg.author().to_hex()
+                author.to_hex()
             );
         }
 
-        let block = msg.block();
-        let block_hash = block.hash();
-
         // TODO: Add block with greater height to queue. (ECR-171)
         if self.state.height() != block.height() {
-            bail!("Received block has another height, msg={:?}", msg);
+            bail!("Received block has another height, block={:?}", block);
         }
 
         // Check block content.
@@ -160,25 +184,21 @@ impl NodeHandler {
             bail!(
                 "Received block prev_hash is distinct from the one in db, \
                  block={:?}, block.prev_hash={:?}, db.last_block_hash={:?}",
-                msg,
+                block,
                 *block.prev_hash(),
                 self.last_block_hash()
             );
         }
 
         if self.state.incomplete_block().is_some() {
-            bail!("Already there is an incomplete block, msg={:?}", msg);
+            bail!("Already there is an incomplete block, block={:?}", block);
         }
 
-        if !msg.verify_tx_hash() {
-            bail!("Received block has invalid tx_hash, msg={:?}", msg);
-        }
-        let precommits: Result<Vec<_>, _> = msg
-            .precommits()
+        let precommits: Result<Vec<_>, _> = precommits
             .into_iter()
             .map(Precommit::verify_precommit)
             .collect();
-        self.verify_precommits(&precommits?, &block_hash, block.height())?;
+        self.verify_precommits(&precommits?, &block.hash(), block.height())?;
 
         Ok(())
     }
@@ -186,7 +206,29 @@ impl NodeHandler {
     /// Handles the `Block` message. For details see the message documentation.
     // TODO: Write helper function which returns Result. (ECR-123)
     pub fn handle_block(&mut self, msg: &Signed<BlockResponse>) -> Result<(), failure::Error> {
-        self.validate_block_response(&msg)?;
+        // Blocks requested ahead of the current height are committed once the previous
+        // blocks are. Only the blocks requested from the author are kept.
+        let height = msg.block().height();
+        if height > self.state.height()
+            && msg.to() == self.state.consensus_public_key()
+            && self.block_sync.add_block(&msg.author(), msg.clone())
+        {
+            trace!("Received block for the future height {}", height);
+            self.finish_block_request(height);
+            self.request_next_block();
+            return Ok(());
+        }
+
+        if let Err(e) = self.validate_block_response(&msg) {
+            // Blocks for other heights or arriving while another block is being assembled
+            // are the result of benign races between responses, so they are not penalized.
+            let is_expected = self.state.height() == msg.block().height()
+                && self.state.incomplete_block().is_none();
+            if is_expected {
+                self.report_misbehavior(&msg.author(), Misbehavior::InvalidBlockResponse);
+            }
+            return Err(e);
+        }
 
         let block = msg.block();
         let block_hash = block.hash();
@@ -198,7 +240,11 @@ impl NodeHandler {
                 .create_incomplete_block(&msg, &schema.transactions(), &schema.transactions_pool())
                 .has_unknown_txs();
 
-            let known_nodes = self.remove_request(&RequestData::Block(block.height()));
+            let start = self
+                .block_sync
+                .finish_range(block.height())
+                .unwrap_or_else(|| block.height());
+            let known_nodes = self.remove_request(&RequestData::Block(start));
 
             if has_unknown_txs {
                 trace!("REQUEST TRANSACTIONS");
@@ -223,6 +269,119 @@ impl NodeHandler {
         Ok(())
     }
 
+    /// Handles the `BlocksResponse` message. For details see the message documentation.
+    pub fn handle_blocks(&mut self, msg: &Signed<BlocksResponse>) -> Result<(), failure::Error> {
+        if msg.to() != self.state.consensus_public_key() {
+            bail!(
+                "Received blocks intended for another peer, to={}, from={}",
+                msg.to().to_hex(),
+                msg.author().to_hex()
+            );
+        }
+
+        if !self.state.connect_list().is_peer_allowed(&msg.author()) {
+            bail!(
+                "Received request message from peer = {} which not in ConnectList.",
+                msg.author().to_hex()
+            );
+        }
+
+        let blocks = match msg.verify_blocks() {
+            Ok(blocks) => blocks,
+            Err(e) => {
+                self.report_misbehavior(&msg.author(), Misbehavior::InvalidBlockResponse);
+                return Err(e);
+            }
+        };
+        if blocks.iter().any(|block| block.author() != msg.author()) {
+            self.report_misbehavior(&msg.author(), Misbehavior::InvalidBlockResponse);
+            bail!("Received blocks signed by another peer, msg={:?}", msg);
+        }
+        let first_height = match blocks.first() {
+            Some(block) => block.block().height(),
+            None => return Ok(()),
+        };
+
+        // Blocks are validated once they are committed in the order of heights.
+        let height = self.state.height();
+        for block in blocks {
+            if block.block().height() >= height {
+                self.block_sync.add_block(&msg.author(), block);
+            }
+        }
+        self.finish_block_request(first_height);
+        self.request_next_block();
+        Ok(())
+    }
+
+    /// Handles the `CompactBlockResponse` message. For details see the message documentation.
+    pub fn handle_compact_block(
+        &mut self,
+        msg: &Signed<CompactBlockResponse>,
+    ) -> Result<(), failure::Error> {
+        let block = msg.block();
+        let validation = self
+            .validate_received_block(&msg.author(), msg.to(), &block, msg.precommits())
+            .and_then(|()| msg.verify_transactions());
+        let prefilled_txs = match validation {
+            Ok(txs) => txs,
+            Err(e) => {
+                let is_expected = self.state.height() == block.height()
+                    && self.state.incomplete_block().is_none();
+                if is_expected {
+                    self.report_misbehavior(&msg.author(), Misbehavior::InvalidBlockResponse);
+                }
+                return Err(e);
+            }
+        };
+
+        for tx in prefilled_txs {
+            if let Err(e) = self.handle_tx(tx) {
+                trace!("Prefilled transaction is not added to the pool: {}", e);
+            }
+            // The block may have been committed by the consensus in the meantime.
+            if self.state.height() != block.height() {
+                return Ok(());
+            }
+        }
+
+        let block_hash = block.hash();
+        match self.restore_compact_block_txs(&block_hash, msg.short_ids()) {
+            Some(ref tx_hashes) if merkle::root_hash(tx_hashes) == *block.tx_hash() => {
+                self.remove_request(&RequestData::Block(block.height()));
+                self.commit_received_block(&block, msg.precommits(), tx_hashes)
+            }
+            _ => {
+                info!(
+                    "Unable to restore compact block at height {}, requesting full block",
+                    block.height()
+                );
+                let request = self.sign_message(BlockRequest::new(&msg.author(), block.height()));
+                self.send_to_peer(msg.author(), request);
+                Ok(())
+            }
+        }
+    }
+
+    /// Restores hashes of the block transactions from their short ids using the transactions
+    /// pool. Returns `None` if some ids are unknown or ambiguous.
+    fn restore_compact_block_txs(&self, block_hash: &Hash, short_ids: &[u64]) -> Option<Vec<Hash>> {
+        let snapshot = self.blockchain.snapshot();
+        let schema = Schema::new(&snapshot);
+        let mut pool = HashMap::new();
+        for tx_hash in schema.transactions_pool().iter() {
+            let short_id = CompactBlockResponse::short_tx_id(block_hash, &tx_hash);
+            // Colliding ids are marked as ambiguous.
+            pool.entry(short_id)
+                .and_modify(|hash| *hash = None)
+                .or_insert_with(|| Some(tx_hash));
+        }
+        short_ids
+            .iter()
+            .map(|short_id| pool.get(short_id).cloned().and_then(|hash| hash))
+            .collect()
+    }
+
     /// Executes and commits block. This function is called when node has full propose information.
     pub fn handle_full_propose(&mut self, hash: Hash, propose_round: Round) {
         // Send prevote
@@ -266,29 +425,41 @@ impl NodeHandler {
     ///
     /// Panics if the received block has incorrect `block_hash`.
     pub fn handle_full_block(&mut self, msg: &Signed<BlockResponse>) -> Result<(), failure::Error> {
-        let block = msg.block();
+        self.commit_received_block(&msg.block(), msg.precommits(), msg.transactions())
+    }
+
+    /// Executes and commits the received block with the given transactions.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the received block has incorrect `block_hash`.
+    fn commit_received_block(
+        &mut self,
+        block: &Block,
+        precommits: Vec<Vec<u8>>,
+        tx_hashes: &[Hash],
+    ) -> Result<(), failure::Error> {
         let block_hash = block.hash();
 
         if self.state.block(&block_hash).is_none() {
             let (computed_block_hash, patch) =
-                self.create_block(block.proposer_id(), block.height(), msg.transactions());
+                self.create_block(block.proposer_id(), block.height(), tx_hashes);
             // Verify block_hash.
             assert!(
                 computed_block_hash == block_hash,
                 "Block_hash incorrect in the received block={:?}. Either a node's \
                  implementation is incorrect or validators majority works incorrectly",
-                msg
+                block
             );
 
             self.state.add_block(
                 computed_block_hash,
                 patch,
-                msg.transactions().to_vec(),
+                tx_hashes.to_vec(),
                 block.proposer_id(),
             );
         }
-        let precommits: Result<Vec<_>, _> = msg
-            .precommits()
+        let precommits: Result<Vec<_>, _> = precommits
             .into_iter()
             .map(Precommit::verify_precommit)
             .collect();
@@ -471,8 +642,7 @@ impl NodeHandler {
                 .commit(block_state.patch(), block_hash, precommits)
                 .unwrap();
             // Update node state.
-            self.state
-                .update_config(Schema::new(&self.blockchain.snapshot()).actual_configuration());
+            self.update_config();
             // Update state to new height.
             let block_hash = self.blockchain.last_hash();
             self.state
@@ -521,15 +691,28 @@ impl NodeHandler {
         let hash = msg.hash();
 
         let snapshot = self.blockchain.snapshot();
-        if Schema::new(&snapshot).transactions().contains(&hash) {
+        let schema = Schema::new(&snapshot);
+        if schema.transactions().contains(&hash) {
+            // Transactions in the pool are not dropped: they are still to be committed.
+            if schema.transactions_locations().contains(&hash) {
+                self.api_state.record_dropped_transaction(
+                    hash,
+                    DropReason::Rejected,
+                    "The transaction is already committed".to_owned(),
+                );
+            }
             bail!("Received already processed transaction, hash {:?}", hash)
         }
 
         if let Err(e) = self.blockchain.tx_from_raw(msg.payload().clone()) {
             error!("Received invalid transaction {:?}, result: {}", msg, e);
+            self.api_state
+                .record_dropped_transaction(hash, DropReason::Invalid, e.to_string());
             bail!("Received malicious transaction.")
         }
 
+        let service_id = msg.payload().service_id();
+        let transaction_id = msg.payload().transaction_id();
         let mut fork = self.blockchain.fork();
         {
             let mut schema = Schema::new(&mut fork);
@@ -538,11 +721,15 @@ impl NodeHandler {
         self.blockchain
             .merge(fork.into_patch())
             .expect("Unable to save transaction to persistent pool.");
+        self.api_state
+            .broadcast_pool_admission(hash, service_id, transaction_id);
 
         if self.state.is_leader() && self.state.round() != Round::zero() {
             self.maybe_add_propose_timeout();
         }
 
+        self.remove_request(&RequestData::AnnouncedTransaction(hash));
+
         let full_proposes = self.state.check_incomplete_proposes(hash);
         // Go to handle full propose if we get last transaction.
         for (hash, round) in full_proposes {
@@ -579,22 +766,105 @@ impl NodeHandler {
             )
         }
         for tx in msg.transactions() {
-            self.execute_later(InternalRequest::VerifyMessage(tx));
+            self.execute_later(InternalRequest::VerifyMessage(msg.author(), tx));
         }
         Ok(())
     }
 
-    /// Handles external boxed transaction. Additionally transaction will be broadcast to the
+    /// Handles the `TransactionsAnnounce` message. Unknown transactions are requested
+    /// from the author of the message.
+    pub fn handle_txs_announce(&mut self, msg: &Signed<TransactionsAnnounce>) {
+        let peer = msg.author();
+        if !self.state.connect_list().is_peer_allowed(&peer) {
+            error!(
+                "Received transactions announce from peer = {:?} which not in ConnectList.",
+                peer
+            );
+            return;
+        }
+
+        let unknown_txs = {
+            let snapshot = self.blockchain.snapshot();
+            let schema = Schema::new(&snapshot);
+            let transactions = schema.transactions();
+            msg.txs()
+                .iter()
+                .filter(|tx_hash| !transactions.contains(tx_hash))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+
+        // Transactions that are already requested from other peers are not requested again,
+        // the author is only remembered as one more peer that knows them.
+        let mut requested_txs = Vec::new();
+        for tx_hash in unknown_txs {
+            let data = RequestData::AnnouncedTransaction(tx_hash);
+            if self.state.request(data.clone(), peer) {
+                self.add_request_timeout(data, Some(peer));
+                requested_txs.push(tx_hash);
+            }
+        }
+
+        let chunk_size = self.tx_hashes_per_message();
+        for txs in requested_txs.chunks(chunk_size) {
+            let request = self.sign_message(TransactionsRequest::new(&peer, txs));
+            self.send_to_peer(peer, request);
+        }
+    }
+
+    /// Handles external boxed transaction. Additionally transaction will be sent to the
     /// Node's peers.
     #[cfg_attr(feature = "cargo-clippy", allow(clippy::needless_pass_by_value))]
     pub fn handle_incoming_tx(&mut self, msg: Signed<RawTransaction>) {
         trace!("Handle incoming transaction");
         match self.handle_tx(msg.clone()) {
-            Ok(_) => self.broadcast(msg),
+            Ok(_) => self.gossip_tx(msg),
             Err(e) => error!("{}", e),
         }
     }
 
+    /// Broadcasts the transaction to all peers if the network is small enough, otherwise
+    /// queues its hash for the next `TransactionsAnnounce` message.
+    fn gossip_tx(&mut self, msg: Signed<RawTransaction>) {
+        if self.is_tx_flooding() {
+            self.broadcast(msg);
+        } else if self.state.add_tx_announcement(msg.hash()) {
+            self.add_tx_announcement_timeout();
+        }
+    }
+
+    /// Returns `true` if transactions are broadcast to peers as is.
+    pub(crate) fn is_tx_flooding(&self) -> bool {
+        self.state.peers().len() <= self.tx_gossip.flood_peers_threshold
+    }
+
+    /// Handles `NodeTimeout::TxAnnouncement`, broadcasts hashes of the transactions
+    /// received since the previous announcement.
+    pub fn handle_tx_announcement_timeout(&mut self) {
+        let txs = self.state.take_tx_announcements();
+        self.broadcast_tx_announcements(&txs);
+    }
+
+    /// Broadcasts `TransactionsAnnounce` messages with the given transaction hashes.
+    pub(crate) fn broadcast_tx_announcements(&mut self, txs: &[Hash]) {
+        let chunk_size = self.tx_hashes_per_message();
+        for txs in txs.chunks(chunk_size) {
+            trace!("Announce {} transactions", txs.len());
+            let announce = self.sign_message(TransactionsAnnounce::new(txs));
+            self.broadcast(announce);
+        }
+    }
+
+    /// Returns the maximum number of transaction hashes in `TransactionsAnnounce`, chosen so
+    /// that the `TransactionsRequest` for the same hashes fits into `max_message_len` as well.
+    fn tx_hashes_per_message(&self) -> usize {
+        let max_message_len = self.state.config().consensus.max_message_len as usize;
+        // `TransactionsRequest` additionally contains the recipient key.
+        let unoccupied_message_size =
+            max_message_len.saturating_sub(EMPTY_SIGNED_MESSAGE_SIZE + PUBLIC_KEY_LENGTH + 4);
+        ::std::cmp::max(unoccupied_message_size / TRANSACTIONS_ANNOUNCE_HASH_SIZE, 1)
+    }
+
     /// Handle new round, after jump.
     pub fn handle_new_round(&mut self, height: Height, round: Round) {
         trace!("Handle new round");
@@ -754,11 +1024,27 @@ impl NodeHandler {
                     ))
                     .into(),
                 RequestData::Block(height) => {
-                    self.sign_message(BlockRequest::new(&peer, height)).into()
+                    let count = self.block_sync.range_len(height);
+                    self.block_sync.set_peer(height, peer);
+                    if count > 1 {
+                        self.sign_message(BlockRequest::range(&peer, height, count))
+                            .into()
+                    } else if self.need_compact_block(&peer, height) {
+                        self.sign_message(CompactBlockRequest::new(&peer, height))
+                            .into()
+                    } else {
+                        self.sign_message(BlockRequest::new(&peer, height)).into()
+                    }
                 }
+                RequestData::AnnouncedTransaction(ref tx_hash) => self
+                    .sign_message(TransactionsRequest::new(&peer, &[*tx_hash]))
+                    .into(),
             };
             trace!("Send request {:?} to peer {:?}", data, peer);
             self.send_to_peer(peer, message);
+        } else if let RequestData::Block(height) = *data {
+            // No peer has responded, so the blocks will be requested anew.
+            self.block_sync.finish_range(height);
         }
     }
 
@@ -821,25 +1107,85 @@ impl NodeHandler {
         }
     }
 
-    /// Requests a block for the next height from all peers with a bigger height. Called when the
-    /// node tries to catch up with other nodes' height.
+    /// Requests blocks following the current height from peers with a bigger height. Called when
+    /// the node tries to catch up with other nodes' height.
+    ///
+    /// Blocks within the window are requested by ranges, each range from the connected peer
+    /// with the least number of pending requests. Received blocks are committed in the order
+    /// of their heights.
     pub fn request_next_block(&mut self) {
-        // TODO: Randomize next peer. (ECR-171)
-        let heights: Vec<_> = self
+        // The outer call requests blocks after the received ones are committed.
+        if self.block_sync.is_applying() {
+            return;
+        }
+        self.apply_received_blocks();
+
+        let height = self.state.height();
+        let peers: Vec<(PublicKey, Height)> = self
             .state
             .nodes_with_bigger_height()
             .into_iter()
-            .cloned()
+            .filter(|peer| self.state.peers().contains_key(*peer))
+            .map(|peer| (*peer, self.state.node_height(peer)))
             .collect();
-        if !heights.is_empty() {
-            for peer in heights {
-                if self.state.peers().contains_key(&peer) {
-                    let height = self.state.height();
-                    self.request(RequestData::Block(height), peer);
-                    break;
-                }
+        let limit = match peers.iter().map(|&(_, peer_height)| peer_height).max() {
+            Some(limit) => limit,
+            None => return,
+        };
+
+        while let Some((start, count)) = self.block_sync.next_range(height, limit) {
+            let (peer, peer_height) = match self.block_sync.select_peer(start, &peers) {
+                Some(peer) => peer,
+                None => break,
+            };
+            let count = cmp::min(u64::from(count), peer_height.0 - start.0) as u32;
+            self.block_sync.start_range(start, count, peer);
+            self.request(RequestData::Block(start), peer);
+        }
+    }
+
+    /// Commits the received blocks which follow the current height.
+    fn apply_received_blocks(&mut self) {
+        self.block_sync.set_applying(true);
+        loop {
+            let height = self.state.height();
+            for start in self.block_sync.prune(height) {
+                self.remove_request(&RequestData::Block(start));
+            }
+            let block = match self.block_sync.take_block(height) {
+                Some(block) => block,
+                None => break,
+            };
+            if let Err(e) = self.handle_block(&block) {
+                error!("Unable to commit the received block: {}", e);
             }
+            // The block is invalid or its transactions are requested.
+            if self.state.height() == height {
+                break;
+            }
+        }
+        self.block_sync.set_applying(false);
+    }
+
+    /// Removes the request of the blocks range containing the given height.
+    fn finish_block_request(&mut self, height: Height) {
+        let start = self.block_sync.finish_range(height).unwrap_or(height);
+        self.remove_request(&RequestData::Block(start));
+    }
+
+    /// Returns `true` if the block at the given height should be requested from the peer
+    /// in the compact form, that is if the node lags only slightly behind the peer and
+    /// has transactions in the pool to restore the block from.
+    fn need_compact_block(&self, peer: &PublicKey, height: Height) -> bool {
+        if !self.compact_blocks.enabled {
+            return false;
         }
+        let lag = self.state.node_height(peer).0.saturating_sub(height.0);
+        if lag > self.compact_blocks.max_lag {
+            return false;
+        }
+        let snapshot = self.blockchain.snapshot();
+        Schema::new(&snapshot).transactions_pool_len() > 0
     }
 
     /// Removes the specified request from the pending request list.
@@ -1,9 +1,21 @@
-use super::{ConnectListConfig, ExternalMessage, NodeHandler, NodeTimeout};
+use super::{
+    ConnectInfo, ConnectListConfig, ExternalMessage, Misbehavior, NodeHandler, NodeTimeout,
+    TraceEvent,
+};
 use blockchain::Schema;
-use events::{error::LogError, Event, EventHandler, InternalEvent, InternalRequest, NetworkEvent};
+use crypto::PublicKey;
+use events::{
+    error::LogError, Event, EventHandler, InternalEvent, InternalRequest, NetworkEvent,
+    NetworkRequest,
+};
 
 impl EventHandler for NodeHandler {
     fn handle_event(&mut self, event: Event) {
+        if self.consensus_trace.is_some() {
+            if let Some(trace_event) = TraceEvent::from_event(&event) {
+                self.record_trace(trace_event);
+            }
+        }
         match event {
             Event::Network(network) => self.handle_network_event(network),
             Event::Api(api) => self.handle_api_event(api),
@@ -22,6 +34,9 @@ impl NodeHandler {
             InternalEvent::JumpToRound(height, round) => self.handle_new_round(height, round),
             InternalEvent::Shutdown => panic!("Shutdown should be processed in the event loop"),
             InternalEvent::MessageVerified(msg) => self.handle_message(*msg),
+            InternalEvent::InvalidMessage(peer) => {
+                self.report_misbehavior(&peer, Misbehavior::InvalidMessage)
+            }
         }
     }
 
@@ -30,8 +45,8 @@ impl NodeHandler {
             NetworkEvent::PeerConnected(peer, connect) => self.handle_connected(&peer, connect),
             NetworkEvent::PeerDisconnected(peer) => self.handle_disconnected(peer),
             NetworkEvent::UnableConnectToPeer(peer) => self.handle_unable_to_connect(peer),
-            NetworkEvent::MessageReceived(raw) => {
-                self.execute_later(InternalRequest::VerifyMessage(raw))
+            NetworkEvent::MessageReceived(peer, raw) => {
+                self.execute_later(InternalRequest::VerifyMessage(peer, raw))
             }
         }
     }
@@ -42,18 +57,28 @@ impl NodeHandler {
                 self.handle_incoming_tx(tx);
             }
             ExternalMessage::PeerAdd(info) => {
+                if !self.state.add_peer_to_connect_list(info.clone()) {
+                    warn!(
+                        "Unable to change address of pinned peer {}",
+                        info.public_key
+                    );
+                    return;
+                }
                 info!("Send Connect message to {}", info);
-                self.state.add_peer_to_connect_list(info.clone());
                 self.connect(info.public_key);
-
-                if self.config_manager.is_some() {
-                    let connect_list_config =
-                        ConnectListConfig::from_connect_list(&self.state.connect_list());
-
-                    self.config_manager
-                        .as_ref()
-                        .unwrap()
-                        .store_connect_list(connect_list_config);
+                self.store_connect_list();
+            }
+            ExternalMessage::PeerRemove(public_key) => self.handle_peer_remove(public_key),
+            ExternalMessage::PeerUpdate(info) => self.handle_peer_update(info),
+            ExternalMessage::PeerPin(public_key, pinned) => {
+                if self.state.connect_list().set_pinned(&public_key, pinned) {
+                    info!("Peer {} is pinned: {}", public_key, pinned);
+                    self.store_connect_list();
+                } else {
+                    warn!(
+                        "Unable to pin peer {}, it is not in the ConnectList",
+                        public_key
+                    );
                 }
             }
             ExternalMessage::Enable(value) => {
@@ -76,7 +101,9 @@ impl NodeHandler {
     }
 
     fn handle_timeout(&mut self, timeout: NodeTimeout) {
-        if !self.is_enabled {
+        // Announcements are sent even by the disabled node, since it still accepts
+        // transactions from the API.
+        if !self.is_enabled && timeout != NodeTimeout::TxAnnouncement {
             info!(
                 "Ignoring a timeout {:?} because the node is disabled",
                 timeout
@@ -90,6 +117,71 @@ impl NodeHandler {
             NodeTimeout::PeerExchange => self.handle_peer_exchange_timeout(),
             NodeTimeout::UpdateApiState => self.handle_update_api_state_timeout(),
             NodeTimeout::Propose(height, round) => self.handle_propose_timeout(height, round),
+            NodeTimeout::TxAnnouncement => self.handle_tx_announcement_timeout(),
+        }
+    }
+
+    /// Removes the peer from the connect list and drops the connection with it.
+    fn handle_peer_remove(&mut self, public_key: PublicKey) {
+        let connect_list = self.state.connect_list();
+        if connect_list.is_pinned(&public_key) {
+            warn!("Unable to remove pinned peer {}", public_key);
+            return;
+        }
+        if !connect_list.remove_peer(&public_key) {
+            warn!(
+                "Unable to remove peer {}, it is not in the ConnectList",
+                public_key
+            );
+            return;
+        }
+
+        info!("Removed peer {} from the ConnectList", public_key);
+        self.state.remove_peer_with_pubkey(&public_key);
+        self.blockchain.remove_peer_with_pubkey(&public_key);
+        self.channel
+            .network_requests
+            .send(NetworkRequest::DisconnectWithPeer(public_key))
+            .log_error();
+        self.store_connect_list();
+    }
+
+    /// Changes the address of the peer and reconnects to it using the new address.
+    fn handle_peer_update(&mut self, info: ConnectInfo) {
+        let mut connect_list = self.state.connect_list();
+        if connect_list.is_pinned(&info.public_key) {
+            warn!(
+                "Unable to change address of pinned peer {}",
+                info.public_key
+            );
+            return;
+        }
+        if !connect_list.is_peer_allowed(&info.public_key) {
+            warn!(
+                "Unable to change address of peer {}, it is not in the ConnectList",
+                info.public_key
+            );
+            return;
+        }
+
+        info!("Changing address of peer {} to {}", info.public_key, info);
+        connect_list.update_peer(&info.public_key, info.address.clone());
+        self.state.remove_peer_with_pubkey(&info.public_key);
+        self.blockchain.remove_peer_with_pubkey(&info.public_key);
+        self.channel
+            .network_requests
+            .send(NetworkRequest::DisconnectWithPeer(info.public_key))
+            .log_error();
+        self.connect(info.public_key);
+        self.store_connect_list();
+    }
+
+    /// Stores the connect list in the config file, if the node has one.
+    pub(crate) fn store_connect_list(&self) {
+        if let Some(ref config_manager) = self.config_manager {
+            let connect_list_config =
+                ConnectListConfig::from_connect_list(&self.state.connect_list());
+            config_manager.store_connect_list(connect_list_config);
         }
     }
 
@@ -98,11 +190,17 @@ impl NodeHandler {
         self.channel.internal_requests.send(event).log_error();
     }
 
-    /// Broadcasts all transactions from the pool to other validators.
+    /// Broadcasts all transactions from the pool to other validators. In large networks
+    /// only hashes of the transactions are announced.
     pub(crate) fn handle_rebroadcast(&mut self) {
         let snapshot = self.blockchain.snapshot();
         let schema = Schema::new(snapshot);
         let pool = schema.transactions_pool();
+        if !self.is_tx_flooding() {
+            let txs = pool.iter().collect::<Vec<_>>();
+            self.broadcast_tx_announcements(&txs);
+            return;
+        }
         for tx_hash in pool.iter() {
             self.broadcast(
                 schema
@@ -0,0 +1,213 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Consensus key rotation of the node and its peers.
+
+use blockchain::{Schema, StoredConfiguration};
+use crypto::{PublicKey, SecretKey};
+use events::{error::LogError, NetworkRequest};
+use helpers::Height;
+use messages::{Connect, Message};
+use node::NodeHandler;
+
+/// Consensus key rotation settings.
+///
+/// The node switches to the next consensus keys once they replace its current key
+/// in the actual configuration. Peers that rotate their keys are followed
+/// in the connect list.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyRotationConfig {
+    /// Next consensus public key of the node.
+    pub next_consensus_public_key: Option<PublicKey>,
+    /// Next consensus secret key of the node.
+    pub next_consensus_secret_key: Option<SecretKey>,
+    /// Number of blocks during which the previous key of a validator which has rotated
+    /// its consensus key is still accepted.
+    pub transition_window: u64,
+}
+
+impl Default for KeyRotationConfig {
+    fn default() -> Self {
+        Self {
+            next_consensus_public_key: None,
+            next_consensus_secret_key: None,
+            transition_window: 100,
+        }
+    }
+}
+
+impl NodeHandler {
+    /// Applies the actual configuration after a block is committed. Consensus keys
+    /// replaced by the configuration are followed by the node and its connect list.
+    ///
+    /// Other changes of the validator list, such as removals, additions or reordering,
+    /// are not treated as key rotations.
+    pub(crate) fn update_config(&mut self) {
+        let config = Schema::new(&self.blockchain.snapshot()).actual_configuration();
+        let height = self.state.height();
+        let previous_keys = self
+            .state
+            .validators()
+            .iter()
+            .map(|keys| keys.consensus_key)
+            .collect::<Vec<_>>();
+
+        self.rotate_own_keys(&config);
+        self.state.update_config(config);
+
+        let connect_list = self.state.connect_list();
+        let until = Height(height.0 + self.key_rotation.transition_window);
+        let current_keys = self
+            .state
+            .validators()
+            .iter()
+            .map(|keys| keys.consensus_key)
+            .collect::<Vec<_>>();
+        let mut is_changed = false;
+        if let Some((old_key, new_key)) = rotated_key(&previous_keys, &current_keys) {
+            if connect_list.rotate_key(&old_key, new_key, until) {
+                info!(
+                    "Validator has rotated consensus key {} to {}",
+                    old_key, new_key
+                );
+                is_changed = true;
+            }
+        }
+
+        for key in connect_list.remove_retired(height) {
+            info!("Transition window for the consensus key {} has ended", key);
+            self.state.remove_peer_with_pubkey(&key);
+            self.blockchain.remove_peer_with_pubkey(&key);
+            self.channel
+                .network_requests
+                .send(NetworkRequest::DisconnectWithPeer(key))
+                .log_error();
+            is_changed = true;
+        }
+
+        if is_changed {
+            self.store_connect_list();
+        }
+    }
+
+    // Switches to the next consensus keys if they replace the current key in the config.
+    fn rotate_own_keys(&mut self, config: &StoredConfiguration) {
+        let (public_key, secret_key) = match (
+            self.key_rotation.next_consensus_public_key,
+            self.key_rotation.next_consensus_secret_key.clone(),
+        ) {
+            (Some(public_key), Some(secret_key)) => (public_key, secret_key),
+            _ => return,
+        };
+        let is_validator_key = |key: &PublicKey| {
+            config
+                .validator_keys
+                .iter()
+                .any(|keys| keys.consensus_key == *key)
+        };
+        if is_validator_key(self.state.consensus_public_key()) || !is_validator_key(&public_key) {
+            return;
+        }
+
+        info!(
+            "Switching consensus key {} to {}",
+            self.state.consensus_public_key(),
+            public_key
+        );
+        let connect = {
+            let current = self.state.our_connect_message();
+            Connect::new(
+                current.pub_addr(),
+                self.system_state.current_time().into(),
+                current.user_agent(),
+            )
+            .with_compression(current.compression().to_vec())
+            .with_rekeying(current.rekeying())
+            .with_addresses(current.addresses().to_vec())
+        };
+        let connect = Message::concrete(connect, public_key, &secret_key);
+
+        self.state
+            .set_consensus_keys(public_key, secret_key.clone(), connect.clone());
+        self.key_rotation.next_consensus_public_key = None;
+        self.key_rotation.next_consensus_secret_key = None;
+        self.channel
+            .network_requests
+            .send(NetworkRequest::UpdateConsensusKeys(
+                public_key,
+                secret_key.clone(),
+                connect.clone(),
+            ))
+            .log_error();
+        if let Some(ref config_manager) = self.config_manager {
+            config_manager.store_consensus_keys(public_key, secret_key);
+        }
+
+        // Peers accept the previous key during the transition window, so the existing
+        // connections are kept, and the peers learn the new key from `Connect`.
+        self.broadcast(connect);
+    }
+}
+
+/// Returns the replaced and the new consensus key if the validator list differs from
+/// the previous one in exactly one slot, and the new key is not a key of another
+/// previous validator.
+fn rotated_key(previous: &[PublicKey], current: &[PublicKey]) -> Option<(PublicKey, PublicKey)> {
+    if previous.len() != current.len() {
+        return None;
+    }
+    let mut changed = previous
+        .iter()
+        .zip(current)
+        .filter(|(old_key, new_key)| old_key != new_key);
+    match (changed.next(), changed.next()) {
+        (Some((old_key, new_key)), None) if !previous.contains(new_key) => {
+            Some((*old_key, *new_key))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::rotated_key;
+    use crypto::gen_keypair;
+
+    #[test]
+    fn test_rotated_key() {
+        let keys = (0..4).map(|_| gen_keypair().0).collect::<Vec<_>>();
+        let (a, b, c, d) = (keys[0], keys[1], keys[2], keys[3]);
+
+        assert_eq!(rotated_key(&[a, b, c], &[a, d, c]), Some((b, d)));
+        assert_eq!(rotated_key(&[a, b, c], &[a, b, c]), None);
+        // Two validators have changed their keys at once.
+        assert_eq!(rotated_key(&[a, b, c], &[d, a, c]), None);
+        // Validators have been reordered.
+        assert_eq!(rotated_key(&[a, b, c], &[a, c, b]), None);
+        // A validator has been added.
+        assert_eq!(rotated_key(&[a, b], &[a, b, d]), None);
+    }
+
+    #[test]
+    fn test_removed_validator_is_not_rotation() {
+        let keys = (0..4).map(|_| gen_keypair().0).collect::<Vec<_>>();
+        let (a, b, c, d) = (keys[0], keys[1], keys[2], keys[3]);
+
+        assert_eq!(rotated_key(&[a, b, c], &[a, c]), None);
+        assert_eq!(rotated_key(&[a, b, c], &[a, b]), None);
+        // Removal and addition of a validator keep the length but move another validator.
+        assert_eq!(rotated_key(&[a, b, c], &[a, c, d]), None);
+    }
+}
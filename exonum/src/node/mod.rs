@@ -4,13 +4,21 @@
 // spell-checker:ignore cors
 
 pub use self::{
-    connect_list::{ConnectList, PeerAddress},
+    block_sync::{BlockSync, BlockSyncConfig},
+    connect_list::{ConnectList, PeerAddress, PeerDiscoveryConfig},
+    key_rotation::KeyRotationConfig,
+    peer_scores::{Misbehavior, PeerScoreInfo, PeerScores, PeerScoringConfig, SharedPeerScores},
+    replay::TraceReplayer,
+    replica::ReplicaConfig,
     state::{RequestData, State, ValidatorState},
+    trace::{ConsensusTrace, TraceEntry, TraceEvent},
 };
 
 // TODO: Temporary solution to get access to WAIT constants. (ECR-167)
 pub mod state;
 
+pub mod simulation;
+
 use failure::{self, Error};
 use futures::{sync::mpsc, Future, Sink};
 use tokio_core::reactor::Core;
@@ -26,8 +34,12 @@ use std::{
     time::{Duration, SystemTime},
 };
 
+#[cfg(feature = "grpc")]
+use api::backends::grpc::GrpcServer;
 use api::{
+    auth::{AccessControl, AuthConfig, Authenticator},
     backends::actix::{AllowOrigin, ApiRuntimeConfig, App, AppConfig, Cors, SystemRuntimeConfig},
+    rate_limit::{RateLimitConfig, RateLimiter},
     ApiAccess, ApiAggregator,
 };
 use blockchain::{
@@ -38,28 +50,42 @@ use events::{
     error::{into_failure, LogError},
     noise::HandshakeParams,
     HandlerPart, InternalEvent, InternalPart, InternalRequest, NetworkConfiguration, NetworkEvent,
-    NetworkPart, NetworkRequest, SyncSender, TimeoutRequest,
+    NetworkPart, NetworkRequest, SharedCompressionStats, SyncSender, TimeoutRequest,
 };
 use helpers::{
     config::ConfigManager,
     fabric::{NodePrivateConfig, NodePublicConfig},
     user_agent, Height, Milliseconds, Round, ValidatorId,
 };
-use messages::{Connect, Message, ProtocolMessage, RawTransaction, Signed, SignedMessage};
+use messages::{
+    Connect, Message, PeerAddressRecord, ProtocolMessage, RawTransaction, Signed, SignedMessage,
+};
 use node::state::SharedConnectList;
 use storage::{Database, DbOptions};
 
 mod basic;
+mod block_sync;
 mod connect_list;
 mod consensus;
 mod events;
+mod key_rotation;
+mod peer_scores;
+mod replay;
+mod replica;
 mod requests;
+mod trace;
 
 /// External messages.
 #[derive(Debug)]
 pub enum ExternalMessage {
     /// Add a new connection.
     PeerAdd(ConnectInfo),
+    /// Remove the peer from the connect list and drop the connection with it.
+    PeerRemove(PublicKey),
+    /// Change the address of the peer in the connect list.
+    PeerUpdate(ConnectInfo),
+    /// Pin or unpin the peer in the connect list.
+    PeerPin(PublicKey, bool),
     /// Transaction that implements the `Transaction` trait.
     Transaction(Signed<RawTransaction>),
     /// Enable or disable the node.
@@ -71,7 +97,7 @@ pub enum ExternalMessage {
 }
 
 /// Node timeout types.
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum NodeTimeout {
     /// Status timeout with the current height.
     Status(Height),
@@ -85,6 +111,8 @@ pub enum NodeTimeout {
     UpdateApiState,
     /// Exchange peers timeout.
     PeerExchange,
+    /// Send accumulated transactions announcements.
+    TxAnnouncement,
 }
 
 /// A helper trait that provides the node with information about the state of the system such
@@ -122,6 +150,24 @@ pub struct NodeHandler {
     config_manager: Option<ConfigManager>,
     /// Can we speed up Propose with transaction pressure?
     allow_expedited_propose: bool,
+    /// Optional trace of the handled consensus events.
+    consensus_trace: Option<ConsensusTrace>,
+    /// Misbehavior scores of the peers.
+    peer_scores: SharedPeerScores,
+    /// Transactions gossip configuration.
+    tx_gossip: TxGossipConfig,
+    /// Compact blocks configuration.
+    compact_blocks: CompactBlocksConfig,
+    /// Blocks requested during catch-up.
+    block_sync: BlockSync,
+    /// Counters of the compression used by the connections.
+    compression_stats: SharedCompressionStats,
+    /// Consensus key rotation settings.
+    key_rotation: KeyRotationConfig,
+    /// Discovery of the peers outside of the connect list.
+    discovery: PeerDiscoveryConfig,
+    /// Whether the node runs as a read replica.
+    replica: bool,
 }
 
 /// Service configuration.
@@ -144,6 +190,20 @@ pub struct ListenerConfig {
     pub connect_list: ConnectList,
     /// Socket address.
     pub address: SocketAddr,
+    /// Consensus key rotation settings.
+    #[serde(default)]
+    pub key_rotation: KeyRotationConfig,
+    /// Additional external addresses of the node advertised to its peers.
+    #[serde(default)]
+    pub external_addresses: Vec<PeerAddressRecord>,
+    /// Whether the node runs as a read replica.
+    #[serde(default)]
+    pub replica: bool,
+    /// Whether the node maintains the explorer indexes of the committed transactions
+    /// by author and by service and of the block times. The indexes are required by
+    /// the transaction search of the explorer API.
+    #[serde(default)]
+    pub explorer_indexes: bool,
 }
 
 /// An api configuration options.
@@ -165,6 +225,18 @@ pub struct NodeApiConfig {
     ///
     /// [cors]: https://developer.mozilla.org/en-US/docs/Web/HTTP/CORS
     pub private_allow_origin: Option<AllowOrigin>,
+    /// Authentication of the private API requests. If not set, the private API is
+    /// accessible to anyone who can reach `private_api_address`.
+    #[serde(default)]
+    pub private_api_auth: Option<AuthConfig>,
+    /// Rate limits of the public API requests. If not set, the requests are not limited.
+    #[serde(default)]
+    pub public_rate_limits: Option<RateLimitConfig>,
+    /// Listen address for the public API methods served over gRPC. The gRPC api is
+    /// available only with the `grpc` feature and enforces only the global rate limits,
+    /// so it should not be exposed to untrusted networks.
+    #[serde(default)]
+    pub grpc_api_address: Option<SocketAddr>,
 }
 
 impl Default for NodeApiConfig {
@@ -175,6 +247,9 @@ impl Default for NodeApiConfig {
             private_api_address: None,
             public_allow_origin: None,
             private_allow_origin: None,
+            private_api_auth: None,
+            public_rate_limits: None,
+            grpc_api_address: None,
         }
     }
 }
@@ -209,12 +284,66 @@ pub struct MemoryPoolConfig {
     /// Sets the maximum number of messages that can be buffered on the event loop's
     /// notification channel before a send will fail.
     pub events_pool_capacity: EventsPoolCapacity,
+    /// Transactions gossip parameters.
+    #[serde(default)]
+    pub tx_gossip: TxGossipConfig,
+    /// Compact blocks parameters.
+    #[serde(default)]
+    pub compact_blocks: CompactBlocksConfig,
 }
 
 impl Default for MemoryPoolConfig {
     fn default() -> Self {
         Self {
             events_pool_capacity: EventsPoolCapacity::default(),
+            tx_gossip: TxGossipConfig::default(),
+            compact_blocks: CompactBlocksConfig::default(),
+        }
+    }
+}
+
+/// Transactions gossip configuration.
+///
+/// In small networks transactions are broadcast to every peer as is. Once the number
+/// of peers exceeds `flood_peers_threshold`, the node announces only transaction hashes
+/// with `TransactionsAnnounce` messages and peers request the bodies they lack.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TxGossipConfig {
+    /// Maximum number of peers for which transactions are broadcast as is.
+    pub flood_peers_threshold: usize,
+    /// Interval during which hashes of new transactions are accumulated before
+    /// the announcement is sent.
+    pub announce_interval: Milliseconds,
+}
+
+impl Default for TxGossipConfig {
+    fn default() -> Self {
+        Self {
+            flood_peers_threshold: 8,
+            announce_interval: 50,
+        }
+    }
+}
+
+/// Compact blocks configuration.
+///
+/// A node lagging slightly behind its peer requests blocks with `CompactBlockRequest`
+/// and restores their transactions from its own pool. Compact blocks are disabled
+/// by default, since nodes of previous versions do not understand these messages.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CompactBlocksConfig {
+    /// Whether the node requests compact blocks.
+    pub enabled: bool,
+    /// Maximum number of blocks the node may lag behind the peer to request
+    /// a compact block from it.
+    pub max_lag: u64,
+}
+
+impl Default for CompactBlocksConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_lag: 8,
         }
     }
 }
@@ -252,6 +381,19 @@ pub struct NodeConfig {
     pub connect_list: ConnectListConfig,
     /// Transaction Verification Thread Pool size.
     pub thread_pool_size: Option<u8>,
+    /// Optional path to the file where the trace of consensus events is appended.
+    #[serde(default)]
+    pub consensus_trace: Option<String>,
+    /// Consensus key rotation settings.
+    #[serde(default)]
+    pub key_rotation: KeyRotationConfig,
+    /// Additional external addresses of the node with their priorities.
+    #[serde(default)]
+    pub external_addresses: Vec<PeerAddressRecord>,
+    /// Whether the node runs as a read replica, which follows the blockchain without
+    /// taking part in consensus. See [`ReplicaConfig`](struct.ReplicaConfig.html).
+    #[serde(default)]
+    pub replica: bool,
 }
 
 /// Configuration for the `NodeHandler`.
@@ -325,7 +467,29 @@ impl NodeRole {
 /// ConnectList representation in node's config file.
 pub struct ConnectListConfig {
     /// Peers to which we can connect.
-    pub peers: Vec<ConnectInfo>,
+    pub peers: Vec<ConnectListPeer>,
+}
+
+/// Peer of the `ConnectListConfig`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ConnectListPeer {
+    /// Peer address.
+    pub address: String,
+    /// Peer public key.
+    pub public_key: PublicKey,
+    /// Whether the peer is pinned.
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+impl From<ConnectInfo> for ConnectListPeer {
+    fn from(info: ConnectInfo) -> Self {
+        ConnectListPeer {
+            address: info.address,
+            public_key: info.public_key,
+            pinned: false,
+        }
+    }
 }
 
 impl ConnectListConfig {
@@ -334,9 +498,10 @@ impl ConnectListConfig {
         let peers = list
             .iter()
             .filter(|config| config.validator_keys.consensus_key != node.consensus_public_key)
-            .map(|config| ConnectInfo {
+            .map(|config| ConnectListPeer {
                 public_key: config.validator_keys.consensus_key,
                 address: config.address.clone(),
+                pinned: false,
             })
             .collect();
 
@@ -348,20 +513,20 @@ impl ConnectListConfig {
         let peers = peers
             .iter()
             .zip(validators_keys.iter())
-            .map(|(a, v)| ConnectInfo {
+            .map(|(a, v)| ConnectListPeer {
                 address: a.clone(),
                 public_key: v.consensus_key,
+                pinned: false,
             })
             .collect();
 
         ConnectListConfig { peers }
     }
 
-    /// Creates `ConnectListConfig` from `ConnectList`.
+    /// Creates `ConnectListConfig` from `ConnectList`. Peers discovered outside
+    /// of the configured connect list are not included.
     pub fn from_connect_list(connect_list: &SharedConnectList) -> Self {
-        ConnectListConfig {
-            peers: connect_list.peers(),
-        }
+        connect_list.to_config()
     }
 
     /// `ConnectListConfig` peers addresses.
@@ -402,12 +567,21 @@ impl NodeHandler {
                 external_address,
                 system_state.current_time().into(),
                 &user_agent::get(),
-            ),
+            )
+            .with_compression(config.network.compression.offer())
+            .with_rekeying(config.network.rekeying.enabled)
+            .with_addresses(config.listener.external_addresses.clone()),
             config.listener.consensus_public_key,
             &config.listener.consensus_secret_key,
         );
 
         let connect_list = config.listener.connect_list;
+        let peer_scores = SharedPeerScores::new(config.network.peer_scoring);
+        let tx_gossip = config.mempool.tx_gossip;
+        let compact_blocks = config.mempool.compact_blocks;
+        let block_sync = BlockSync::new(config.network.block_sync);
+        let key_rotation = config.listener.key_rotation.clone();
+        let replica = config.listener.replica;
         let state = State::new(
             validator_id,
             config.listener.consensus_public_key,
@@ -426,6 +600,7 @@ impl NodeHandler {
         let node_role = NodeRole::new(validator_id);
         let is_enabled = api_state.is_enabled();
         api_state.set_node_role(node_role);
+        api_state.set_connect_list(state.connect_list());
 
         let config_manager = match config_file_path {
             Some(path) => Some(ConfigManager::new(path)),
@@ -443,6 +618,15 @@ impl NodeHandler {
             node_role,
             config_manager,
             allow_expedited_propose: true,
+            consensus_trace: None,
+            peer_scores,
+            tx_gossip,
+            compact_blocks,
+            block_sync,
+            compression_stats: SharedCompressionStats::new(),
+            key_rotation,
+            discovery: config.network.discovery,
+            replica,
         }
     }
 
@@ -454,11 +638,49 @@ impl NodeHandler {
         )
     }
 
+    /// Attaches the trace that records every consensus event handled by the node
+    /// from now on. The trace starts with the `Started` entry describing the current state.
+    pub fn set_consensus_trace(&mut self, trace: ConsensusTrace) {
+        self.consensus_trace = Some(trace);
+        let event = TraceEvent::Started {
+            height: self.state.height(),
+            round: self.state.round(),
+            last_block_hash: self.last_block_hash(),
+        };
+        self.record_trace(event);
+    }
+
+    /// Appends the event to the consensus trace, if any.
+    fn record_trace(&mut self, event: TraceEvent) {
+        let time = self.system_state.current_time();
+        if let Some(ref mut trace) = self.consensus_trace {
+            trace.record(time, event).log_error();
+        }
+    }
+
     /// Return internal `SharedNodeState`
     pub fn api_state(&self) -> &SharedNodeState {
         &self.api_state
     }
 
+    /// Returns misbehavior scores of the peers.
+    pub fn peer_scores(&self) -> &SharedPeerScores {
+        &self.peer_scores
+    }
+
+    /// Increases the misbehavior score of the peer and disconnects from it
+    /// if the peer becomes banned.
+    pub(crate) fn report_misbehavior(&mut self, peer: &PublicKey, misbehavior: Misbehavior) {
+        warn!("Peer {:?} misbehaves: {:?}", peer, misbehavior);
+        if self.peer_scores.report(peer, misbehavior) {
+            warn!("Peer {:?} has been banned", peer);
+            self.channel
+                .network_requests
+                .send(NetworkRequest::DisconnectWithPeer(*peer))
+                .log_error();
+        }
+    }
+
     /// Returns value of the `first_round_timeout` field from the current `ConsensusConfig`.
     pub fn first_round_timeout(&self) -> Milliseconds {
         self.state().consensus_config().first_round_timeout
@@ -501,6 +723,11 @@ impl NodeHandler {
         self.state().consensus_config().propose_timeout_threshold
     }
 
+    /// Checks if the node runs as a read replica.
+    pub fn is_replica(&self) -> bool {
+        self.replica
+    }
+
     /// Returns `State` of the node.
     pub fn state(&self) -> &State {
         &self.state
@@ -605,8 +832,12 @@ impl NodeHandler {
         }
     }
 
-    /// Adds `NodeTimeout::Round` timeout to the channel.
+    /// Adds `NodeTimeout::Round` timeout to the channel. Replicas do not track rounds,
+    /// since they commit only the blocks received with precommits.
     pub fn add_round_timeout(&mut self) {
+        if self.replica {
+            return;
+        }
         let time = self.round_start_time(self.state.round().next());
         trace!(
             "ADD ROUND TIMEOUT: time={:?}, height={}, round={}",
@@ -618,8 +849,11 @@ impl NodeHandler {
         self.add_timeout(timeout, time);
     }
 
-    /// Adds `NodeTimeout::Propose` timeout to the channel.
+    /// Adds `NodeTimeout::Propose` timeout to the channel. Replicas never propose blocks.
     pub fn add_propose_timeout(&mut self) {
+        if self.replica {
+            return;
+        }
         let timeout = if self.need_faster_propose() {
             self.min_propose_timeout()
         } else {
@@ -673,6 +907,13 @@ impl NodeHandler {
         self.add_timeout(NodeTimeout::PeerExchange, time);
     }
 
+    /// Adds `NodeTimeout::TxAnnouncement` timeout to the channel.
+    pub fn add_tx_announcement_timeout(&mut self) {
+        let time = self.system_state.current_time()
+            + Duration::from_millis(self.tx_gossip.announce_interval);
+        self.add_timeout(NodeTimeout::TxAnnouncement, time);
+    }
+
     /// Adds `NodeTimeout::UpdateApiState` timeout to the channel.
     pub fn add_update_api_state_timeout(&mut self) {
         let time = self.system_state.current_time()
@@ -722,6 +963,24 @@ impl ApiSender {
         self.send_external_message(msg)
     }
 
+    /// Remove peer from peer list
+    pub fn peer_remove(&self, public_key: PublicKey) -> Result<(), Error> {
+        let msg = ExternalMessage::PeerRemove(public_key);
+        self.send_external_message(msg)
+    }
+
+    /// Change address of the peer in peer list
+    pub fn peer_update(&self, addr: ConnectInfo) -> Result<(), Error> {
+        let msg = ExternalMessage::PeerUpdate(addr);
+        self.send_external_message(msg)
+    }
+
+    /// Pin or unpin peer in peer list
+    pub fn peer_pin(&self, public_key: PublicKey, pinned: bool) -> Result<(), Error> {
+        let msg = ExternalMessage::PeerPin(public_key, pinned);
+        self.send_external_message(msg)
+    }
+
     /// Sends an external message.
     pub fn send_external_message(&self, message: ExternalMessage) -> Result<(), Error> {
         self.0
@@ -803,6 +1062,8 @@ pub struct Node {
     channel: NodeChannel,
     max_message_len: u32,
     thread_pool_size: Option<u8>,
+    consensus_trace: Option<String>,
+    private_api_authenticators: Vec<Arc<dyn Authenticator>>,
 }
 
 impl NodeChannel {
@@ -845,6 +1106,7 @@ impl Node {
             node_cfg.service_secret_key.clone(),
             ApiSender::new(channel.api_requests.0.clone()),
         );
+        blockchain.set_explorer_indexes(node_cfg.explorer_indexes);
         blockchain.initialize(node_cfg.genesis.clone()).unwrap();
 
         let peers = node_cfg.connect_list.addresses();
@@ -855,6 +1117,9 @@ impl Node {
                 consensus_secret_key: node_cfg.consensus_secret_key,
                 connect_list: ConnectList::from_config(node_cfg.connect_list),
                 address: node_cfg.listen_address,
+                key_rotation: node_cfg.key_rotation,
+                external_addresses: node_cfg.external_addresses,
+                replica: node_cfg.replica,
             },
             service: ServiceConfig {
                 service_public_key: node_cfg.service_public_key,
@@ -884,13 +1149,27 @@ impl Node {
             network_config,
             max_message_len: node_cfg.genesis.consensus.max_message_len,
             thread_pool_size: node_cfg.thread_pool_size,
+            consensus_trace: node_cfg.consensus_trace,
+            private_api_authenticators: Vec::new(),
         }
     }
 
+    /// Adds an authenticator of the private API requests, in addition to
+    /// the authentication configured in `NodeApiConfig::private_api_auth`.
+    pub fn add_private_api_authenticator<A: Authenticator + 'static>(&mut self, authenticator: A) {
+        self.private_api_authenticators
+            .push(Arc::new(authenticator));
+    }
+
     /// Launches only consensus messages handler.
     /// This may be used if you want to customize api with the `ApiContext`.
     pub fn run_handler(mut self, handshake_params: &HandshakeParams) -> Result<(), Error> {
         self.handler.initialize();
+        if let Some(ref path) = self.consensus_trace {
+            info!("Writing consensus trace to {}", path);
+            let trace = ConsensusTrace::open(path).map_err(into_failure)?;
+            self.handler.set_consensus_trace(trace);
+        }
 
         let pool_size = self.thread_pool_size;
         let (handler_part, network_part, internal_part) = self.into_reactor();
@@ -928,15 +1207,53 @@ impl Node {
     /// Private api prefix is `/api/services/{service_name}`
     pub fn run(self) -> Result<(), failure::Error> {
         trace!("Running node.");
+        let api_aggregator = ApiAggregator::new(
+            self.handler.blockchain.clone(),
+            self.handler.api_state.clone(),
+        );
+        let access_control = if self.api_options.private_api_auth.is_some()
+            || !self.private_api_authenticators.is_empty()
+        {
+            let auth_config = self
+                .api_options
+                .private_api_auth
+                .clone()
+                .unwrap_or_default();
+            Some(auth_config.access_control(&api_aggregator, &self.private_api_authenticators)?)
+        } else {
+            None
+        };
+        let rate_limiter = self
+            .api_options
+            .public_rate_limits
+            .map(|config| RateLimiter::new(config, self.handler.api_state.clone()));
+
         // Runs actix-web api.
         let actix_api_runtime = SystemRuntimeConfig {
             api_runtimes: {
-                fn into_app_config(allow_origin: AllowOrigin) -> AppConfig {
-                    let app_config = move |app: App| -> App {
-                        let cors = Cors::from(allow_origin.clone());
-                        app.middleware(cors)
+                fn into_app_config(
+                    allow_origin: Option<AllowOrigin>,
+                    access_control: Option<AccessControl>,
+                    rate_limiter: Option<RateLimiter>,
+                ) -> Option<AppConfig> {
+                    if allow_origin.is_none() && access_control.is_none() && rate_limiter.is_none()
+                    {
+                        return None;
+                    }
+                    let app_config = move |mut app: App| -> App {
+                        // Throttled requests are rejected before any other processing.
+                        if let Some(ref rate_limiter) = rate_limiter {
+                            app = app.middleware(rate_limiter.clone());
+                        }
+                        if let Some(ref allow_origin) = allow_origin {
+                            app = app.middleware(Cors::from(allow_origin.clone()));
+                        }
+                        if let Some(ref access_control) = access_control {
+                            app = app.middleware(access_control.clone());
+                        }
+                        app
                     };
-                    Arc::new(app_config)
+                    Some(Arc::new(app_config))
                 };
 
                 let public_api_handler = self
@@ -945,11 +1262,11 @@ impl Node {
                     .map(|listen_address| ApiRuntimeConfig {
                         listen_address,
                         access: ApiAccess::Public,
-                        app_config: self
-                            .api_options
-                            .public_allow_origin
-                            .clone()
-                            .map(into_app_config),
+                        app_config: into_app_config(
+                            self.api_options.public_allow_origin.clone(),
+                            None,
+                            rate_limiter.clone(),
+                        ),
                     })
                     .into_iter();
                 let private_api_handler = self
@@ -958,11 +1275,11 @@ impl Node {
                     .map(|listen_address| ApiRuntimeConfig {
                         listen_address,
                         access: ApiAccess::Private,
-                        app_config: self
-                            .api_options
-                            .private_allow_origin
-                            .clone()
-                            .map(into_app_config),
+                        app_config: into_app_config(
+                            self.api_options.private_allow_origin.clone(),
+                            access_control,
+                            None,
+                        ),
                     })
                     .into_iter();
                 // Collects API handlers.
@@ -970,25 +1287,53 @@ impl Node {
                     .chain(private_api_handler)
                     .collect::<Vec<_>>()
             },
-            api_aggregator: ApiAggregator::new(
-                self.handler.blockchain.clone(),
-                self.handler.api_state.clone(),
-            ),
+            api_aggregator: api_aggregator.clone(),
         }
         .start()?;
 
+        // Runs gRPC api. The rate limiter is shared with the HTTP public api.
+        #[cfg(feature = "grpc")]
+        let grpc_server = match self.api_options.grpc_api_address {
+            Some(listen_address) => Some(GrpcServer::start(
+                &api_aggregator,
+                listen_address,
+                rate_limiter,
+            )?),
+            None => None,
+        };
+        #[cfg(not(feature = "grpc"))]
+        {
+            if let Some(listen_address) = self.api_options.grpc_api_address {
+                warn!(
+                    "gRPC api is not started on {}, exonum is built without the `grpc` feature",
+                    listen_address
+                );
+            }
+        }
+
         // Runs NodeHandler.
-        let handshake_params = HandshakeParams::new(
+        let mut handshake_params = HandshakeParams::new(
             *self.state().consensus_public_key(),
             self.state().consensus_secret_key().clone(),
             self.state().connect_list().clone(),
             self.state().our_connect_message().clone(),
             self.max_message_len,
         );
+        handshake_params.set_compression(
+            self.network_config.compression,
+            self.handler.compression_stats.clone(),
+        );
+        handshake_params.set_rekeying(self.network_config.rekeying);
         self.run_handler(&handshake_params)?;
 
         // Stops actix web runtime.
         actix_api_runtime.stop()?;
+        #[cfg(feature = "grpc")]
+        {
+            if let Some(grpc_server) = grpc_server {
+                grpc_server.stop();
+            }
+        }
 
         info!("Exonum node stopped");
         Ok(())
@@ -1007,6 +1352,7 @@ impl Node {
             network_config: self.network_config,
             max_message_len: self.max_message_len,
             connect_list,
+            peer_scores: self.handler.peer_scores.clone(),
         };
 
         let (internal_tx, internal_rx) = self.channel.internal_events;
@@ -0,0 +1,365 @@
+//! Misbehavior scores, message rate limits and temporary bans of the peers.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use crypto::PublicKey;
+use helpers::Milliseconds;
+
+/// Amount by which the misbehavior score decreases every second.
+const SCORE_DECAY_PER_SECOND: u64 = 1;
+
+/// Peer scoring configuration.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct PeerScoringConfig {
+    /// Maximum number of messages per second accepted from a single peer. Short bursts
+    /// up to this amount of messages are allowed as well.
+    pub max_messages_per_second: u32,
+    /// Misbehavior score at which the peer is banned.
+    pub ban_threshold: u32,
+    /// Duration of the ban.
+    pub ban_duration: Milliseconds,
+}
+
+impl Default for PeerScoringConfig {
+    fn default() -> Self {
+        Self {
+            max_messages_per_second: 1000,
+            ban_threshold: 100,
+            ban_duration: 60_000,
+        }
+    }
+}
+
+/// Kinds of peer misbehavior.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Misbehavior {
+    /// Peer has exceeded the message rate limit.
+    RateLimitExceeded,
+    /// Data received from the peer cannot be decoded.
+    MalformedMessage,
+    /// Message has an invalid signature or an unknown type.
+    InvalidMessage,
+    /// `Propose` message from the peer is invalid.
+    InvalidPropose,
+    /// `BlockResponse` message from the peer has failed validation.
+    InvalidBlockResponse,
+}
+
+impl Misbehavior {
+    /// Returns the amount by which the misbehavior score is increased.
+    pub fn penalty(self) -> u32 {
+        match self {
+            Misbehavior::RateLimitExceeded => 1,
+            Misbehavior::InvalidMessage | Misbehavior::InvalidPropose => 25,
+            Misbehavior::MalformedMessage | Misbehavior::InvalidBlockResponse => 50,
+        }
+    }
+}
+
+/// Scoring state of the peer, available through the private API.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PeerScoreInfo {
+    /// Public key of the peer.
+    pub public_key: PublicKey,
+    /// Current misbehavior score.
+    pub score: u32,
+    /// Number of messages dropped because of the rate limit.
+    pub dropped_messages: u64,
+    /// Number of times the peer has been banned.
+    pub bans: u32,
+    /// Time left until the ban is lifted, if the peer is banned.
+    pub banned_for: Option<Milliseconds>,
+}
+
+#[derive(Debug)]
+struct PeerScore {
+    score: u32,
+    score_updated: Instant,
+    tokens: f64,
+    tokens_updated: Instant,
+    dropped_messages: u64,
+    bans: u32,
+    banned_until: Option<Instant>,
+}
+
+impl PeerScore {
+    fn new(config: &PeerScoringConfig, now: Instant) -> Self {
+        Self {
+            score: 0,
+            score_updated: now,
+            tokens: f64::from(config.max_messages_per_second),
+            tokens_updated: now,
+            dropped_messages: 0,
+            bans: 0,
+            banned_until: None,
+        }
+    }
+
+    fn update(&mut self, config: &PeerScoringConfig, now: Instant) {
+        if let Some(until) = self.banned_until {
+            if until <= now {
+                self.banned_until = None;
+                self.score = 0;
+                self.score_updated = now;
+            }
+        }
+
+        let elapsed = now.duration_since(self.score_updated).as_secs();
+        if elapsed > 0 {
+            let decay = elapsed
+                .saturating_mul(SCORE_DECAY_PER_SECOND)
+                .min(u64::from(u32::max_value()));
+            self.score = self.score.saturating_sub(decay as u32);
+            self.score_updated += Duration::from_secs(elapsed);
+        }
+
+        let limit = f64::from(config.max_messages_per_second);
+        let elapsed = now.duration_since(self.tokens_updated);
+        let elapsed = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) * 1e-9;
+        self.tokens = (self.tokens + elapsed * limit).min(limit);
+        self.tokens_updated = now;
+    }
+
+    fn is_banned(&self) -> bool {
+        self.banned_until.is_some()
+    }
+
+    fn info(&self, public_key: PublicKey, now: Instant) -> PeerScoreInfo {
+        let banned_for = self.banned_until.map(|until| {
+            let left = until.duration_since(now);
+            left.as_secs() * 1000 + u64::from(left.subsec_millis())
+        });
+        PeerScoreInfo {
+            public_key,
+            score: self.score,
+            dropped_messages: self.dropped_messages,
+            bans: self.bans,
+            banned_for,
+        }
+    }
+}
+
+/// Scores of the peers.
+#[derive(Debug, Default)]
+pub struct PeerScores {
+    config: PeerScoringConfig,
+    peers: HashMap<PublicKey, PeerScore>,
+}
+
+impl PeerScores {
+    /// Creates peer scores with the given configuration.
+    pub fn new(config: PeerScoringConfig) -> Self {
+        Self {
+            config,
+            peers: HashMap::new(),
+        }
+    }
+
+    fn peer(&mut self, public_key: &PublicKey, now: Instant) -> &mut PeerScore {
+        let config = self.config;
+        let peer = self
+            .peers
+            .entry(*public_key)
+            .or_insert_with(|| PeerScore::new(&config, now));
+        peer.update(&config, now);
+        peer
+    }
+
+    /// Accounts an incoming message from the peer. Returns `false` if the message
+    /// should be dropped, either because the peer is banned or because it has exceeded
+    /// the rate limit.
+    pub fn check_message(&mut self, public_key: &PublicKey, now: Instant) -> bool {
+        let accepted = {
+            let peer = self.peer(public_key, now);
+            if peer.is_banned() {
+                return false;
+            }
+            if peer.tokens >= 1.0 {
+                peer.tokens -= 1.0;
+                true
+            } else {
+                peer.dropped_messages += 1;
+                false
+            }
+        };
+        if !accepted {
+            self.report(public_key, Misbehavior::RateLimitExceeded, now);
+        }
+        accepted
+    }
+
+    /// Increases the misbehavior score of the peer. Returns `true` if the peer
+    /// has been banned as a result.
+    pub fn report(
+        &mut self,
+        public_key: &PublicKey,
+        misbehavior: Misbehavior,
+        now: Instant,
+    ) -> bool {
+        let config = self.config;
+        let peer = self.peer(public_key, now);
+        if peer.is_banned() {
+            return false;
+        }
+        peer.score = peer.score.saturating_add(misbehavior.penalty());
+        if peer.score >= config.ban_threshold {
+            peer.bans += 1;
+            peer.banned_until = Some(now + Duration::from_millis(config.ban_duration));
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns `true` if the peer is banned at the given moment.
+    pub fn is_banned(&self, public_key: &PublicKey, now: Instant) -> bool {
+        self.peers
+            .get(public_key)
+            .and_then(|peer| peer.banned_until)
+            .map_or(false, |until| until > now)
+    }
+
+    /// Returns scoring information for all known peers.
+    pub fn peers_info(&mut self, now: Instant) -> Vec<PeerScoreInfo> {
+        let config = self.config;
+        self.peers
+            .iter_mut()
+            .map(|(public_key, peer)| {
+                peer.update(&config, now);
+                peer.info(*public_key, now)
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+/// Shared `PeerScores` representation to be used in network.
+pub struct SharedPeerScores {
+    inner: Arc<RwLock<PeerScores>>,
+}
+
+impl SharedPeerScores {
+    /// Creates `SharedPeerScores` with the given configuration.
+    pub fn new(config: PeerScoringConfig) -> Self {
+        SharedPeerScores {
+            inner: Arc::new(RwLock::new(PeerScores::new(config))),
+        }
+    }
+
+    /// Accounts an incoming message from the peer. Returns `false` if the message
+    /// should be dropped.
+    pub fn check_message(&self, public_key: &PublicKey) -> bool {
+        let mut scores = self.inner.write().expect("PeerScores write lock");
+        scores.check_message(public_key, Instant::now())
+    }
+
+    /// Increases the misbehavior score of the peer. Returns `true` if the peer
+    /// has been banned as a result.
+    pub fn report(&self, public_key: &PublicKey, misbehavior: Misbehavior) -> bool {
+        let mut scores = self.inner.write().expect("PeerScores write lock");
+        scores.report(public_key, misbehavior, Instant::now())
+    }
+
+    /// Returns `true` if the peer is banned.
+    pub fn is_banned(&self, public_key: &PublicKey) -> bool {
+        let scores = self.inner.read().expect("PeerScores read lock");
+        scores.is_banned(public_key, Instant::now())
+    }
+
+    /// Returns scoring information for all known peers.
+    pub fn peers_info(&self) -> Vec<PeerScoreInfo> {
+        let mut scores = self.inner.write().expect("PeerScores write lock");
+        scores.peers_info(Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto::gen_keypair;
+
+    fn config() -> PeerScoringConfig {
+        PeerScoringConfig {
+            max_messages_per_second: 10,
+            ban_threshold: 100,
+            ban_duration: 1_000,
+        }
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let (key, _) = gen_keypair();
+        let mut scores = PeerScores::new(config());
+        let now = Instant::now();
+
+        for _ in 0..10 {
+            assert!(scores.check_message(&key, now));
+        }
+        assert!(!scores.check_message(&key, now));
+
+        // Half a second later half of the limit is available again.
+        let now = now + Duration::from_millis(500);
+        for _ in 0..5 {
+            assert!(scores.check_message(&key, now));
+        }
+        assert!(!scores.check_message(&key, now));
+
+        let info = scores.peers_info(now);
+        assert_eq!(info.len(), 1);
+        assert_eq!(info[0].dropped_messages, 2);
+        assert_eq!(info[0].score, 2 * Misbehavior::RateLimitExceeded.penalty());
+        assert_eq!(info[0].banned_for, None);
+    }
+
+    #[test]
+    fn test_ban_and_unban() {
+        let (key, _) = gen_keypair();
+        let (other_key, _) = gen_keypair();
+        let mut scores = PeerScores::new(config());
+        let now = Instant::now();
+
+        assert!(!scores.report(&key, Misbehavior::InvalidBlockResponse, now));
+        assert!(scores.report(&key, Misbehavior::InvalidBlockResponse, now));
+        assert!(scores.is_banned(&key, now));
+        assert!(!scores.is_banned(&other_key, now));
+        assert!(!scores.check_message(&key, now));
+        assert!(scores.check_message(&other_key, now));
+
+        let info = scores
+            .peers_info(now)
+            .into_iter()
+            .find(|info| info.public_key == key)
+            .unwrap();
+        assert_eq!(info.bans, 1);
+        assert_eq!(info.banned_for, Some(1_000));
+
+        // The ban is lifted and the score is reset.
+        let now = now + Duration::from_millis(1_000);
+        assert!(!scores.is_banned(&key, now));
+        assert!(scores.check_message(&key, now));
+        let info = scores
+            .peers_info(now)
+            .into_iter()
+            .find(|info| info.public_key == key)
+            .unwrap();
+        assert_eq!(info.score, 0);
+        assert_eq!(info.banned_for, None);
+    }
+
+    #[test]
+    fn test_score_decay() {
+        let (key, _) = gen_keypair();
+        let mut scores = PeerScores::new(config());
+        let now = Instant::now();
+
+        scores.report(&key, Misbehavior::InvalidMessage, now);
+        let now = now + Duration::from_secs(10);
+        assert_eq!(scores.peers_info(now)[0].score, 15);
+        let now = now + Duration::from_secs(100);
+        assert_eq!(scores.peers_info(now)[0].score, 0);
+    }
+}
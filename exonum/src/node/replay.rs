@@ -0,0 +1,435 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Offline replay of the consensus trace recorded by a node.
+//!
+//! `TraceReplayer` drives a `NodeHandler` with a virtual clock, so the handler observes
+//! exactly the time recorded in the trace. Timeouts and round jumps produced by the
+//! handler are queued and are delivered only when the trace says so; a recorded event
+//! which the replayed handler has not scheduled means that the replay has diverged from
+//! the original run.
+//!
+//! To reproduce a trace of a real node, create the replayer with a copy of the node
+//! database taken before the trace session has started and the node configuration.
+//! The same is done by the `replay-trace` action of the `maintenance` command.
+
+use failure::Error;
+use futures::{self, sync::mpsc, Async, Future, Sink, Stream};
+
+use std::{
+    collections::{BinaryHeap, VecDeque},
+    fmt, mem,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use super::simulation::{SimulatedSystemState, VirtualClock};
+use blockchain::{Blockchain, Service, SharedNodeState};
+use events::{Event, EventHandler, InternalEvent, InternalRequest, NetworkRequest, TimeoutRequest};
+use messages::{Message, SignedMessage};
+use node::{
+    ApiSender, Configuration, ConnectList, ExternalMessage, ListenerConfig, NodeConfig,
+    NodeHandler, NodeSender, NodeTimeout, ServiceConfig, TraceEntry, TraceEvent,
+};
+use storage::Database;
+
+/// Node handler replaying a recorded consensus trace.
+pub struct TraceReplayer {
+    time: VirtualClock,
+    handler: NodeHandler,
+    timers: BinaryHeap<TimeoutRequest>,
+    internal_events: VecDeque<InternalEvent>,
+    sent_messages: usize,
+    network_requests_rx: mpsc::Receiver<NetworkRequest>,
+    internal_requests_rx: mpsc::Receiver<InternalRequest>,
+    api_requests_rx: mpsc::Receiver<ExternalMessage>,
+}
+
+impl fmt::Debug for TraceReplayer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TraceReplayer")
+            .field("time", &self.time)
+            .field("height", &self.handler.state().height())
+            .field("round", &self.handler.state().round())
+            .field("sent_messages", &self.sent_messages)
+            .finish()
+    }
+}
+
+impl TraceReplayer {
+    /// Creates a node handler for the given database and configuration and initializes
+    /// it at the given time.
+    pub fn new<D: Into<Arc<dyn Database>>>(
+        db: D,
+        services: Vec<Box<dyn Service>>,
+        node_cfg: NodeConfig,
+        time: SystemTime,
+    ) -> Self {
+        let network_channel = mpsc::channel(100);
+        let internal_channel = mpsc::channel(100);
+        let api_channel = mpsc::channel(100);
+
+        let mut blockchain = Blockchain::new(
+            db,
+            services,
+            node_cfg.service_public_key,
+            node_cfg.service_secret_key.clone(),
+            ApiSender::new(api_channel.0.clone()),
+        );
+        blockchain.initialize(node_cfg.genesis.clone()).unwrap();
+
+        let config = Configuration {
+            listener: ListenerConfig {
+                consensus_public_key: node_cfg.consensus_public_key,
+                consensus_secret_key: node_cfg.consensus_secret_key,
+                connect_list: ConnectList::from_config(node_cfg.connect_list.clone()),
+                address: node_cfg.listen_address,
+                key_rotation: node_cfg.key_rotation.clone(),
+                external_addresses: node_cfg.external_addresses.clone(),
+                replica: node_cfg.replica,
+            },
+            service: ServiceConfig {
+                service_public_key: node_cfg.service_public_key,
+                service_secret_key: node_cfg.service_secret_key,
+            },
+            network: node_cfg.network,
+            peer_discovery: node_cfg.connect_list.addresses(),
+            mempool: node_cfg.mempool,
+        };
+
+        let node_sender = NodeSender {
+            network_requests: network_channel.0.clone().wait(),
+            internal_requests: internal_channel.0.clone().wait(),
+            api_requests: api_channel.0.clone().wait(),
+        };
+
+        let time = VirtualClock::new(time);
+        let system_state = SimulatedSystemState::new(node_cfg.listen_address, time.clone());
+
+        let mut handler = NodeHandler::new(
+            blockchain,
+            &node_cfg.external_address,
+            node_sender,
+            Box::new(system_state),
+            config,
+            SharedNodeState::new(node_cfg.api.state_update_timeout as u64),
+            None,
+        );
+        handler.initialize();
+
+        let mut replayer = Self {
+            time,
+            handler,
+            timers: BinaryHeap::new(),
+            internal_events: VecDeque::new(),
+            sent_messages: 0,
+            network_requests_rx: network_channel.1,
+            internal_requests_rx: internal_channel.1,
+            api_requests_rx: api_channel.1,
+        };
+        replayer.process_requests();
+        replayer
+    }
+
+    /// Returns the replayed node handler.
+    pub fn handler(&self) -> &NodeHandler {
+        &self.handler
+    }
+
+    /// Returns the replayed node handler for modification.
+    pub fn handler_mut(&mut self) -> &mut NodeHandler {
+        &mut self.handler
+    }
+
+    /// Returns the number of messages sent by the node so far.
+    pub fn sent_messages(&self) -> usize {
+        self.sent_messages
+    }
+
+    /// Returns the time observed by the node.
+    pub fn time(&self) -> SystemTime {
+        self.time.now()
+    }
+
+    /// Handles the event as a running node would do: the events scheduled by the handler
+    /// for itself and the transactions sent through the API are delivered immediately.
+    pub fn handle_event<E: Into<Event>>(&mut self, event: E) {
+        self.handler.handle_event(event.into());
+        self.process_requests();
+        loop {
+            if let Some(event) = self.internal_events.pop_front() {
+                self.handler.handle_event(event.into());
+            } else if let Some(message) = self.poll_api_request() {
+                self.handler.handle_event(message.into());
+            } else {
+                break;
+            }
+            self.process_requests();
+        }
+    }
+
+    /// Advances the time and handles all timeouts that have expired.
+    pub fn add_time(&mut self, duration: Duration) {
+        let now = self.time.now() + duration;
+        self.time.set(now);
+        loop {
+            match self.timers.pop() {
+                Some(TimeoutRequest(time, timeout)) => {
+                    if time > now {
+                        self.timers.push(TimeoutRequest(time, timeout));
+                        break;
+                    }
+                    self.handle_event(timeout);
+                }
+                None => break,
+            }
+        }
+    }
+
+    /// Replays the trace entries. Returns the number of events delivered to the handler.
+    pub fn replay<I>(&mut self, entries: I) -> Result<usize, Error>
+    where
+        I: IntoIterator<Item = TraceEntry>,
+    {
+        let mut handled = 0;
+        for entry in entries {
+            self.time.set(entry.time);
+            match entry.event {
+                TraceEvent::Started {
+                    height,
+                    round,
+                    last_block_hash,
+                } => {
+                    let state = self.handler.state();
+                    ensure!(
+                        state.height() == height
+                            && state.round() == round
+                            && self.handler.last_block_hash() == last_block_hash,
+                        "Trace entry {}: the trace has been started at height {}, round {} \
+                         with the last block {:?}, but the node is at height {}, round {} \
+                         with the last block {:?}",
+                        entry.seq,
+                        height,
+                        round,
+                        last_block_hash,
+                        state.height(),
+                        state.round(),
+                        self.handler.last_block_hash()
+                    );
+                }
+                TraceEvent::Timeout(ref timeout) => {
+                    ensure!(
+                        self.take_timeout(timeout),
+                        "Trace entry {}: timeout {:?} has not been scheduled by the node",
+                        entry.seq,
+                        timeout
+                    );
+                }
+                TraceEvent::JumpToRound(height, round) => {
+                    let position = self.internal_events.iter().position(|event| match *event {
+                        InternalEvent::JumpToRound(h, r) => h == height && r == round,
+                        _ => false,
+                    });
+                    match position {
+                        Some(position) => drop(self.internal_events.remove(position)),
+                        None => bail!(
+                            "Trace entry {}: jump to round {} at height {} has not been \
+                             requested by the node",
+                            entry.seq,
+                            round,
+                            height
+                        ),
+                    }
+                }
+                _ => {}
+            }
+
+            if let Some(event) = entry.event.into_event()? {
+                self.handler.handle_event(event);
+                self.process_requests();
+                // Transactions sent by the services are a part of the trace.
+                while self.poll_api_request().is_some() {}
+                handled += 1;
+            }
+        }
+        Ok(handled)
+    }
+
+    fn take_timeout(&mut self, timeout: &NodeTimeout) -> bool {
+        let mut timers = mem::replace(&mut self.timers, BinaryHeap::new()).into_vec();
+        let position = timers.iter().position(|request| request.1 == *timeout);
+        if let Some(position) = position {
+            timers.remove(position);
+        }
+        self.timers = timers.into();
+        position.is_some()
+    }
+
+    fn process_requests(&mut self) {
+        let network_getter = futures::lazy(|| -> Result<(), ()> {
+            while let Async::Ready(Some(network)) = self.network_requests_rx.poll()? {
+                match network {
+                    NetworkRequest::SendMessage(..) => self.sent_messages += 1,
+                    NetworkRequest::DisconnectWithPeer(_)
+                    | NetworkRequest::UpdateConsensusKeys(..)
+                    | NetworkRequest::Shutdown => {}
+                }
+            }
+            Ok(())
+        });
+        network_getter.wait().unwrap();
+
+        let internal_getter = futures::lazy(|| -> Result<(), ()> {
+            while let Async::Ready(Some(internal)) = self.internal_requests_rx.poll()? {
+                match internal {
+                    InternalRequest::Timeout(timeout) => self.timers.push(timeout),
+                    InternalRequest::JumpToRound(height, round) => self
+                        .internal_events
+                        .push_back(InternalEvent::JumpToRound(height, round)),
+                    InternalRequest::VerifyMessage(peer, raw) => {
+                        let event = match SignedMessage::from_raw_buffer(raw)
+                            .and_then(Message::deserialize)
+                        {
+                            Ok(message) => InternalEvent::MessageVerified(Box::new(message)),
+                            Err(_) => InternalEvent::InvalidMessage(peer),
+                        };
+                        self.internal_events.push_back(event);
+                    }
+                    InternalRequest::Shutdown => {}
+                }
+            }
+            Ok(())
+        });
+        internal_getter.wait().unwrap();
+    }
+
+    fn poll_api_request(&mut self) -> Option<ExternalMessage> {
+        let api_getter = futures::lazy(|| -> Result<Option<ExternalMessage>, ()> {
+            match self.api_requests_rx.poll()? {
+                Async::Ready(message) => Ok(message),
+                Async::NotReady => Ok(None),
+            }
+        });
+        api_getter.wait().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use std::{
+        fs::File,
+        io::BufReader,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use super::*;
+    use blockchain::Schema;
+    use helpers::{generate_testnet_config, Height, Round};
+    use node::ConsensusTrace;
+    use sandbox::timestamping::{TimestampingService, TimestampingTxGenerator, DATA_SIZE};
+    use storage::MemoryDB;
+
+    const START_TIME_IN_SECS: u64 = 1_486_720_340;
+
+    fn create_replayer(node_cfg: &NodeConfig) -> TraceReplayer {
+        TraceReplayer::new(
+            MemoryDB::new(),
+            vec![Box::new(TimestampingService::new()) as Box<dyn Service>],
+            node_cfg.clone(),
+            UNIX_EPOCH + Duration::from_secs(START_TIME_IN_SECS),
+        )
+    }
+
+    fn record_trace(node_cfg: &NodeConfig) -> (TraceReplayer, Vec<TraceEntry>) {
+        let dir = TempDir::new("exonum_trace").unwrap();
+        let path = dir.path().join("consensus.trace");
+
+        let mut node = create_replayer(node_cfg);
+        node.handler_mut()
+            .set_consensus_trace(ConsensusTrace::open(&path).unwrap());
+        for tx in TimestampingTxGenerator::new(DATA_SIZE).take(3) {
+            node.handle_event(ExternalMessage::Transaction(tx));
+        }
+        for _ in 0..20 {
+            node.add_time(Duration::from_millis(500));
+        }
+
+        let entries = ConsensusTrace::read(BufReader::new(File::open(&path).unwrap())).unwrap();
+        (node, entries)
+    }
+
+    #[test]
+    fn test_replay_reproduces_state() {
+        let node_cfg = generate_testnet_config(1, 16_600)[0].clone();
+        let (node, entries) = record_trace(&node_cfg);
+        assert!(node.handler().state().height() > Height(1));
+
+        let mut replayer = create_replayer(&node_cfg);
+        let handled = replayer.replay(entries.clone()).unwrap();
+        assert_eq!(handled, entries.len() - 1);
+
+        let state = replayer.handler().state();
+        assert_eq!(state.height(), node.handler().state().height());
+        assert_eq!(state.round(), node.handler().state().round());
+        assert_eq!(
+            replayer.handler().last_block_hash(),
+            node.handler().last_block_hash()
+        );
+        assert_eq!(replayer.sent_messages(), node.sent_messages());
+
+        let snapshot = replayer.handler().blockchain.snapshot();
+        let schema = Schema::new(&snapshot);
+        assert_eq!(schema.transactions_pool_len(), 0);
+        assert_eq!(schema.transactions_len(), 3);
+    }
+
+    #[test]
+    fn test_replay_detects_divergence() {
+        let node_cfg = generate_testnet_config(1, 16_600)[0].clone();
+        let (_, mut entries) = record_trace(&node_cfg);
+
+        let position = entries
+            .iter()
+            .position(|entry| match entry.event {
+                TraceEvent::Timeout(NodeTimeout::Round(..)) => true,
+                _ => false,
+            })
+            .unwrap();
+        entries[position].event = TraceEvent::Timeout(NodeTimeout::Round(Height(100), Round(1)));
+
+        let mut replayer = create_replayer(&node_cfg);
+        let err = replayer.replay(entries).unwrap_err();
+        assert!(err.to_string().contains("has not been scheduled"));
+    }
+
+    #[test]
+    fn test_replay_checks_start_state() {
+        let node_cfg = generate_testnet_config(1, 16_600)[0].clone();
+        let (node, _) = record_trace(&node_cfg);
+
+        let entry = TraceEntry {
+            seq: 0,
+            time: node.time(),
+            event: TraceEvent::Started {
+                height: node.handler().state().height(),
+                round: node.handler().state().round(),
+                last_block_hash: node.handler().last_block_hash(),
+            },
+        };
+        let mut replayer = create_replayer(&node_cfg);
+        assert!(replayer.replay(vec![entry]).is_err());
+    }
+}
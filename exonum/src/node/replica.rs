@@ -0,0 +1,128 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Read replicas following the blockchain from trusted nodes.
+
+use failure;
+use toml::Value;
+
+use std::{collections::BTreeMap, net::SocketAddr};
+
+use blockchain::GenesisConfig;
+use crypto::{self, PublicKey, SecretKey};
+use events::NetworkConfiguration;
+use node::{
+    ConnectInfo, ConnectListConfig, ConnectListPeer, MemoryPoolConfig, NodeApiConfig, NodeConfig,
+};
+use storage::DbOptions;
+
+/// Configuration of a read replica.
+///
+/// A replica requests blocks together with their precommits from the trusted nodes,
+/// verifies them against the actual configuration and commits them. It serves the public
+/// and service APIs like any other node, but never takes part in consensus and ignores
+/// consensus messages of its peers.
+///
+/// The consensus keys of the replica are required. Trusted nodes only accept connections
+/// from the keys in their connect list, so the consensus public key of the replica must be
+/// added to the connect list of each trusted node.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReplicaConfig {
+    /// Initial config that will be written in the first block.
+    pub genesis: GenesisConfig,
+    /// Network listening address.
+    pub listen_address: SocketAddr,
+    /// Remote network address of the replica, the listening address by default.
+    #[serde(default)]
+    pub external_address: Option<String>,
+    /// Nodes from which the replica follows the blockchain.
+    pub trusted_peers: Vec<ConnectInfo>,
+    /// Api configuration.
+    #[serde(default)]
+    pub api: NodeApiConfig,
+    /// Network configuration.
+    #[serde(default)]
+    pub network: NetworkConfiguration,
+    /// Memory pool configuration.
+    #[serde(default)]
+    pub mempool: MemoryPoolConfig,
+    /// Additional config, usable for services.
+    #[serde(default)]
+    pub services_configs: BTreeMap<String, Value>,
+    /// Optional database configuration.
+    #[serde(default)]
+    pub database: DbOptions,
+    /// Consensus public key identifying the replica to the trusted nodes.
+    #[serde(default)]
+    pub consensus_public_key: Option<PublicKey>,
+    /// Consensus secret key of the replica.
+    #[serde(default)]
+    pub consensus_secret_key: Option<SecretKey>,
+    /// Transaction Verification Thread Pool size.
+    #[serde(default)]
+    pub thread_pool_size: Option<u8>,
+    /// Whether the replica maintains the explorer indexes.
+    #[serde(default)]
+    pub explorer_indexes: bool,
+}
+
+impl ReplicaConfig {
+    /// Converts the replica configuration into the configuration of a node running
+    /// in the replica mode. The trusted peers are pinned in the connect list, so the
+    /// replica always reconnects to them.
+    ///
+    /// Returns an error if the consensus keys of the replica are not specified.
+    pub fn into_node_config(self) -> Result<NodeConfig, failure::Error> {
+        let (consensus_public_key, consensus_secret_key) =
+            match (self.consensus_public_key, self.consensus_secret_key) {
+                (Some(public_key), Some(secret_key)) => (public_key, secret_key),
+                _ => bail!("Consensus keys of the replica are not specified"),
+            };
+        let (service_public_key, service_secret_key) = crypto::gen_keypair();
+        let listen_address = self.listen_address;
+        let external_address = self
+            .external_address
+            .unwrap_or_else(|| listen_address.to_string());
+        let peers = self
+            .trusted_peers
+            .into_iter()
+            .map(|peer| ConnectListPeer {
+                pinned: true,
+                ..peer.into()
+            })
+            .collect();
+
+        Ok(NodeConfig {
+            genesis: self.genesis,
+            listen_address,
+            external_address,
+            network: self.network,
+            consensus_public_key,
+            consensus_secret_key,
+            service_public_key,
+            service_secret_key,
+            api: self.api,
+            mempool: self.mempool,
+            services_configs: self.services_configs,
+            database: self.database,
+            connect_list: ConnectListConfig { peers },
+            thread_pool_size: self.thread_pool_size,
+            consensus_trace: None,
+            key_rotation: Default::default(),
+            external_addresses: Vec::new(),
+            replica: true,
+            explorer_indexes: self.explorer_indexes,
+        })
+    }
+}
@@ -1,10 +1,16 @@
+use std::cmp;
+
 use super::NodeHandler;
 use blockchain::Schema;
+use crypto::PublicKey;
+use helpers::Height;
 use messages::{
-    BlockRequest, BlockResponse, PrevotesRequest, ProposeRequest, Requests, Signed,
-    TransactionsRequest, TransactionsResponse, RAW_TRANSACTION_HEADER,
-    TRANSACTION_RESPONSE_EMPTY_SIZE,
+    BinaryForm, BlockRequest, BlockResponse, BlocksResponse, CompactBlockRequest,
+    CompactBlockResponse, PrevotesRequest, ProposeRequest, Requests, Signed, TransactionsRequest,
+    TransactionsResponse, BLOCKS_RESPONSE_BLOCK_HEADER, BLOCKS_RESPONSE_EMPTY_SIZE,
+    EMPTY_SIGNED_MESSAGE_SIZE, RAW_TRANSACTION_HEADER, TRANSACTION_RESPONSE_EMPTY_SIZE,
 };
+use storage::Snapshot;
 
 // TODO: Height should be updated after any message, not only after status (if signature is correct). (ECR-171)
 // TODO: Request propose makes sense only if we know that node is on our height. (ECR-171)
@@ -32,6 +38,7 @@ impl NodeHandler {
             Requests::PrevotesRequest(ref msg) => self.handle_request_prevotes(msg),
             Requests::PeersRequest(ref msg) => self.handle_request_peers(msg),
             Requests::BlockRequest(ref msg) => self.handle_request_block(msg),
+            Requests::CompactBlockRequest(ref msg) => self.handle_request_compact_block(msg),
         }
     }
 
@@ -115,8 +122,9 @@ impl NodeHandler {
     /// Handles `BlockRequest` message. For details see the message documentation.
     pub fn handle_request_block(&mut self, msg: &Signed<BlockRequest>) {
         trace!(
-            "Handle block request with height:{}, our height: {}",
+            "Handle block request with height:{}, count: {}, our height: {}",
             msg.height(),
+            msg.count(),
             self.state.height()
         );
         if msg.height() >= self.state.height() {
@@ -126,21 +134,126 @@ impl NodeHandler {
         let snapshot = self.blockchain.snapshot();
         let schema = Schema::new(&snapshot);
 
-        let height = msg.height();
+        let count = cmp::min(msg.count(), self.block_sync.config().blocks_per_request);
+        let count = cmp::min(u64::from(count), self.state.height().0 - msg.height().0);
+        let max_message_len = self.state.config().consensus.max_message_len as usize;
+
+        // The first block is always sent, the following ones while they fit into the message.
+        let mut message_size = BLOCKS_RESPONSE_EMPTY_SIZE;
+        let mut blocks = Vec::new();
+        for height in msg.height().0..msg.height().0 + cmp::max(count, 1) {
+            let block = self.block_response(&schema, &msg.author(), Height(height));
+            message_size += block.signed_message().raw().len() + BLOCKS_RESPONSE_BLOCK_HEADER;
+            if !blocks.is_empty() && message_size > max_message_len {
+                break;
+            }
+            blocks.push(block);
+        }
+
+        // A single block is sent as is, so that nodes of previous versions understand it.
+        if blocks.len() == 1 {
+            let block_msg = blocks.remove(0);
+            self.send_to_peer(msg.author(), block_msg);
+        } else {
+            let blocks = blocks
+                .iter()
+                .map(|block| block.signed_message().raw().to_vec())
+                .collect();
+            let blocks_msg = self.sign_message(BlocksResponse::new(&msg.author(), blocks));
+            self.send_to_peer(msg.author(), blocks_msg);
+        }
+    }
+
+    /// Creates `BlockResponse` with the committed block at the given height.
+    fn block_response<T: AsRef<dyn Snapshot>>(
+        &self,
+        schema: &Schema<T>,
+        to: &PublicKey,
+        height: Height,
+    ) -> Signed<BlockResponse> {
         let block_hash = schema.block_hash_by_height(height).unwrap();
 
         let block = schema.blocks().get(&block_hash).unwrap();
         let precommits = schema.precommits(&block_hash);
         let transactions = schema.block_transactions(height);
 
-        let block_msg = self.sign_message(BlockResponse::new(
-            &msg.author(),
+        self.sign_message(BlockResponse::new(
+            to,
             block,
             precommits
                 .iter()
                 .map(|p| p.signed_message().raw().to_vec())
                 .collect(),
             &transactions.iter().collect::<Vec<_>>(),
+        ))
+    }
+
+    /// Handles `CompactBlockRequest` message. For details see the message documentation.
+    pub fn handle_request_compact_block(&mut self, msg: &Signed<CompactBlockRequest>) {
+        trace!(
+            "Handle compact block request with height:{}, our height: {}",
+            msg.height(),
+            self.state.height()
+        );
+        if msg.height() >= self.state.height() {
+            return;
+        }
+
+        let snapshot = self.blockchain.snapshot();
+        let schema = Schema::new(&snapshot);
+
+        let height = msg.height();
+        let block_hash = schema.block_hash_by_height(height).unwrap();
+
+        let block = schema.blocks().get(&block_hash).unwrap();
+        let precommits = schema
+            .precommits(&block_hash)
+            .iter()
+            .map(|p| p.signed_message().raw().to_vec())
+            .collect::<Vec<_>>();
+        let tx_hashes = schema.block_transactions(height).iter().collect::<Vec<_>>();
+        let short_ids = tx_hashes
+            .iter()
+            .map(|tx_hash| CompactBlockResponse::short_tx_id(&block_hash, tx_hash))
+            .collect::<Vec<_>>();
+
+        // Transactions that were unknown to this node are prefilled while they fit into
+        // the message.
+        let mut message_size = EMPTY_SIGNED_MESSAGE_SIZE
+            + CompactBlockResponse::new(
+                &msg.author(),
+                block.clone(),
+                precommits.clone(),
+                short_ids.clone(),
+                Vec::new(),
+            )
+            .encode()
+            .expect("Unable to encode compact block")
+            .len();
+        let max_message_len = self.state.config().consensus.max_message_len as usize;
+        let mut prefilled_txs = Vec::new();
+        for tx_hash in &tx_hashes {
+            if !self.state.is_late_tx(height, tx_hash) {
+                continue;
+            }
+            let tx = schema
+                .transactions()
+                .get(tx_hash)
+                .expect("Compact block: invalid transaction hash");
+            let raw = tx.signed_message().raw().to_vec();
+            if message_size + raw.len() + RAW_TRANSACTION_HEADER > max_message_len {
+                break;
+            }
+            message_size += raw.len() + RAW_TRANSACTION_HEADER;
+            prefilled_txs.push(raw);
+        }
+
+        let block_msg = self.sign_message(CompactBlockResponse::new(
+            &msg.author(),
+            block,
+            precommits,
+            short_ids,
+            prefilled_txs,
         ));
         self.send_to_peer(msg.author(), block_msg);
     }
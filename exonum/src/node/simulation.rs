@@ -0,0 +1,1219 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Deterministic network of nodes running in a single process.
+//!
+//! `SimulatedNetwork` runs several full nodes without sockets and threads. Each node is an
+//! ordinary `NodeHandler` with its own in-memory database, which observes the time of a
+//! shared `VirtualClock`. The messages of the nodes are delivered by an in-memory network
+//! which can delay, lose and reorder them and split the nodes into partitions.
+//!
+//! Events are processed one by one in the order of their scheduled time, and latencies
+//! and losses are drawn from a generator seeded by the builder, so the same seed and the
+//! same sequence of calls reproduce the same run. The exceptions are the choices the
+//! nodes make themselves: the peer for the peer exchange is random, and a request which
+//! has timed out is repeated to a peer taken from a hash set. Hence runs with lost or
+//! late messages, or longer than the `peers_timeout` of the consensus configuration,
+//! may diverge.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate exonum;
+//!
+//! use exonum::helpers::Height;
+//! use exonum::node::simulation::{NetworkConditions, SimulatedNetworkBuilder};
+//! use std::time::Duration;
+//!
+//! # fn main() {
+//! let mut network = SimulatedNetworkBuilder::new(4)
+//!     .with_conditions(NetworkConditions {
+//!         max_latency: Duration::from_millis(50),
+//!         drop_rate: 0.05,
+//!         ..Default::default()
+//!     })
+//!     .build();
+//! assert!(network.run_until_height(Height(2), Duration::from_secs(60)));
+//!
+//! // Neither half of the network has a majority of validators.
+//! network.partition(&[&[0, 1], &[2, 3]]);
+//! let height = network.node(0).height();
+//! network.advance(Duration::from_secs(10));
+//! assert_eq!(network.node(0).height(), height);
+//!
+//! network.heal();
+//! assert!(network.run_until_height(height.next(), Duration::from_secs(60)));
+//! # }
+//! ```
+
+use byteorder::{ByteOrder, LittleEndian};
+use futures::{self, sync::mpsc, Async, Future, Sink, Stream};
+use rand::{Rng, RngCore, SeedableRng};
+use rand_xorshift::XorShiftRng;
+
+use std::{
+    cmp::{self, Ordering},
+    collections::{BTreeMap, BinaryHeap, VecDeque},
+    fmt,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use api::ServiceApiState;
+use blockchain::{
+    Blockchain, ConsensusConfig, GenesisConfig, Schema, Service, SharedNodeState, ValidatorKeys,
+};
+use crypto::{self, PublicKey, SecretKey, Seed, SEED_LENGTH};
+use events::{
+    network::ConnectedPeerAddr, Event, EventHandler, InternalEvent, InternalRequest, NetworkEvent,
+    NetworkRequest, TimeoutRequest,
+};
+use helpers::Height;
+use messages::{Message, RawTransaction, Signed, SignedMessage};
+use node::{
+    ApiSender, Configuration, ConnectInfo, ConnectList, ConnectListConfig, ConnectListPeer,
+    ExternalMessage, ListenerConfig, NodeConfig, NodeHandler, NodeSender, ServiceConfig,
+    SystemStateProvider,
+};
+use storage::MemoryDB;
+
+const CHANNEL_CAPACITY: usize = 1_024;
+const FIRST_PORT: u16 = 2_000;
+const START_TIME_IN_SECS: u64 = 1_486_720_340;
+
+/// Clock shared by the nodes of a simulated network.
+///
+/// The time only changes when the network processes its events, so the nodes never
+/// observe the time passing while they are handling an event.
+#[derive(Debug, Clone)]
+pub struct VirtualClock(Arc<Mutex<SystemTime>>);
+
+impl VirtualClock {
+    /// Creates a clock showing the given time.
+    pub fn new(time: SystemTime) -> Self {
+        VirtualClock(Arc::new(Mutex::new(time)))
+    }
+
+    /// Returns the current time.
+    pub fn now(&self) -> SystemTime {
+        *self.0.lock().unwrap()
+    }
+
+    pub(crate) fn set(&self, time: SystemTime) {
+        *self.0.lock().unwrap() = time;
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct SimulatedSystemState {
+    listen_address: SocketAddr,
+    clock: VirtualClock,
+}
+
+impl SimulatedSystemState {
+    pub(crate) fn new(listen_address: SocketAddr, clock: VirtualClock) -> Self {
+        Self {
+            listen_address,
+            clock,
+        }
+    }
+}
+
+impl SystemStateProvider for SimulatedSystemState {
+    fn listen_address(&self) -> SocketAddr {
+        self.listen_address
+    }
+
+    fn current_time(&self) -> SystemTime {
+        self.clock.now()
+    }
+}
+
+/// Conditions of the links between the nodes of a simulated network.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetworkConditions {
+    /// Minimal time in which a message reaches its recipient.
+    pub min_latency: Duration,
+    /// Maximal time in which a message reaches its recipient. Latencies are distributed
+    /// uniformly between the minimal and the maximal ones.
+    pub max_latency: Duration,
+    /// Probability of a message being lost, from 0 to 1. Connection handshakes are
+    /// never lost.
+    pub drop_rate: f64,
+    /// Whether the messages sent over a link may overtake each other. Otherwise every
+    /// link delivers messages in the order they were sent in.
+    pub reorder: bool,
+    /// Time after which a node learns that it cannot connect to an unreachable peer.
+    pub connect_timeout: Duration,
+}
+
+impl Default for NetworkConditions {
+    fn default() -> Self {
+        Self {
+            min_latency: Duration::from_millis(10),
+            max_latency: Duration::from_millis(10),
+            drop_rate: 0.0,
+            reorder: false,
+            connect_timeout: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Builder of a simulated network.
+pub struct SimulatedNetworkBuilder {
+    validators: u16,
+    auditors: u16,
+    services: Option<Box<dyn Fn() -> Vec<Box<dyn Service>>>>,
+    node_config: Option<Box<dyn Fn(usize, &mut NodeConfig)>>,
+    consensus: ConsensusConfig,
+    conditions: NetworkConditions,
+    seed: u64,
+    start_time: SystemTime,
+}
+
+impl fmt::Debug for SimulatedNetworkBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SimulatedNetworkBuilder")
+            .field("validators", &self.validators)
+            .field("auditors", &self.auditors)
+            .field("consensus", &self.consensus)
+            .field("conditions", &self.conditions)
+            .field("seed", &self.seed)
+            .field("start_time", &self.start_time)
+            .finish()
+    }
+}
+
+impl SimulatedNetworkBuilder {
+    /// Creates a builder of the network with the given number of validators.
+    pub fn new(validators: u16) -> Self {
+        assert!(
+            validators > 0,
+            "The network should have at least one validator"
+        );
+        Self {
+            validators,
+            auditors: 0,
+            services: None,
+            node_config: None,
+            consensus: ConsensusConfig::default(),
+            conditions: NetworkConditions::default(),
+            seed: 0,
+            start_time: UNIX_EPOCH + Duration::from_secs(START_TIME_IN_SECS),
+        }
+    }
+
+    /// Adds the given number of auditors, which follow the blockchain without taking part
+    /// in consensus. Auditors are placed after the validators.
+    pub fn with_auditors(mut self, auditors: u16) -> Self {
+        self.auditors = auditors;
+        self
+    }
+
+    /// Sets the function creating the services of a node. The function is called once
+    /// for each node.
+    pub fn with_services<F>(mut self, services: F) -> Self
+    where
+        F: Fn() -> Vec<Box<dyn Service>> + 'static,
+    {
+        self.services = Some(Box::new(services));
+        self
+    }
+
+    /// Sets the function adjusting the configuration of the node with the given index
+    /// before the node is created.
+    pub fn with_node_config<F>(mut self, node_config: F) -> Self
+    where
+        F: Fn(usize, &mut NodeConfig) + 'static,
+    {
+        self.node_config = Some(Box::new(node_config));
+        self
+    }
+
+    /// Sets the consensus configuration of the genesis block.
+    pub fn with_consensus(mut self, consensus: ConsensusConfig) -> Self {
+        self.consensus = consensus;
+        self
+    }
+
+    /// Sets the initial conditions of the network links.
+    pub fn with_conditions(mut self, conditions: NetworkConditions) -> Self {
+        self.conditions = conditions;
+        self
+    }
+
+    /// Sets the seed from which the keys of the nodes, latencies and message losses
+    /// are derived.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Sets the time at which the network starts.
+    pub fn with_start_time(mut self, start_time: SystemTime) -> Self {
+        self.start_time = start_time;
+        self
+    }
+
+    /// Creates the nodes and connects them to each other.
+    pub fn build(self) -> SimulatedNetwork {
+        crypto::init();
+
+        let mut rng = seeded_rng(self.seed);
+        let count = (self.validators + self.auditors) as usize;
+        let keys = (0..count)
+            .map(|_| (gen_keypair(&mut rng), gen_keypair(&mut rng)))
+            .collect::<Vec<_>>();
+        let addresses = (0..count)
+            .map(|i| SocketAddr::from(([127, 0, 0, 1], FIRST_PORT + i as u16)))
+            .collect::<Vec<_>>();
+
+        let genesis = GenesisConfig::new_with_consensus(
+            self.consensus.clone(),
+            keys.iter().take(self.validators as usize).map(
+                |&((consensus_key, _), (service_key, _))| ValidatorKeys {
+                    consensus_key,
+                    service_key,
+                },
+            ),
+        );
+        let peers = keys
+            .iter()
+            .zip(&addresses)
+            .map(|(keys, address)| ConnectInfo {
+                address: address.to_string(),
+                public_key: (keys.0).0,
+            })
+            .collect::<Vec<_>>();
+
+        let clock = VirtualClock::new(self.start_time);
+        let mut nodes = Vec::with_capacity(count);
+        for (index, (keys, address)) in keys.into_iter().zip(addresses).enumerate() {
+            let (
+                (consensus_public_key, consensus_secret_key),
+                (service_public_key, service_secret_key),
+            ) = keys;
+            let mut node_cfg = NodeConfig {
+                listen_address: address,
+                external_address: address.to_string(),
+                network: Default::default(),
+                consensus_public_key,
+                consensus_secret_key,
+                service_public_key,
+                service_secret_key,
+                genesis: genesis.clone(),
+                connect_list: ConnectListConfig {
+                    peers: peers
+                        .iter()
+                        .filter(|peer| peer.public_key != consensus_public_key)
+                        .cloned()
+                        .map(ConnectListPeer::from)
+                        .collect(),
+                },
+                api: Default::default(),
+                mempool: Default::default(),
+                services_configs: Default::default(),
+                database: Default::default(),
+                thread_pool_size: Default::default(),
+                consensus_trace: None,
+                key_rotation: Default::default(),
+                external_addresses: Vec::new(),
+                replica: false,
+                explorer_indexes: false,
+            };
+            if let Some(ref node_config) = self.node_config {
+                node_config(index, &mut node_cfg);
+            }
+            let services = self
+                .services
+                .as_ref()
+                .map_or_else(Vec::new, |services| services());
+            nodes.push(SimulatedNode::new(node_cfg, services, clock.clone()));
+        }
+
+        let mut network = SimulatedNetwork {
+            clock,
+            groups: vec![0; nodes.len()],
+            nodes,
+            queue: BinaryHeap::new(),
+            links: BTreeMap::new(),
+            conditions: self.conditions,
+            rng,
+            seq: 0,
+            sent_messages: 0,
+            dropped_messages: 0,
+        };
+        for index in 0..network.nodes.len() {
+            network.nodes[index].handler.initialize();
+            network.process_node(index);
+        }
+        network
+    }
+}
+
+/// Node of a simulated network.
+pub struct SimulatedNode {
+    config: NodeConfig,
+    handler: NodeHandler,
+    internal_events: VecDeque<InternalEvent>,
+    network_requests_rx: mpsc::Receiver<NetworkRequest>,
+    internal_requests_rx: mpsc::Receiver<InternalRequest>,
+    api_requests_rx: mpsc::Receiver<ExternalMessage>,
+}
+
+impl fmt::Debug for SimulatedNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SimulatedNode")
+            .field("address", &self.config.listen_address)
+            .field("consensus_public_key", &self.consensus_public_key())
+            .field("height", &self.height())
+            .finish()
+    }
+}
+
+impl SimulatedNode {
+    fn new(node_cfg: NodeConfig, services: Vec<Box<dyn Service>>, clock: VirtualClock) -> Self {
+        let network_channel = mpsc::channel(CHANNEL_CAPACITY);
+        let internal_channel = mpsc::channel(CHANNEL_CAPACITY);
+        let api_channel = mpsc::channel(CHANNEL_CAPACITY);
+
+        let mut blockchain = Blockchain::new(
+            MemoryDB::new(),
+            services,
+            node_cfg.service_public_key,
+            node_cfg.service_secret_key.clone(),
+            ApiSender::new(api_channel.0.clone()),
+        );
+        blockchain.initialize(node_cfg.genesis.clone()).unwrap();
+
+        let config = Configuration {
+            listener: ListenerConfig {
+                consensus_public_key: node_cfg.consensus_public_key,
+                consensus_secret_key: node_cfg.consensus_secret_key.clone(),
+                connect_list: ConnectList::from_config(node_cfg.connect_list.clone()),
+                address: node_cfg.listen_address,
+                key_rotation: node_cfg.key_rotation.clone(),
+                external_addresses: node_cfg.external_addresses.clone(),
+                replica: node_cfg.replica,
+            },
+            service: ServiceConfig {
+                service_public_key: node_cfg.service_public_key,
+                service_secret_key: node_cfg.service_secret_key.clone(),
+            },
+            network: node_cfg.network,
+            peer_discovery: node_cfg.connect_list.addresses(),
+            mempool: node_cfg.mempool.clone(),
+        };
+
+        let node_sender = NodeSender {
+            network_requests: network_channel.0.clone().wait(),
+            internal_requests: internal_channel.0.clone().wait(),
+            api_requests: api_channel.0.clone().wait(),
+        };
+        let system_state = SimulatedSystemState {
+            listen_address: node_cfg.listen_address,
+            clock,
+        };
+
+        let handler = NodeHandler::new(
+            blockchain,
+            &node_cfg.external_address,
+            node_sender,
+            Box::new(system_state),
+            config,
+            SharedNodeState::new(node_cfg.api.state_update_timeout as u64),
+            None,
+        );
+
+        Self {
+            config: node_cfg,
+            handler,
+            internal_events: VecDeque::new(),
+            network_requests_rx: network_channel.1,
+            internal_requests_rx: internal_channel.1,
+            api_requests_rx: api_channel.1,
+        }
+    }
+
+    /// Returns the configuration the node has been created with.
+    pub fn config(&self) -> &NodeConfig {
+        &self.config
+    }
+
+    /// Returns the handler of the node.
+    pub fn handler(&self) -> &NodeHandler {
+        &self.handler
+    }
+
+    /// Returns the blockchain of the node.
+    pub fn blockchain(&self) -> &Blockchain {
+        &self.handler.blockchain
+    }
+
+    /// Returns the state shared by the node with its node API.
+    pub fn api_state(&self) -> &SharedNodeState {
+        self.handler.api_state()
+    }
+
+    /// Creates the state passed to the API handlers of services. Transactions broadcast
+    /// through it are delivered to the node when the network processes its next event.
+    pub fn service_api_state(&self) -> ServiceApiState {
+        ServiceApiState::new(self.handler.blockchain.clone())
+    }
+
+    /// Returns the current consensus public key of the node.
+    pub fn consensus_public_key(&self) -> PublicKey {
+        *self.handler.state().consensus_public_key()
+    }
+
+    /// Returns the height of the latest committed block.
+    pub fn height(&self) -> Height {
+        self.handler.blockchain.last_block().height()
+    }
+
+    fn poll_requests(&mut self) -> (Vec<NetworkRequest>, Vec<TimeoutRequest>) {
+        let mut network_requests = Vec::new();
+        let mut timeouts = Vec::new();
+
+        let network_getter = futures::lazy(|| -> Result<(), ()> {
+            while let Async::Ready(Some(request)) = self.network_requests_rx.poll()? {
+                network_requests.push(request);
+            }
+            Ok(())
+        });
+        network_getter.wait().unwrap();
+
+        let internal_getter = futures::lazy(|| -> Result<(), ()> {
+            while let Async::Ready(Some(internal)) = self.internal_requests_rx.poll()? {
+                match internal {
+                    InternalRequest::Timeout(timeout) => timeouts.push(timeout),
+                    InternalRequest::JumpToRound(height, round) => self
+                        .internal_events
+                        .push_back(InternalEvent::JumpToRound(height, round)),
+                    InternalRequest::VerifyMessage(peer, raw) => {
+                        let event = match SignedMessage::from_raw_buffer(raw)
+                            .and_then(Message::deserialize)
+                        {
+                            Ok(message) => InternalEvent::MessageVerified(Box::new(message)),
+                            Err(_) => InternalEvent::InvalidMessage(peer),
+                        };
+                        self.internal_events.push_back(event);
+                    }
+                    InternalRequest::Shutdown => {}
+                }
+            }
+            Ok(())
+        });
+        internal_getter.wait().unwrap();
+
+        (network_requests, timeouts)
+    }
+
+    fn next_event(&mut self) -> Option<Event> {
+        if let Some(event) = self.internal_events.pop_front() {
+            return Some(event.into());
+        }
+        let api_getter = futures::lazy(|| -> Result<Option<ExternalMessage>, ()> {
+            match self.api_requests_rx.poll()? {
+                Async::Ready(message) => Ok(message),
+                Async::NotReady => Ok(None),
+            }
+        });
+        api_getter.wait().unwrap().map(Into::into)
+    }
+}
+
+/// Event scheduled for a node.
+struct Scheduled {
+    time: SystemTime,
+    seq: u64,
+    node: usize,
+    /// The sender and the identifier of the link over which the event is delivered.
+    /// The event is lost if the link is closed before its delivery.
+    link: Option<(usize, u64)>,
+    event: Event,
+}
+
+impl PartialEq for Scheduled {
+    fn eq(&self, other: &Self) -> bool {
+        self.time == other.time && self.seq == other.seq
+    }
+}
+
+impl Eq for Scheduled {}
+
+impl PartialOrd for Scheduled {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scheduled {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.time, self.seq)
+            .cmp(&(other.time, other.seq))
+            .reverse()
+    }
+}
+
+/// Open direction of a connection between two nodes.
+#[derive(Debug, Clone, Copy)]
+struct Link {
+    id: u64,
+    /// Time at which the receiver learns about the connection.
+    opened: SystemTime,
+    /// Latest delivery time of a message sent over the link.
+    last_delivery: SystemTime,
+}
+
+/// Network of nodes running in a single process over an in-memory network.
+pub struct SimulatedNetwork {
+    clock: VirtualClock,
+    nodes: Vec<SimulatedNode>,
+    queue: BinaryHeap<Scheduled>,
+    links: BTreeMap<(usize, usize), Link>,
+    groups: Vec<usize>,
+    conditions: NetworkConditions,
+    rng: XorShiftRng,
+    seq: u64,
+    sent_messages: usize,
+    dropped_messages: usize,
+}
+
+impl fmt::Debug for SimulatedNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SimulatedNetwork")
+            .field("time", &self.clock.now())
+            .field("nodes", &self.nodes)
+            .field("scheduled_events", &self.queue.len())
+            .field("conditions", &self.conditions)
+            .finish()
+    }
+}
+
+impl SimulatedNetwork {
+    /// Returns the clock of the network.
+    pub fn clock(&self) -> &VirtualClock {
+        &self.clock
+    }
+
+    /// Returns the current time of the network.
+    pub fn time(&self) -> SystemTime {
+        self.clock.now()
+    }
+
+    /// Returns the nodes of the network; validators go first.
+    pub fn nodes(&self) -> &[SimulatedNode] {
+        &self.nodes
+    }
+
+    /// Returns the node with the given index.
+    pub fn node(&self, index: usize) -> &SimulatedNode {
+        &self.nodes[index]
+    }
+
+    /// Returns the current conditions of the network links.
+    pub fn conditions(&self) -> &NetworkConditions {
+        &self.conditions
+    }
+
+    /// Changes the conditions of the network links. The messages which are already
+    /// in flight are not affected.
+    pub fn set_conditions(&mut self, conditions: NetworkConditions) {
+        self.conditions = conditions;
+    }
+
+    /// Returns the number of messages the nodes have sent to each other so far,
+    /// including the lost ones.
+    pub fn sent_messages(&self) -> usize {
+        self.sent_messages
+    }
+
+    /// Returns the number of messages lost so far.
+    pub fn dropped_messages(&self) -> usize {
+        self.dropped_messages
+    }
+
+    /// Splits the network into the given groups of nodes. Nodes of different groups
+    /// cannot reach each other, and their connections are closed. Nodes which are not
+    /// listed form one more group.
+    pub fn partition(&mut self, groups: &[&[usize]]) {
+        let mut assignment = vec![0; self.nodes.len()];
+        for (group, nodes) in groups.iter().enumerate() {
+            for &node in nodes.iter() {
+                assignment[node] = group + 1;
+            }
+        }
+        self.groups = assignment;
+
+        let broken = self
+            .links
+            .keys()
+            .filter(|&&(from, to)| from < to && !self.is_reachable(from, to))
+            .cloned()
+            .collect::<Vec<_>>();
+        for (from, to) in broken {
+            self.close(from, to);
+        }
+    }
+
+    /// Separates the node from all other nodes.
+    pub fn isolate(&mut self, node: usize) {
+        self.partition(&[&[node]]);
+    }
+
+    /// Removes the partitions. Nodes reconnect to each other on their own.
+    pub fn heal(&mut self) {
+        self.groups = vec![0; self.nodes.len()];
+    }
+
+    /// Sends the transaction to the node as if it has been submitted through its API.
+    pub fn send_transaction(&mut self, node: usize, tx: Signed<RawTransaction>) {
+        self.handle_event(node, ExternalMessage::Transaction(tx).into());
+    }
+
+    /// Delivers the message to the node as if it has been received from the peer with
+    /// the given consensus key. The message is not checked in any way, so faulty and
+    /// malicious peers can be emulated, for example, by a validator isolated from the
+    /// network whose keys are used to sign arbitrary consensus messages.
+    pub fn deliver_message(&mut self, node: usize, author: PublicKey, message: Vec<u8>) {
+        self.handle_event(node, NetworkEvent::MessageReceived(author, message).into());
+    }
+
+    /// Returns the lowest height at which two nodes have committed different blocks,
+    /// or `None` if the committed blocks of all nodes agree.
+    pub fn find_conflicting_blocks(&self) -> Option<Height> {
+        let block_hashes = self
+            .nodes
+            .iter()
+            .map(|node| {
+                let snapshot = node.blockchain.snapshot();
+                let hashes = Schema::new(&snapshot)
+                    .block_hashes_by_height()
+                    .iter()
+                    .collect::<Vec<_>>();
+                hashes
+            })
+            .collect::<Vec<_>>();
+        let max_len = block_hashes.iter().map(Vec::len).max().unwrap_or(0);
+
+        (0..max_len)
+            .find(|&height| {
+                let mut committed = block_hashes.iter().filter_map(|hashes| hashes.get(height));
+                let first = committed.next();
+                committed.any(|hash| Some(hash) != first)
+            })
+            .map(|height| Height(height as u64))
+    }
+
+    /// Handles the next scheduled event and advances the clock to its time. Returns
+    /// `false` if there are no scheduled events.
+    pub fn step(&mut self) -> bool {
+        self.flush();
+        let scheduled = match self.queue.pop() {
+            Some(scheduled) => scheduled,
+            None => return false,
+        };
+        if scheduled.time > self.clock.now() {
+            self.clock.set(scheduled.time);
+        }
+        if let Some((from, id)) = scheduled.link {
+            let is_open = self
+                .links
+                .get(&(from, scheduled.node))
+                .map_or(false, |link| link.id == id);
+            if !is_open {
+                return true;
+            }
+        }
+        self.handle_event(scheduled.node, scheduled.event);
+        true
+    }
+
+    /// Handles all events scheduled for the given period and advances the clock
+    /// to its end.
+    pub fn advance(&mut self, duration: Duration) {
+        let deadline = self.clock.now() + duration;
+        self.flush();
+        while self.next_time().map_or(false, |time| time <= deadline) {
+            self.step();
+        }
+        self.clock.set(deadline);
+    }
+
+    /// Handles events until the condition is satisfied or the timeout expires.
+    /// Returns whether the condition has been satisfied.
+    pub fn run_until<F>(&mut self, timeout: Duration, mut condition: F) -> bool
+    where
+        F: FnMut(&Self) -> bool,
+    {
+        let deadline = self.clock.now() + timeout;
+        loop {
+            self.flush();
+            if condition(self) {
+                return true;
+            }
+            match self.next_time() {
+                Some(time) if time <= deadline => {
+                    self.step();
+                }
+                _ => {
+                    self.clock.set(cmp::max(deadline, self.clock.now()));
+                    return condition(self);
+                }
+            }
+        }
+    }
+
+    /// Handles events until all nodes commit the block at the given height or
+    /// the timeout expires. Returns whether the block has been committed.
+    pub fn run_until_height(&mut self, height: Height, timeout: Duration) -> bool {
+        self.run_until(timeout, |network| {
+            network.nodes.iter().all(|node| node.height() >= height)
+        })
+    }
+
+    fn next_time(&self) -> Option<SystemTime> {
+        self.queue.peek().map(|scheduled| scheduled.time)
+    }
+
+    fn next_seq(&mut self) -> u64 {
+        self.seq += 1;
+        self.seq
+    }
+
+    fn schedule(
+        &mut self,
+        time: SystemTime,
+        node: usize,
+        link: Option<(usize, u64)>,
+        event: Event,
+    ) {
+        let seq = self.next_seq();
+        self.queue.push(Scheduled {
+            time,
+            seq,
+            node,
+            link,
+            event,
+        });
+    }
+
+    /// Processes the requests sent by the services and the tests to all nodes.
+    fn flush(&mut self) {
+        for index in 0..self.nodes.len() {
+            self.process_node(index);
+        }
+    }
+
+    fn handle_event(&mut self, index: usize, event: Event) {
+        self.nodes[index].handler.handle_event(event);
+        self.process_node(index);
+    }
+
+    /// Routes the requests of the node and handles the events the node has scheduled
+    /// for itself without a delay.
+    fn process_node(&mut self, index: usize) {
+        loop {
+            let (network_requests, timeouts) = self.nodes[index].poll_requests();
+            for TimeoutRequest(time, timeout) in timeouts {
+                self.schedule(time, index, None, timeout.into());
+            }
+            self.route(index, network_requests);
+
+            match self.nodes[index].next_event() {
+                Some(event) => self.nodes[index].handler.handle_event(event),
+                None => break,
+            }
+        }
+    }
+
+    fn route(&mut self, from: usize, mut requests: Vec<NetworkRequest>) {
+        // Broadcasts iterate over a hash map of peers, so the relative order of messages
+        // to different peers is arbitrary. The stable sort keeps the order of messages to
+        // each peer and makes latencies drawn for them independent of the hash map order.
+        requests.sort_by_key(|request| match *request {
+            NetworkRequest::SendMessage(key, _) | NetworkRequest::DisconnectWithPeer(key) => {
+                Some(key)
+            }
+            NetworkRequest::UpdateConsensusKeys(..) | NetworkRequest::Shutdown => None,
+        });
+
+        for request in requests {
+            match request {
+                NetworkRequest::SendMessage(key, message) => self.send_message(from, key, message),
+                NetworkRequest::DisconnectWithPeer(key) => {
+                    if let Some(to) = self.find_node(&key) {
+                        self.close(from, to);
+                    }
+                }
+                NetworkRequest::UpdateConsensusKeys(..) | NetworkRequest::Shutdown => {}
+            }
+        }
+    }
+
+    fn find_node(&self, key: &PublicKey) -> Option<usize> {
+        self.nodes
+            .iter()
+            .position(|node| node.consensus_public_key() == *key)
+    }
+
+    fn is_reachable(&self, from: usize, to: usize) -> bool {
+        self.groups[from] == self.groups[to]
+    }
+
+    fn send_message(&mut self, from: usize, key: PublicKey, message: SignedMessage) {
+        let to = match self.find_node(&key) {
+            Some(to) if to != from && self.is_reachable(from, to) => to,
+            _ => {
+                let time = self.clock.now() + self.conditions.connect_timeout;
+                self.schedule(
+                    time,
+                    from,
+                    None,
+                    NetworkEvent::UnableConnectToPeer(key).into(),
+                );
+                return;
+            }
+        };
+
+        if !self.links.contains_key(&(from, to)) {
+            let is_connect = message
+                == *self.nodes[from]
+                    .handler
+                    .state()
+                    .our_connect_message()
+                    .signed_message();
+            self.open(from, to);
+            // The `Connect` message is a part of the handshake.
+            if is_connect {
+                return;
+            }
+        }
+
+        self.sent_messages += 1;
+        if self.conditions.drop_rate > 0.0 && self.rng.gen_bool(self.conditions.drop_rate) {
+            self.dropped_messages += 1;
+            return;
+        }
+        let author = self.nodes[from].consensus_public_key();
+        let now = self.clock.now();
+        self.transmit(
+            from,
+            to,
+            now,
+            NetworkEvent::MessageReceived(author, message.raw().to_vec()),
+        );
+    }
+
+    /// Opens a connection initiated by the `from` node. The initiator learns about the
+    /// connection after the peer does.
+    fn open(&mut self, from: usize, to: usize) {
+        let now = self.clock.now();
+        for &(sender, receiver) in &[(from, to), (to, from)] {
+            let id = self.next_seq();
+            let link = Link {
+                id,
+                opened: now,
+                last_delivery: now,
+            };
+            self.links.insert((sender, receiver), link);
+        }
+
+        let event = {
+            let initiator = &self.nodes[from];
+            NetworkEvent::PeerConnected(
+                ConnectedPeerAddr::In(initiator.config.listen_address),
+                initiator.handler.state().our_connect_message().clone(),
+            )
+        };
+        let accepted = self.transmit(from, to, now, event);
+        self.links.get_mut(&(from, to)).unwrap().opened = accepted;
+
+        let event = {
+            let acceptor = &self.nodes[to];
+            NetworkEvent::PeerConnected(
+                ConnectedPeerAddr::Out(
+                    acceptor.config.external_address.clone(),
+                    acceptor.config.listen_address,
+                ),
+                acceptor.handler.state().our_connect_message().clone(),
+            )
+        };
+        let established = self.transmit(to, from, accepted, event);
+        self.links.get_mut(&(to, from)).unwrap().opened = established;
+    }
+
+    /// Closes the connection between the nodes. Both nodes learn about it immediately.
+    fn close(&mut self, first: usize, second: usize) {
+        if self.links.remove(&(first, second)).is_none() {
+            return;
+        }
+        self.links.remove(&(second, first));
+
+        let now = self.clock.now();
+        let first_key = self.nodes[first].consensus_public_key();
+        let second_key = self.nodes[second].consensus_public_key();
+        self.schedule(
+            now,
+            first,
+            None,
+            NetworkEvent::PeerDisconnected(second_key).into(),
+        );
+        self.schedule(
+            now,
+            second,
+            None,
+            NetworkEvent::PeerDisconnected(first_key).into(),
+        );
+    }
+
+    /// Schedules the delivery of the event sent over the link at the given time and
+    /// returns the delivery time.
+    fn transmit(
+        &mut self,
+        from: usize,
+        to: usize,
+        sent: SystemTime,
+        event: NetworkEvent,
+    ) -> SystemTime {
+        let latency = self.latency();
+        let reorder = self.conditions.reorder;
+        let (id, time) = {
+            let link = self.links.get_mut(&(from, to)).expect("Link is not open");
+            let earliest = if reorder {
+                link.opened
+            } else {
+                link.last_delivery
+            };
+            let time = cmp::max(sent + latency, earliest);
+            link.last_delivery = cmp::max(link.last_delivery, time);
+            (link.id, time)
+        };
+        self.schedule(time, to, Some((from, id)), event.into());
+        time
+    }
+
+    fn latency(&mut self) -> Duration {
+        let min = duration_to_nanos(self.conditions.min_latency);
+        let max = duration_to_nanos(self.conditions.max_latency);
+        let nanos = if max > min {
+            self.rng.gen_range(min, max + 1)
+        } else {
+            min
+        };
+        Duration::from_nanos(nanos)
+    }
+}
+
+fn duration_to_nanos(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000_000 + u64::from(duration.subsec_nanos())
+}
+
+fn seeded_rng(seed: u64) -> XorShiftRng {
+    let mut bytes = [0; 16];
+    LittleEndian::write_u64(&mut bytes[..8], seed);
+    // Keeps the seed nonzero, which the generator requires.
+    LittleEndian::write_u64(&mut bytes[8..], seed ^ 0x5a5a_5a5a_5a5a_5a5a);
+    XorShiftRng::from_seed(bytes)
+}
+
+fn gen_keypair(rng: &mut XorShiftRng) -> (PublicKey, SecretKey) {
+    let mut seed = [0; SEED_LENGTH];
+    rng.fill_bytes(&mut seed);
+    crypto::gen_keypair_from_seed(&Seed::new(seed))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crypto::hash;
+    use helpers::{Round, ValidatorId};
+    use messages::{Precommit, Propose};
+    use sandbox::timestamping::{TimestampingService, TimestampingTxGenerator, DATA_SIZE};
+
+    fn timestamping_network(seed: u64, conditions: NetworkConditions) -> SimulatedNetwork {
+        SimulatedNetworkBuilder::new(4)
+            .with_auditors(1)
+            .with_services(|| vec![Box::new(TimestampingService::new()) as Box<dyn Service>])
+            .with_conditions(conditions)
+            .with_seed(seed)
+            .build()
+    }
+
+    fn lossy_conditions() -> NetworkConditions {
+        NetworkConditions {
+            min_latency: Duration::from_millis(5),
+            max_latency: Duration::from_millis(100),
+            drop_rate: 0.1,
+            reorder: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_nodes_commit_same_blocks() {
+        let mut network = timestamping_network(1, lossy_conditions());
+        assert!(network.run_until_height(Height(5), Duration::from_secs(120)));
+        assert!(network.dropped_messages() > 0);
+
+        let hashes = network
+            .nodes()
+            .iter()
+            .map(|node| {
+                let snapshot = node.blockchain().snapshot();
+                Schema::new(&snapshot).block_hash_by_height(Height(5))
+            })
+            .collect::<Vec<_>>();
+        assert!(hashes[0].is_some());
+        assert!(hashes.iter().all(|hash| *hash == hashes[0]));
+    }
+
+    #[test]
+    fn test_transactions_reach_all_nodes() {
+        let mut network = timestamping_network(2, NetworkConditions::default());
+        let txs = TimestampingTxGenerator::new(DATA_SIZE)
+            .take(5)
+            .collect::<Vec<_>>();
+        for (i, tx) in txs.iter().enumerate() {
+            let node = i % network.nodes().len();
+            network.send_transaction(node, tx.clone());
+        }
+
+        let committed = network.run_until(Duration::from_secs(60), |network| {
+            network.nodes().iter().all(|node| {
+                let snapshot = node.blockchain().snapshot();
+                let schema = Schema::new(&snapshot);
+                txs.iter()
+                    .all(|tx| schema.transactions_locations().contains(&tx.hash()))
+            })
+        });
+        assert!(committed);
+    }
+
+    #[test]
+    fn test_partition_without_majority_stalls() {
+        let mut network = timestamping_network(3, NetworkConditions::default());
+        assert!(network.run_until_height(Height(1), Duration::from_secs(60)));
+
+        network.partition(&[&[0, 1], &[2, 3]]);
+        network.advance(Duration::from_secs(1));
+        let heights = network
+            .nodes()
+            .iter()
+            .map(SimulatedNode::height)
+            .collect::<Vec<_>>();
+        network.advance(Duration::from_secs(20));
+        for (node, height) in network.nodes().iter().zip(heights) {
+            assert_eq!(node.height(), height);
+        }
+
+        network.heal();
+        let height = network.node(0).height().next();
+        assert!(network.run_until_height(height, Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_isolated_validator_catches_up() {
+        let mut network = timestamping_network(4, NetworkConditions::default());
+        network.isolate(3);
+        assert!(network.run_until(Duration::from_secs(60), |network| {
+            network.node(0).height() >= Height(5)
+        }));
+        assert!(network.node(3).height() < Height(5));
+
+        network.heal();
+        assert!(network.run_until_height(Height(5), Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_equivocating_validator_cannot_fork() {
+        let mut network = timestamping_network(6, NetworkConditions::default());
+        // The keys of the isolated validator are used to send conflicting messages.
+        network.isolate(3);
+        let (author, secret_key) = {
+            let config = network.node(3).config();
+            (
+                config.consensus_public_key,
+                config.consensus_secret_key.clone(),
+            )
+        };
+
+        for _ in 0..5 {
+            let height = network.node(0).height().next();
+            let last_hash = network.node(0).blockchain().last_hash();
+            for node in 0..3 {
+                let tx_hashes = vec![hash(&[node as u8]); node];
+                let propose = Message::concrete(
+                    Propose::new(ValidatorId(3), height, Round(1), &last_hash, &tx_hashes),
+                    author,
+                    &secret_key,
+                );
+                let precommit = Message::concrete(
+                    Precommit::new(
+                        ValidatorId(3),
+                        height,
+                        Round(1),
+                        &propose.hash(),
+                        &hash(&[node as u8]),
+                        Utc::now(),
+                    ),
+                    author,
+                    &secret_key,
+                );
+                network.deliver_message(node, author, propose.serialize());
+                network.deliver_message(node, author, precommit.serialize());
+            }
+            network.advance(Duration::from_secs(1));
+        }
+
+        assert!(network.run_until(Duration::from_secs(60), |network| {
+            (0..3).all(|node| network.node(node).height() >= Height(5))
+        }));
+        assert_eq!(network.find_conflicting_blocks(), None);
+    }
+
+    #[test]
+    fn test_same_seed_reproduces_run() {
+        // Latencies are below the request timeouts and the run is shorter than
+        // `peers_timeout`, so the nodes make no choices of their own.
+        let conditions = NetworkConditions {
+            min_latency: Duration::from_millis(5),
+            max_latency: Duration::from_millis(30),
+            reorder: true,
+            ..Default::default()
+        };
+        let run = |seed| {
+            let mut network = timestamping_network(seed, conditions.clone());
+            network.advance(Duration::from_secs(5));
+            let last_blocks = network
+                .nodes()
+                .iter()
+                .map(|node| node.blockchain().last_block())
+                .collect::<Vec<_>>();
+            (
+                network.sent_messages(),
+                network.dropped_messages(),
+                last_blocks,
+            )
+        };
+
+        let first = run(5);
+        assert!(first.2[0].height() > Height(0));
+        assert_eq!(first, run(5));
+    }
+}
@@ -16,12 +16,12 @@ use crypto::{Hash, PublicKey, SecretKey};
 use events::network::ConnectedPeerAddr;
 use helpers::{Height, Milliseconds, Round, ValidatorId};
 use messages::{
-    BlockResponse, Connect, Consensus as ConsensusMessage, Precommit, Prevote, Propose,
-    RawTransaction, Signed,
+    BlockResponse, Connect, Consensus as ConsensusMessage, PeerAddressRecord, Precommit, Prevote,
+    Propose, RawTransaction, Signed,
 };
 use node::{
     connect_list::{ConnectList, PeerAddress},
-    ConnectInfo,
+    ConnectInfo, ConnectListConfig,
 };
 use storage::{KeySetIndex, MapIndex, Patch, Snapshot};
 
@@ -36,6 +36,9 @@ pub const PREVOTES_REQUEST_TIMEOUT: Milliseconds = 100;
 /// Timeout value for the `BlockRequest` message.
 pub const BLOCK_REQUEST_TIMEOUT: Milliseconds = 100;
 
+// Number of recent heights for which late transactions are remembered.
+const LATE_TXS_RETAINED_HEIGHTS: u64 = 16;
+
 /// State of the `NodeHandler`.
 #[derive(Debug)]
 pub struct State {
@@ -74,6 +77,12 @@ pub struct State {
     // Our requests state.
     requests: HashMap<RequestData, RequestState>,
 
+    // Hashes of transactions waiting to be announced.
+    tx_announcements: Vec<Hash>,
+
+    // Transactions that were unknown when the propose or block for the height was received.
+    late_txs: BTreeMap<Height, HashSet<Hash>>,
+
     // Maximum of node height in consensus messages.
     nodes_max_height: BTreeMap<PublicKey, Height>,
 
@@ -92,7 +101,7 @@ pub struct ValidatorState {
 
 /// `RequestData` represents a request for some data to other nodes. Each enum variant will be
 /// translated to the corresponding request-message.
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum RequestData {
     /// Represents `ProposeRequest` message.
     Propose(Hash),
@@ -104,6 +113,8 @@ pub enum RequestData {
     Prevotes(Round, Hash),
     /// Represents `BlockRequest` message.
     Block(Height),
+    /// Represents `TransactionsRequest` message for `TransactionsAnnounce`.
+    AnnouncedTransaction(Hash),
 }
 
 #[derive(Debug)]
@@ -244,9 +255,9 @@ impl RequestData {
     pub fn timeout(&self) -> Duration {
         let ms = match *self {
             RequestData::Propose(..) => PROPOSE_REQUEST_TIMEOUT,
-            RequestData::ProposeTransactions(..) | RequestData::BlockTransactions => {
-                TRANSACTIONS_REQUEST_TIMEOUT
-            }
+            RequestData::ProposeTransactions(..)
+            | RequestData::BlockTransactions
+            | RequestData::AnnouncedTransaction(..) => TRANSACTIONS_REQUEST_TIMEOUT,
             RequestData::Prevotes(..) => PREVOTES_REQUEST_TIMEOUT,
             RequestData::Block(..) => BLOCK_REQUEST_TIMEOUT,
         };
@@ -391,6 +402,13 @@ impl SharedConnectList {
         connect_list.is_peer_allowed(public_key)
     }
 
+    /// Returns keys of the peers which can connect, including the previous keys
+    /// of the peers which have rotated their consensus keys recently.
+    pub fn allowed_keys(&self) -> Vec<PublicKey> {
+        let connect_list = self.inner.read().expect("ConnectList read lock");
+        connect_list.allowed_keys()
+    }
+
     /// Return `peers` from underlying `ConnectList`
     pub fn peers(&self) -> Vec<ConnectInfo> {
         self.inner
@@ -416,6 +434,75 @@ impl SharedConnectList {
         let connect_list = self.inner.read().expect("ConnectList read lock");
         connect_list.find_address_by_pubkey(public_key).cloned()
     }
+
+    /// Removes peer from the connect list. Returns `true` if the peer has been removed.
+    pub fn remove_peer(&self, public_key: &PublicKey) -> bool {
+        let mut conn_list = self.inner.write().expect("ConnectList write lock");
+        conn_list.remove(public_key).is_some()
+    }
+
+    /// Replaces the key of the peer. The previous key is accepted until `until` height.
+    /// Returns `false` if the peer is not in the connect list.
+    pub fn rotate_key(&self, old_key: &PublicKey, new_key: PublicKey, until: Height) -> bool {
+        let mut conn_list = self.inner.write().expect("ConnectList write lock");
+        conn_list.rotate_key(old_key, new_key, until)
+    }
+
+    /// Forgets the previous keys of the peers whose transition window ends at
+    /// the given height. Returns the forgotten keys.
+    pub fn remove_retired(&self, height: Height) -> Vec<PublicKey> {
+        let mut conn_list = self.inner.write().expect("ConnectList write lock");
+        conn_list.remove_retired(height)
+    }
+
+    /// Pins or unpins the peer. Returns `false` if the peer is not in the connect list.
+    pub fn set_pinned(&self, public_key: &PublicKey, pinned: bool) -> bool {
+        let mut conn_list = self.inner.write().expect("ConnectList write lock");
+        conn_list.set_pinned(public_key, pinned)
+    }
+
+    /// Returns `true` if the peer is pinned.
+    pub fn is_pinned(&self, public_key: &PublicKey) -> bool {
+        let connect_list = self.inner.read().expect("ConnectList read lock");
+        connect_list.is_pinned(public_key)
+    }
+
+    /// Returns public keys of the pinned peers.
+    pub fn pinned(&self) -> Vec<PublicKey> {
+        let connect_list = self.inner.read().expect("ConnectList read lock");
+        connect_list.pinned()
+    }
+
+    /// Creates `ConnectListConfig` from the underlying `ConnectList`.
+    pub fn to_config(&self) -> ConnectListConfig {
+        let connect_list = self.inner.read().expect("ConnectList read lock");
+        connect_list.to_config()
+    }
+
+    /// Replaces the additional addresses of the peer. Returns `true` if the addresses
+    /// have been updated.
+    pub fn update_addresses(
+        &self,
+        public_key: &PublicKey,
+        addresses: &[PeerAddressRecord],
+    ) -> bool {
+        let mut conn_list = self.inner.write().expect("ConnectList write lock");
+        conn_list.update_addresses(public_key, addresses)
+    }
+
+    /// Adds a peer discovered outside of the configured connect list. Returns `false`
+    /// if the peer is already known or the limit of discovered peers is reached.
+    pub fn add_discovered(&self, public_key: PublicKey, address: String, max_peers: usize) -> bool {
+        let mut conn_list = self.inner.write().expect("ConnectList write lock");
+        conn_list.add_discovered(public_key, address, max_peers)
+    }
+
+    /// Returns `true` if the peer has been discovered outside of the configured
+    /// connect list.
+    pub fn is_discovered(&self, public_key: &PublicKey) -> bool {
+        let connect_list = self.inner.read().expect("ConnectList read lock");
+        connect_list.is_discovered(public_key)
+    }
 }
 
 impl State {
@@ -467,6 +554,8 @@ impl State {
             our_connect_message: connect,
 
             requests: HashMap::new(),
+            tx_announcements: Vec::new(),
+            late_txs: BTreeMap::new(),
 
             config: stored,
 
@@ -805,6 +894,8 @@ impl State {
         }
         self.requests.clear(); // FIXME: Clear all timeouts. (ECR-171)
         self.incomplete_block = None;
+        let retained = Height(self.height.0.saturating_sub(LATE_TXS_RETAINED_HEIGHTS));
+        self.late_txs = self.late_txs.split_off(&retained);
     }
 
     /// Returns a list of queued consensus messages.
@@ -934,6 +1025,10 @@ impl State {
                         .or_insert_with(Vec::new)
                         .push(propose_hash);
                 }
+                self.late_txs
+                    .entry(msg.height())
+                    .or_insert_with(HashSet::new)
+                    .extend(&unknown_txs);
 
                 Ok(e.insert(ProposeState {
                     propose: msg,
@@ -994,6 +1089,10 @@ impl State {
             }
         }
 
+        self.late_txs
+            .entry(msg.block().height())
+            .or_insert_with(HashSet::new)
+            .extend(&unknown_txs);
         self.incomplete_block = Some(IncompleteBlock {
             msg: msg.clone(),
             unknown_txs,
@@ -1178,6 +1277,27 @@ impl State {
         state.map(|s| s.known_nodes).unwrap_or_default()
     }
 
+    /// Adds the transaction hash to the pending announcements. Returns `true` if
+    /// there were no pending announcements before.
+    pub fn add_tx_announcement(&mut self, tx_hash: Hash) -> bool {
+        let is_first = self.tx_announcements.is_empty();
+        self.tx_announcements.push(tx_hash);
+        is_first
+    }
+
+    /// Returns the pending announcements and clears them.
+    pub fn take_tx_announcements(&mut self) -> Vec<Hash> {
+        ::std::mem::replace(&mut self.tx_announcements, Vec::new())
+    }
+
+    /// Returns `true` if the transaction from the block at the given height was unknown
+    /// to the node when it received the propose or block.
+    pub fn is_late_tx(&self, height: Height, tx_hash: &Hash) -> bool {
+        self.late_txs
+            .get(&height)
+            .map_or(false, |txs| txs.contains(tx_hash))
+    }
+
     /// Returns the `Connect` message of the current node.
     pub fn our_connect_message(&self) -> &Signed<Connect> {
         &self.our_connect_message
@@ -1188,13 +1308,26 @@ impl State {
         self.our_connect_message = msg;
     }
 
-    /// Add peer to node's `ConnectList`.
-    pub fn add_peer_to_connect_list(&mut self, peer: ConnectInfo) {
+    /// Replaces the consensus keys of the node together with the `Connect` message
+    /// signed by the new key. Validator id is updated with the next configuration.
+    pub fn set_consensus_keys(
+        &mut self,
+        public_key: PublicKey,
+        secret_key: SecretKey,
+        connect: Signed<Connect>,
+    ) {
+        self.consensus_public_key = public_key;
+        self.consensus_secret_key = secret_key;
+        self.our_connect_message = connect;
+    }
+
+    /// Add peer to node's `ConnectList`. Returns `false` if the peer is pinned.
+    pub fn add_peer_to_connect_list(&mut self, peer: ConnectInfo) -> bool {
         let mut list = self
             .connect_list
             .inner
             .write()
             .expect("ConnectList write lock");
-        list.add(peer);
+        list.add(peer)
     }
 }
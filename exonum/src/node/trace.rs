@@ -0,0 +1,270 @@
+//! Structured trace of the inputs processed by the `NodeHandler`.
+//!
+//! The trace is an append-only sequence of JSON lines. Each line holds a single
+//! [`TraceEntry`] with the logical timestamp (a sequence number together with the time
+//! reported by the `SystemStateProvider`) and the consensus-relevant event which was
+//! handled at that moment. Since the handler is deterministic with respect to these inputs,
+//! a trace can be fed back to a node with the same database to reproduce its state
+//! transitions.
+//!
+//! [`TraceEntry`]: struct.TraceEntry.html
+
+use failure::Error;
+use serde_json;
+
+use std::{
+    fmt,
+    fs::OpenOptions,
+    io::{self, BufRead, LineWriter, Write},
+    path::Path,
+    time::SystemTime,
+};
+
+use super::{ConnectInfo, ExternalMessage, NodeTimeout};
+use crypto::{Hash, PublicKey};
+use events::{network::ConnectedPeerAddr, Event, InternalEvent, NetworkEvent};
+use helpers::{Height, Round};
+use messages::{Connect, HexStringRepresentation, Message, RawTransaction, Signed, SignedMessage};
+
+/// Input of the `NodeHandler` recorded in the consensus trace.
+///
+/// Messages are recorded after their signatures have been verified, in the order the
+/// handler has processed them. Events produced by the node for itself, such as round
+/// jumps and timeouts, are recorded at the moment they are delivered to the handler.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TraceEvent {
+    /// Start of the trace session. Contains the node state at the moment the trace
+    /// has been attached to the handler.
+    Started {
+        /// Current height.
+        height: Height,
+        /// Current round.
+        round: Round,
+        /// Hash of the last committed block.
+        last_block_hash: Hash,
+    },
+    /// Verified message from the network.
+    MessageVerified(#[serde(with = "HexStringRepresentation")] SignedMessage),
+    /// Peer has connected.
+    PeerConnected(ConnectedPeerAddr, Signed<Connect>),
+    /// Peer has disconnected.
+    PeerDisconnected(PublicKey),
+    /// Unable to connect to the peer.
+    UnableConnectToPeer(PublicKey),
+    /// Node timeout.
+    Timeout(NodeTimeout),
+    /// Jump to the round.
+    JumpToRound(Height, Round),
+    /// Transaction received through the API.
+    Transaction(Signed<RawTransaction>),
+    /// Peer added through the API.
+    PeerAdd(ConnectInfo),
+    /// Peer removed through the API.
+    PeerRemove(PublicKey),
+    /// Peer address changed through the API.
+    PeerUpdate(ConnectInfo),
+    /// Peer pinned or unpinned through the API.
+    PeerPin(PublicKey, bool),
+    /// Node has been enabled or disabled through the API.
+    Enable(bool),
+    /// Shutdown requested through the API.
+    Shutdown,
+    /// Transactions rebroadcast requested through the API.
+    Rebroadcast,
+}
+
+impl TraceEvent {
+    /// Creates a trace event for the given handler event. Returns `None` for events
+    /// that do not affect the consensus state, e.g. unverified network messages.
+    pub fn from_event(event: &Event) -> Option<Self> {
+        let trace_event = match *event {
+            Event::Network(ref event) => match *event {
+                NetworkEvent::MessageReceived(..) => return None,
+                NetworkEvent::PeerConnected(ref addr, ref connect) => {
+                    TraceEvent::PeerConnected(addr.clone(), connect.clone())
+                }
+                NetworkEvent::PeerDisconnected(key) => TraceEvent::PeerDisconnected(key),
+                NetworkEvent::UnableConnectToPeer(key) => TraceEvent::UnableConnectToPeer(key),
+            },
+            Event::Api(ref message) => match *message {
+                ExternalMessage::Transaction(ref tx) => TraceEvent::Transaction(tx.clone()),
+                ExternalMessage::PeerAdd(ref info) => TraceEvent::PeerAdd(info.clone()),
+                ExternalMessage::PeerRemove(key) => TraceEvent::PeerRemove(key),
+                ExternalMessage::PeerUpdate(ref info) => TraceEvent::PeerUpdate(info.clone()),
+                ExternalMessage::PeerPin(key, pinned) => TraceEvent::PeerPin(key, pinned),
+                ExternalMessage::Enable(value) => TraceEvent::Enable(value),
+                ExternalMessage::Shutdown => TraceEvent::Shutdown,
+                ExternalMessage::Rebroadcast => TraceEvent::Rebroadcast,
+            },
+            Event::Internal(ref event) => match *event {
+                InternalEvent::Timeout(ref timeout) => TraceEvent::Timeout(timeout.clone()),
+                InternalEvent::JumpToRound(height, round) => TraceEvent::JumpToRound(height, round),
+                InternalEvent::MessageVerified(ref msg) => {
+                    TraceEvent::MessageVerified(msg.signed_message().clone())
+                }
+                InternalEvent::Shutdown | InternalEvent::InvalidMessage(_) => return None,
+            },
+        };
+        Some(trace_event)
+    }
+
+    /// Converts the trace event back into the handler event. Returns `None` for
+    /// the `Started` marker.
+    pub fn into_event(self) -> Result<Option<Event>, Error> {
+        let event = match self {
+            TraceEvent::Started { .. } => return Ok(None),
+            TraceEvent::MessageVerified(message) => {
+                let message = Message::deserialize(message)?;
+                InternalEvent::MessageVerified(Box::new(message)).into()
+            }
+            TraceEvent::PeerConnected(addr, connect) => {
+                NetworkEvent::PeerConnected(addr, connect).into()
+            }
+            TraceEvent::PeerDisconnected(key) => NetworkEvent::PeerDisconnected(key).into(),
+            TraceEvent::UnableConnectToPeer(key) => NetworkEvent::UnableConnectToPeer(key).into(),
+            TraceEvent::Timeout(timeout) => InternalEvent::Timeout(timeout).into(),
+            TraceEvent::JumpToRound(height, round) => {
+                InternalEvent::JumpToRound(height, round).into()
+            }
+            TraceEvent::Transaction(tx) => ExternalMessage::Transaction(tx).into(),
+            TraceEvent::PeerAdd(info) => ExternalMessage::PeerAdd(info).into(),
+            TraceEvent::PeerRemove(key) => ExternalMessage::PeerRemove(key).into(),
+            TraceEvent::PeerUpdate(info) => ExternalMessage::PeerUpdate(info).into(),
+            TraceEvent::PeerPin(key, pinned) => ExternalMessage::PeerPin(key, pinned).into(),
+            TraceEvent::Enable(value) => ExternalMessage::Enable(value).into(),
+            TraceEvent::Shutdown => ExternalMessage::Shutdown.into(),
+            TraceEvent::Rebroadcast => ExternalMessage::Rebroadcast.into(),
+        };
+        Ok(Some(event))
+    }
+}
+
+/// Single record of the consensus trace.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraceEntry {
+    /// Sequence number of the entry within the trace session.
+    pub seq: u64,
+    /// Time reported by the system state provider when the event was handled.
+    pub time: SystemTime,
+    /// Recorded event.
+    pub event: TraceEvent,
+}
+
+/// Append-only writer of the consensus trace.
+pub struct ConsensusTrace {
+    writer: Box<dyn Write + Send>,
+    seq: u64,
+}
+
+impl ConsensusTrace {
+    /// Creates a trace which writes entries into the given writer.
+    pub fn new<W: Write + Send + 'static>(writer: W) -> Self {
+        Self {
+            writer: Box::new(writer),
+            seq: 0,
+        }
+    }
+
+    /// Opens the trace file at the given path. New entries are appended to the end of
+    /// the file, the file is created if it does not exist.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::new(LineWriter::new(file)))
+    }
+
+    /// Appends a new entry to the trace.
+    pub fn record(&mut self, time: SystemTime, event: TraceEvent) -> Result<(), Error> {
+        let entry = TraceEntry {
+            seq: self.seq,
+            time,
+            event,
+        };
+        self.seq += 1;
+        serde_json::to_writer(&mut self.writer, &entry)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    /// Reads all entries from the trace.
+    pub fn read<R: BufRead>(reader: R) -> Result<Vec<TraceEntry>, Error> {
+        let mut entries = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            entries.push(serde_json::from_str(&line)?);
+        }
+        Ok(entries)
+    }
+}
+
+impl fmt::Debug for ConsensusTrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ConsensusTrace")
+            .field("seq", &self.seq)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{self, Write},
+        sync::{Arc, Mutex},
+        time::UNIX_EPOCH,
+    };
+
+    use super::*;
+    use crypto::{gen_keypair, hash};
+    use messages::Status;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_trace_roundtrip() {
+        let (public_key, secret_key) = gen_keypair();
+        let status = Message::concrete(
+            Status::new(Height(2), &hash(&[1, 2, 3])),
+            public_key,
+            &secret_key,
+        );
+        let events = vec![
+            TraceEvent::Started {
+                height: Height(2),
+                round: Round(1),
+                last_block_hash: hash(&[0]),
+            },
+            TraceEvent::MessageVerified(status.signed_message().clone()),
+            TraceEvent::Timeout(NodeTimeout::Round(Height(2), Round(1))),
+            TraceEvent::JumpToRound(Height(2), Round(3)),
+            TraceEvent::Enable(false),
+        ];
+
+        let buffer = SharedBuffer::default();
+        let mut trace = ConsensusTrace::new(buffer.clone());
+        for event in events.clone() {
+            trace.record(UNIX_EPOCH, event).unwrap();
+        }
+
+        let bytes = buffer.0.lock().unwrap().clone();
+        let entries = ConsensusTrace::read(&bytes[..]).unwrap();
+        assert_eq!(entries.len(), events.len());
+        for (seq, (entry, event)) in entries.into_iter().zip(events).enumerate() {
+            assert_eq!(entry.seq, seq as u64);
+            assert_eq!(entry.time, UNIX_EPOCH);
+            assert_eq!(entry.event, event);
+        }
+    }
+}
@@ -48,8 +48,9 @@
 pub use self::schema::blockchain::{Block, ConfigReference, TransactionResult, TxLocation};
 pub use self::schema::helpers::{BitVec, Hash, PublicKey};
 pub use self::schema::protocol::{
-    BlockRequest, BlockResponse, Connect, PeersRequest, Precommit, Prevote, PrevotesRequest,
-    Propose, ProposeRequest, Status, TransactionsRequest, TransactionsResponse,
+    BlockRequest, BlockResponse, BlocksResponse, CompactBlockRequest, CompactBlockResponse,
+    Connect, PeerAddressRecord, PeersRequest, Precommit, Prevote, PrevotesRequest, Propose,
+    ProposeRequest, Status, TransactionsAnnounce, TransactionsRequest, TransactionsResponse,
 };
 
 pub mod schema;
@@ -0,0 +1,214 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tests of the export and import of the blocks archive.
+
+use blockchain::Schema;
+use explorer::archive::{
+    export_blocks, ArchiveChunks, ArchiveError, ArchiveImporter, ArchiveReader, ArchiveRecord,
+    ArchiveWriter, ArchivedBlock,
+};
+use helpers::Height;
+use storage::{Database, MemoryDB};
+
+use super::{
+    sandbox::timestamping_sandbox,
+    sandbox_tests_helper::{add_one_height, SandboxState, TimestampingSandbox},
+};
+
+fn sandbox_with_blocks(count: usize) -> TimestampingSandbox {
+    let sandbox = timestamping_sandbox();
+    let sandbox_state = SandboxState::new();
+    for _ in 0..count {
+        add_one_height(&sandbox, &sandbox_state);
+    }
+    sandbox
+}
+
+fn export(sandbox: &TimestampingSandbox, from: Height, to: Height) -> Vec<u8> {
+    let snapshot = sandbox.blockchain_ref().snapshot();
+    let mut writer = ArchiveWriter::new(Vec::new()).unwrap();
+    export_blocks(snapshot.as_ref(), from, to, &mut writer).unwrap();
+    writer.into_inner()
+}
+
+fn records(archive: &[u8]) -> Vec<ArchiveRecord> {
+    ArchiveReader::new(archive)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+#[test]
+fn test_archive_roundtrip() {
+    let sandbox = sandbox_with_blocks(3);
+    let archive = export(&sandbox, Height(0), Height(10));
+
+    let db = MemoryDB::new();
+    let count = ArchiveImporter::new(&db)
+        .import(ArchiveReader::new(&archive[..]).unwrap())
+        .unwrap();
+    assert_eq!(count, 4);
+
+    let expected = sandbox.blockchain_ref().snapshot();
+    let imported = db.snapshot();
+    for height in 0..4 {
+        let height = Height(height);
+        assert_eq!(
+            ArchivedBlock::from_snapshot(imported.as_ref(), height),
+            ArchivedBlock::from_snapshot(expected.as_ref(), height)
+        );
+    }
+
+    let expected = Schema::new(&expected);
+    let imported = Schema::new(&imported);
+    assert_eq!(imported.last_block(), expected.last_block());
+    assert_eq!(
+        imported.actual_configuration(),
+        expected.actual_configuration()
+    );
+    for tx_hash in expected.block_transactions(Height(2)).iter() {
+        assert_eq!(
+            imported.transactions_locations().get(&tx_hash),
+            expected.transactions_locations().get(&tx_hash)
+        );
+    }
+}
+
+#[test]
+fn test_archive_append() {
+    let sandbox = timestamping_sandbox();
+    let sandbox_state = SandboxState::new();
+    for _ in 0..3 {
+        add_one_height(&sandbox, &sandbox_state);
+    }
+    let mut writer = ArchiveWriter::new(Vec::new()).unwrap();
+    let snapshot = sandbox.blockchain_ref().snapshot();
+    export_blocks(snapshot.as_ref(), Height(0), Height(2), &mut writer).unwrap();
+    let mut writer = ArchiveWriter::append(writer.into_inner());
+    export_blocks(snapshot.as_ref(), Height(2), Height(10), &mut writer).unwrap();
+    let archive = writer.into_inner();
+
+    let db = MemoryDB::new();
+    let count = ArchiveImporter::new(&db)
+        .import(ArchiveReader::new(&archive[..]).unwrap())
+        .unwrap();
+    assert_eq!(count, 4);
+
+    // The import continues the history stored in the database.
+    add_one_height(&sandbox, &sandbox_state);
+    let archive = export(&sandbox, Height(4), Height(10));
+    let mut importer = ArchiveImporter::new(&db);
+    let count = importer
+        .import(ArchiveReader::new(&archive[..]).unwrap())
+        .unwrap();
+    assert_eq!(count, 1);
+    assert_eq!(importer.last_block(), Some(&sandbox.last_block()));
+}
+
+#[test]
+fn test_archive_chunks() {
+    let sandbox = sandbox_with_blocks(3);
+    let snapshot = sandbox.blockchain_ref().snapshot();
+    let chunks = ArchiveChunks::new(snapshot, Height(1), Height(10))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    // The header goes with the first block, which is preceded by its configuration.
+    assert_eq!(chunks.len(), 3);
+    assert_eq!(records(&chunks[0]).len(), 2);
+    assert_eq!(chunks.concat(), export(&sandbox, Height(1), Height(10)));
+
+    // The archive of an empty range consists of its header.
+    let snapshot = sandbox.blockchain_ref().snapshot();
+    let chunks = ArchiveChunks::new(snapshot, Height(5), Height(10)).collect::<Vec<_>>();
+    assert_eq!(chunks.len(), 1);
+    assert!(records(chunks[0].as_ref().unwrap()).is_empty());
+}
+
+#[test]
+fn test_archive_detects_damage() {
+    let sandbox = sandbox_with_blocks(1);
+    let mut archive = export(&sandbox, Height(0), Height(10));
+    // Damage the payload of the first record following the 12-byte header.
+    archive[20] ^= 1;
+
+    let result: Result<Vec<_>, _> = ArchiveReader::new(&archive[..]).unwrap().collect();
+    match result {
+        Err(ArchiveError::ChecksumMismatch(12)) => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    let result = ArchiveReader::new(&b"EXNMARCX\x01\x00\x00\x00"[..]);
+    match result {
+        Err(ArchiveError::InvalidHeader) => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    // The length of the record is checked before reading it.
+    let archive = b"EXNMARCH\x01\x00\x00\x00\x02\xff\xff\xff\xff";
+    let result: Result<Vec<_>, _> = ArchiveReader::new(&archive[..]).unwrap().collect();
+    match result {
+        Err(ArchiveError::Malformed(_)) => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+    // A record shorter than its length is truncated.
+    let archive = b"EXNMARCH\x01\x00\x00\x00\x02\x00\x00\x00\x08\x00";
+    let result: Result<Vec<_>, _> = ArchiveReader::new(&archive[..]).unwrap().collect();
+    match result {
+        Err(ArchiveError::Io(ref e)) if e.kind() == ::std::io::ErrorKind::UnexpectedEof => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_archive_rejects_unverified_blocks() {
+    let sandbox = sandbox_with_blocks(2);
+    let archive = export(&sandbox, Height(0), Height(10));
+    let records = records(&archive);
+
+    let db = MemoryDB::new();
+    let mut importer = ArchiveImporter::new(&db);
+    let mut blocks = Vec::new();
+    for record in records {
+        match record {
+            ArchiveRecord::Configuration(config) => importer.import_configuration(config).unwrap(),
+            ArchiveRecord::Block(block) => blocks.push(block),
+        }
+    }
+
+    // A block cannot be imported before the previous one.
+    match importer.import_block(blocks[1].clone()) {
+        Err(ArchiveError::UnexpectedBlock(Height(1))) => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+    importer.import_block(blocks[0].clone()).unwrap();
+
+    let mut block = blocks[1].clone();
+    block.precommits.truncate(1);
+    match importer.import_block(block) {
+        Err(ArchiveError::InvalidPrecommits(Height(1), _)) => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    let mut block = blocks[1].clone();
+    block.transactions.clear();
+    match importer.import_block(block) {
+        Err(ArchiveError::TransactionsMismatch(Height(1))) => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    importer.import_block(blocks[1].clone()).unwrap();
+    importer.import_block(blocks[2].clone()).unwrap();
+    assert_eq!(importer.last_block(), Some(&sandbox.last_block()));
+}
@@ -22,10 +22,10 @@ use std::collections::BTreeMap;
 
 use blockchain::{Blockchain, Schema, CORE_SERVICE};
 use crypto::{gen_keypair_from_seed, CryptoHash, Hash, Seed, HASH_SIZE, SEED_LENGTH};
-use helpers::{Height, Round, ValidatorId};
-use messages::{Precommit, Signed};
+use helpers::{user_agent, Height, Round, ValidatorId};
+use messages::{Connect, Message, PeerAddressRecord, Precommit, Signed};
 use sandbox::{
-    sandbox::{self, timestamping_sandbox},
+    sandbox::{self, timestamping_sandbox, SandboxBuilder},
     sandbox_tests_helper::*,
     timestamping::{TimestampingTxGenerator, DATA_SIZE, TIMESTAMPING_SERVICE},
 };
@@ -279,3 +279,45 @@ fn test_store_txs_positions() {
         assert_eq!(committed_height, location.block_height());
     }
 }
+
+/// - Node 0 receives `Connect` with additional addresses from Node 1
+/// - Addresses of Node 1 in the connect list are ordered by priority
+#[test]
+fn test_update_peer_addresses_from_connect() {
+    let sandbox = SandboxBuilder::new()
+        .do_not_initialize_connections()
+        .build();
+
+    let (v0, v1) = (ValidatorId(0), ValidatorId(1));
+    let (p0, s0, a0) = (sandbox.p(v0), sandbox.s(v0).clone(), sandbox.a(v0));
+    let (p1, s1, a1) = (sandbox.p(v1), sandbox.s(v1).clone(), sandbox.a(v1));
+
+    let time = sandbox.time();
+    let connect_from_0 = sandbox.create_connect(&p0, a0, time.into(), &user_agent::get(), &s0);
+    let addresses = vec![
+        PeerAddressRecord::new("10.0.0.1:2000", 1),
+        PeerAddressRecord::new("192.168.0.1:2000", 0),
+    ];
+    let connect_from_1 = Message::concrete(
+        Connect::new(&a1, time.into(), &user_agent::get()).with_addresses(addresses),
+        p1,
+        &s1,
+    );
+
+    sandbox.recv(&connect_from_1);
+    sandbox.send(p1, &connect_from_0);
+
+    let address = sandbox
+        .node_state()
+        .connect_list()
+        .find_address_by_key(&p1)
+        .unwrap();
+    assert_eq!(
+        address.addresses_by_priority(),
+        vec![
+            "192.168.0.1:2000".to_owned(),
+            "10.0.0.1:2000".to_owned(),
+            a1
+        ]
+    );
+}
@@ -16,10 +16,15 @@
 
 use std::time::Duration;
 
-use crypto::CryptoHash;
+use blockchain::Schema;
+use crypto::{CryptoHash, Hash};
 use helpers::{Height, Round, ValidatorId};
+use messages::{BlockResponse, Precommit, Signed};
 use node::state::{BLOCK_REQUEST_TIMEOUT, TRANSACTIONS_REQUEST_TIMEOUT};
-use sandbox::{sandbox::timestamping_sandbox, sandbox_tests_helper::*};
+use sandbox::{
+    sandbox::{timestamping_sandbox, timestamping_sandbox_builder},
+    sandbox_tests_helper::*,
+};
 
 /// HANDLE block response
 
@@ -943,3 +948,651 @@ fn transactions_request_to_multiple_nodes() {
         sandbox.s(ValidatorId(0)),
     ));
 }
+
+fn compact_blocks_sandbox() -> TimestampingSandbox {
+    timestamping_sandbox_builder()
+        .with_mempool(|config| config.compact_blocks.enabled = true)
+        .build()
+}
+
+fn block_precommits(
+    sandbox: &TimestampingSandbox,
+    propose_hash: &Hash,
+    block_hash: &Hash,
+) -> Vec<Signed<Precommit>> {
+    (1..4)
+        .map(|i| {
+            sandbox.create_precommit(
+                ValidatorId(i),
+                Height(1),
+                Round(1),
+                propose_hash,
+                block_hash,
+                sandbox.time().into(),
+                sandbox.s(ValidatorId(i)),
+            )
+        })
+        .collect()
+}
+
+/// Receives `Status` with the next height from the third node and checks that
+/// the compact block is requested from it.
+fn request_compact_block(sandbox: &TimestampingSandbox, block_hash: &Hash) {
+    sandbox.recv(&sandbox.create_status(
+        &sandbox.p(ValidatorId(3)),
+        Height(2),
+        block_hash,
+        sandbox.s(ValidatorId(3)),
+    ));
+
+    sandbox.add_time(Duration::from_millis(BLOCK_REQUEST_TIMEOUT));
+    sandbox.send(
+        sandbox.p(ValidatorId(3)),
+        &sandbox.create_compact_block_request(
+            &sandbox.p(ValidatorId(0)),
+            &sandbox.p(ValidatorId(3)),
+            Height(1),
+            sandbox.s(ValidatorId(0)),
+        ),
+    );
+}
+
+/// HANDLE compact block response
+
+/// - the block should be restored from the pool
+/// idea of test is:
+/// - receive some tx A
+/// - getting Status from other node with later height, send CompactBlockRequest to this node
+/// - receive CompactBlockResponse with short id of tx A
+/// - Block should be executed and committed
+#[test]
+fn handle_compact_block_response_tx_in_pool() {
+    let sandbox = compact_blocks_sandbox();
+
+    let tx = gen_timestamping_tx();
+    sandbox.recv(&tx);
+
+    let propose = ProposeBuilder::new(&sandbox).build();
+    let block = BlockBuilder::new(&sandbox)
+        .with_tx_hash(&tx.hash())
+        .with_state_hash(&sandbox.compute_state_hash(&[tx.clone()]))
+        .build();
+
+    request_compact_block(&sandbox, &block.hash());
+
+    sandbox.recv(&sandbox.create_compact_block_response(
+        &sandbox.p(ValidatorId(3)),
+        &sandbox.p(ValidatorId(0)),
+        block.clone(),
+        block_precommits(&sandbox, &propose.hash(), &block.hash()),
+        &[tx.hash()],
+        &[],
+        sandbox.s(ValidatorId(3)),
+    ));
+
+    sandbox.assert_state(Height(2), Round(1));
+    sandbox.broadcast(&sandbox.create_status(
+        &sandbox.p(ValidatorId(0)),
+        Height(2),
+        &block.hash(),
+        sandbox.s(ValidatorId(0)),
+    ));
+}
+
+/// HANDLE compact block response
+
+/// - prefilled transactions should be used to restore the block
+/// idea of test is:
+/// - receive some tx A
+/// - getting Status from other node with later height, send CompactBlockRequest to this node
+/// - receive CompactBlockResponse with short ids of txs A and B and prefilled tx B
+/// - Block should be executed and committed without `TransactionsRequest`
+#[test]
+fn handle_compact_block_response_with_prefilled_tx() {
+    let sandbox = compact_blocks_sandbox();
+
+    let tx1 = gen_timestamping_tx();
+    let tx2 = gen_timestamping_tx();
+    sandbox.recv(&tx1);
+
+    let propose = ProposeBuilder::new(&sandbox).build();
+    let block = BlockBuilder::new(&sandbox)
+        .with_txs_hashes(&[tx1.hash(), tx2.hash()])
+        .with_state_hash(&sandbox.compute_state_hash(&[tx1.clone(), tx2.clone()]))
+        .build();
+
+    request_compact_block(&sandbox, &block.hash());
+
+    sandbox.recv(&sandbox.create_compact_block_response(
+        &sandbox.p(ValidatorId(3)),
+        &sandbox.p(ValidatorId(0)),
+        block.clone(),
+        block_precommits(&sandbox, &propose.hash(), &block.hash()),
+        &[tx1.hash(), tx2.hash()],
+        &[tx2.clone()],
+        sandbox.s(ValidatorId(3)),
+    ));
+
+    sandbox.assert_state(Height(2), Round(1));
+    sandbox.broadcast(&sandbox.create_status(
+        &sandbox.p(ValidatorId(0)),
+        Height(2),
+        &block.hash(),
+        sandbox.s(ValidatorId(0)),
+    ));
+}
+
+/// HANDLE compact block response
+
+/// - the full block should be requested if the compact block cannot be restored
+/// idea of test is:
+/// - receive some tx A
+/// - getting Status from other node with later height, send CompactBlockRequest to this node
+/// - receive CompactBlockResponse with short ids of known tx A and unknown tx B
+/// - send BlockRequest to this node
+#[test]
+fn handle_compact_block_response_with_unknown_tx() {
+    let sandbox = compact_blocks_sandbox();
+
+    let tx1 = gen_timestamping_tx();
+    let tx2 = gen_timestamping_tx();
+    sandbox.recv(&tx1);
+
+    let propose = ProposeBuilder::new(&sandbox).build();
+    let block = BlockBuilder::new(&sandbox)
+        .with_txs_hashes(&[tx1.hash(), tx2.hash()])
+        .with_state_hash(&sandbox.compute_state_hash(&[tx1.clone(), tx2.clone()]))
+        .build();
+
+    request_compact_block(&sandbox, &block.hash());
+
+    sandbox.recv(&sandbox.create_compact_block_response(
+        &sandbox.p(ValidatorId(3)),
+        &sandbox.p(ValidatorId(0)),
+        block.clone(),
+        block_precommits(&sandbox, &propose.hash(), &block.hash()),
+        &[tx1.hash(), tx2.hash()],
+        &[],
+        sandbox.s(ValidatorId(3)),
+    ));
+
+    sandbox.send(
+        sandbox.p(ValidatorId(3)),
+        &sandbox.create_block_request(
+            &sandbox.p(ValidatorId(0)),
+            &sandbox.p(ValidatorId(3)),
+            Height(1),
+            sandbox.s(ValidatorId(0)),
+        ),
+    );
+    sandbox.assert_state(Height(1), Round(1));
+}
+
+/// HANDLE compact block request
+
+/// - transactions unknown to the node when it received the block should be prefilled
+/// idea of test is:
+/// - receive some tx A
+/// - receive BlockResponse with known tx A and unknown tx B, request and receive tx B
+/// - receive CompactBlockRequest for the committed block
+/// - send CompactBlockResponse with short ids of txs A and B and prefilled tx B
+#[test]
+fn handle_compact_block_request_prefills_late_txs() {
+    let sandbox = timestamping_sandbox();
+
+    let tx1 = gen_timestamping_tx();
+    let tx2 = gen_timestamping_tx();
+    sandbox.recv(&tx1);
+
+    let propose = ProposeBuilder::new(&sandbox).build();
+    let block = BlockBuilder::new(&sandbox)
+        .with_txs_hashes(&[tx1.hash(), tx2.hash()])
+        .with_state_hash(&sandbox.compute_state_hash(&[tx1.clone(), tx2.clone()]))
+        .build();
+
+    sandbox.recv(&sandbox.create_status(
+        &sandbox.p(ValidatorId(3)),
+        Height(2),
+        &block.hash(),
+        sandbox.s(ValidatorId(3)),
+    ));
+    sandbox.add_time(Duration::from_millis(BLOCK_REQUEST_TIMEOUT));
+    sandbox.send(
+        sandbox.p(ValidatorId(3)),
+        &sandbox.create_block_request(
+            &sandbox.p(ValidatorId(0)),
+            &sandbox.p(ValidatorId(3)),
+            Height(1),
+            sandbox.s(ValidatorId(0)),
+        ),
+    );
+    sandbox.recv(&sandbox.create_block_response(
+        &sandbox.p(ValidatorId(3)),
+        &sandbox.p(ValidatorId(0)),
+        block.clone(),
+        block_precommits(&sandbox, &propose.hash(), &block.hash()),
+        &[tx1.hash(), tx2.hash()],
+        sandbox.s(ValidatorId(3)),
+    ));
+    sandbox.add_time(Duration::from_millis(TRANSACTIONS_REQUEST_TIMEOUT));
+    sandbox.send(
+        sandbox.p(ValidatorId(3)),
+        &sandbox.create_transactions_request(
+            &sandbox.p(ValidatorId(0)),
+            &sandbox.p(ValidatorId(3)),
+            &[tx2.hash()],
+            sandbox.s(ValidatorId(0)),
+        ),
+    );
+    sandbox.recv(&sandbox.create_transactions_response(
+        &sandbox.p(ValidatorId(3)),
+        &sandbox.p(ValidatorId(0)),
+        vec![tx2.clone()],
+        sandbox.s(ValidatorId(3)),
+    ));
+    sandbox.assert_state(Height(2), Round(1));
+    sandbox.broadcast(&sandbox.create_status(
+        &sandbox.p(ValidatorId(0)),
+        Height(2),
+        &block.hash(),
+        sandbox.s(ValidatorId(0)),
+    ));
+
+    sandbox.recv(&sandbox.create_compact_block_request(
+        &sandbox.p(ValidatorId(1)),
+        &sandbox.p(ValidatorId(0)),
+        Height(1),
+        sandbox.s(ValidatorId(1)),
+    ));
+
+    let precommits = {
+        let snapshot = sandbox.blockchain_ref().snapshot();
+        let schema = Schema::new(&snapshot);
+        schema.precommits(&block.hash()).iter().collect::<Vec<_>>()
+    };
+    sandbox.send(
+        sandbox.p(ValidatorId(1)),
+        &sandbox.create_compact_block_response(
+            &sandbox.p(ValidatorId(0)),
+            &sandbox.p(ValidatorId(1)),
+            block.clone(),
+            precommits,
+            &[tx1.hash(), tx2.hash()],
+            &[tx2.clone()],
+            sandbox.s(ValidatorId(0)),
+        ),
+    );
+}
+
+fn committed_block_response(
+    sandbox: &TimestampingSandbox,
+    height: Height,
+    to: ValidatorId,
+) -> Signed<BlockResponse> {
+    let snapshot = sandbox.blockchain_ref().snapshot();
+    let schema = Schema::new(&snapshot);
+    let block_hash = schema.block_hash_by_height(height).unwrap();
+    sandbox.create_block_response(
+        &sandbox.p(ValidatorId(0)),
+        &sandbox.p(to),
+        schema.blocks().get(&block_hash).unwrap(),
+        schema.precommits(&block_hash).iter(),
+        &schema.block_transactions(height).iter().collect::<Vec<_>>(),
+        sandbox.s(ValidatorId(0)),
+    )
+}
+
+/// HANDLE status
+
+/// - blocks should be requested by ranges if the node lags behind by several blocks
+/// idea of test is:
+/// - getting Status from other node with a height bigger by three blocks
+/// - send BlockRequest for three blocks to this node
+#[test]
+fn request_block_range() {
+    let sandbox = timestamping_sandbox();
+
+    sandbox.recv(&sandbox.create_status(
+        &sandbox.p(ValidatorId(3)),
+        Height(4),
+        &sandbox.last_hash(),
+        sandbox.s(ValidatorId(3)),
+    ));
+
+    sandbox.add_time(Duration::from_millis(BLOCK_REQUEST_TIMEOUT));
+    sandbox.send(
+        sandbox.p(ValidatorId(3)),
+        &sandbox.create_block_range_request(
+            &sandbox.p(ValidatorId(0)),
+            &sandbox.p(ValidatorId(3)),
+            Height(1),
+            3,
+            sandbox.s(ValidatorId(0)),
+        ),
+    );
+}
+
+/// HANDLE status
+
+/// - blocks should be requested in parallel from several nodes
+/// idea of test is:
+/// - getting Status from the third node with the next height
+/// - getting Status from the second node with a height bigger by five blocks
+/// - send BlockRequest for the next block to the third node
+/// - send BlockRequests for the following blocks to the second node, at most two blocks
+///   in a request
+#[test]
+fn request_blocks_from_several_peers() {
+    let sandbox = timestamping_sandbox_builder()
+        .with_network(|config| config.block_sync.blocks_per_request = 2)
+        .build();
+
+    sandbox.recv(&sandbox.create_status(
+        &sandbox.p(ValidatorId(3)),
+        Height(2),
+        &sandbox.last_hash(),
+        sandbox.s(ValidatorId(3)),
+    ));
+    sandbox.recv(&sandbox.create_status(
+        &sandbox.p(ValidatorId(2)),
+        Height(6),
+        &sandbox.last_hash(),
+        sandbox.s(ValidatorId(2)),
+    ));
+
+    sandbox.add_time(Duration::from_millis(BLOCK_REQUEST_TIMEOUT));
+    sandbox.send(
+        sandbox.p(ValidatorId(3)),
+        &sandbox.create_block_request(
+            &sandbox.p(ValidatorId(0)),
+            &sandbox.p(ValidatorId(3)),
+            Height(1),
+            sandbox.s(ValidatorId(0)),
+        ),
+    );
+    for &height in &[Height(2), Height(4)] {
+        sandbox.send(
+            sandbox.p(ValidatorId(2)),
+            &sandbox.create_block_range_request(
+                &sandbox.p(ValidatorId(0)),
+                &sandbox.p(ValidatorId(2)),
+                height,
+                2,
+                sandbox.s(ValidatorId(0)),
+            ),
+        );
+    }
+}
+
+/// HANDLE block response
+
+/// - blocks for the future heights should be kept only if they are requested from their author
+/// idea of test is:
+/// - request blocks from two nodes as in `request_blocks_from_several_peers`
+/// - receive the block at height 2 from the third node, which is requested from the second one
+/// - the block should be ignored, so the following blocks are not requested anew
+#[test]
+fn ignore_future_block_from_another_peer() {
+    let sandbox = timestamping_sandbox_builder()
+        .with_network(|config| config.block_sync.blocks_per_request = 2)
+        .build();
+
+    sandbox.recv(&sandbox.create_status(
+        &sandbox.p(ValidatorId(3)),
+        Height(2),
+        &sandbox.last_hash(),
+        sandbox.s(ValidatorId(3)),
+    ));
+    sandbox.recv(&sandbox.create_status(
+        &sandbox.p(ValidatorId(2)),
+        Height(6),
+        &sandbox.last_hash(),
+        sandbox.s(ValidatorId(2)),
+    ));
+
+    sandbox.add_time(Duration::from_millis(BLOCK_REQUEST_TIMEOUT));
+    sandbox.send(
+        sandbox.p(ValidatorId(3)),
+        &sandbox.create_block_request(
+            &sandbox.p(ValidatorId(0)),
+            &sandbox.p(ValidatorId(3)),
+            Height(1),
+            sandbox.s(ValidatorId(0)),
+        ),
+    );
+    for &height in &[Height(2), Height(4)] {
+        sandbox.send(
+            sandbox.p(ValidatorId(2)),
+            &sandbox.create_block_range_request(
+                &sandbox.p(ValidatorId(0)),
+                &sandbox.p(ValidatorId(2)),
+                height,
+                2,
+                sandbox.s(ValidatorId(0)),
+            ),
+        );
+    }
+
+    let block = BlockBuilder::new(&sandbox).with_height(Height(2)).build();
+    sandbox.recv(&sandbox.create_block_response(
+        &sandbox.p(ValidatorId(3)),
+        &sandbox.p(ValidatorId(0)),
+        block,
+        vec![],
+        &[],
+        sandbox.s(ValidatorId(3)),
+    ));
+    sandbox.assert_state(Height(1), Round(1));
+}
+
+/// HANDLE blocks response
+
+/// - the block from the response should be committed
+/// idea of test is:
+/// - receive some tx A
+/// - getting Status from other node with later height, send BlockRequest to this node
+/// - receive BlocksResponse with the block containing tx A
+/// - Block should be executed and committed
+#[test]
+fn handle_blocks_response() {
+    let sandbox = timestamping_sandbox();
+
+    let tx = gen_timestamping_tx();
+    sandbox.recv(&tx);
+
+    let propose = ProposeBuilder::new(&sandbox).build();
+    let block = BlockBuilder::new(&sandbox)
+        .with_tx_hash(&tx.hash())
+        .with_state_hash(&sandbox.compute_state_hash(&[tx.clone()]))
+        .build();
+
+    sandbox.recv(&sandbox.create_status(
+        &sandbox.p(ValidatorId(3)),
+        Height(2),
+        &block.hash(),
+        sandbox.s(ValidatorId(3)),
+    ));
+    sandbox.add_time(Duration::from_millis(BLOCK_REQUEST_TIMEOUT));
+    sandbox.send(
+        sandbox.p(ValidatorId(3)),
+        &sandbox.create_block_request(
+            &sandbox.p(ValidatorId(0)),
+            &sandbox.p(ValidatorId(3)),
+            Height(1),
+            sandbox.s(ValidatorId(0)),
+        ),
+    );
+
+    let block_response = sandbox.create_block_response(
+        &sandbox.p(ValidatorId(3)),
+        &sandbox.p(ValidatorId(0)),
+        block.clone(),
+        block_precommits(&sandbox, &propose.hash(), &block.hash()),
+        &[tx.hash()],
+        sandbox.s(ValidatorId(3)),
+    );
+    sandbox.recv(&sandbox.create_blocks_response(
+        &sandbox.p(ValidatorId(3)),
+        &sandbox.p(ValidatorId(0)),
+        &[block_response],
+        sandbox.s(ValidatorId(3)),
+    ));
+
+    sandbox.assert_state(Height(2), Round(1));
+    sandbox.broadcast(&sandbox.create_status(
+        &sandbox.p(ValidatorId(0)),
+        Height(2),
+        &block.hash(),
+        sandbox.s(ValidatorId(0)),
+    ));
+}
+
+/// HANDLE blocks response
+
+/// - blocks signed by another node should be ignored
+#[test]
+fn handle_blocks_response_signed_by_another_node() {
+    let sandbox = timestamping_sandbox();
+
+    let tx = gen_timestamping_tx();
+    sandbox.recv(&tx);
+
+    let propose = ProposeBuilder::new(&sandbox).build();
+    let block = BlockBuilder::new(&sandbox)
+        .with_tx_hash(&tx.hash())
+        .with_state_hash(&sandbox.compute_state_hash(&[tx.clone()]))
+        .build();
+
+    sandbox.recv(&sandbox.create_status(
+        &sandbox.p(ValidatorId(3)),
+        Height(2),
+        &block.hash(),
+        sandbox.s(ValidatorId(3)),
+    ));
+
+    let block_response = sandbox.create_block_response(
+        &sandbox.p(ValidatorId(2)),
+        &sandbox.p(ValidatorId(0)),
+        block.clone(),
+        block_precommits(&sandbox, &propose.hash(), &block.hash()),
+        &[tx.hash()],
+        sandbox.s(ValidatorId(2)),
+    );
+    sandbox.recv(&sandbox.create_blocks_response(
+        &sandbox.p(ValidatorId(3)),
+        &sandbox.p(ValidatorId(0)),
+        &[block_response],
+        sandbox.s(ValidatorId(3)),
+    ));
+
+    sandbox.assert_state(Height(1), Round(1));
+}
+
+/// HANDLE block request
+
+/// - several blocks should be sent in a single response
+/// idea of test is:
+/// - commit three blocks
+/// - receive BlockRequest for two blocks, send BlocksResponse with them
+/// - receive BlockRequest for five blocks starting from the last committed one,
+///   send BlockResponse with the only available block
+#[test]
+fn handle_block_range_request() {
+    let sandbox = timestamping_sandbox();
+    let sandbox_state = SandboxState::new();
+
+    add_one_height(&sandbox, &sandbox_state);
+    add_one_height(&sandbox, &sandbox_state);
+    add_one_height(&sandbox, &sandbox_state);
+    sandbox.assert_state(Height(4), Round(1));
+
+    sandbox.recv(&sandbox.create_block_range_request(
+        &sandbox.p(ValidatorId(1)),
+        &sandbox.p(ValidatorId(0)),
+        Height(1),
+        2,
+        sandbox.s(ValidatorId(1)),
+    ));
+    let blocks = [Height(1), Height(2)]
+        .iter()
+        .map(|&height| committed_block_response(&sandbox, height, ValidatorId(1)))
+        .collect::<Vec<_>>();
+    sandbox.send(
+        sandbox.p(ValidatorId(1)),
+        &sandbox.create_blocks_response(
+            &sandbox.p(ValidatorId(0)),
+            &sandbox.p(ValidatorId(1)),
+            &blocks,
+            sandbox.s(ValidatorId(0)),
+        ),
+    );
+
+    sandbox.recv(&sandbox.create_block_range_request(
+        &sandbox.p(ValidatorId(1)),
+        &sandbox.p(ValidatorId(0)),
+        Height(3),
+        5,
+        sandbox.s(ValidatorId(1)),
+    ));
+    sandbox.send(
+        sandbox.p(ValidatorId(1)),
+        &committed_block_response(&sandbox, Height(3), ValidatorId(1)),
+    );
+}
+
+/// HANDLE block response in the replica mode
+
+/// - replica should ignore consensus messages, but commit blocks received with precommits
+/// idea of test is:
+/// - receive Propose, nothing is sent in response
+/// - round timeout passes, the round of the replica does not change
+/// - getting Status from other node with later height, send BlockRequest to this node
+/// - receive BlockResponse with the empty block
+/// - Block should be executed and committed
+#[test]
+fn replica_commits_received_block() {
+    let sandbox = timestamping_sandbox_builder().as_replica().build();
+
+    let propose = ProposeBuilder::new(&sandbox).build();
+    let block = BlockBuilder::new(&sandbox).build();
+    let precommits = block_precommits(&sandbox, &propose.hash(), &block.hash());
+
+    sandbox.recv(&propose);
+    sandbox.add_time(Duration::from_millis(sandbox.first_round_timeout()));
+    sandbox.assert_state(Height(1), Round(1));
+
+    sandbox.recv(&sandbox.create_status(
+        &sandbox.p(ValidatorId(3)),
+        Height(2),
+        &block.hash(),
+        sandbox.s(ValidatorId(3)),
+    ));
+
+    sandbox.add_time(Duration::from_millis(BLOCK_REQUEST_TIMEOUT));
+    sandbox.send(
+        sandbox.p(ValidatorId(3)),
+        &sandbox.create_block_request(
+            &sandbox.p(ValidatorId(0)),
+            &sandbox.p(ValidatorId(3)),
+            Height(1),
+            sandbox.s(ValidatorId(0)),
+        ),
+    );
+
+    sandbox.recv(&sandbox.create_block_response(
+        &sandbox.p(ValidatorId(3)),
+        &sandbox.p(ValidatorId(0)),
+        block.clone(),
+        precommits,
+        &[],
+        sandbox.s(ValidatorId(3)),
+    ));
+
+    sandbox.assert_state(Height(2), Round(1));
+    sandbox.broadcast(&sandbox.create_status(
+        &sandbox.p(ValidatorId(0)),
+        Height(2),
+        &block.hash(),
+        sandbox.s(ValidatorId(0)),
+    ));
+}
@@ -21,7 +21,7 @@ use std::time::Duration;
 use crypto::{gen_keypair, CryptoHash, Hash};
 use helpers::{Height, Milliseconds, Round, ValidatorId};
 use messages::{RawTransaction, Signed};
-use node::state::TRANSACTIONS_REQUEST_TIMEOUT;
+use node::{state::TRANSACTIONS_REQUEST_TIMEOUT, TxGossipConfig};
 use sandbox::{
     config_updater::TxConfig,
     sandbox::{timestamping_sandbox, timestamping_sandbox_builder, Sandbox},
@@ -48,6 +48,22 @@ fn timestamping_sandbox_with_threshold() -> Sandbox {
     sandbox
 }
 
+/// Sandbox in which transactions are announced instead of being broadcast.
+fn timestamping_sandbox_with_announcements() -> Sandbox {
+    timestamping_sandbox_builder()
+        .with_mempool(|config| config.tx_gossip.flood_peers_threshold = 0)
+        .build()
+}
+
+/// Pops all sent messages and returns their total size in bytes.
+fn sent_bytes(sandbox: &Sandbox) -> usize {
+    let mut total = 0;
+    while let Some((_, msg)) = sandbox.pop_sent() {
+        total += msg.signed_message().raw().len();
+    }
+    total
+}
+
 fn tx_hashes(transactions: &[Signed<RawTransaction>]) -> Vec<Hash> {
     let mut hashes = transactions.iter().map(|tx| tx.hash()).collect::<Vec<_>>();
     hashes.sort();
@@ -259,6 +275,146 @@ fn rebroadcast_transactions() {
     }
 }
 
+#[test]
+fn broadcast_incoming_tx_in_small_network() {
+    let sandbox = timestamping_sandbox();
+
+    let tx = gen_timestamping_tx();
+    sandbox.recv_api_transaction(&tx);
+    sandbox.broadcast(&tx);
+}
+
+#[test]
+fn announce_incoming_txs() {
+    let sandbox = timestamping_sandbox_with_announcements();
+    let announce_interval = TxGossipConfig::default().announce_interval;
+
+    let transactions = TimestampingTxGenerator::new(DATA_SIZE)
+        .take(3)
+        .collect::<Vec<_>>();
+    for tx in &transactions {
+        sandbox.recv_api_transaction(tx);
+    }
+
+    // Hashes are accumulated until the announce interval expires.
+    sandbox.add_time(Duration::from_millis(announce_interval - 1));
+    sandbox.add_time(Duration::from_millis(1));
+
+    let hashes = transactions.iter().map(|tx| tx.hash()).collect::<Vec<_>>();
+    sandbox.broadcast(&sandbox.create_transactions_announce(
+        &sandbox.p(ValidatorId(0)),
+        &hashes,
+        sandbox.s(ValidatorId(0)),
+    ));
+}
+
+#[test]
+fn rebroadcast_announcements() {
+    let sandbox = timestamping_sandbox_with_announcements();
+
+    let transactions = send_txs_into_pool(
+        &sandbox,
+        TimestampingTxGenerator::new(DATA_SIZE).take(5).collect(),
+    );
+
+    sandbox.recv_rebroadcast();
+    sandbox.broadcast(&sandbox.create_transactions_announce(
+        &sandbox.p(ValidatorId(0)),
+        &tx_hashes(&transactions),
+        sandbox.s(ValidatorId(0)),
+    ));
+}
+
+#[test]
+fn announcements_reduce_rebroadcast_volume() {
+    let transactions = TimestampingTxGenerator::new(DATA_SIZE)
+        .take(20)
+        .collect::<Vec<_>>();
+
+    let flooding = timestamping_sandbox();
+    send_txs_into_pool(&flooding, transactions.clone());
+    flooding.recv_rebroadcast();
+    let flooding_bytes = sent_bytes(&flooding);
+
+    let announcing = timestamping_sandbox_with_announcements();
+    send_txs_into_pool(&announcing, transactions);
+    announcing.recv_rebroadcast();
+    let announcing_bytes = sent_bytes(&announcing);
+
+    // Peers that already know the transactions do not request their bodies,
+    // so the announcements are the only traffic.
+    assert!(announcing_bytes > 0);
+    assert!(
+        announcing_bytes * 2 < flooding_bytes,
+        "announcements: {} bytes, flooding: {} bytes",
+        announcing_bytes,
+        flooding_bytes
+    );
+}
+
+#[test]
+fn request_announced_txs() {
+    let sandbox = timestamping_sandbox();
+
+    let known_tx = gen_timestamping_tx();
+    let unknown_tx = TimestampingTxGenerator::new(DATA_SIZE).next().unwrap();
+    sandbox.recv(&known_tx);
+
+    // Only the unknown transaction is requested from the author of the announcement.
+    sandbox.recv(&sandbox.create_transactions_announce(
+        &sandbox.p(ValidatorId(1)),
+        &[known_tx.hash(), unknown_tx.hash()],
+        sandbox.s(ValidatorId(1)),
+    ));
+    sandbox.send(
+        sandbox.p(ValidatorId(1)),
+        &sandbox.create_transactions_request(
+            &sandbox.p(ValidatorId(0)),
+            &sandbox.p(ValidatorId(1)),
+            &[unknown_tx.hash()],
+            sandbox.s(ValidatorId(0)),
+        ),
+    );
+
+    // The same transaction announced by another peer is not requested twice...
+    sandbox.recv(&sandbox.create_transactions_announce(
+        &sandbox.p(ValidatorId(2)),
+        &[unknown_tx.hash()],
+        sandbox.s(ValidatorId(2)),
+    ));
+
+    // ...until the first request times out.
+    sandbox.add_time(Duration::from_millis(TRANSACTIONS_REQUEST_TIMEOUT));
+    sandbox.send(
+        sandbox.p(ValidatorId(2)),
+        &sandbox.create_transactions_request(
+            &sandbox.p(ValidatorId(0)),
+            &sandbox.p(ValidatorId(2)),
+            &[unknown_tx.hash()],
+            sandbox.s(ValidatorId(0)),
+        ),
+    );
+
+    // Received transaction is not requested anymore.
+    sandbox.recv(&unknown_tx);
+    sandbox.add_time(Duration::from_millis(TRANSACTIONS_REQUEST_TIMEOUT));
+}
+
+#[test]
+fn ignore_announce_of_known_txs() {
+    let sandbox = timestamping_sandbox();
+
+    let tx = gen_timestamping_tx();
+    sandbox.recv(&tx);
+
+    sandbox.recv(&sandbox.create_transactions_announce(
+        &sandbox.p(ValidatorId(1)),
+        &[tx.hash()],
+        sandbox.s(ValidatorId(1)),
+    ));
+    sandbox.add_time(Duration::from_millis(TRANSACTIONS_REQUEST_TIMEOUT));
+}
+
 // TODO: transaction verification logic is duplicated,
 // in sandbox so this test is testing sandbox
 #[test]
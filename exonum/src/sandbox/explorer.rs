@@ -0,0 +1,304 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tests of the blockchain explorer and its API.
+
+use serde_json;
+
+use api::{
+    node::public::explorer::{
+        BlocksQuery, ExplorerApi, MissingTransaction, TransactionHex, TransactionQuery,
+        TransactionSearchQuery,
+    },
+    pagination::{Cursor, Page, PageRequest},
+    Error as ApiError, ServiceApiState,
+};
+use blockchain::SharedNodeState;
+use crypto::{self, gen_keypair, Hash};
+use explorer::{
+    BlockchainExplorer, CommittedTransaction, DropReason, TransactionFilter, TxOutcome,
+};
+use helpers::Height;
+
+use super::{
+    sandbox::timestamping_sandbox,
+    sandbox_tests_helper::{add_one_height_with_transactions, SandboxState, TimestampingSandbox},
+    timestamping::{TimestampingTxGenerator, DATA_SIZE, TIMESTAMPING_SERVICE},
+};
+
+fn sandbox_with_indexed_transactions(count: usize) -> TimestampingSandbox {
+    let sandbox = timestamping_sandbox();
+    sandbox.blockchain_mut().set_explorer_indexes(true);
+    let sandbox_state = SandboxState::new();
+    let txs: Vec<_> = TimestampingTxGenerator::new(DATA_SIZE)
+        .take(count)
+        .collect();
+    add_one_height_with_transactions(&sandbox, &sandbox_state, &txs);
+    sandbox
+}
+
+#[test]
+fn test_search_transactions_scan_limit() {
+    let sandbox = sandbox_with_indexed_transactions(5);
+    let blockchain = sandbox.blockchain_ref();
+    let explorer = BlockchainExplorer::new(&blockchain);
+    let filter = TransactionFilter {
+        service_id: Some(TIMESTAMPING_SERVICE),
+        ..Default::default()
+    };
+
+    // The page is cut short by the scan limit and continues after the examined entries.
+    let page = explorer
+        .search_transactions_scanning(&filter, PageRequest::new(None, 10), 2)
+        .unwrap();
+    assert_eq!(page.items.len(), 2);
+    assert_eq!(page.next_cursor, Some(Cursor::new(2)));
+    let page = explorer
+        .search_transactions_scanning(&filter, PageRequest::new(page.next_cursor, 10), 2)
+        .unwrap();
+    assert_eq!(page.items.len(), 2);
+    assert_eq!(page.next_cursor, Some(Cursor::new(0)));
+    let page = explorer
+        .search_transactions_scanning(&filter, PageRequest::new(page.next_cursor, 10), 2)
+        .unwrap();
+    assert_eq!(page.items.len(), 1);
+    assert_eq!(page.next_cursor, None);
+
+    // No examined entry matches the filter.
+    let failures = TransactionFilter {
+        outcome: Some(TxOutcome::Failure),
+        ..filter
+    };
+    let page = explorer
+        .search_transactions_scanning(&failures, PageRequest::new(None, 10), 2)
+        .unwrap();
+    assert!(page.items.is_empty());
+    assert_eq!(page.next_cursor, Some(Cursor::new(2)));
+}
+
+#[test]
+fn test_transaction_search() {
+    let sandbox = timestamping_sandbox();
+    sandbox.blockchain_mut().set_explorer_indexes(true);
+    let sandbox_state = SandboxState::new();
+    let alice = gen_keypair();
+    let bob = gen_keypair();
+    let alice_txs: Vec<_> = TimestampingTxGenerator::with_keypair(DATA_SIZE, alice.clone())
+        .take(3)
+        .collect();
+    for tx in &alice_txs {
+        add_one_height_with_transactions(&sandbox, &sandbox_state, &[tx.clone()]);
+    }
+    let bob_tx = TimestampingTxGenerator::with_keypair(DATA_SIZE, bob.clone())
+        .next()
+        .unwrap();
+    add_one_height_with_transactions(&sandbox, &sandbox_state, &[bob_tx.clone()]);
+
+    let state = ServiceApiState::new(sandbox.blockchain_ref().clone());
+    let search = |query: TransactionSearchQuery| -> Page<CommittedTransaction> {
+        ExplorerApi::search_transactions(&state, query).unwrap()
+    };
+    let hashes = |page: &Page<CommittedTransaction>| -> Vec<Hash> {
+        page.items
+            .iter()
+            .map(|tx| tx.content().message().hash())
+            .collect()
+    };
+
+    let first = search(TransactionSearchQuery {
+        author: Some(alice.0),
+        limit: Some(2),
+        ..Default::default()
+    });
+    assert_eq!(
+        hashes(&first),
+        vec![alice_txs[2].hash(), alice_txs[1].hash()]
+    );
+    let second = search(TransactionSearchQuery {
+        author: Some(alice.0),
+        cursor: first.next_cursor,
+        limit: Some(2),
+        ..Default::default()
+    });
+    assert_eq!(hashes(&second), vec![alice_txs[0].hash()]);
+    assert_eq!(second.next_cursor, None);
+
+    let successes = search(TransactionSearchQuery {
+        service_id: Some(TIMESTAMPING_SERVICE),
+        outcome: Some(TxOutcome::Success),
+        ..Default::default()
+    });
+    assert_eq!(successes.items.len(), 4);
+    let failures = search(TransactionSearchQuery {
+        service_id: Some(TIMESTAMPING_SERVICE),
+        outcome: Some(TxOutcome::Failure),
+        ..Default::default()
+    });
+    assert!(failures.items.is_empty());
+
+    // Each height is committed after a round timeout, so block times are increasing.
+    let block_time = BlockchainExplorer::new(&sandbox.blockchain_ref())
+        .block_time(Height(3))
+        .unwrap();
+    let before = search(TransactionSearchQuery {
+        author: Some(alice.0),
+        until: Some(block_time),
+        ..Default::default()
+    });
+    assert_eq!(
+        hashes(&before),
+        vec![alice_txs[1].hash(), alice_txs[0].hash()]
+    );
+    let since = search(TransactionSearchQuery {
+        service_id: Some(TIMESTAMPING_SERVICE),
+        since: Some(block_time),
+        ..Default::default()
+    });
+    assert_eq!(hashes(&since), vec![bob_tx.hash(), alice_txs[2].hash()]);
+
+    match ExplorerApi::search_transactions(&state, TransactionSearchQuery::default()) {
+        Err(ApiError::BadRequest(_)) => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_blocks_cursor() {
+    let sandbox = timestamping_sandbox();
+    let sandbox_state = SandboxState::new();
+    for _ in 0..3 {
+        add_one_height_with_transactions(&sandbox, &sandbox_state, &[]);
+    }
+    let state = ServiceApiState::new(sandbox.blockchain_ref().clone());
+    let blocks = |cursor: u64| {
+        let query = BlocksQuery {
+            count: 2,
+            cursor: Some(Cursor::new(cursor)),
+            ..BlocksQuery::default()
+        };
+        ExplorerApi::blocks(&state, query)
+    };
+
+    let range = blocks(3).unwrap();
+    let heights: Vec<_> = range.blocks.iter().map(|block| block.height()).collect();
+    assert_eq!(heights, vec![Height(3), Height(2)]);
+    assert_eq!(range.next_cursor, Some(Cursor::new(1)));
+    let range = blocks(1).unwrap();
+    assert_eq!(range.blocks.len(), 2);
+    assert_eq!(range.next_cursor, None);
+
+    for &cursor in &[4, u64::max_value()] {
+        match blocks(cursor) {
+            Err(ApiError::BadRequest(_)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn test_dropped_transactions() {
+    let sandbox = timestamping_sandbox();
+    let state = ServiceApiState::new(sandbox.blockchain_ref().clone());
+    let node_state = SharedNodeState::new(5000);
+    let mut tx_body = TimestampingTxGenerator::new(DATA_SIZE)
+        .next()
+        .unwrap()
+        .serialize();
+    // Corrupts the signature.
+    *tx_body.last_mut().unwrap() ^= 1;
+    let tx_hash = crypto::hash(&tx_body);
+
+    let query = TransactionHex {
+        tx_body: tx_body.iter().map(|byte| format!("{:02x}", byte)).collect(),
+    };
+    assert!(ExplorerApi::add_transaction(&state, &node_state, query).is_err());
+
+    let missing = |hash: Hash| -> MissingTransaction {
+        let error = ExplorerApi::transaction_info(&state, &node_state, TransactionQuery::new(hash))
+            .unwrap_err();
+        match error {
+            ApiError::NotFound(description) => serde_json::from_str(&description).unwrap(),
+            other => panic!("Unexpected error: {:?}", other),
+        }
+    };
+    match missing(tx_hash) {
+        MissingTransaction::Dropped(tx) => {
+            assert_eq!(tx.tx_hash, tx_hash);
+            assert_eq!(tx.reason, DropReason::Invalid);
+        }
+        other => panic!("Unexpected transaction status: {:?}", other),
+    }
+    assert_eq!(missing(crypto::hash(&[0])), MissingTransaction::Unknown);
+}
+
+#[test]
+fn test_rejected_transactions() {
+    let sandbox = timestamping_sandbox();
+    let sandbox_state = SandboxState::new();
+    let txs: Vec<_> = TimestampingTxGenerator::new(DATA_SIZE).take(2).collect();
+    add_one_height_with_transactions(&sandbox, &sandbox_state, &txs[..1]);
+    let node_state = sandbox.node_handler_mut().api_state.clone();
+
+    // The transaction in the pool is received once more.
+    sandbox.recv(&txs[1]);
+    sandbox.recv(&txs[1]);
+    assert!(node_state.dropped_transaction(&txs[1].hash()).is_none());
+
+    sandbox.recv(&txs[0]);
+    let tx = node_state.dropped_transaction(&txs[0].hash()).unwrap();
+    assert_eq!(tx.reason, DropReason::Rejected);
+    assert_eq!(serde_json::to_value(&tx.reason).unwrap(), json!("rejected"));
+}
+
+#[test]
+fn test_transaction_proof() {
+    let sandbox = timestamping_sandbox();
+    let sandbox_state = SandboxState::new();
+    let txs: Vec<_> = TimestampingTxGenerator::new(DATA_SIZE).take(3).collect();
+    let hashes = add_one_height_with_transactions(&sandbox, &sandbox_state, &txs[..2]);
+    add_one_height_with_transactions(&sandbox, &sandbox_state, &txs[2..]);
+    let tx_hash = txs[1].hash();
+    let position = hashes.iter().position(|hash| *hash == tx_hash).unwrap() as u64;
+
+    let state = ServiceApiState::new(sandbox.blockchain_ref().clone());
+    let proof = ExplorerApi::transaction_proof(&state, TransactionQuery::new(tx_hash)).unwrap();
+    let block = &proof.block_proof.block;
+    assert_eq!(block.height(), Height(1));
+    assert_eq!(proof.state_block_proof.block.height(), Height(2));
+    assert_eq!(proof.location.position_in_block(), position);
+    let location = proof
+        .location_proof
+        .validate(*block.tx_hash(), u64::from(block.tx_count()))
+        .unwrap();
+    assert_eq!(location, vec![(position, &tx_hash)]);
+
+    let to_table = proof.to_table.check().unwrap();
+    assert_eq!(
+        to_table.merkle_root(),
+        *proof.state_block_proof.block.state_hash()
+    );
+    let to_result = proof.to_result.check().unwrap();
+    assert!(to_table
+        .entries()
+        .any(|(_, table_hash)| *table_hash == to_result.merkle_root()));
+    let results = to_result.entries().collect::<Vec<_>>();
+    assert_eq!(results.len(), 1);
+    assert_eq!(*results[0].0, tx_hash);
+    assert_eq!((results[0].1).0, Ok(()));
+
+    match ExplorerApi::transaction_proof(&state, TransactionQuery::new(crypto::hash(&[0]))) {
+        Err(ApiError::NotFound(_)) => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
@@ -12,10 +12,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod archive;
 mod config_updater;
 mod consensus;
+mod explorer;
 mod old;
 mod requests;
 mod sandbox;
 mod sandbox_tests_helper;
-mod timestamping;
+pub(crate) mod timestamping;
@@ -38,21 +38,25 @@ use blockchain::{
     Block, BlockProof, Blockchain, ConsensusConfig, GenesisConfig, Schema, Service,
     SharedNodeState, StoredConfiguration, Transaction, ValidatorKeys,
 };
-use crypto::{gen_keypair, gen_keypair_from_seed, Hash, PublicKey, SecretKey, Seed, SEED_LENGTH};
+use crypto::{
+    gen_keypair, gen_keypair_from_seed, CryptoHash, Hash, PublicKey, SecretKey, Seed, SEED_LENGTH,
+};
 use events::{
     network::NetworkConfiguration, Event, EventHandler, InternalEvent, InternalRequest,
     NetworkEvent, NetworkRequest, TimeoutRequest,
 };
 use helpers::{user_agent, Height, Milliseconds, Round, ValidatorId};
 use messages::{
-    BlockRequest, BlockResponse, Connect, Message, PeersRequest, Precommit, Prevote,
-    PrevotesRequest, Propose, ProposeRequest, ProtocolMessage, RawTransaction, Signed,
-    SignedMessage, Status, TransactionsRequest, TransactionsResponse,
+    BlockRequest, BlockResponse, BlocksResponse, CompactBlockRequest, CompactBlockResponse,
+    Connect, Message, PeersRequest, Precommit, Prevote, PrevotesRequest, Propose, ProposeRequest,
+    ProtocolMessage, RawTransaction, Signed, SignedMessage, Status, TransactionsAnnounce,
+    TransactionsRequest, TransactionsResponse,
 };
 use node::ConnectInfo;
 use node::{
     ApiSender, Configuration, ConnectList, ConnectListConfig, ExternalMessage, ListenerConfig,
-    NodeHandler, NodeSender, PeerAddress, ServiceConfig, State, SystemStateProvider,
+    MemoryPoolConfig, NodeHandler, NodeSender, PeerAddress, ServiceConfig, State,
+    SystemStateProvider,
 };
 use storage::{MapProof, MemoryDB};
 
@@ -110,7 +114,9 @@ impl SandboxInner {
                             Message::deserialize(msg).expect("Expected valid message.");
                         self.sent.push_back((peer, protocol_msg))
                     }
-                    NetworkRequest::DisconnectWithPeer(_) | NetworkRequest::Shutdown => {}
+                    NetworkRequest::DisconnectWithPeer(_)
+                    | NetworkRequest::UpdateConsensusKeys(..)
+                    | NetworkRequest::Shutdown => {}
                 }
             }
             Ok(())
@@ -127,7 +133,7 @@ impl SandboxInner {
                         .handler
                         .handle_event(InternalEvent::JumpToRound(height, round).into()),
                     InternalRequest::Shutdown => unimplemented!(),
-                    InternalRequest::VerifyMessage(message) => {
+                    InternalRequest::VerifyMessage(_, message) => {
                         let protocol =
                             Message::deserialize(SignedMessage::from_raw_buffer(message).unwrap())
                                 .unwrap();
@@ -223,6 +229,18 @@ impl Sandbox {
         Message::concrete(BlockRequest::new(to, height), *author, secret_key)
     }
 
+    /// Creates a `BlockRequest` message for several blocks signed by this validator.
+    pub fn create_block_range_request(
+        &self,
+        author: &PublicKey,
+        to: &PublicKey,
+        height: Height,
+        count: u32,
+        secret_key: &SecretKey,
+    ) -> Signed<BlockRequest> {
+        Message::concrete(BlockRequest::range(to, height, count), *author, secret_key)
+    }
+
     /// Creates a `Status` message signed by this validator.
     pub fn create_status(
         &self,
@@ -256,6 +274,69 @@ impl Sandbox {
         )
     }
 
+    /// Creates a `BlocksResponse` message signed by this validator.
+    pub fn create_blocks_response(
+        &self,
+        public_key: &PublicKey,
+        to: &PublicKey,
+        blocks: &[Signed<BlockResponse>],
+        secret_key: &SecretKey,
+    ) -> Signed<BlocksResponse> {
+        Message::concrete(
+            BlocksResponse::new(
+                to,
+                blocks
+                    .iter()
+                    .map(|block| block.clone().serialize())
+                    .collect(),
+            ),
+            *public_key,
+            secret_key,
+        )
+    }
+
+    /// Creates a `CompactBlockRequest` message signed by this validator.
+    pub fn create_compact_block_request(
+        &self,
+        author: &PublicKey,
+        to: &PublicKey,
+        height: Height,
+        secret_key: &SecretKey,
+    ) -> Signed<CompactBlockRequest> {
+        Message::concrete(CompactBlockRequest::new(to, height), *author, secret_key)
+    }
+
+    /// Creates a `CompactBlockResponse` message signed by this validator.
+    pub fn create_compact_block_response<I: IntoIterator<Item = Signed<Precommit>>>(
+        &self,
+        public_key: &PublicKey,
+        to: &PublicKey,
+        block: Block,
+        precommits: I,
+        tx_hashes: &[Hash],
+        prefilled_txs: &[Signed<RawTransaction>],
+        secret_key: &SecretKey,
+    ) -> Signed<CompactBlockResponse> {
+        let block_hash = block.hash();
+        Message::concrete(
+            CompactBlockResponse::new(
+                to,
+                block,
+                precommits.into_iter().map(|x| x.serialize()).collect(),
+                tx_hashes
+                    .iter()
+                    .map(|tx_hash| CompactBlockResponse::short_tx_id(&block_hash, tx_hash))
+                    .collect(),
+                prefilled_txs
+                    .iter()
+                    .map(|tx| tx.clone().serialize())
+                    .collect(),
+            ),
+            *public_key,
+            secret_key,
+        )
+    }
+
     /// Creates a `Connect` message signed by this validator.
     pub fn create_connect(
         &self,
@@ -392,6 +473,16 @@ impl Sandbox {
         Message::concrete(TransactionsRequest::new(to, txs), *author, secret_key)
     }
 
+    /// Creates a `TransactionsAnnounce` message signed by this validator.
+    pub fn create_transactions_announce(
+        &self,
+        author: &PublicKey,
+        txs: &[Hash],
+        secret_key: &SecretKey,
+    ) -> Signed<TransactionsAnnounce> {
+        Message::concrete(TransactionsAnnounce::new(txs), *author, secret_key)
+    }
+
     /// Creates a `TransactionsResponse` message signed by this validator.
     pub fn create_transactions_response<I>(
         &self,
@@ -458,10 +549,18 @@ impl Sandbox {
 
     pub fn recv<T: ProtocolMessage>(&self, msg: &Signed<T>) {
         self.check_unexpected_message();
-        let event = NetworkEvent::MessageReceived(msg.clone().serialize());
+        let event = NetworkEvent::MessageReceived(msg.author(), msg.clone().serialize());
         self.inner.borrow_mut().handle_event(event);
     }
 
+    /// Submits the transaction to the node as if it was received through the API.
+    pub fn recv_api_transaction(&self, tx: &Signed<RawTransaction>) {
+        self.check_unexpected_message();
+        self.inner
+            .borrow_mut()
+            .handle_event(ExternalMessage::Transaction(tx.clone()));
+    }
+
     pub fn recv_rebroadcast(&self) {
         self.check_unexpected_message();
         self.inner
@@ -850,6 +949,9 @@ impl Sandbox {
                 consensus_public_key: *inner.handler.state.consensus_public_key(),
                 consensus_secret_key: inner.handler.state.consensus_secret_key().clone(),
                 connect_list,
+                key_rotation: Default::default(),
+                external_addresses: Vec::new(),
+                replica: inner.handler.is_replica(),
             },
             service: ServiceConfig {
                 service_public_key: *inner.handler.state.service_public_key(),
@@ -948,7 +1050,9 @@ impl ConnectList {
             .iter()
             .map(|(p, c)| (*p, PeerAddress::new(c.pub_addr().to_owned())))
             .collect();
-        ConnectList { peers }
+        let mut connect_list = ConnectList::default();
+        connect_list.peers = peers;
+        connect_list
     }
 }
 
@@ -957,6 +1061,9 @@ pub struct SandboxBuilder {
     services: Vec<Box<dyn Service>>,
     validators_count: u8,
     consensus_config: ConsensusConfig,
+    mempool_config: MemoryPoolConfig,
+    network_config: NetworkConfiguration,
+    replica: bool,
 }
 
 impl SandboxBuilder {
@@ -975,6 +1082,9 @@ impl SandboxBuilder {
                 max_propose_timeout: PROPOSE_TIMEOUT,
                 propose_timeout_threshold: std::u32::MAX,
             },
+            mempool_config: MemoryPoolConfig::default(),
+            network_config: NetworkConfiguration::default(),
+            replica: false,
         }
     }
 
@@ -993,11 +1103,26 @@ impl SandboxBuilder {
         self
     }
 
+    pub fn with_mempool<F: FnOnce(&mut MemoryPoolConfig)>(mut self, update: F) -> Self {
+        update(&mut self.mempool_config);
+        self
+    }
+
+    pub fn with_network<F: FnOnce(&mut NetworkConfiguration)>(mut self, update: F) -> Self {
+        update(&mut self.network_config);
+        self
+    }
+
     pub fn with_validators(mut self, n: u8) -> Self {
         self.validators_count = n;
         self
     }
 
+    pub fn as_replica(mut self) -> Self {
+        self.replica = true;
+        self
+    }
+
     pub fn build(self) -> Sandbox {
         let _ = env_logger::Builder::from_default_env()
             .target(env_logger::Target::Stdout)
@@ -1006,7 +1131,10 @@ impl SandboxBuilder {
         let mut sandbox = sandbox_with_services_uninitialized(
             self.services,
             self.consensus_config,
+            self.mempool_config,
+            self.network_config,
             self.validators_count,
+            self.replica,
         );
 
         sandbox.inner.borrow_mut().sent.clear(); // To clear initial connect messages.
@@ -1028,7 +1156,10 @@ fn gen_primitive_socket_addr(idx: u8) -> SocketAddr {
 fn sandbox_with_services_uninitialized(
     services: Vec<Box<dyn Service>>,
     consensus: ConsensusConfig,
+    mempool: MemoryPoolConfig,
+    network: NetworkConfiguration,
     validators_count: u8,
+    replica: bool,
 ) -> Sandbox {
     let validators = (0..validators_count)
         .map(|i| gen_keypair_from_seed(&Seed::new([i; SEED_LENGTH])))
@@ -1086,14 +1217,17 @@ fn sandbox_with_services_uninitialized(
             consensus_public_key: validators[0].0,
             consensus_secret_key: validators[0].1.clone(),
             connect_list: ConnectList::from_config(connect_list_config),
+            key_rotation: Default::default(),
+            external_addresses: Vec::new(),
+            replica,
         },
         service: ServiceConfig {
             service_public_key: service_keys[0].0,
             service_secret_key: service_keys[0].1.clone(),
         },
-        network: NetworkConfiguration::default(),
+        network,
         peer_discovery: Vec::new(),
-        mempool: Default::default(),
+        mempool,
     };
 
     let system_state = SandboxSystemStateProvider {
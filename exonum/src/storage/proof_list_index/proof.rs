@@ -1,3 +1,9 @@
+#[cfg(feature = "openapi")]
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{InstanceType, Schema, SchemaObject, SubschemaValidation},
+    JsonSchema,
+};
 use serde::{de::Error, ser::SerializeStruct, Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::{from_value, Error as SerdeJsonError, Value};
 
@@ -111,6 +117,45 @@ impl<V: Serialize> Serialize for ListProof<V> {
         state.end()
     }
 }
+
+#[cfg(feature = "openapi")]
+impl<V: JsonSchema> JsonSchema for ListProof<V> {
+    fn schema_name() -> String {
+        format!("ListProof_{}", V::schema_name())
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        // Branches of the proof are either nested proofs or hashes of the omitted subtrees.
+        let branch: Schema = SchemaObject {
+            subschemas: Some(Box::new(SubschemaValidation {
+                any_of: Some(vec![
+                    gen.subschema_for::<Self>(),
+                    gen.subschema_for::<Hash>(),
+                ]),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into();
+
+        let mut schema = SchemaObject {
+            instance_type: Some(InstanceType::Object.into()),
+            ..Default::default()
+        };
+        schema.metadata().description =
+            Some("Proof of existence for elements of a `ProofListIndex`".to_owned());
+        {
+            let object = schema.object();
+            object.properties.insert("left".to_owned(), branch.clone());
+            object.properties.insert("right".to_owned(), branch);
+            object
+                .properties
+                .insert("val".to_owned(), gen.subschema_for::<V>());
+        }
+        schema.into()
+    }
+}
+
 impl<'a, V> Deserialize<'a> for ListProof<V>
 where
     for<'de> V: Deserialize<'de>,
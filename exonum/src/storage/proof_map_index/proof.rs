@@ -2,6 +2,8 @@
 // ECR-1771 for the details.
 #![allow(bare_trait_objects)]
 
+#[cfg(feature = "openapi")]
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use super::{
@@ -34,6 +36,24 @@ impl Serialize for ProofPath {
     }
 }
 
+#[cfg(feature = "openapi")]
+impl JsonSchema for ProofPath {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        "ProofPath".to_owned()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let mut schema = String::json_schema(gen).into_object();
+        schema.metadata().description =
+            Some("Path in a `ProofMapIndex` as a binary string".to_owned());
+        schema.into()
+    }
+}
+
 impl<'de> Deserialize<'de> for ProofPath {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -112,6 +132,7 @@ pub enum MapProofError {
 
 // Used instead of `(ProofPath, Hash)` only for the purpose of clearer (de)serialization.
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(JsonSchema))]
 struct MapProofEntry {
     path: ProofPath,
     hash: Hash,
@@ -119,6 +140,7 @@ struct MapProofEntry {
 
 // Used instead of `(K, Option<V>)` only for the purpose of clearer (de)serialization.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(JsonSchema))]
 #[serde(untagged)]
 enum OptionalEntry<K, V> {
     Missing { missing: K },
@@ -245,6 +267,7 @@ impl<K, V> Into<(K, Option<V>)> for OptionalEntry<K, V> {
 /// [`check()`]: #method.check
 /// [`ProofPath`]: struct.ProofPath.html
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(JsonSchema))]
 pub struct MapProof<K, V> {
     entries: Vec<OptionalEntry<K, V>>,
     proof: Vec<MapProofEntry>,
@@ -1,15 +1,23 @@
 use exonum::{
-    api::{self, ServiceApiBuilder, ServiceApiState},
-    blockchain::{Schema as CoreSchema, StoredConfiguration},
+    api::{
+        self,
+        pagination::{Cursor, Page, PageRequest},
+        ServiceApiBuilder, ServiceApiState,
+    },
+    blockchain::{BlockProof, Schema as CoreSchema, StoredConfiguration, CORE_SERVICE},
     crypto::{CryptoHash, Hash},
     helpers::Height,
-    storage::StorageValue,
+    storage::{MapProof, StorageValue},
 };
 
 use super::{Propose, ProposeData, Schema, Vote, VoteAgainst, VotingDecision};
 
 pub type VotesInfo = Option<Vec<Option<VotingDecision>>>;
 
+/// The maximum number of configurations returned on a page of
+/// the `v1/configs/proposed` and `v1/configs/committed` endpoints.
+pub const MAX_CONFIGS_PER_PAGE: usize = 100;
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ConfigHashInfo {
     pub hash: Hash,
@@ -30,6 +38,13 @@ pub struct ConfigInfo {
     pub propose: Option<ProposeData>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigProof {
+    pub block_proof: BlockProof,
+    pub to_table: MapProof<Hash, Hash>,
+    pub to_config: MapProof<Hash, StoredConfiguration>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ProposeResponse {
     pub tx_hash: Hash,
@@ -51,6 +66,13 @@ pub struct HashQuery {
 pub struct FilterQuery {
     pub previous_cfg_hash: Option<Hash>,
     pub actual_from: Option<Height>,
+    /// Cursor returned in `next_cursor` of the previous page.
+    #[serde(default)]
+    pub cursor: Option<Cursor>,
+    /// The maximum number of configurations on the page. Should not be greater
+    /// than `MAX_CONFIGS_PER_PAGE`, which is also the default value.
+    #[serde(default)]
+    pub limit: Option<usize>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -101,14 +123,18 @@ impl PublicApi {
         }
     }
 
-    #[cfg_attr(feature = "cargo-clippy", allow(clippy::let_and_return))]
-    fn proposed_configs(state: &ServiceApiState, filter: &FilterQuery) -> Vec<ProposeHashInfo> {
+    fn proposed_configs(
+        state: &ServiceApiState,
+        filter: &FilterQuery,
+        request: PageRequest,
+    ) -> Page<ProposeHashInfo> {
         let schema = Schema::new(state.snapshot());
         let index = schema.config_hash_by_ordinal();
         let proposes_by_hash = schema.propose_data_by_config_hash();
 
+        let start = request.start(0);
         let proposes = index
-            .iter()
+            .iter_from(start)
             .map(|cfg_hash| {
                 let propose_data = proposes_by_hash.get(&cfg_hash).unwrap_or_else(|| {
                     panic!("Not found propose for following cfg_hash: {:?}", cfg_hash)
@@ -116,38 +142,45 @@ impl PublicApi {
 
                 (cfg_hash, propose_data)
             })
-            .filter(|&(_, ref propose_data)| {
+            .zip(start..)
+            .filter(|&((_, ref propose_data), _)| {
                 let cfg = <StoredConfiguration as StorageValue>::from_bytes(
                     propose_data.tx_propose.cfg.as_bytes().into(),
                 );
                 filter.matches(&cfg)
             })
-            .map(|(hash, propose_data)| ProposeHashInfo { hash, propose_data })
-            .collect();
-        proposes
+            .map(|((hash, propose_data), position)| {
+                (position, ProposeHashInfo { hash, propose_data })
+            });
+        request.collect(proposes)
     }
 
-    #[cfg_attr(feature = "cargo-clippy", allow(clippy::let_and_return))]
-    fn committed_configs(state: &ServiceApiState, filter: &FilterQuery) -> Vec<ConfigHashInfo> {
+    fn committed_configs(
+        state: &ServiceApiState,
+        filter: &FilterQuery,
+        request: PageRequest,
+    ) -> Page<ConfigHashInfo> {
         let core_schema = CoreSchema::new(state.snapshot());
         let actual_from = core_schema.configs_actual_from();
         let configs = core_schema.configs();
 
+        let start = request.start(0);
         let committed_configs = actual_from
-            .iter()
-            .map(|config_ref| {
+            .iter_from(start)
+            .zip(start..)
+            .map(|(config_ref, position)| {
                 let config_hash = config_ref.cfg_hash();
-                configs.get(config_hash).unwrap_or_else(|| {
+                let config = configs.get(config_hash).unwrap_or_else(|| {
                     panic!(
                         "Config with hash {:?} is absent in configs table",
                         config_hash
                     )
-                })
+                });
+                (position, config)
             })
-            .filter(|config| filter.matches(config))
-            .map(|config| Self::config_with_proofs(state, config))
-            .collect();
-        committed_configs
+            .filter(|&(_, ref config)| filter.matches(config))
+            .map(|(position, config)| (position, Self::config_with_proofs(state, config)));
+        request.collect(committed_configs)
     }
 
     fn handle_actual_config(state: &ServiceApiState, _query: ()) -> api::Result<ConfigHashInfo> {
@@ -178,6 +211,27 @@ impl PublicApi {
         })
     }
 
+    fn handle_config_proof(state: &ServiceApiState, query: HashQuery) -> api::Result<ConfigProof> {
+        let snapshot = state.snapshot();
+        let core_schema = CoreSchema::new(&snapshot);
+        let configs = core_schema.configs();
+        if !configs.contains(&query.hash) {
+            return Err(api::Error::NotFound(format!(
+                "Configuration {:?} is not committed",
+                query.hash
+            )));
+        }
+
+        let block_proof = core_schema
+            .block_and_precommits(core_schema.height())
+            .expect("Latest block is absent");
+        Ok(ConfigProof {
+            block_proof,
+            to_table: core_schema.get_proof_to_service_table(CORE_SERVICE, 0),
+            to_config: configs.get_proof(query.hash),
+        })
+    }
+
     fn handle_votes_for_propose(
         state: &ServiceApiState,
         query: HashQuery,
@@ -188,15 +242,17 @@ impl PublicApi {
     fn handle_proposed_configs(
         state: &ServiceApiState,
         query: FilterQuery,
-    ) -> api::Result<Vec<ProposeHashInfo>> {
-        Ok(Self::proposed_configs(state, &query))
+    ) -> api::Result<Page<ProposeHashInfo>> {
+        let request = PageRequest::with_max_limit(query.cursor, query.limit, MAX_CONFIGS_PER_PAGE)?;
+        Ok(Self::proposed_configs(state, &query, request))
     }
 
     fn handle_committed_configs(
         state: &ServiceApiState,
         query: FilterQuery,
-    ) -> api::Result<Vec<ConfigHashInfo>> {
-        Ok(Self::committed_configs(state, &query))
+    ) -> api::Result<Page<ConfigHashInfo>> {
+        let request = PageRequest::with_max_limit(query.cursor, query.limit, MAX_CONFIGS_PER_PAGE)?;
+        Ok(Self::committed_configs(state, &query, request))
     }
 
     pub fn wire(builder: &mut ServiceApiBuilder) {
@@ -205,6 +261,7 @@ impl PublicApi {
             .endpoint("v1/configs/actual", Self::handle_actual_config)
             .endpoint("v1/configs/following", Self::handle_following_config)
             .endpoint("v1/configs", Self::handle_config_by_hash)
+            .endpoint("v1/configs/proof", Self::handle_config_proof)
             .endpoint("v1/configs/votes", Self::handle_votes_for_propose)
             .endpoint("v1/configs/proposed", Self::handle_proposed_configs)
             .endpoint("v1/configs/committed", Self::handle_committed_configs);
@@ -198,6 +198,11 @@ pub fn generate_testnet_config(
             services_configs: service_config.clone(),
             database: Default::default(),
             thread_pool_size: Default::default(),
+            consensus_trace: None,
+            key_rotation: Default::default(),
+            external_addresses: Vec::new(),
+            replica: false,
+            explorer_indexes: false,
         })
         .collect::<Vec<_>>()
 }
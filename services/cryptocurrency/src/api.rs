@@ -1,7 +1,11 @@
 //! Cryptocurrency API.
 
 use exonum::{
-    api::{self, ServiceApiBuilder, ServiceApiState},
+    api::{
+        self,
+        pagination::{Cursor, PageRequest},
+        ServiceApiBuilder, ServiceApiState,
+    },
     blockchain::{self, BlockProof, TransactionMessage},
     crypto::{Hash, PublicKey},
     explorer::BlockchainExplorer,
@@ -12,6 +16,9 @@ use exonum::{
 use wallet::Wallet;
 use {Schema, CRYPTOCURRENCY_SERVICE_ID};
 
+/// The maximum number of transactions on a page of the wallet history.
+pub const MAX_HISTORY_PAGE_SIZE: usize = 100;
+
 /// Describes the query parameters for the `get_wallet` endpoint.
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
 pub struct WalletQuery {
@@ -19,6 +26,20 @@ pub struct WalletQuery {
     pub pub_key: PublicKey,
 }
 
+/// Describes the query parameters for the `wallet_history` endpoint.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct WalletHistoryQuery {
+    /// Public key of the queried wallet.
+    pub pub_key: PublicKey,
+    /// Cursor returned in `next_cursor` of the previous page.
+    #[serde(default)]
+    pub cursor: Option<Cursor>,
+    /// The maximum number of transactions on the page. Should not be greater than
+    /// `MAX_HISTORY_PAGE_SIZE`, which is also the default value.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
 /// Proof of existence for specific wallet.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct WalletProof {
@@ -37,6 +58,17 @@ pub struct WalletHistory {
     pub transactions: Vec<TransactionMessage>,
 }
 
+/// Page of the wallet history.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WalletHistoryPage {
+    /// Proof of the transaction hashes on the page in the wallet history.
+    pub proof: ListProof<Hash>,
+    /// Transactions on the page, from the oldest to the newest.
+    pub transactions: Vec<TransactionMessage>,
+    /// Cursor of the next page, or `None` if this page is the last one.
+    pub next_cursor: Option<Cursor>,
+}
+
 /// Wallet information.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct WalletInfo {
@@ -102,10 +134,48 @@ impl PublicApi {
         })
     }
 
-    /// Wires the above endpoint to public scope of the given `ServiceApiBuilder`.
+    /// Endpoint for getting a page of the wallet history.
+    pub fn wallet_history(
+        state: &ServiceApiState,
+        query: WalletHistoryQuery,
+    ) -> api::Result<WalletHistoryPage> {
+        let request =
+            PageRequest::with_max_limit(query.cursor, query.limit, MAX_HISTORY_PAGE_SIZE)?;
+
+        let snapshot = state.snapshot();
+        let currency_schema = Schema::new(&snapshot);
+        if currency_schema.wallet(&query.pub_key).is_none() {
+            return Err(api::Error::NotFound("Wallet not found".to_owned()));
+        }
+        let history = currency_schema.wallet_history(&query.pub_key);
+        let start = request.start(0);
+        if start >= history.len() {
+            return Err(api::Error::NotFound(
+                "Cursor is beyond the wallet history".to_owned(),
+            ));
+        }
+
+        let page = request.collect((start..).zip(history.iter_from(start)));
+        let proof = history.get_range_proof(start, start + page.items.len() as u64);
+        let explorer = BlockchainExplorer::new(state.blockchain());
+        let transactions = page
+            .items
+            .iter()
+            .map(|tx_hash| explorer.transaction_without_proof(tx_hash).unwrap())
+            .collect();
+
+        Ok(WalletHistoryPage {
+            proof,
+            transactions,
+            next_cursor: page.next_cursor,
+        })
+    }
+
+    /// Wires the above endpoints to public scope of the given `ServiceApiBuilder`.
     pub fn wire(builder: &mut ServiceApiBuilder) {
         builder
             .public_scope()
-            .endpoint("v1/wallets/info", Self::wallet_info);
+            .endpoint("v1/wallets/info", Self::wallet_info)
+            .endpoint("v1/wallets/history", Self::wallet_history);
     }
 }
@@ -0,0 +1,72 @@
+//! Tests of the cryptocurrency service API.
+
+extern crate cryptocurrency;
+extern crate exonum;
+extern crate exonum_testkit;
+
+use cryptocurrency::{
+    api::{WalletHistoryPage, WalletHistoryQuery},
+    transactions::{CreateWallet, Transfer},
+    Service as CurrencyService,
+};
+use exonum::{api, crypto};
+use exonum_testkit::{ApiKind, TestKit, TestKitApi};
+
+fn wallet_history(api: &TestKitApi, query: &WalletHistoryQuery) -> api::Result<WalletHistoryPage> {
+    api.public(ApiKind::Service("cryptocurrency"))
+        .query(query)
+        .get("v1/wallets/history")
+}
+
+#[test]
+fn test_wallet_history_pages() {
+    let mut testkit = TestKit::for_service(CurrencyService);
+    let (alice, alice_key) = crypto::gen_keypair();
+    let (bob, bob_key) = crypto::gen_keypair();
+    testkit.create_block_with_transactions(vec![
+        CreateWallet::sign("Alice", &alice, &alice_key),
+        CreateWallet::sign("Bob", &bob, &bob_key),
+    ]);
+    testkit.create_block_with_transactions(
+        (0..3).map(|seed| Transfer::sign(&alice, &bob, 10, seed, &alice_key)),
+    );
+
+    let api = testkit.api();
+    let first = wallet_history(
+        &api,
+        &WalletHistoryQuery {
+            pub_key: alice,
+            cursor: None,
+            limit: Some(3),
+        },
+    )
+    .unwrap();
+    assert_eq!(first.transactions.len(), 3);
+    assert!(first.next_cursor.is_some());
+
+    let second = wallet_history(
+        &api,
+        &WalletHistoryQuery {
+            pub_key: alice,
+            cursor: first.next_cursor,
+            limit: Some(3),
+        },
+    )
+    .unwrap();
+    assert_eq!(second.transactions.len(), 1);
+    assert_eq!(second.next_cursor, None);
+
+    let error = wallet_history(
+        &api,
+        &WalletHistoryQuery {
+            pub_key: alice,
+            cursor: None,
+            limit: Some(0),
+        },
+    )
+    .unwrap_err();
+    match error {
+        api::Error::BadRequest(_) => {}
+        other => panic!("Unexpected error: {:?}", other),
+    }
+}